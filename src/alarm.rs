@@ -0,0 +1,231 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::notifier::{Notification, Notifier};
+use crate::player::{PlayerHandle, SetPlaylistMode};
+use crate::playlist::find_playlist;
+use crate::settings::AlarmSettings;
+use crate::state::State as Kv;
+
+/// The volume is raised this often while ramping.
+const RAMP_STEP_SECS: u64 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmPhase {
+    #[default]
+    Idle,
+    /// Waiting for `at`.
+    Armed,
+    /// Playing while the volume goes up.
+    Ramping,
+    /// Playing at the target volume.
+    Ringing,
+    Snoozed,
+}
+
+/// Body of `POST /alarm`; anything not given uses the `alarm` settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AlarmRequest {
+    /// Playlist to switch to, the current one keeps playing without.
+    pub playlist_id: Option<String>,
+    /// When to ring, right away without.
+    pub at: Option<DateTime<Utc>>,
+    pub target_volume: Option<f32>,
+    pub ramp_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AlarmStatus {
+    pub phase: AlarmPhase,
+    pub playlist_id: Option<String>,
+    pub target_volume: f32,
+    pub ramp_secs: u64,
+    /// When an armed or snoozed alarm rings.
+    pub rings_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    status: AlarmStatus,
+    /// Cancels the pending wait or ramp.
+    cancel: Option<CancellationToken>,
+}
+
+/// At most one alarm at a time, ringing by playing a playlist with the volume ramped up from 0.
+#[derive(Clone)]
+pub struct Alarm {
+    inner: Arc<Mutex<Inner>>,
+    settings: AlarmSettings,
+    playlists: PathBuf,
+    kv: Arc<Kv>,
+    player: PlayerHandle,
+    notifier: Notifier,
+}
+
+impl Alarm {
+    pub fn new(
+        settings: AlarmSettings,
+        playlists: PathBuf,
+        kv: Arc<Kv>,
+        player: PlayerHandle,
+        notifier: Notifier,
+    ) -> Self {
+        Self {
+            inner: Default::default(),
+            settings,
+            playlists,
+            kv,
+            player,
+            notifier,
+        }
+    }
+
+    pub fn status(&self) -> AlarmStatus {
+        self.inner.lock().unwrap().status.clone()
+    }
+
+    /// Replace the alarm with `req`, ringing at `req.at` or now.
+    pub fn set(&self, req: AlarmRequest) -> anyhow::Result<AlarmStatus> {
+        if let Some(id) = &req.playlist_id
+            && find_playlist(&self.playlists, id).is_none()
+        {
+            anyhow::bail!("Playlist {id} not found");
+        }
+
+        let at = req.at.filter(|at| *at > Utc::now());
+        let status = AlarmStatus {
+            phase: if at.is_some() {
+                AlarmPhase::Armed
+            } else {
+                AlarmPhase::Ramping
+            },
+            playlist_id: req.playlist_id,
+            target_volume: req.target_volume.unwrap_or(self.settings.target_volume).clamp(0.0, 1.0),
+            ramp_secs: req.ramp_secs.unwrap_or(self.settings.ramp_secs),
+            rings_at: at,
+        };
+        let cancel = self.replace(status.clone());
+        self.spawn(at, true, cancel);
+        Ok(status)
+    }
+
+    /// Pause a ringing alarm and ring again after `secs`, or `alarm.snooze_secs`.
+    pub fn snooze(&self, secs: Option<u64>) -> anyhow::Result<AlarmStatus> {
+        let mut status = self.status();
+        if !matches!(status.phase, AlarmPhase::Ramping | AlarmPhase::Ringing) {
+            anyhow::bail!("Alarm is not ringing");
+        }
+
+        let until = Utc::now() + chrono::Duration::seconds(secs.unwrap_or(self.settings.snooze_secs) as i64);
+        status.phase = AlarmPhase::Snoozed;
+        status.rings_at = Some(until);
+        let cancel = self.replace(status.clone());
+        self.player.pause();
+        self.spawn(Some(until), false, cancel);
+        self.notifier.notify(Notification::AlarmSnoozed { until });
+        Ok(status)
+    }
+
+    /// Cancel the alarm. A ringing or snoozed one is paused, with the volume left at the target.
+    pub fn stop(&self) {
+        let status = {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(cancel) = inner.cancel.take() {
+                cancel.cancel();
+            }
+            std::mem::take(&mut inner.status)
+        };
+        if status.phase == AlarmPhase::Idle {
+            return;
+        }
+
+        if matches!(
+            status.phase,
+            AlarmPhase::Ramping | AlarmPhase::Ringing | AlarmPhase::Snoozed
+        ) {
+            self.player.pause();
+            self.player.set_volume(status.target_volume);
+        }
+        self.notifier.notify(Notification::AlarmStopped);
+    }
+
+    /// Set `status`, cancelling the pending wait or ramp, and return the token of the next one.
+    fn replace(&self, status: AlarmStatus) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(previous) = inner.cancel.replace(cancel.clone()) {
+            previous.cancel();
+        }
+        inner.status = status;
+        cancel
+    }
+
+    fn spawn(&self, at: Option<DateTime<Utc>>, switch: bool, cancel: CancellationToken) {
+        let alarm = self.clone();
+        tokio::spawn(async move {
+            if let Some(at) = at {
+                let wait = (at - Utc::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+            alarm.ring(switch, &cancel).await;
+        });
+    }
+
+    async fn ring(&self, switch: bool, cancel: &CancellationToken) {
+        let status = {
+            let mut inner = self.inner.lock().unwrap();
+            // Replaced while waking up
+            if cancel.is_cancelled() {
+                return;
+            }
+            inner.status.phase = AlarmPhase::Ramping;
+            inner.status.rings_at = None;
+            inner.status.clone()
+        };
+
+        self.player.set_volume(0.0);
+        if switch
+            && let Some(id) = &status.playlist_id
+            && let Some((folder, meta)) = find_playlist(&self.playlists, id)
+        {
+            if let Err(error) = self.kv.set_current_playlist_id(&meta.id) {
+                tracing::warn!("kv set failed: {error:#}");
+            }
+            self.player
+                .set_playlist_dir(self.playlists.join(folder), SetPlaylistMode::Skip);
+        }
+        self.player.play();
+        tracing::info!(
+            "Alarm ringing, ramping to {} over {}s",
+            status.target_volume,
+            status.ramp_secs
+        );
+        self.notifier.notify(Notification::AlarmRinging {
+            playlist_id: status.playlist_id.clone(),
+        });
+
+        let steps = (status.ramp_secs / RAMP_STEP_SECS).max(1);
+        for step in 1..=steps {
+            tokio::select! {
+                _ = cancel.cancelled() => return,
+                _ = tokio::time::sleep(Duration::from_secs(RAMP_STEP_SECS)) => {}
+            }
+            self.player
+                .set_volume(status.target_volume * step as f32 / steps as f32);
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        if !cancel.is_cancelled() {
+            inner.status.phase = AlarmPhase::Ringing;
+        }
+    }
+}
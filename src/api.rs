@@ -1,38 +1,90 @@
+use std::collections::{BTreeMap, HashSet};
 use std::net::SocketAddr;
 use std::ops::ControlFlow;
-use std::sync::Arc;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::Context;
-use axum::extract::FromRequest;
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart, Request};
+use axum::middleware::{self, Next};
 use axum::{
     Json, Router,
     extract::connect_info::ConnectInfo,
     extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    extract::{Path as AxPath, State as AxState},
-    http::{StatusCode, Uri, header},
+    extract::{Path as AxPath, Query, State as AxState},
+    http::{HeaderMap, StatusCode, Uri, header},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
     response::{Html, IntoResponse, Response},
-    routing::{any, get, post},
+    routing::{any, delete, get, post, put},
 };
 use axum_extra::TypedHeader;
+use chrono::{DateTime, Local, Utc};
 use futures_util::{sink::SinkExt, stream::StreamExt};
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
+use serde_json::{Value, json};
+use tower_http::sensitive_headers::SetSensitiveRequestHeadersLayer;
+use tower_http::trace::TraceLayer;
 
+use crate::alarm::{Alarm, AlarmRequest};
+use crate::archive;
+use crate::backup;
 use crate::downloader::DownloaderKind;
-use crate::job::{Job, JobManager};
-use crate::notifier::Notifier;
-use crate::player::{PlayerHandle, SetPlaylistMode};
-use crate::playlist::{PlaylistMeta, get_playlists};
-use crate::publisher::Publisher;
-use crate::settings::Paths;
-use crate::state::State as Kv;
+use crate::equalizer::{EqualizerChange, EqualizerState};
+use crate::insert;
+use crate::job::{Job, JobFileError, JobManager};
+use crate::manifest::ManifestStatus;
+use crate::notifier::{Notification, Notifier};
+use crate::player::{PlayerHandle, QueuedTrack, SeekTarget, SetPlaylistMode};
+use crate::playlist::dedupe::{self, DedupeBy, DuplicateGroup};
+use crate::playlist::export::{self, ExportFormat, ExportPaths};
+use crate::playlist::library::{LibraryCache, LibraryFilter, LibraryTrack};
+use crate::playlist::lyrics;
+use crate::playlist::merge::MergeOrder;
+use crate::playlist::names::normalize_file_name;
+use crate::playlist::peaks::{self, Peaks};
+use crate::playlist::sort::{self, TrackSort};
+use crate::playlist::usage::{self, UsageCache};
+use crate::playlist::versions::{self, VersionInfo};
+use crate::playlist::{
+    MAX_RATING, PlaylistMeta, PlaylistSettings, TrackMeta, find_playlist, get_playlists, is_audio_file, normalize_tag,
+};
+use crate::publisher::{PublishState, Publisher};
+use crate::rate_limit::{Bucket, RateLimiter};
+use crate::schema;
+use crate::settings::{BackupSettings, JournalSettings, LyricsSettings, Paths};
+use crate::state::{State as Kv, StateSnapshot, Station};
+use crate::stats::StatsWindow;
+use crate::streamer::{StreamFormat, Streamer};
 use crate::utils::hhmmss::Hhmmss;
+use crate::zone::Zones;
 
 static INDEX_HTML: &str = "index.html";
 
+const DEFAULT_PREVIEW_COUNT: usize = 5;
+const MAX_PREVIEW_COUNT: usize = 100;
+
+const DEFAULT_STATS_LIMIT: usize = 20;
+const DEFAULT_EVENT_LOG_LIMIT: usize = 1000;
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+const MAX_HISTORY_LIMIT: usize = 1000;
+const DEFAULT_JOB_RUNS_LIMIT: usize = 20;
+
+/// Shortest scheduled refresh interval, every refresh goes through the whole source playlist.
+const MIN_REFRESH_INTERVAL_SECS: u64 = 3600;
+
+const MAX_KV_NAME_LEN: usize = 128;
+const MAX_KV_VALUE_BYTES: usize = 64 * 1024;
+const MAX_KV_KEYS: usize = 1000;
+
+const MAX_INSERT_BYTES: usize = 50 * 1024 * 1024;
+
+/// Largest request of uploaded tracks, all files together.
+const MAX_UPLOAD_BYTES: usize = 2 * 1024 * 1024 * 1024;
+
+const MAX_QUEUE_LEN: usize = 1000;
+
 #[derive(Embed)]
 #[folder = "static"]
 struct StaticAssets;
@@ -45,6 +97,24 @@ pub struct AppCtx {
     pub publisher: Publisher,
     pub player: PlayerHandle,
     pub job_manager: JobManager,
+    pub usage: UsageCache,
+    pub library: LibraryCache,
+    pub backup: BackupSettings,
+    /// Key required on requests besides static assets, unset leaves the API open.
+    pub api_key: Option<String>,
+    /// Settings files in use, included in full backups.
+    pub settings_files: Vec<PathBuf>,
+    pub journal: JournalSettings,
+    /// Publishes are refused below this much free disk space (bytes).
+    pub min_free_bytes: u64,
+    pub manifest_status: Arc<Mutex<ManifestStatus>>,
+    pub alarm: Alarm,
+    pub lyrics: LyricsSettings,
+    pub zones: Zones,
+    /// Serves `/stream`, unset when streaming is disabled.
+    pub streamer: Option<Streamer>,
+    /// Publishes and controls a minute per client, and the largest request body.
+    pub rate_limiter: RateLimiter,
 }
 
 enum AppError {
@@ -97,6 +167,51 @@ impl From<anyhow::Error> for AppError {
 struct ListPlaylistItem {
     folder: String,
     meta: PlaylistMeta,
+    /// Bytes used by the track files.
+    size: u64,
+    /// Total of the known track durations.
+    duration: Duration,
+}
+
+#[derive(Serialize)]
+struct StoragePlaylistItem {
+    id: String,
+    name: String,
+    folder: String,
+    tracks: usize,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct StorageResp {
+    success: bool,
+    /// Largest first.
+    playlists: Vec<StoragePlaylistItem>,
+    playlists_size: u64,
+    tmp_size: u64,
+    db_size: u64,
+    available_space: Option<u64>,
+    total_space: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct PlaylistTrackItem {
+    file: String,
+    title: String,
+    duration: Option<Duration>,
+    duration_display: String,
+    rating: Option<u8>,
+}
+
+#[derive(Serialize)]
+struct PlaylistDetailResp {
+    success: bool,
+    folder: String,
+    meta: PlaylistMeta,
+    tracks: Vec<PlaylistTrackItem>,
+    total_duration: Duration,
+    total_duration_display: String,
+    size: u64,
 }
 
 #[derive(Deserialize)]
@@ -107,9 +222,194 @@ pub struct PublishParams {
     downloader: Option<DownloaderKind>,
 }
 
+#[derive(Deserialize)]
+pub struct ImportParams {
+    /// Defaults to the `#PLAYLIST` name in the file.
+    #[serde(default)]
+    name: Option<String>,
+    /// Contents of the M3U/M3U8 or PLS file.
+    #[serde(default)]
+    content: Option<String>,
+    /// URL of the playlist file, instead of `content`.
+    #[serde(default)]
+    url: Option<String>,
+    /// Directory that relative local entries are resolved against.
+    #[serde(default)]
+    base_dir: Option<std::path::PathBuf>,
+    #[serde(default)]
+    downloader: Option<DownloaderKind>,
+}
+
+#[derive(Deserialize)]
+pub struct LocalPublishParams {
+    /// Defaults to the name of the directory.
+    #[serde(default)]
+    name: Option<String>,
+    /// Server directory within `publish.local_roots`.
+    path: std::path::PathBuf,
+    /// Symlink the files instead of copying them.
+    #[serde(default)]
+    link: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SetTracksParams {
+    /// Files of the tracks to keep, in their new order. Tracks left out are removed.
+    files: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RatingParams {
+    /// Track file, defaults to the current track when rating via `/control`.
+    #[serde(default)]
+    file: Option<String>,
+    /// 1 to 5, or null to clear.
+    rating: Option<u8>,
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    #[serde(default)]
+    window: StatsWindow,
+    /// Number of top tracks and playlists.
+    #[serde(default = "default_stats_limit")]
+    limit: usize,
+}
+
+fn default_stats_limit() -> usize {
+    DEFAULT_STATS_LIMIT
+}
+
+#[derive(Deserialize)]
+pub struct EventLogQuery {
+    /// Only events at or after this time.
+    since: Option<DateTime<Utc>>,
+    #[serde(default = "default_event_log_limit")]
+    limit: usize,
+}
+
+fn default_event_log_limit() -> usize {
+    DEFAULT_EVENT_LOG_LIMIT
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default = "default_history_limit")]
+    limit: usize,
+    /// Entries skipped from the newest.
+    #[serde(default)]
+    offset: usize,
+}
+
+fn default_history_limit() -> usize {
+    DEFAULT_HISTORY_LIMIT
+}
+
+#[derive(Deserialize)]
+pub struct JobRunsQuery {
+    #[serde(default = "default_job_runs_limit")]
+    limit: usize,
+}
+
+fn default_job_runs_limit() -> usize {
+    DEFAULT_JOB_RUNS_LIMIT
+}
+
+#[derive(Deserialize)]
+pub struct PublishesQuery {
+    /// Only publishes in this state, eg. `failed`.
+    #[serde(default)]
+    state: Option<PublishState>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportStateQuery {
+    /// Write a backup into the data dir instead of returning it.
+    #[serde(default)]
+    to_file: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ImportStateQuery {
+    /// Restore this backup file (from `/admin/state/backups`) instead of the request body.
+    #[serde(default)]
+    backup: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BackupQuery {
+    /// Include the track files of playlists.
+    #[serde(default)]
+    audio: bool,
+}
+
+#[derive(Deserialize)]
+pub struct WsQuery {
+    /// Replay the events after this sequence number, eg. the last one seen before a reconnect.
+    #[serde(default)]
+    since_seq: Option<u64>,
+}
+
+/// Playback command sent over the websocket, eg. `{"command": "seek", "secs": 30, "id": 1}`.
+#[derive(Deserialize)]
+struct WsRequest {
+    /// Echoed in the `COMMAND_RESULT` reply, to match it to the command.
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(flatten)]
+    command: WsCommand,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum WsCommand {
+    Play,
+    Pause,
+    Prev,
+    Next,
+    Seek {
+        #[serde(flatten)]
+        params: SeekParams,
+    },
+    SetVolume {
+        value: f32,
+    },
+    SetPlaylist {
+        id: String,
+        #[serde(default)]
+        mode: Option<SetPlaylistMode>,
+    },
+    SetTrack {
+        idx: usize,
+    },
+}
+
+/// Exactly one of an absolute position, a relative one or a percent of the track.
 #[derive(Deserialize)]
 pub struct SeekParams {
-    secs: u64,
+    secs: Option<u64>,
+    relative_secs: Option<i64>,
+    percent: Option<f32>,
+}
+
+impl SeekParams {
+    fn target(&self) -> Result<SeekTarget, &'static str> {
+        match (self.secs, self.relative_secs, self.percent) {
+            (Some(secs), None, None) => Ok(SeekTarget::Secs(secs)),
+            (None, Some(secs), None) => Ok(SeekTarget::Relative(secs)),
+            (None, None, Some(percent)) if (0.0..=100.0).contains(&percent) => Ok(SeekTarget::Percent(percent)),
+            (None, None, Some(_)) => Err("Percent must be between 0 and 100"),
+            _ => Err("Expected exactly one of secs, relative_secs or percent"),
+        }
+    }
+}
+
+fn invalid_seek(message: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"success": false, "message": message})),
+    )
+        .into_response()
 }
 
 #[derive(Deserialize)]
@@ -117,30 +417,256 @@ pub struct SetVolumeParams {
     value: f32,
 }
 
+/// Either a preset or gains, anything unset stays.
+#[derive(Deserialize, Default)]
+struct SetEqualizerParams {
+    enable: Option<bool>,
+    preset: Option<String>,
+    /// Gain of each band (dB), -12 to 12.
+    gains_db: Option<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    #[serde(default)]
+    format: StreamFormat,
+}
+
+#[derive(Deserialize)]
+pub struct SetDeviceParams {
+    /// Unset plays on the default device.
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EnqueueParams {
+    playlist_id: String,
+    /// Index of the track in the playlist, in its sort order.
+    track_idx: usize,
+}
+
+#[derive(Deserialize)]
+pub struct StationParams {
+    name: String,
+    /// http(s) stream URL.
+    url: String,
+}
+
 #[derive(Deserialize)]
 pub struct SetPlaylistParams {
     mode: SetPlaylistMode,
 }
 
+#[derive(Deserialize)]
+pub struct UpdatePlaylistParams {
+    #[serde(default)]
+    sort: Option<TrackSort>,
+    /// Seed for `shuffle-seed`; a new one is picked when omitted.
+    #[serde(default)]
+    sort_seed: Option<u64>,
+    /// Replaces all tags.
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    /// Empty string clears.
+    #[serde(default)]
+    description: Option<String>,
+    /// Empty string clears.
+    #[serde(default)]
+    author: Option<String>,
+    /// Merged into `extra`; keys set to null are removed.
+    #[serde(default)]
+    extra: Option<serde_json::Map<String, Value>>,
+    /// Podcast or audiobook, resumes each track where it was left.
+    #[serde(default)]
+    long_form: Option<bool>,
+    /// Protect from the retention policy.
+    #[serde(default)]
+    pinned: Option<bool>,
+    /// Refresh from the sources this often; 0 stops.
+    #[serde(default)]
+    refresh_interval_secs: Option<u64>,
+    /// Replaces the playback settings; `{}` clears them.
+    #[serde(default)]
+    settings: Option<PlaylistSettings>,
+}
+
+#[derive(Deserialize)]
+pub struct TagsParams {
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListPlaylistsQuery {
+    /// Only playlists with this tag.
+    #[serde(default)]
+    tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MergeParams {
+    name: String,
+    /// Playlist ids, in the order they are merged.
+    ids: Vec<String>,
+    #[serde(default)]
+    order: MergeOrder,
+    /// Hardlink files instead of copying them when possible.
+    #[serde(default)]
+    link: bool,
+}
+
+#[derive(Deserialize)]
+pub struct LibraryPlaylistParams {
+    name: String,
+    /// Library track keys, in playlist order.
+    keys: Vec<String>,
+    /// Hardlink files instead of copying them when possible.
+    #[serde(default)]
+    link: bool,
+    /// Play the playlist once published.
+    #[serde(default)]
+    play: bool,
+}
+
+#[derive(Serialize)]
+struct LibraryResp {
+    success: bool,
+    total: usize,
+    tracks: Vec<LibraryTrack>,
+}
+
+#[derive(Deserialize)]
+pub struct DedupeParams {
+    #[serde(default)]
+    by: DedupeBy,
+}
+
+#[derive(Serialize)]
+struct DedupeResp {
+    success: bool,
+    groups: Vec<DuplicateGroup>,
+    removed: usize,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    #[serde(default)]
+    paths: ExportPaths,
+}
+
+#[derive(Deserialize)]
+pub struct PeaksQuery {
+    count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct PreviewQuery {
+    count: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewRun {
+    utc: DateTime<Utc>,
+    local: DateTime<Local>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewResp {
+    success: bool,
+    id: String,
+    runs: Vec<PreviewRun>,
+}
+
 #[derive(Serialize)]
 pub struct StatusResp {
     playlist_id: Option<String>,
     playlist_name: Option<String>,
     current_index: usize,
     current_track: Option<String>,
+    /// Title of the current track, `artist - title` when both are tagged, else the file name.
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
     current_pos: Option<Duration>,
     total_duration: Option<Duration>,
     is_paused: Option<bool>,
     volume: Option<f32>,
     position: Option<String>,
+    /// Set while a station plays instead of the playlist.
+    station: Option<Station>,
+    /// Insert clip playing between tracks.
+    insert: Option<String>,
+    /// Track of the queue playing instead of the playlist.
+    queued: Option<QueuedTrack>,
+    /// Tracks lined up to play next.
+    queue: Vec<QueuedTrack>,
+    equalizer: EqualizerState,
 }
 
 pub fn router(ctx: AppCtx) -> Router {
+    let max_body_bytes = ctx.rate_limiter.max_body_bytes();
     Router::new()
         .route("/status", get(status))
+        .route("/storage", get(storage))
+        .route("/stats", get(stats))
+        .route("/history", get(history))
+        .route("/events/log", get(event_log))
+        .route("/manifest/status", get(manifest_status))
         .route("/playlists", get(list_playlists))
-        .route("/jobs", get(list_jobs))
-        .route("/publish", post(publish))
+        .route("/playlists/import", post(import_playlist))
+        .route("/playlists/merge", post(merge_playlists))
+        .route("/playlists/local", post(publish_local))
+        .route("/playlists/{id}/refresh", post(refresh_playlist))
+        .route("/playlists/duplicates", get(list_duplicates))
+        .route("/playlists/dedupe", post(dedupe_playlists))
+        .route("/playlists/{id}/duplicates", get(list_playlist_duplicates))
+        .route("/playlists/{id}/dedupe", post(dedupe_playlist))
+        .route("/playlists/tags", get(list_tags))
+        .route("/playlists/{id}", get(get_playlist).patch(update_playlist))
+        .route("/playlists/{id}/tags", post(add_playlist_tags))
+        .route("/playlists/{id}/ratings", post(rate_track))
+        .route("/playlists/{id}/tracks", put(set_tracks))
+        .route(
+            "/playlists/{id}/upload",
+            post(upload_tracks).layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/playlists/{id}/versions", get(list_playlist_versions))
+        .route("/playlists/{id}/versions/{version}/rollback", post(rollback_playlist))
+        .route("/playlists/{id}/tags/{tag}", delete(remove_playlist_tag))
+        .route("/playlists/{id}/export", get(export_playlist))
+        .route("/playlists/{id}/progress", get(playlist_progress))
+        .route("/playlists/{id}/tracks/{idx}/peaks", get(track_peaks))
+        .route("/playlists/{id}/tracks/{idx}/lyrics", get(track_lyrics))
+        .route("/playlists/{id}/art", get(playlist_art))
+        .route("/playlists/{id}/tracks/{idx}/art", get(track_art))
+        .route("/library", get(list_library))
+        .route("/library/playlist", post(library_playlist))
+        .route("/jobs", get(list_jobs).post(upsert_job))
+        .route("/jobs/{id}", delete(delete_job))
+        .route("/jobs/errors", get(list_job_errors))
+        .route("/jobs/export", get(list_jobs))
+        .route("/jobs/import", post(import_jobs))
+        .route("/jobs/preview", post(preview_job))
+        .route("/jobs/{id}/next", get(next_job_runs))
+        .route("/jobs/{id}/runs", get(job_runs))
+        .route("/admin/state/export", post(export_state))
+        .route("/admin/state/import", post(import_state))
+        .route("/admin/state/backups", get(list_state_backups))
+        .route("/admin/backup", post(backup_archive))
+        .route(
+            "/admin/restore",
+            post(restore_archive).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/publish", get(list_publishes).post(publish))
+        .route(
+            "/publish/upload",
+            post(publish_upload).layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/publish/tasks", get(list_publishes))
+        .route("/publish/{id}", get(get_publish))
+        .route("/publish/{id}/cancel", post(cancel_publish))
         .route("/clean", post(clean))
         .route("/control/play", post(play))
         .route("/control/pause", post(pause))
@@ -148,16 +674,184 @@ pub fn router(ctx: AppCtx) -> Router {
         .route("/control/next", post(next))
         .route("/control/seek", post(seek))
         .route("/control/volume", post(set_volume))
+        .route("/control/eq", post(set_equalizer))
+        .route("/control/device", post(set_device))
+        .route("/devices", get(list_devices))
+        .route("/stream", get(stream_audio))
         .route("/control/playlist/{id}", post(set_playlist))
         .route("/control/track/{idx}", post(set_track))
+        .route("/control/rating", post(rate_current_track))
+        .route("/control/station/{id}", post(set_station))
+        .route("/queue", get(list_queue).post(enqueue).delete(clear_queue))
+        .route("/stations", get(list_stations).post(add_station))
+        .route("/inserts", get(list_inserts))
+        .route(
+            "/inserts/{file}",
+            put(upload_insert)
+                .delete(delete_insert)
+                .layer(DefaultBodyLimit::max(MAX_INSERT_BYTES)),
+        )
+        .route("/stations/{id}", put(update_station).delete(delete_station))
+        .route("/zones", get(list_zones))
+        .route("/zones/{zone}/status", get(zone_status))
+        .route("/zones/{zone}/control/play", post(zone_play))
+        .route("/zones/{zone}/control/pause", post(zone_pause))
+        .route("/zones/{zone}/control/prev", post(zone_prev))
+        .route("/zones/{zone}/control/next", post(zone_next))
+        .route("/zones/{zone}/control/seek", post(zone_seek))
+        .route("/zones/{zone}/control/volume", post(zone_set_volume))
+        .route("/zones/{zone}/control/eq", post(zone_set_equalizer))
+        .route("/zones/{zone}/control/device", post(zone_set_device))
+        .route("/zones/{zone}/control/playlist/{id}", post(zone_set_playlist))
+        .route("/zones/{zone}/control/track/{idx}", post(zone_set_track))
+        .route("/zones/{zone}/control/station/{id}", post(zone_set_station))
+        .route("/zones/{zone}/ws", any(zone_ws_handler))
+        .route("/zones/{zone}/events", get(zone_sse_handler))
+        .route("/alarm", get(get_alarm).post(set_alarm))
+        .route("/alarm/snooze", post(snooze_alarm))
+        .route("/alarm/stop", post(stop_alarm))
+        .route("/kv/{namespace}", get(list_kv))
+        .route("/kv/{namespace}/{key}", get(get_kv).put(put_kv).delete(delete_kv))
+        .route("/schema/{name}", get(get_schema))
         .route("/ws", any(ws_handler))
+        .route("/events", get(sse_handler))
+        .route_layer(middleware::from_fn_with_state(ctx.clone(), rate_limit))
+        // Static assets are the fallback, left open so the web UI can load and ask for the key
+        .route_layer(middleware::from_fn_with_state(ctx.clone(), require_api_key))
         .fallback(static_handler)
+        // Routes with a limit of their own keep it
+        .layer(DefaultBodyLimit::max(max_body_bytes))
         .with_state(ctx)
-        .layer(TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::default().include_headers(true)))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        // Marked before the span records them, so the API key isn't logged
+        .layer(SetSensitiveRequestHeadersLayer::new([header::AUTHORIZATION]))
+}
+
+/// Span of a request with its headers, without the API key of its URI.
+fn request_span(req: &Request) -> tracing::Span {
+    let uri = match req.uri().query() {
+        Some(query) if query.split('&').any(|p| p.starts_with("api_key=")) => {
+            let query: Vec<&str> = query
+                .split('&')
+                .map(|p| {
+                    if p.starts_with("api_key=") {
+                        "api_key=redacted"
+                    } else {
+                        p
+                    }
+                })
+                .collect();
+            format!("{}?{}", req.uri().path(), query.join("&"))
+        }
+        _ => req.uri().to_string(),
+    };
+    tracing::debug_span!(
+        "request",
+        method = %req.method(),
+        uri,
+        version = ?req.version(),
+        headers = ?req.headers(),
+    )
+}
+
+#[derive(Deserialize)]
+struct ApiKeyQuery {
+    api_key: Option<String>,
+}
+
+/// Compare in constant time, so the key can't be guessed from response times.
+fn keys_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Reject requests without the API key as `Authorization: Bearer` header or `api_key` query param.
+/// Browsers can't set headers on websockets, so those pass it as query param.
+async fn require_api_key(AxState(ctx): AxState<AppCtx>, req: Request, next: Next) -> Response {
+    let Some(api_key) = &ctx.api_key else {
+        return next.run(req).await;
+    };
+
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string());
+    let query = Query::<ApiKeyQuery>::try_from_uri(req.uri())
+        .ok()
+        .and_then(|Query(q)| q.api_key);
+    if bearer.or(query).is_some_and(|key| keys_match(&key, api_key)) {
+        return next.run(req).await;
+    }
+
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({"success": false, "message": "Unauthorized"})),
+    )
+        .into_response()
+}
+
+/// Turn away clients over their publishes or controls a minute with `429 Too Many Requests`.
+async fn rate_limit(
+    AxState(ctx): AxState<AppCtx>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(bucket) = Bucket::of(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let Err(retry_after) = ctx.rate_limiter.check(addr.ip(), bucket) else {
+        return next.run(req).await;
+    };
+
+    let secs = retry_after.as_secs().max(1);
+    tracing::debug!("Rate limited {} on {}", addr.ip(), req.uri().path());
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, secs.to_string())],
+        Json(json!({"success": false, "message": format!("Too many requests, try again in {secs}s")})),
+    )
+        .into_response()
+}
+
+async fn sse_handler(
+    AxState(ctx): AxState<AppCtx>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Response {
+    tracing::info!("{addr} connected to the event stream");
+    event_stream(&ctx.notifier, query.since_seq.or_else(|| last_event_id(&headers)))
+}
+
+/// The `seq` of the last event a reconnecting event stream client saw.
+fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers.get("last-event-id")?.to_str().ok()?.trim().parse().ok()
+}
+
+/// Server-sent events of `notifier`, named by their `type` and with their `seq` as id, so browsers
+/// resume where they left off. The data is the same JSON as on `/ws`.
+fn event_stream(notifier: &Notifier, since_seq: Option<u64>) -> Response {
+    let (missed, rx) = notifier.subscribe(since_seq);
+    // A client that lags behind is dropped, and catches up on the kept events when it reconnects
+    let live = futures_util::stream::unfold(
+        rx,
+        |mut rx| async move { rx.recv().await.ok().map(|event| (event, rx)) },
+    );
+    let events = futures_util::stream::iter(missed).chain(live).map(|event| {
+        SseEvent::default()
+            .event(event.notification.kind())
+            .id(event.seq.to_string())
+            .json_data(&event)
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
 }
 
 async fn ws_handler(
     AxState(ctx): AxState<AppCtx>,
+    Query(query): Query<WsQuery>,
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
@@ -169,7 +863,8 @@ async fn ws_handler(
     };
     tracing::info!("`{user_agent}` at {addr} connected to websocket");
 
-    ws.on_upgrade(move |socket| handle_socket(socket, addr, ctx.notifier.clone()))
+    let notifier = ctx.notifier.clone();
+    ws.on_upgrade(move |socket| handle_socket(socket, addr, ctx, None, notifier, query.since_seq))
 }
 
 async fn static_handler(uri: Uri) -> impl IntoResponse {
@@ -207,50 +902,1390 @@ async fn not_found() -> Response {
 }
 
 async fn status(AxState(ctx): AxState<AppCtx>) -> Result<Json<StatusResp>, AppError> {
-    let s = ctx.player.status()?;
+    Ok(Json(status_resp(&ctx.player)?))
+}
+
+fn status_resp(player: &PlayerHandle) -> anyhow::Result<StatusResp> {
+    let s = player.status()?;
 
     let current_pos_display = s.current_pos.map(|x| x.hhmmss()).unwrap_or("-".to_string());
     let total_duration_display = s.total_duration.map(|x| x.hhmmss()).unwrap_or("-".to_string());
 
-    Ok(Json(StatusResp {
+    Ok(StatusResp {
         playlist_id: s.playlist_id,
         playlist_name: s.playlist_name,
         current_index: s.current_index,
         current_track: s.current_track,
+        title: s.current_meta.as_ref().map(|t| t.display_name()),
+        artist: s.current_meta.as_ref().and_then(|t| t.artist.clone()),
+        album: s.current_meta.and_then(|t| t.album),
         current_pos: s.current_pos,
         total_duration: s.total_duration,
         is_paused: s.is_paused,
         volume: s.volume,
         position: format!("{current_pos_display} / {total_duration_display}").into(),
-    }))
+        station: s.station,
+        insert: s.insert,
+        queued: s.queued,
+        queue: s.queue,
+        equalizer: player.equalizer(),
+    })
+}
+
+async fn stats(AxState(ctx): AxState<AppCtx>, Query(query): Query<StatsQuery>) -> Result<Json<Value>, AppError> {
+    let since = query.window.since(Utc::now());
+    let entries = ctx.kv.list_history_since(&since)?;
+    let stats = crate::stats::aggregate(since, &entries, query.limit);
+    Ok(Json(json!({"success": true, "window": query.window, "stats": stats})))
+}
+
+async fn history(AxState(ctx): AxState<AppCtx>, Query(query): Query<HistoryQuery>) -> Result<Json<Value>, AppError> {
+    let limit = query.limit.min(MAX_HISTORY_LIMIT);
+    let (entries, total) = ctx.kv.list_history(limit, query.offset)?;
+    Ok(Json(
+        json!({"success": true, "history": entries, "total": total, "offset": query.offset}),
+    ))
 }
 
-async fn list_playlists(AxState(ctx): AxState<AppCtx>) -> Json<Vec<ListPlaylistItem>> {
+async fn event_log(AxState(ctx): AxState<AppCtx>, Query(query): Query<EventLogQuery>) -> Result<Json<Value>, AppError> {
+    if !ctx.journal.enable {
+        return Ok(Json(json!({"success": false, "message": "Event journal is disabled"})));
+    }
+
+    let path = ctx.paths.journal.clone();
+    let events = tokio::task::spawn_blocking(move || crate::journal::read(&path, query.since, query.limit))
+        .await
+        .context("Event log task failed")?;
+    Ok(Json(json!({"success": true, "events": events})))
+}
+
+async fn storage(AxState(ctx): AxState<AppCtx>) -> Result<Json<StorageResp>, AppError> {
+    let playlists = get_playlists(&ctx.paths.playlists)?;
+    let res = tokio::task::spawn_blocking(move || {
+        let mut items: Vec<StoragePlaylistItem> = playlists
+            .into_iter()
+            .map(|(folder, meta)| StoragePlaylistItem {
+                size: ctx.usage.size(&ctx.paths.playlists, &folder, &meta),
+                id: meta.id,
+                name: meta.name,
+                folder,
+                tracks: meta.tracks.len(),
+            })
+            .collect();
+        items.sort_by_key(|p| std::cmp::Reverse(p.size));
+
+        StorageResp {
+            success: true,
+            playlists_size: items.iter().map(|p| p.size).sum(),
+            playlists: items,
+            tmp_size: usage::dir_size(&ctx.paths.tmp),
+            db_size: usage::dir_size(&ctx.paths.db),
+            available_space: fs2::available_space(&ctx.paths.root).ok(),
+            total_space: fs2::total_space(&ctx.paths.root).ok(),
+        }
+    })
+    .await
+    .context("Storage task failed")?;
+
+    Ok(Json(res))
+}
+
+async fn list_playlists(
+    AxState(ctx): AxState<AppCtx>,
+    Query(query): Query<ListPlaylistsQuery>,
+) -> Json<Vec<ListPlaylistItem>> {
+    let tag = query.tag.map(|t| normalize_tag(&t));
     let items = get_playlists(&ctx.paths.playlists).unwrap_or_default();
     Json(
         items
             .into_iter()
-            .map(|(f, m)| ListPlaylistItem { folder: f, meta: m })
+            .filter(|(_, m)| tag.as_ref().is_none_or(|t| m.tags.contains(t)))
+            .map(|(f, mut m)| {
+                m.apply_sort();
+                m.apply_ratings(&ctx.kv.list_ratings(&m.id).unwrap_or_default());
+                let size = ctx.usage.size(&ctx.paths.playlists, &f, &m);
+                ListPlaylistItem {
+                    folder: f,
+                    duration: m.total_duration(),
+                    meta: m,
+                    size,
+                }
+            })
             .collect(),
     )
 }
 
-async fn list_jobs(AxState(ctx): AxState<AppCtx>) -> Json<Vec<Job>> {
-    let jobs = ctx.job_manager.current_jobs.lock().unwrap().clone();
-    Json(jobs)
+async fn get_playlist(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Response {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    // Backfill durations once for playlists published before they were stored
+    let dir = ctx.paths.playlists.join(&folder);
+    if meta.needs_probe() {
+        tracing::info!("Backfilling track metadata for playlist '{}'", meta.name);
+        meta.probe_tracks_async(&dir).await;
+        if let Err(error) = meta.save_atomic(&dir.join("playlist.json")) {
+            tracing::warn!("Write meta failed: {error:#}");
+        }
+    }
+
+    meta.apply_sort();
+    meta.apply_ratings(&ctx.kv.list_ratings(&meta.id).unwrap_or_default());
+    let tracks = meta
+        .tracks
+        .iter()
+        .map(|t| PlaylistTrackItem {
+            file: t.file.clone(),
+            title: t.display_name(),
+            duration: t.duration,
+            duration_display: t.duration.map(|d| d.hhmmss()).unwrap_or("-".to_string()),
+            rating: t.rating,
+        })
+        .collect();
+    let total_duration = meta.total_duration();
+    let size = ctx.usage.size(&ctx.paths.playlists, &folder, &meta);
+
+    Json(PlaylistDetailResp {
+        success: true,
+        folder,
+        meta,
+        tracks,
+        total_duration,
+        total_duration_display: total_duration.hhmmss(),
+        size,
+    })
+    .into_response()
 }
 
-async fn publish(AxState(ctx): AxState<AppCtx>, Json(params): Json<PublishParams>) -> impl IntoResponse {
-    ctx.publisher
-        .publish_in_background(&params.name, &params.source_urls, params.downloader);
+async fn playlist_progress(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Result<Response, AppError> {
+    let Some((_, mut meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return Ok(playlist_not_found());
+    };
 
-    Json(json!({"success": true}))
+    meta.apply_sort();
+    let mut progress = ctx.kv.list_progress(&meta.id)?;
+    let tracks: Vec<Value> = meta
+        .tracks
+        .iter()
+        .map(|t| {
+            let p = progress.remove(&t.file);
+            let position = p.as_ref().map(|p| p.position).unwrap_or_default();
+            let percent = t
+                .duration
+                .filter(|d| !d.is_zero())
+                .map(|d| (position.as_secs_f64() / d.as_secs_f64() * 100.0).min(100.0));
+            json!({
+                "file": t.file,
+                "title": t.display_name(),
+                "duration": t.duration,
+                "position": position,
+                "position_display": position.hhmmss(),
+                "percent": percent,
+                "finished": p.as_ref().is_some_and(|p| p.finished),
+                "updated_at": p.map(|p| p.updated_at),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({"success": true, "long_form": meta.long_form, "tracks": tracks})).into_response())
 }
 
-async fn clean(AxState(ctx): AxState<AppCtx>) -> Result<impl IntoResponse, AppError> {
-    let dir = ctx.paths.tmp;
+async fn track_peaks(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((id, idx)): AxPath<(String, usize)>,
+    Query(query): Query<PeaksQuery>,
+) -> Result<Response, AppError> {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return Ok(playlist_not_found());
+    };
 
-    let mut entries = tokio::fs::read_dir(&dir)
+    // Same order as the listed tracks
+    meta.apply_sort();
+    let Some(track) = meta.tracks.get(idx).cloned() else {
+        return Ok(Json(json!({"success": false, "message": "Track not found"})).into_response());
+    };
+
+    let count = query.count.unwrap_or(peaks::DEFAULT_PEAKS).clamp(1, peaks::MAX_PEAKS);
+    let dir = ctx.paths.playlists.join(&folder);
+    let res = tokio::task::spawn_blocking(move || peaks::load_or_generate(&dir, &track, count))
+        .await
+        .context("Peaks task failed")?;
+
+    Ok(match res {
+        Ok(Peaks { duration_ms, peaks, .. }) => {
+            Json(json!({"success": true, "duration_ms": duration_ms, "peaks": peaks})).into_response()
+        }
+        Err(error) => Json(json!({"success": false, "message": format!("{error:#}")})).into_response(),
+    })
+}
+
+async fn track_lyrics(AxState(ctx): AxState<AppCtx>, AxPath((id, idx)): AxPath<(String, usize)>) -> Response {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    // Same order as the listed tracks
+    meta.apply_sort();
+    let Some(track) = meta.tracks.get(idx) else {
+        return Json(json!({"success": false, "message": "Track not found"})).into_response();
+    };
+
+    let lyrics = lyrics::get(&ctx.paths.playlists.join(&folder), track, &ctx.lyrics).await;
+    Json(json!({
+        "success": true,
+        "source": lyrics.source,
+        "plain": lyrics.plain,
+        "synced": lyrics.synced,
+        "instrumental": lyrics.instrumental,
+    }))
+    .into_response()
+}
+
+async fn playlist_art(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Response {
+    let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    image_response(&ctx.paths.playlists.join(&folder), meta.cover.as_deref()).await
+}
+
+async fn track_art(AxState(ctx): AxState<AppCtx>, AxPath((id, idx)): AxPath<(String, usize)>) -> Response {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    // Same order as the listed tracks
+    meta.apply_sort();
+    let Some(track) = meta.tracks.get(idx) else {
+        return playlist_not_found();
+    };
+
+    image_response(&ctx.paths.playlists.join(&folder), track.art.as_deref()).await
+}
+
+/// The image at `file` relative to the playlist `dir`, not found when there's none.
+async fn image_response(dir: &Path, file: Option<&str>) -> Response {
+    // Only files within the playlist
+    let Some(file) = file.filter(|f| Path::new(f).components().all(|c| matches!(c, Component::Normal(_)))) else {
+        return playlist_not_found();
+    };
+
+    match tokio::fs::read(dir.join(file)).await {
+        Ok(data) => {
+            let mime = mime_guess::from_path(file).first_or_octet_stream();
+
+            (
+                [
+                    (header::CONTENT_TYPE, mime.to_string()),
+                    (header::CACHE_CONTROL, "no-cache".to_string()),
+                ],
+                data,
+            )
+                .into_response()
+        }
+        Err(_) => playlist_not_found(),
+    }
+}
+
+fn playlist_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"success": false, "message": "Not found"})),
+    )
+        .into_response()
+}
+
+/// Apply `f` to the stored meta of playlist `id`, save it and reload the player if active.
+fn modify_playlist(ctx: &AppCtx, id: &str, f: impl FnOnce(&mut PlaylistMeta)) -> anyhow::Result<Option<PlaylistMeta>> {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, id) else {
+        return Ok(None);
+    };
+
+    f(&mut meta);
+
+    let dir = ctx.paths.playlists.join(&folder);
+    meta.save_atomic(&dir.join("playlist.json"))?;
+    ctx.player.reload_if_current(&dir);
+    Ok(Some(meta))
+}
+
+async fn update_playlist(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Json(params): Json<UpdatePlaylistParams>,
+) -> Result<Response, AppError> {
+    let meta = modify_playlist(&ctx, &id, |meta| {
+        if let Some(sort) = params.sort {
+            meta.sort = sort;
+        }
+
+        // Setting `shuffle-seed` again without a seed reshuffles
+        meta.sort_seed = match (params.sort_seed, params.sort) {
+            (Some(seed), _) => Some(seed),
+            (None, Some(TrackSort::ShuffleSeed)) => Some(sort::new_seed()),
+            (None, _) => meta.sort_seed,
+        };
+
+        if let Some(tags) = params.tags {
+            meta.tags = vec![];
+            meta.add_tags(&tags);
+        }
+
+        if let Some(description) = params.description {
+            meta.description = Some(description).filter(|d| !d.trim().is_empty());
+        }
+        if let Some(author) = params.author {
+            meta.author = Some(author).filter(|a| !a.trim().is_empty());
+        }
+        for (key, value) in params.extra.unwrap_or_default() {
+            if value.is_null() {
+                meta.extra.remove(&key);
+            } else {
+                meta.extra.insert(key, value);
+            }
+        }
+
+        if let Some(long_form) = params.long_form {
+            meta.long_form = long_form;
+        }
+        if let Some(pinned) = params.pinned {
+            meta.pinned = pinned;
+        }
+        if let Some(secs) = params.refresh_interval_secs {
+            meta.refresh_interval_secs = Some(secs.max(MIN_REFRESH_INTERVAL_SECS)).filter(|_| secs > 0);
+        }
+        if let Some(settings) = params.settings {
+            meta.settings = settings.clamped();
+            // Shuffling needs a seed to keep its order
+            if meta.settings.shuffle == Some(true) && meta.sort_seed.is_none() {
+                meta.sort_seed = Some(sort::new_seed());
+            }
+        }
+    })?;
+
+    match meta {
+        Some(meta) => Ok(Json(json!({
+            "success": true,
+            "sort": meta.sort,
+            "sort_seed": meta.sort_seed,
+            "tags": meta.tags,
+            "description": meta.description,
+            "author": meta.author,
+            "extra": meta.extra,
+            "long_form": meta.long_form,
+            "pinned": meta.pinned,
+            "refresh_interval_secs": meta.refresh_interval_secs,
+            "settings": meta.settings,
+        }))
+        .into_response()),
+        None => Ok(playlist_not_found()),
+    }
+}
+
+async fn list_playlist_versions(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Response {
+    let Some((folder, _)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    let versions: Vec<VersionInfo> = versions::list(&ctx.paths.playlists.join(folder));
+    Json(json!({"success": true, "versions": versions})).into_response()
+}
+
+async fn rollback_playlist(AxState(ctx): AxState<AppCtx>, AxPath((id, version)): AxPath<(String, String)>) -> Response {
+    let Some((folder, _)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    let dir = ctx.paths.playlists.join(folder);
+    match versions::rollback(&dir, &version) {
+        Ok((meta, missing)) => {
+            tracing::info!("Rolled back playlist '{}' to version {}", meta.name, version);
+            ctx.player.reload_if_current(&dir);
+            Json(json!({"success": true, "missing": missing})).into_response()
+        }
+        Err(error) => Json(json!({"success": false, "message": format!("{error:#}")})).into_response(),
+    }
+}
+
+/// Reorder the tracks of a playlist and remove the ones left out. Track files stay on disk,
+/// so the previous version can be rolled back to.
+async fn set_tracks(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Json(params): Json<SetTracksParams>,
+) -> Result<Response, AppError> {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return Ok(playlist_not_found());
+    };
+
+    let invalid = |message: String| Ok(Json(json!({"success": false, "message": message})).into_response());
+    if params.files.is_empty() {
+        return invalid("At least one track is required".to_string());
+    }
+    let mut seen = HashSet::new();
+    let mut tracks = Vec::with_capacity(params.files.len());
+    for file in &params.files {
+        if !seen.insert(file.as_str()) {
+            return invalid(format!("Track {file} is listed twice"));
+        }
+        match meta.tracks.iter().find(|t| &t.file == file) {
+            Some(track) => tracks.push(track.clone()),
+            None => return invalid(format!("Track {file} is not in the playlist")),
+        }
+    }
+
+    let dir = ctx.paths.playlists.join(folder);
+    let removed = meta.tracks.len() - tracks.len();
+    meta.tracks = tracks;
+    meta.save_atomic(&dir.join("playlist.json"))?;
+    ctx.player.reload_if_current(&dir);
+
+    tracing::info!("Updated tracks of playlist '{}' ({} removed)", meta.name, removed);
+    ctx.notifier.notify(Notification::PlaylistUpdated((&meta).into()));
+    Ok(Json(json!({"success": true, "tracks": meta.tracks.len(), "removed": removed})).into_response())
+}
+
+/// Tracks received by `receive_tracks`.
+#[derive(Default)]
+struct Upload {
+    /// Saved file names, in upload order.
+    files: Vec<String>,
+    /// Uploaded files that weren't saved, with why.
+    skipped: Vec<Value>,
+    /// Text fields by name.
+    fields: BTreeMap<String, String>,
+}
+
+/// Stream the file fields of `multipart` into `dir` as `{:03}-{name}`, numbered from `first`.
+/// Files without an audio extension and MIME type, or that can't be read as audio, are skipped.
+async fn receive_tracks(multipart: &mut Multipart, dir: &Path, first: usize) -> anyhow::Result<Upload> {
+    use tokio::io::AsyncWriteExt;
+
+    tokio::fs::create_dir_all(dir).await?;
+    let mut upload = Upload::default();
+    while let Some(mut field) = multipart.next_field().await? {
+        let Some(original) = field.file_name().map(str::to_string) else {
+            let name = field.name().unwrap_or_default().to_string();
+            upload.fields.insert(name, field.text().await?);
+            continue;
+        };
+
+        // Browsers may send a relative path, only the name is kept
+        let name = normalize_file_name(Path::new(&original).file_name().unwrap_or_default());
+        let audio_mime = field
+            .content_type()
+            .is_none_or(|mime| mime.starts_with("audio/") || mime == "application/octet-stream");
+        if !is_audio_file(Path::new(&name)) || !audio_mime {
+            upload
+                .skipped
+                .push(json!({"file": original, "reason": "Not an audio file"}));
+            continue;
+        }
+
+        let file = format!("{:03}-{name}", first + upload.files.len());
+        let path = dir.join(&file);
+        let mut out = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        while let Some(chunk) = field.chunk().await? {
+            out.write_all(&chunk).await?;
+        }
+        out.flush().await?;
+
+        let probe = path.clone();
+        let readable = tokio::task::spawn_blocking(move || lofty::read_from_path(&probe).is_ok()).await?;
+        if !readable {
+            tokio::fs::remove_file(&path).await.ok();
+            upload
+                .skipped
+                .push(json!({"file": original, "reason": "Not a readable audio file"}));
+            continue;
+        }
+        upload.files.push(file);
+    }
+    Ok(upload)
+}
+
+async fn upload_tracks(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return Ok(playlist_not_found());
+    };
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return Ok(resp);
+    }
+
+    // Received next to the playlist first, so a failed upload leaves it as it was
+    let staging = ctx.paths.tmp.join(format!("upload-{}", uuid::Uuid::new_v4()));
+    let res = receive_tracks(&mut multipart, &staging, meta.tracks.len() + 1).await;
+    let res = match res {
+        Ok(upload) if upload.files.is_empty() => Ok((upload, vec![])),
+        Ok(upload) => {
+            let (from, dir) = (staging.clone(), ctx.paths.playlists.join(&folder));
+            let files = upload.files.clone();
+            tokio::task::spawn_blocking(move || add_uploaded(&from, &dir, &files))
+                .await
+                .context("Upload task failed")?
+                .map(|tracks| (upload, tracks))
+        }
+        Err(error) => Err(error),
+    };
+    tokio::fs::remove_dir_all(&staging).await.ok();
+
+    let (upload, tracks) = match res {
+        Ok(res) => res,
+        Err(error) => {
+            return Ok(Json(json!({"success": false, "message": format!("Upload failed: {error:#}")})).into_response());
+        }
+    };
+    if tracks.is_empty() {
+        return Ok(Json(json!({
+            "success": false,
+            "message": "No audio files uploaded",
+            "skipped": upload.skipped,
+        }))
+        .into_response());
+    }
+
+    let Some(meta) = modify_playlist(&ctx, &id, |meta| meta.tracks.extend(tracks))? else {
+        return Ok(playlist_not_found());
+    };
+    tracing::info!("Uploaded {} tracks to playlist '{}'", upload.files.len(), meta.name);
+    ctx.notifier.notify(Notification::PlaylistUpdated((&meta).into()));
+    Ok(Json(json!({"success": true, "files": upload.files, "skipped": upload.skipped})).into_response())
+}
+
+/// Move the uploaded `files` from `from` into the playlist `dir`, returning their probed tracks.
+fn add_uploaded(from: &Path, dir: &Path, files: &[String]) -> anyhow::Result<Vec<TrackMeta>> {
+    if let Some(file) = files.iter().find(|f| dir.join(f).exists()) {
+        anyhow::bail!("{file} already exists");
+    }
+    let mut tracks = vec![];
+    for file in files {
+        let target = dir.join(file);
+        if std::fs::rename(from.join(file), &target).is_err() {
+            // Across filesystems
+            std::fs::copy(from.join(file), &target).with_context(|| format!("Failed to save {file}"))?;
+        }
+        let mut track = TrackMeta::new(file);
+        track.probe(dir);
+        tracks.push(track);
+    }
+    Ok(tracks)
+}
+
+fn set_rating(ctx: &AppCtx, playlist_id: &str, file: &str, rating: Option<u8>) -> anyhow::Result<Json<Value>> {
+    if rating.is_some_and(|r| !(1..=MAX_RATING).contains(&r)) {
+        return Ok(Json(
+            json!({"success": false, "message": format!("Rating must be between 1 and {MAX_RATING}")}),
+        ));
+    }
+
+    ctx.kv.set_rating(playlist_id, file, rating)?;
+    Ok(Json(json!({"success": true, "file": file, "rating": rating})))
+}
+
+async fn rate_track(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Json(params): Json<RatingParams>,
+) -> Result<Response, AppError> {
+    let Some((_, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return Ok(playlist_not_found());
+    };
+    let Some(file) = params.file.filter(|f| meta.tracks.iter().any(|t| &t.file == f)) else {
+        return Ok(Json(json!({"success": false, "message": "Track not found"})).into_response());
+    };
+
+    Ok(set_rating(&ctx, &meta.id, &file, params.rating)?.into_response())
+}
+
+async fn rate_current_track(
+    AxState(ctx): AxState<AppCtx>,
+    Json(params): Json<RatingParams>,
+) -> Result<Json<Value>, AppError> {
+    let s = ctx.player.status()?;
+    let (Some(playlist_id), Some(file)) = (s.playlist_id, params.file.or(s.current_track)) else {
+        return Ok(Json(json!({"success": false, "message": "Nothing is playing"})));
+    };
+
+    Ok(set_rating(&ctx, &playlist_id, &file, params.rating)?)
+}
+
+/// Namespaces and keys are single path segments of printable ASCII.
+fn valid_kv_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_KV_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+fn invalid_kv_name() -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"success": false, "message": "Invalid namespace or key"})),
+    )
+        .into_response()
+}
+
+async fn list_kv(AxState(ctx): AxState<AppCtx>, AxPath(namespace): AxPath<String>) -> Result<Response, AppError> {
+    if !valid_kv_name(&namespace) {
+        return Ok(invalid_kv_name());
+    }
+    let keys: Vec<Value> = ctx
+        .kv
+        .kv_list(&namespace)?
+        .into_iter()
+        .map(|(key, size)| json!({"key": key, "size": size}))
+        .collect();
+    Ok(Json(json!({"success": true, "keys": keys})).into_response())
+}
+
+async fn get_kv(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((namespace, key)): AxPath<(String, String)>,
+) -> Result<Response, AppError> {
+    if !valid_kv_name(&namespace) || !valid_kv_name(&key) {
+        return Ok(invalid_kv_name());
+    }
+    match ctx.kv.kv_get(&namespace, &key)? {
+        Some(value) => Ok(Json(json!({"success": true, "value": value})).into_response()),
+        None => Ok((
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Key not found"})),
+        )
+            .into_response()),
+    }
+}
+
+async fn put_kv(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((namespace, key)): AxPath<(String, String)>,
+    body: axum::body::Bytes,
+) -> Result<Response, AppError> {
+    if !valid_kv_name(&namespace) || !valid_kv_name(&key) {
+        return Ok(invalid_kv_name());
+    }
+    if body.len() > MAX_KV_VALUE_BYTES {
+        return Ok((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({"success": false, "message": format!("Values are limited to {MAX_KV_VALUE_BYTES} bytes")})),
+        )
+            .into_response());
+    }
+    let value: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(error) => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"success": false, "message": format!("Invalid JSON: {error}")})),
+            )
+                .into_response());
+        }
+    };
+
+    let keys = ctx.kv.kv_list(&namespace)?;
+    if keys.len() >= MAX_KV_KEYS && !keys.iter().any(|(k, _)| *k == key) {
+        return Ok((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({"success": false, "message": format!("Namespaces are limited to {MAX_KV_KEYS} keys")})),
+        )
+            .into_response());
+    }
+
+    ctx.kv.kv_put(&namespace, &key, &value)?;
+    Ok(Json(json!({"success": true})).into_response())
+}
+
+async fn delete_kv(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((namespace, key)): AxPath<(String, String)>,
+) -> Result<Response, AppError> {
+    if !valid_kv_name(&namespace) || !valid_kv_name(&key) {
+        return Ok(invalid_kv_name());
+    }
+    match ctx.kv.kv_remove(&namespace, &key)? {
+        true => Ok(Json(json!({"success": true})).into_response()),
+        false => Ok(Json(json!({"success": false, "message": "Not found"})).into_response()),
+    }
+}
+
+async fn get_schema(AxPath(name): AxPath<String>) -> Response {
+    match schema::get(&name) {
+        Some(data) => ([(header::CONTENT_TYPE, "application/schema+json")], data).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Not found", "names": schema::NAMES})),
+        )
+            .into_response(),
+    }
+}
+
+async fn list_tags(AxState(ctx): AxState<AppCtx>) -> Json<BTreeMap<String, usize>> {
+    let mut tags = BTreeMap::new();
+    for (_, meta) in get_playlists(&ctx.paths.playlists).unwrap_or_default() {
+        for tag in meta.tags {
+            *tags.entry(tag).or_insert(0) += 1;
+        }
+    }
+    Json(tags)
+}
+
+async fn add_playlist_tags(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Json(params): Json<TagsParams>,
+) -> Result<Response, AppError> {
+    match modify_playlist(&ctx, &id, |meta| meta.add_tags(&params.tags))? {
+        Some(meta) => Ok(Json(json!({"success": true, "tags": meta.tags})).into_response()),
+        None => Ok(playlist_not_found()),
+    }
+}
+
+async fn remove_playlist_tag(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((id, tag)): AxPath<(String, String)>,
+) -> Result<Response, AppError> {
+    let tag = normalize_tag(&tag);
+    match modify_playlist(&ctx, &id, |meta| meta.tags.retain(|t| t != &tag))? {
+        Some(meta) => Ok(Json(json!({"success": true, "tags": meta.tags})).into_response()),
+        None => Ok(playlist_not_found()),
+    }
+}
+
+async fn list_duplicates(
+    AxState(ctx): AxState<AppCtx>,
+    Query(params): Query<DedupeParams>,
+) -> Result<Response, AppError> {
+    run_dedupe(&ctx, None, params.by, false).await
+}
+
+async fn dedupe_playlists(
+    AxState(ctx): AxState<AppCtx>,
+    Json(params): Json<DedupeParams>,
+) -> Result<Response, AppError> {
+    run_dedupe(&ctx, None, params.by, true).await
+}
+
+async fn list_playlist_duplicates(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Query(params): Query<DedupeParams>,
+) -> Result<Response, AppError> {
+    run_dedupe(&ctx, Some(id), params.by, false).await
+}
+
+async fn dedupe_playlist(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Json(params): Json<DedupeParams>,
+) -> Result<Response, AppError> {
+    run_dedupe(&ctx, Some(id), params.by, true).await
+}
+
+/// Find duplicates in one playlist (or across all, oldest kept first) and optionally remove them.
+async fn run_dedupe(ctx: &AppCtx, id: Option<String>, by: DedupeBy, remove: bool) -> Result<Response, AppError> {
+    let mut playlists = get_playlists(&ctx.paths.playlists)?;
+    playlists.reverse();
+    if let Some(id) = &id {
+        playlists.retain(|(_, m)| &m.id == id);
+        if playlists.is_empty() {
+            return Ok(playlist_not_found());
+        }
+    }
+
+    let root = ctx.paths.playlists.clone();
+    let (groups, removed, changed) = tokio::task::spawn_blocking(move || {
+        let groups = dedupe::find_duplicates(&root, &playlists, by);
+        let mut removed = 0;
+        let mut changed = vec![];
+        if remove {
+            for (folder, files) in dedupe::removals(&groups) {
+                let dir = root.join(&folder);
+                match dedupe::remove_tracks(&dir, &files) {
+                    Ok(n) => {
+                        tracing::info!("Removed {} duplicate tracks from {}", n, folder);
+                        removed += n;
+                        changed.push(dir);
+                    }
+                    Err(error) => tracing::warn!("Failed to dedupe {}: {:#}", folder, error),
+                }
+            }
+        }
+        (groups, removed, changed)
+    })
+    .await
+    .context("Dedupe task failed")?;
+
+    for dir in changed {
+        ctx.player.reload_if_current(dir);
+    }
+
+    Ok(Json(DedupeResp {
+        success: true,
+        groups,
+        removed,
+    })
+    .into_response())
+}
+
+async fn export_playlist(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+
+    let dir = ctx.paths.playlists.join(&folder);
+    let mut meta = meta;
+    // JSON is the stored playlist, to be imported or restored as it was
+    if !matches!(query.format, ExportFormat::Json) {
+        meta.apply_sort();
+    }
+    let body = export::export(&meta, &dir, &query.format, &query.paths);
+    let disposition = format!("attachment; filename=\"{}.{}\"", folder, query.format.extension());
+
+    (
+        [
+            (header::CONTENT_TYPE, query.format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+async fn list_jobs(AxState(ctx): AxState<AppCtx>) -> Json<Vec<Job>> {
+    let jobs = ctx.job_manager.current_jobs.lock().unwrap().clone();
+    Json(jobs)
+}
+
+async fn upsert_job(AxState(ctx): AxState<AppCtx>, Json(value): Json<Value>) -> impl IntoResponse {
+    let result = serde_json::from_value::<Job>(value)
+        .map_err(anyhow::Error::from)
+        .and_then(|job| ctx.job_manager.upsert_job(&job));
+
+    match result {
+        Ok(()) => Json(json!({"success": true})),
+        Err(error) => Json(json!({"success": false, "message": format!("{error:#}")})),
+    }
+}
+
+async fn delete_job(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> impl IntoResponse {
+    match ctx.job_manager.delete_job(&id) {
+        Ok(true) => Json(json!({"success": true})),
+        Ok(false) => Json(json!({"success": false, "message": "Not found"})),
+        Err(error) => Json(json!({"success": false, "message": format!("{error:#}")})),
+    }
+}
+
+async fn import_jobs(AxState(ctx): AxState<AppCtx>, Json(entries): Json<Vec<Value>>) -> impl IntoResponse {
+    match ctx.job_manager.import_jobs(entries) {
+        Ok(errors) => Json(json!({"success": true, "errors": errors})),
+        Err(error) => Json(json!({"success": false, "message": format!("{error:#}")})),
+    }
+}
+
+async fn list_job_errors(AxState(ctx): AxState<AppCtx>) -> Json<Vec<JobFileError>> {
+    let errors = ctx.job_manager.errors.lock().unwrap().clone();
+    Json(errors)
+}
+
+fn preview_resp(ctx: &AppCtx, job: &Job, count: Option<usize>) -> PreviewResp {
+    let count = count.unwrap_or(DEFAULT_PREVIEW_COUNT).min(MAX_PREVIEW_COUNT);
+    let runs = ctx
+        .job_manager
+        .preview(job, count)
+        .into_iter()
+        .map(|utc| PreviewRun {
+            utc,
+            local: utc.with_timezone(&Local),
+        })
+        .collect();
+
+    PreviewResp {
+        success: true,
+        id: job.id.clone(),
+        runs,
+    }
+}
+
+async fn next_job_runs(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Query(query): Query<PreviewQuery>,
+) -> Response {
+    let job = ctx
+        .job_manager
+        .current_jobs
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|j| j.id == id)
+        .cloned();
+
+    match job {
+        Some(job) => Json(preview_resp(&ctx, &job, query.count)).into_response(),
+        None => Json(json!({"success": false, "message": "Not found"})).into_response(),
+    }
+}
+
+async fn job_runs(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Query(query): Query<JobRunsQuery>,
+) -> Result<Json<Value>, AppError> {
+    let runs = ctx.kv.list_job_runs(&id, query.limit)?;
+    Ok(Json(json!({"success": true, "id": id, "runs": runs})))
+}
+
+async fn preview_job(
+    AxState(ctx): AxState<AppCtx>,
+    Query(query): Query<PreviewQuery>,
+    Json(value): Json<Value>,
+) -> Response {
+    let job = match serde_json::from_value::<Job>(value)
+        .map_err(|error| error.to_string())
+        .and_then(|job| job.validate(&ctx.job_manager.settings).map(|_| job))
+    {
+        Ok(job) => job,
+        Err(message) => return Json(json!({"success": false, "message": message})).into_response(),
+    };
+
+    Json(preview_resp(&ctx, &job, query.count)).into_response()
+}
+
+async fn export_state(
+    AxState(ctx): AxState<AppCtx>,
+    Query(query): Query<ExportStateQuery>,
+) -> Result<Response, AppError> {
+    if query.to_file {
+        let path = backup::write_backup(&ctx.kv, &ctx.paths.backups)?;
+        backup::rotate(&ctx.paths.backups, ctx.backup.keep);
+        tracing::info!("Backed up state to {}", path.display());
+        return Ok(Json(json!({"success": true, "path": path})).into_response());
+    }
+
+    let snapshot = ctx.kv.export_snapshot()?;
+    let disposition = format!(
+        "attachment; filename=\"musicd-state-{}.json\"",
+        snapshot.exported_at.format("%Y%m%dT%H%M%SZ")
+    );
+    Ok(([(header::CONTENT_DISPOSITION, disposition)], Json(snapshot)).into_response())
+}
+
+async fn import_state(
+    AxState(ctx): AxState<AppCtx>,
+    Query(query): Query<ImportStateQuery>,
+    body: axum::body::Bytes,
+) -> Result<Json<Value>, AppError> {
+    let snapshot: StateSnapshot = match &query.backup {
+        Some(name) => {
+            let Some(path) = backup::list_backups(&ctx.paths.backups)
+                .into_iter()
+                .find(|p| p.file_name().is_some_and(|n| n.to_string_lossy() == *name))
+            else {
+                return Ok(Json(json!({"success": false, "message": "Backup not found"})));
+            };
+            serde_json::from_slice(&tokio::fs::read(&path).await.context("Failed to read backup")?)
+                .context("Invalid backup")?
+        }
+        None => match serde_json::from_slice(&body) {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                return Ok(Json(
+                    json!({"success": false, "message": format!("Invalid snapshot: {error}")}),
+                ));
+            }
+        },
+    };
+
+    // Keep the current state in case the import was a mistake
+    let before = backup::write_backup(&ctx.kv, &ctx.paths.backups)?;
+    backup::rotate(&ctx.paths.backups, ctx.backup.keep);
+    ctx.kv.import_snapshot(&snapshot)?;
+    tracing::info!(
+        "Imported state exported at {} (previous state in {})",
+        snapshot.exported_at,
+        before.display()
+    );
+
+    // Jobs may live in the state db
+    ctx.job_manager.schedule_jobs();
+
+    Ok(Json(json!({"success": true, "previous": before})))
+}
+
+async fn backup_archive(AxState(ctx): AxState<AppCtx>, Query(query): Query<BackupQuery>) -> Response {
+    let rx = archive::stream(
+        ctx.paths.clone(),
+        ctx.settings_files.clone(),
+        ctx.kv.clone(),
+        query.audio,
+    );
+    let chunks = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    let disposition = format!(
+        "attachment; filename=\"musicd-backup-{}.tar.gz\"",
+        Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+    (
+        [
+            (header::CONTENT_TYPE, "application/gzip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        axum::body::Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+async fn restore_archive(AxState(ctx): AxState<AppCtx>, body: axum::body::Body) -> Result<Json<Value>, AppError> {
+    // Receive the whole archive first, it's checked before anything is applied
+    tokio::fs::create_dir_all(&ctx.paths.tmp)
+        .await
+        .context("Failed to create tmp dir")?;
+    let upload = ctx.paths.tmp.join(format!("restore-{}.tar.gz", uuid::Uuid::new_v4()));
+    let res = receive_file(body, &upload).await;
+    let res = match res {
+        Ok(()) => {
+            let (path, paths, settings_files, kv) = (
+                upload.clone(),
+                ctx.paths.clone(),
+                ctx.settings_files.clone(),
+                ctx.kv.clone(),
+            );
+            let keep = ctx.backup.keep;
+            tokio::task::spawn_blocking(move || archive::restore(&path, &paths, &settings_files, &kv, keep))
+                .await
+                .context("Restore task failed")?
+        }
+        Err(error) => Err(error),
+    };
+    tokio::fs::remove_file(&upload).await.ok();
+
+    let summary = match res {
+        Ok(summary) => summary,
+        Err(error) => {
+            return Ok(Json(
+                json!({"success": false, "message": format!("Restore failed: {error:#}")}),
+            ));
+        }
+    };
+
+    // Jobs may live in the state db or the restored jobs file
+    ctx.job_manager.schedule_jobs();
+    if let Ok(playlists) = get_playlists(&ctx.paths.playlists) {
+        for (folder, _) in playlists {
+            ctx.player.reload_if_current(ctx.paths.playlists.join(folder));
+        }
+    }
+
+    Ok(Json(json!({"success": true, "restore": summary})))
+}
+
+async fn receive_file(body: axum::body::Body, path: &std::path::Path) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+async fn list_state_backups(AxState(ctx): AxState<AppCtx>) -> Json<Value> {
+    let mut backups: Vec<String> = backup::list_backups(&ctx.paths.backups)
+        .iter()
+        .filter_map(|p| Some(p.file_name()?.to_string_lossy().to_string()))
+        .collect();
+    backups.reverse();
+    Json(json!({"success": true, "backups": backups}))
+}
+
+async fn manifest_status(AxState(ctx): AxState<AppCtx>) -> impl IntoResponse {
+    let status = ctx.manifest_status.lock().unwrap().clone();
+    Json(json!({"success": true, "status": status}))
+}
+
+async fn list_publishes(AxState(ctx): AxState<AppCtx>, Query(query): Query<PublishesQuery>) -> impl IntoResponse {
+    let mut jobs = ctx.publisher.jobs();
+    if let Some(state) = query.state {
+        jobs.retain(|job| job.state == state);
+    }
+    Json(json!({"success": true, "jobs": jobs}))
+}
+
+async fn get_publish(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Response {
+    match ctx.publisher.job(&id) {
+        Some(job) => Json(json!({"success": true, "job": job})).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Publish not found"})),
+        )
+            .into_response(),
+    }
+}
+
+async fn cancel_publish(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Response {
+    match ctx.publisher.cancel(&id) {
+        Some(true) => Json(json!({"success": true})).into_response(),
+        Some(false) => (
+            StatusCode::CONFLICT,
+            Json(json!({"success": false, "message": "Publish already finished"})),
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Publish not found"})),
+        )
+            .into_response(),
+    }
+}
+
+/// Refuse to start downloads without room for them.
+fn insufficient_storage(ctx: &AppCtx) -> Option<Response> {
+    crate::disk::ensure_free(&ctx.paths.root, ctx.min_free_bytes)
+        .err()
+        .map(|error| {
+            (
+                StatusCode::INSUFFICIENT_STORAGE,
+                Json(json!({"success": false, "message": format!("{error:#}")})),
+            )
+                .into_response()
+        })
+}
+
+async fn publish(AxState(ctx): AxState<AppCtx>, Json(params): Json<PublishParams>) -> Response {
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return resp;
+    }
+
+    let job_id = ctx
+        .publisher
+        .publish_in_background(&params.name, &params.source_urls, params.downloader);
+
+    Json(json!({"success": true, "job_id": job_id})).into_response()
+}
+
+async fn import_playlist(AxState(ctx): AxState<AppCtx>, Json(params): Json<ImportParams>) -> Response {
+    let parsed = match (&params.content, &params.url) {
+        (Some(content), None) => crate::playlist::import::parse(content),
+        (None, Some(url)) => match crate::playlist::import::fetch(url).await {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                return Json(json!({"success": false, "message": format!("Failed to fetch playlist: {error:#}")}))
+                    .into_response();
+            }
+        },
+        _ => return Json(json!({"success": false, "message": "Expected content or url"})).into_response(),
+    };
+    if parsed.entries.is_empty() {
+        return Json(json!({"success": false, "message": "No entries found in playlist"})).into_response();
+    }
+
+    let name = params
+        .name
+        .filter(|n| !n.trim().is_empty())
+        .or(parsed.name)
+        .unwrap_or_else(|| "Imported".to_string());
+
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return resp;
+    }
+
+    let job_id = ctx
+        .publisher
+        .import_in_background(&name, parsed.entries, params.base_dir, params.downloader);
+
+    Json(json!({"success": true, "job_id": job_id})).into_response()
+}
+
+async fn publish_local(AxState(ctx): AxState<AppCtx>, Json(params): Json<LocalPublishParams>) -> Response {
+    if !params.path.is_dir() {
+        return Json(json!({"success": false, "message": "Not a directory"})).into_response();
+    }
+    if !crate::playlist::import::is_within_roots(&params.path, &ctx.publisher.publish_settings().local_roots) {
+        return Json(json!({"success": false, "message": "Directory is not within publish.local_roots"}))
+            .into_response();
+    }
+
+    let name = params
+        .name
+        .filter(|n| !n.trim().is_empty())
+        .or_else(|| params.path.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "Local".to_string());
+
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return resp;
+    }
+
+    let job_id = ctx
+        .publisher
+        .publish_local_in_background(&name, params.path, params.link);
+
+    Json(json!({"success": true, "job_id": job_id})).into_response()
+}
+
+async fn publish_upload(AxState(ctx): AxState<AppCtx>, mut multipart: Multipart) -> Result<Response, AppError> {
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return Ok(resp);
+    }
+
+    let staging = ctx.paths.tmp.join(format!("upload-{}", uuid::Uuid::new_v4()));
+    let upload = match receive_tracks(&mut multipart, &staging, 1).await {
+        Ok(upload) => upload,
+        Err(error) => {
+            tokio::fs::remove_dir_all(&staging).await.ok();
+            return Ok(Json(json!({"success": false, "message": format!("Upload failed: {error:#}")})).into_response());
+        }
+    };
+    let name = upload
+        .fields
+        .get("name")
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+    let (Some(name), false) = (name, upload.files.is_empty()) else {
+        tokio::fs::remove_dir_all(&staging).await.ok();
+        return Ok(Json(json!({
+            "success": false,
+            "message": "Expected a name field and at least one audio file",
+            "skipped": upload.skipped,
+        }))
+        .into_response());
+    };
+
+    let job_id = ctx
+        .publisher
+        .publish_upload_in_background(&name, staging, upload.files.clone());
+
+    Ok(
+        Json(json!({"success": true, "job_id": job_id, "files": upload.files, "skipped": upload.skipped}))
+            .into_response(),
+    )
+}
+
+async fn merge_playlists(AxState(ctx): AxState<AppCtx>, Json(params): Json<MergeParams>) -> Response {
+    if params.ids.len() < 2 {
+        return Json(json!({"success": false, "message": "At least two playlists are required"})).into_response();
+    }
+
+    let all = get_playlists(&ctx.paths.playlists).unwrap_or_default();
+    let mut playlists = vec![];
+    for id in &params.ids {
+        let Some((folder, meta)) = all.iter().find(|(_, m)| &m.id == id) else {
+            return Json(json!({"success": false, "message": format!("Playlist {id} not found")})).into_response();
+        };
+        playlists.push((ctx.paths.playlists.join(folder), meta.clone()));
+    }
+
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return resp;
+    }
+
+    let job_id = ctx
+        .publisher
+        .merge_in_background(&params.name, playlists, params.order, params.link);
+
+    Json(json!({"success": true, "job_id": job_id})).into_response()
+}
+
+async fn list_library(
+    AxState(ctx): AxState<AppCtx>,
+    Query(filter): Query<LibraryFilter>,
+) -> Result<Json<LibraryResp>, AppError> {
+    let playlists = get_playlists(&ctx.paths.playlists)?;
+    let tracks = tokio::task::spawn_blocking(move || ctx.library.search(&ctx.paths.playlists, &playlists, &filter))
+        .await
+        .context("Library task failed")?;
+
+    Ok(Json(LibraryResp {
+        success: true,
+        total: tracks.len(),
+        tracks,
+    }))
+}
+
+async fn library_playlist(
+    AxState(ctx): AxState<AppCtx>,
+    Json(params): Json<LibraryPlaylistParams>,
+) -> Result<Response, AppError> {
+    if params.keys.is_empty() {
+        return Ok(Json(json!({"success": false, "message": "No tracks given"})).into_response());
+    }
+
+    let playlists = get_playlists(&ctx.paths.playlists)?;
+    let (library, root, keys) = (ctx.library.clone(), ctx.paths.playlists.clone(), params.keys);
+    let picked = tokio::task::spawn_blocking(move || library.pick(&root, &playlists, &keys))
+        .await
+        .context("Library task failed")?;
+    let tracks = match picked {
+        Ok(tracks) => tracks,
+        Err(missing) => {
+            return Ok(
+                Json(json!({"success": false, "message": "Tracks not found in the library", "keys": missing}))
+                    .into_response(),
+            );
+        }
+    };
+
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return Ok(resp);
+    }
+
+    let job_id = ctx
+        .publisher
+        .library_in_background(&params.name, tracks, params.link, params.play);
+
+    Ok(Json(json!({"success": true, "job_id": job_id})).into_response())
+}
+
+#[derive(Deserialize, Default)]
+struct RefreshParams {
+    /// Also remove the tracks that are no longer in the sources.
+    #[serde(default)]
+    remove_missing: bool,
+}
+
+async fn refresh_playlist(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    params: Option<Json<RefreshParams>>,
+) -> Response {
+    let params = params.map(|Json(p)| p).unwrap_or_default();
+    let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+    if meta.folder.is_some() {
+        return Json(json!({"success": false, "message": "Watched folders are synced on their own"})).into_response();
+    }
+    if !meta.can_refresh() {
+        return Json(json!({"success": false, "message": "Only playlists published from urls can be refreshed"}))
+            .into_response();
+    }
+
+    if let Some(resp) = insufficient_storage(&ctx) {
+        return resp;
+    }
+
+    let job_id =
+        ctx.publisher
+            .refresh_in_background(ctx.paths.playlists.join(folder), meta, None, params.remove_missing);
+
+    Json(json!({"success": true, "job_id": job_id})).into_response()
+}
+
+async fn clean(AxState(ctx): AxState<AppCtx>) -> Result<impl IntoResponse, AppError> {
+    let dir = ctx.paths.tmp;
+
+    let mut entries = tokio::fs::read_dir(&dir)
         .await
         .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
 
@@ -295,9 +2330,14 @@ async fn next(AxState(ctx): AxState<AppCtx>) -> impl IntoResponse {
     Json(json!({"success": true}))
 }
 
-async fn seek(AxState(ctx): AxState<AppCtx>, Json(params): Json<SeekParams>) -> impl IntoResponse {
-    ctx.player.seek(params.secs);
-    Json(json!({"success": true}))
+async fn seek(AxState(ctx): AxState<AppCtx>, Json(params): Json<SeekParams>) -> Response {
+    match params.target() {
+        Ok(target) => {
+            ctx.player.seek(target);
+            Json(json!({"success": true})).into_response()
+        }
+        Err(message) => invalid_seek(message),
+    }
 }
 
 async fn set_volume(AxState(ctx): AxState<AppCtx>, Json(params): Json<SetVolumeParams>) -> impl IntoResponse {
@@ -305,14 +2345,121 @@ async fn set_volume(AxState(ctx): AxState<AppCtx>, Json(params): Json<SetVolumeP
     Json(json!({"success": true}))
 }
 
+/// Change the equalizer of `player` as asked, answering with its state and the presets to pick from.
+fn change_equalizer(player: &PlayerHandle, params: Option<Json<SetEqualizerParams>>) -> Response {
+    let params = params.map(|Json(p)| p).unwrap_or_default();
+    let change = EqualizerChange {
+        enable: params.enable,
+        preset: params.preset,
+        gains_db: params.gains_db,
+    };
+    // Without changes it only tells the state
+    let res = match (&change.enable, &change.preset, &change.gains_db) {
+        (None, None, None) => Ok(player.equalizer()),
+        _ => player.set_equalizer(change),
+    };
+    match res {
+        Ok(equalizer) => Json(json!({"success": true, "equalizer": equalizer, "presets": player.equalizer_presets()}))
+            .into_response(),
+        Err(message) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"success": false, "message": message})),
+        )
+            .into_response(),
+    }
+}
+
+async fn set_equalizer(AxState(ctx): AxState<AppCtx>, params: Option<Json<SetEqualizerParams>>) -> Response {
+    change_equalizer(&ctx.player, params)
+}
+
+async fn list_devices(AxState(ctx): AxState<AppCtx>) -> Result<Json<Value>, AppError> {
+    let devices = tokio::task::spawn_blocking(crate::output::devices)
+        .await
+        .context("Device task failed")??;
+    Ok(Json(
+        json!({"success": true, "devices": devices, "current": ctx.player.output_device()}),
+    ))
+}
+
+/// Switch `player` to the device of `params`, off the async runtime as opening a device blocks.
+async fn switch_device(player: PlayerHandle, params: SetDeviceParams) -> Result<Json<Value>, AppError> {
+    let name = params.name.filter(|n| !n.trim().is_empty());
+    let res = tokio::task::spawn_blocking(move || player.set_output_device(name))
+        .await
+        .context("Device switch task failed")?;
+    Ok(match res {
+        Ok(()) => Json(json!({"success": true})),
+        Err(error) => Json(json!({"success": false, "message": format!("{error:#}")})),
+    })
+}
+
+async fn set_device(
+    AxState(ctx): AxState<AppCtx>,
+    Json(params): Json<SetDeviceParams>,
+) -> Result<Json<Value>, AppError> {
+    switch_device(ctx.player.clone(), params).await
+}
+
+async fn stream_audio(AxState(ctx): AxState<AppCtx>, Query(query): Query<StreamQuery>) -> Response {
+    let Some(streamer) = &ctx.streamer else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Streaming is disabled"})),
+        )
+            .into_response();
+    };
+    let rx = match streamer.listen(query.format) {
+        Ok(rx) => rx,
+        Err(error) => return Json(json!({"success": false, "message": format!("{error:#}")})).into_response(),
+    };
+    let chunks = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    (
+        [
+            (header::CONTENT_TYPE, query.format.content_type()),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        axum::body::Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+async fn get_alarm(AxState(ctx): AxState<AppCtx>) -> impl IntoResponse {
+    Json(json!({"success": true, "alarm": ctx.alarm.status()}))
+}
+
+async fn set_alarm(AxState(ctx): AxState<AppCtx>, Json(req): Json<AlarmRequest>) -> impl IntoResponse {
+    match ctx.alarm.set(req) {
+        Ok(alarm) => Json(json!({"success": true, "alarm": alarm})),
+        Err(error) => Json(json!({"success": false, "message": error.to_string()})),
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct SnoozeParams {
+    secs: Option<u64>,
+}
+
+async fn snooze_alarm(AxState(ctx): AxState<AppCtx>, params: Option<Json<SnoozeParams>>) -> impl IntoResponse {
+    let params = params.map(|Json(p)| p).unwrap_or_default();
+    match ctx.alarm.snooze(params.secs) {
+        Ok(alarm) => Json(json!({"success": true, "alarm": alarm})),
+        Err(error) => Json(json!({"success": false, "message": error.to_string()})),
+    }
+}
+
+async fn stop_alarm(AxState(ctx): AxState<AppCtx>) -> impl IntoResponse {
+    ctx.alarm.stop();
+    Json(json!({"success": true}))
+}
+
 async fn set_playlist(
     AxState(ctx): AxState<AppCtx>,
     AxPath(id): AxPath<String>,
     Json(params): Json<SetPlaylistParams>,
 ) -> impl IntoResponse {
     // Find playlist by id
-    let items = get_playlists(&ctx.paths.playlists).unwrap_or_default();
-    if let Some((folder, meta)) = items.into_iter().find(|(_, m)| m.id == id) {
+    if let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) {
         let dir = ctx.paths.playlists.join(folder);
         if let Err(error) = ctx.kv.set_current_playlist_id(&meta.id) {
             tracing::warn!("kv set failed: {error:#}");
@@ -328,15 +2475,365 @@ async fn set_track(AxState(ctx): AxState<AppCtx>, AxPath(idx): AxPath<usize>) ->
     Json(json!({"success": true}))
 }
 
-async fn handle_socket(socket: WebSocket, who: SocketAddr, notifier: Notifier) {
+async fn list_queue(AxState(ctx): AxState<AppCtx>) -> impl IntoResponse {
+    Json(json!({"success": true, "queue": ctx.player.queue()}))
+}
+
+async fn enqueue(AxState(ctx): AxState<AppCtx>, Json(params): Json<EnqueueParams>) -> Response {
+    let Some((folder, mut meta)) = find_playlist(&ctx.paths.playlists, &params.playlist_id) else {
+        return playlist_not_found();
+    };
+    // Indexes are in the sort order the player and playlist view use
+    meta.apply_sort();
+    let Some(track) = meta.tracks.get(params.track_idx) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Track not found"})),
+        )
+            .into_response();
+    };
+    if ctx.player.queue().len() >= MAX_QUEUE_LEN {
+        return Json(json!({"success": false, "message": format!("Queue is full ({MAX_QUEUE_LEN} tracks)")}))
+            .into_response();
+    }
+
+    ctx.player.enqueue(QueuedTrack {
+        playlist_id: meta.id.clone(),
+        playlist_name: meta.name.clone(),
+        file: track.file.clone(),
+        title: track.display_name(),
+        duration: track.duration,
+        dir: ctx.paths.playlists.join(folder),
+    });
+    Json(json!({"success": true, "queue": ctx.player.queue()})).into_response()
+}
+
+async fn clear_queue(AxState(ctx): AxState<AppCtx>) -> impl IntoResponse {
+    ctx.player.clear_queue();
+    Json(json!({"success": true}))
+}
+
+fn zone_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"success": false, "message": "Zone not found"})),
+    )
+        .into_response()
+}
+
+/// Run `f` on the player of `zone`.
+fn control_zone(ctx: &AppCtx, zone: &str, f: impl FnOnce(&PlayerHandle)) -> Response {
+    match ctx.zones.get(zone) {
+        Some(zone) => {
+            f(&zone.player);
+            Json(json!({"success": true})).into_response()
+        }
+        None => zone_not_found(),
+    }
+}
+
+async fn list_zones(AxState(ctx): AxState<AppCtx>) -> Result<Json<Value>, AppError> {
+    let mut zones = vec![];
+    for (name, zone) in ctx.zones.iter() {
+        zones.push(json!({"name": name, "status": status_resp(&zone.player)?}));
+    }
+    Ok(Json(json!({"success": true, "zones": zones})))
+}
+
+async fn zone_status(AxState(ctx): AxState<AppCtx>, AxPath(zone): AxPath<String>) -> Result<Response, AppError> {
+    let Some(zone) = ctx.zones.get(&zone) else {
+        return Ok(zone_not_found());
+    };
+    Ok(Json(status_resp(&zone.player)?).into_response())
+}
+
+async fn zone_play(AxState(ctx): AxState<AppCtx>, AxPath(zone): AxPath<String>) -> Response {
+    control_zone(&ctx, &zone, |player| player.play())
+}
+
+async fn zone_pause(AxState(ctx): AxState<AppCtx>, AxPath(zone): AxPath<String>) -> Response {
+    control_zone(&ctx, &zone, |player| player.pause())
+}
+
+async fn zone_prev(AxState(ctx): AxState<AppCtx>, AxPath(zone): AxPath<String>) -> Response {
+    control_zone(&ctx, &zone, |player| player.prev())
+}
+
+async fn zone_next(AxState(ctx): AxState<AppCtx>, AxPath(zone): AxPath<String>) -> Response {
+    control_zone(&ctx, &zone, |player| player.next())
+}
+
+async fn zone_seek(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(zone): AxPath<String>,
+    Json(params): Json<SeekParams>,
+) -> Response {
+    match params.target() {
+        Ok(target) => control_zone(&ctx, &zone, |player| player.seek(target)),
+        Err(message) => invalid_seek(message),
+    }
+}
+
+async fn zone_set_volume(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(zone): AxPath<String>,
+    Json(params): Json<SetVolumeParams>,
+) -> Response {
+    control_zone(&ctx, &zone, |player| player.set_volume(params.value))
+}
+
+async fn zone_set_equalizer(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(zone): AxPath<String>,
+    params: Option<Json<SetEqualizerParams>>,
+) -> Response {
+    match ctx.zones.get(&zone) {
+        Some(zone) => change_equalizer(&zone.player, params),
+        None => zone_not_found(),
+    }
+}
+
+async fn zone_set_device(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(zone): AxPath<String>,
+    Json(params): Json<SetDeviceParams>,
+) -> Result<Response, AppError> {
+    let Some(zone) = ctx.zones.get(&zone) else {
+        return Ok(zone_not_found());
+    };
+    Ok(switch_device(zone.player.clone(), params).await?.into_response())
+}
+
+async fn zone_set_playlist(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((zone, id)): AxPath<(String, String)>,
+    Json(params): Json<SetPlaylistParams>,
+) -> Response {
+    if !ctx.zones.contains_key(&zone) {
+        return zone_not_found();
+    }
+    let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+        return playlist_not_found();
+    };
+    if let Err(error) = ctx.kv.set_zone_playlist_id(&zone, &meta.id) {
+        tracing::warn!("kv set failed: {error:#}");
+    }
+    let dir = ctx.paths.playlists.join(folder);
+    control_zone(&ctx, &zone, |player| player.set_playlist_dir(dir, params.mode))
+}
+
+async fn zone_sse_handler(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(zone): AxPath<String>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(notifier) = ctx.zones.get(&zone).map(|z| z.notifier.clone()) else {
+        return zone_not_found();
+    };
+    event_stream(&notifier, query.since_seq.or_else(|| last_event_id(&headers)))
+}
+
+async fn zone_set_track(AxState(ctx): AxState<AppCtx>, AxPath((zone, idx)): AxPath<(String, usize)>) -> Response {
+    control_zone(&ctx, &zone, |player| player.set_index(idx))
+}
+
+async fn zone_ws_handler(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(zone): AxPath<String>,
+    Query(query): Query<WsQuery>,
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Response {
+    let Some(notifier) = ctx.zones.get(&zone).map(|z| z.notifier.clone()) else {
+        return zone_not_found();
+    };
+    ws.on_upgrade(move |socket| handle_socket(socket, addr, ctx, Some(zone), notifier, query.since_seq))
+}
+
+async fn list_inserts(AxState(ctx): AxState<AppCtx>) -> Result<Json<Value>, AppError> {
+    let dir = ctx.paths.inserts.clone();
+    let clips = tokio::task::spawn_blocking(move || insert::list(&dir))
+        .await
+        .context("Insert listing task failed")?;
+    Ok(Json(json!({"success": true, "inserts": clips})))
+}
+
+async fn upload_insert(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(file): AxPath<String>,
+    body: axum::body::Bytes,
+) -> Result<Json<Value>, AppError> {
+    if !insert::valid_name(&file) {
+        return Ok(Json(
+            json!({"success": false, "message": "Expected an audio file name"}),
+        ));
+    }
+
+    // Write then rename, so a partial clip is never played
+    let path = ctx.paths.inserts.join(&file);
+    let tmp = ctx.paths.tmp.join(format!("insert-{}-{file}", uuid::Uuid::new_v4()));
+    tokio::fs::write(&tmp, &body).await.context("Failed to write insert")?;
+    let probe = tmp.clone();
+    let readable = tokio::task::spawn_blocking(move || lofty::read_from_path(&probe).is_ok())
+        .await
+        .context("Insert probe task failed")?;
+    if !readable {
+        tokio::fs::remove_file(&tmp).await.ok();
+        return Ok(Json(json!({"success": false, "message": "Not a readable audio file"})));
+    }
+    tokio::fs::rename(&tmp, &path).await.context("Failed to save insert")?;
+
+    tracing::info!("Saved insert {}", file);
+    Ok(Json(json!({"success": true, "file": file})))
+}
+
+async fn delete_insert(AxState(ctx): AxState<AppCtx>, AxPath(file): AxPath<String>) -> Result<Response, AppError> {
+    let path = ctx.paths.inserts.join(&file);
+    if !insert::valid_name(&file) || !path.is_file() {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            Json(json!({"success": false, "message": "Insert not found"})),
+        )
+            .into_response());
+    }
+    tokio::fs::remove_file(&path).await.context("Failed to remove insert")?;
+    Ok(Json(json!({"success": true})).into_response())
+}
+
+/// Trimmed name and URL of `params`, or why they're invalid.
+fn valid_station(params: StationParams) -> Result<(String, String), &'static str> {
+    let (name, url) = (params.name.trim(), params.url.trim());
+    if name.is_empty() {
+        return Err("Missing name");
+    }
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err("Expected an http(s) url");
+    }
+    Ok((name.to_string(), url.to_string()))
+}
+
+fn station_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"success": false, "message": "Station not found"})),
+    )
+        .into_response()
+}
+
+async fn list_stations(AxState(ctx): AxState<AppCtx>) -> Result<Json<Value>, AppError> {
+    Ok(Json(json!({"success": true, "stations": ctx.kv.list_stations()?})))
+}
+
+async fn add_station(
+    AxState(ctx): AxState<AppCtx>,
+    Json(params): Json<StationParams>,
+) -> Result<Json<Value>, AppError> {
+    let (name, url) = match valid_station(params) {
+        Ok(v) => v,
+        Err(message) => return Ok(Json(json!({"success": false, "message": message}))),
+    };
+    let station = Station {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        url,
+        created_at: Utc::now(),
+    };
+    ctx.kv.set_station(&station)?;
+    Ok(Json(json!({"success": true, "station": station})))
+}
+
+async fn update_station(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath(id): AxPath<String>,
+    Json(params): Json<StationParams>,
+) -> Result<Response, AppError> {
+    let Some(station) = ctx.kv.get_station(&id)? else {
+        return Ok(station_not_found());
+    };
+    let (name, url) = match valid_station(params) {
+        Ok(v) => v,
+        Err(message) => return Ok(Json(json!({"success": false, "message": message})).into_response()),
+    };
+    let station = Station { name, url, ..station };
+    ctx.kv.set_station(&station)?;
+
+    // Reconnect to the new url if it's playing
+    for player in std::iter::once(&ctx.player).chain(ctx.zones.values().map(|z| &z.player)) {
+        if player.status()?.station.is_some_and(|s| s.id == id) {
+            player.set_station(station.clone());
+        }
+    }
+    Ok(Json(json!({"success": true, "station": station})).into_response())
+}
+
+async fn delete_station(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Result<Response, AppError> {
+    if !ctx.kv.remove_station(&id)? {
+        return Ok(station_not_found());
+    }
+    ctx.player.clear_station(&id);
+    for zone in ctx.zones.values() {
+        zone.player.clear_station(&id);
+    }
+    Ok(Json(json!({"success": true})).into_response())
+}
+
+async fn set_station(AxState(ctx): AxState<AppCtx>, AxPath(id): AxPath<String>) -> Result<Response, AppError> {
+    let Some(station) = ctx.kv.get_station(&id)? else {
+        return Ok(station_not_found());
+    };
+    ctx.player.set_station(station);
+    Ok(Json(json!({"success": true})).into_response())
+}
+
+async fn zone_set_station(
+    AxState(ctx): AxState<AppCtx>,
+    AxPath((zone, id)): AxPath<(String, String)>,
+) -> Result<Response, AppError> {
+    let Some(station) = ctx.kv.get_station(&id)? else {
+        return Ok(station_not_found());
+    };
+    Ok(control_zone(&ctx, &zone, |player| player.set_station(station)))
+}
+
+/// Send the events of `notifier` to the client, and run the commands it sends on the player of `zone`,
+/// or the main player.
+async fn handle_socket(
+    socket: WebSocket,
+    who: SocketAddr,
+    ctx: AppCtx,
+    zone: Option<String>,
+    notifier: Notifier,
+    since_seq: Option<u64>,
+) {
     let (mut sender, mut receiver) = socket.split();
 
-    let mut rx = notifier.subscribe();
+    let (missed, mut rx) = notifier.subscribe(since_seq);
+    // Command results, sent along with the events
+    let (reply_tx, mut reply_rx) = tokio::sync::mpsc::unbounded_channel::<Value>();
 
     let mut send_task = tokio::spawn(async move {
-        while let Ok(notification) = rx.recv().await
-            && let Ok(text) = serde_json::to_string(&notification)
-        {
+        // Catch up first
+        for event in missed {
+            if let Ok(text) = serde_json::to_string(&event)
+                && let Err(error) = sender.send(Message::Text(text.into())).await
+            {
+                tracing::warn!("[ws] Failed to send message to WebSocket client: {error}");
+                return;
+            }
+        }
+
+        loop {
+            let text = tokio::select! {
+                event = rx.recv() => match event {
+                    Ok(event) => serde_json::to_string(&event),
+                    Err(_) => break,
+                },
+                Some(reply) = reply_rx.recv() => serde_json::to_string(&reply),
+            };
+            let Ok(text) = text else {
+                break;
+            };
             if let Err(error) = sender.send(Message::Text(text.into())).await {
                 tracing::warn!("[ws] Failed to send message to WebSocket client: {error}");
                 break;
@@ -346,6 +2843,11 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, notifier: Notifier) {
 
     let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
+            if let Message::Text(text) = &msg
+                && reply_tx.send(run_ws_command(&ctx, zone.as_deref(), text)).is_err()
+            {
+                break;
+            }
             if process_ws_message(msg, who).is_break() {
                 break;
             }
@@ -374,6 +2876,56 @@ async fn handle_socket(socket: WebSocket, who: SocketAddr, notifier: Notifier) {
     tracing::info!("[ws] Context {who} destroyed");
 }
 
+/// Run the command in `text` on the player of `zone`, returning the `COMMAND_RESULT` to reply with.
+fn run_ws_command(ctx: &AppCtx, zone: Option<&str>, text: &str) -> Value {
+    let reply = |id: Option<Value>, res: Result<(), String>| {
+        let payload = match res {
+            Ok(()) => json!({"id": id, "success": true}),
+            Err(message) => json!({"id": id, "success": false, "message": message}),
+        };
+        json!({"type": "COMMAND_RESULT", "payload": payload})
+    };
+
+    let request: WsRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(error) => return reply(None, Err(format!("Invalid command: {error}"))),
+    };
+    let player = match zone {
+        Some(zone) => match ctx.zones.get(zone) {
+            Some(zone) => &zone.player,
+            None => return reply(request.id, Err("Zone not found".to_string())),
+        },
+        None => &ctx.player,
+    };
+
+    match request.command {
+        WsCommand::Play => player.play(),
+        WsCommand::Pause => player.pause(),
+        WsCommand::Prev => player.prev(),
+        WsCommand::Next => player.next(),
+        WsCommand::Seek { params } => match params.target() {
+            Ok(target) => player.seek(target),
+            Err(message) => return reply(request.id, Err(message.to_string())),
+        },
+        WsCommand::SetVolume { value } => player.set_volume(value),
+        WsCommand::SetTrack { idx } => player.set_index(idx),
+        WsCommand::SetPlaylist { id, mode } => {
+            let Some((folder, meta)) = find_playlist(&ctx.paths.playlists, &id) else {
+                return reply(request.id, Err("Not found".to_string()));
+            };
+            let res = match zone {
+                Some(zone) => ctx.kv.set_zone_playlist_id(zone, &meta.id),
+                None => ctx.kv.set_current_playlist_id(&meta.id),
+            };
+            if let Err(error) = res {
+                tracing::warn!("kv set failed: {error:#}");
+            }
+            player.set_playlist_dir(ctx.paths.playlists.join(folder), mode.unwrap_or(SetPlaylistMode::Queue));
+        }
+    }
+    reply(request.id, Ok(()))
+}
+
 fn process_ws_message(msg: Message, who: SocketAddr) -> ControlFlow<(), ()> {
     match msg {
         Message::Text(t) => {
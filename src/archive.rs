@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::backup;
+use crate::playlist::{PlaylistMeta, get_playlists};
+use crate::settings::Paths;
+use crate::state::{State as Kv, StateSnapshot};
+
+/// Bumped when the layout of archives changes incompatibly.
+const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const STATE: &str = "state.json";
+const JOBS: &str = "jobs.json";
+const SETTINGS_DIR: &str = "settings";
+const PLAYLISTS_DIR: &str = "playlists";
+const INSERTS_DIR: &str = "inserts";
+
+/// Archive data is sent in chunks of about this size.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// First entry of an archive, describing it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format: u32,
+    /// musicd version that wrote the archive.
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    /// Whether the track files of playlists are included.
+    pub audio: bool,
+}
+
+/// What a restore applied.
+#[derive(Debug, Serialize)]
+pub struct RestoreSummary {
+    pub created_at: DateTime<Utc>,
+    /// State db backup taken before restoring.
+    pub previous: PathBuf,
+    pub playlists: usize,
+    pub settings: Vec<PathBuf>,
+    pub jobs: bool,
+}
+
+/// Sends what's written as chunks to the receiving end, failing once it's gone.
+struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Archive receiver closed"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// Write a tar.gz of the state db, jobs file, `settings_files`, playlists and inserts, in the background.
+/// The archive arrives in chunks on the returned receiver; an error ends it early.
+pub fn stream(
+    paths: Paths,
+    settings_files: Vec<PathBuf>,
+    kv: std::sync::Arc<Kv>,
+    audio: bool,
+) -> mpsc::Receiver<io::Result<Bytes>> {
+    let (tx, rx) = mpsc::channel(8);
+    tokio::task::spawn_blocking(move || {
+        let writer = ChannelWriter {
+            tx: tx.clone(),
+            buf: Vec::with_capacity(CHUNK_BYTES),
+        };
+        let res = write(writer, &paths, &settings_files, &kv, audio);
+        if let Err(error) = res {
+            tracing::warn!("Backup archive failed: {error:#}");
+            let _ = tx.blocking_send(Err(io::Error::other(format!("{error:#}"))));
+        }
+    });
+    rx
+}
+
+fn write(writer: impl Write, paths: &Paths, settings_files: &[PathBuf], kv: &Kv, audio: bool) -> anyhow::Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    let manifest = ArchiveManifest {
+        format: FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        audio,
+    };
+    append_json(&mut tar, MANIFEST, &manifest)?;
+    append_json(&mut tar, STATE, &kv.export_snapshot()?)?;
+
+    if paths.jobs.is_file() {
+        tar.append_path_with_name(&paths.jobs, JOBS)?;
+    }
+    for file in settings_files.iter().filter(|f| f.is_file()) {
+        if let Some(name) = file.file_name() {
+            tar.append_path_with_name(file, Path::new(SETTINGS_DIR).join(name))?;
+        }
+    }
+
+    for (folder, meta) in get_playlists(&paths.playlists)? {
+        // Without audio, everything but the tracks: metadata, art, lyrics, versions
+        let skip: HashSet<PathBuf> = if audio {
+            HashSet::new()
+        } else {
+            meta.tracks
+                .iter()
+                .map(|t| paths.playlists.join(&folder).join(&t.file))
+                .collect()
+        };
+        append_dir(
+            &mut tar,
+            &paths.playlists.join(&folder),
+            &Path::new(PLAYLISTS_DIR).join(&folder),
+            &skip,
+        )?;
+    }
+
+    if paths.inserts.is_dir() {
+        append_dir(&mut tar, &paths.inserts, Path::new(INSERTS_DIR), &HashSet::new())?;
+    }
+
+    tar.into_inner()?.finish()?.flush()?;
+    Ok(())
+}
+
+fn append_json<W: Write>(tar: &mut tar::Builder<W>, name: &str, value: &impl Serialize) -> anyhow::Result<()> {
+    let data = serde_json::to_vec_pretty(value)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    tar.append_data(&mut header, name, data.as_slice())?;
+    Ok(())
+}
+
+fn append_dir<W: Write>(
+    tar: &mut tar::Builder<W>,
+    dir: &Path,
+    name: &Path,
+    skip: &HashSet<PathBuf>,
+) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        if skip.contains(&path) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            append_dir(tar, &path, &name.join(entry.file_name()), skip)?;
+        } else if file_type.is_file() {
+            tar.append_path_with_name(&path, name.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Check the archive at `path` and apply it: state db, jobs file, settings files with the same name
+/// as one of `settings_files`, and playlists, over the existing ones with the same folder.
+/// Nothing is applied when the archive is invalid. The state db is backed up first.
+pub fn restore(
+    path: &Path,
+    paths: &Paths,
+    settings_files: &[PathBuf],
+    kv: &Kv,
+    keep: usize,
+) -> anyhow::Result<RestoreSummary> {
+    let staging = paths.tmp.join(format!("restore-{}", uuid::Uuid::new_v4()));
+    let res = restore_from(path, &staging, paths, settings_files, kv, keep);
+    if let Err(error) = fs::remove_dir_all(&staging) {
+        tracing::warn!("Failed to remove {}: {}", staging.display(), error);
+    }
+    res
+}
+
+fn restore_from(
+    path: &Path,
+    staging: &Path,
+    paths: &Paths,
+    settings_files: &[PathBuf],
+    kv: &Kv,
+    keep: usize,
+) -> anyhow::Result<RestoreSummary> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    fs::create_dir_all(staging)?;
+    // Entries escaping the staging dir are refused by unpack
+    tar::Archive::new(GzDecoder::new(file))
+        .unpack(staging)
+        .context("Invalid archive")?;
+
+    let manifest: ArchiveManifest = read_json(&staging.join(MANIFEST)).context("Not a musicd backup")?;
+    if manifest.format != FORMAT {
+        anyhow::bail!("Unsupported backup format {}, expected {FORMAT}", manifest.format);
+    }
+    let snapshot: StateSnapshot = read_json(&staging.join(STATE))?;
+    let playlists_dir = staging.join(PLAYLISTS_DIR);
+    let mut folders = vec![];
+    for entry in fs::read_dir(&playlists_dir).into_iter().flatten() {
+        let entry = entry?;
+        let meta_path = entry.path().join("playlist.json");
+        PlaylistMeta::load(&meta_path).with_context(|| format!("Invalid {}", meta_path.display()))?;
+        folders.push(entry.file_name());
+    }
+
+    // The import decodes everything before writing, so a bad state changes nothing
+    let previous = backup::write_backup(kv, &paths.backups)?;
+    backup::rotate(&paths.backups, keep);
+    kv.import_snapshot(&snapshot)?;
+
+    let jobs = staging.join(JOBS).is_file();
+    if jobs {
+        replace_file(&staging.join(JOBS), &paths.jobs)?;
+    }
+
+    let mut settings = vec![];
+    for entry in fs::read_dir(staging.join(SETTINGS_DIR)).into_iter().flatten() {
+        let entry = entry?;
+        match settings_files
+            .iter()
+            .find(|f| f.file_name() == Some(&entry.file_name()))
+        {
+            Some(target) => {
+                replace_file(&entry.path(), target)?;
+                settings.push(target.clone());
+            }
+            None => tracing::warn!("Skipping unused settings file {:?}", entry.file_name()),
+        }
+    }
+
+    for folder in &folders {
+        move_tree(&playlists_dir.join(folder), &paths.playlists.join(folder))?;
+    }
+    if staging.join(INSERTS_DIR).is_dir() {
+        move_tree(&staging.join(INSERTS_DIR), &paths.inserts)?;
+    }
+
+    tracing::info!(
+        "Restored backup of {} ({} playlists, previous state in {})",
+        manifest.created_at,
+        folders.len(),
+        previous.display()
+    );
+    Ok(RestoreSummary {
+        created_at: manifest.created_at,
+        previous,
+        playlists: folders.len(),
+        settings,
+        jobs,
+    })
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_slice(&data).with_context(|| format!("Invalid {}", path.display()))
+}
+
+/// Copy `from` over `to` through a temp file, so `to` is never half written.
+fn replace_file(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = PathBuf::from(format!("{}.tmp", to.display()));
+    fs::copy(from, &tmp).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, to).with_context(|| format!("Failed to replace {}", to.display()))?;
+    Ok(())
+}
+
+/// Move the files of `from` into `to`, replacing files with the same path. Other files of `to` stay.
+fn move_tree(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            move_tree(&entry.path(), &target)?;
+        } else if fs::rename(entry.path(), &target).is_err() {
+            // Across filesystems
+            replace_file(&entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
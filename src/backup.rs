@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::settings::BackupSettings;
+use crate::state::State as Kv;
+
+const BACKUP_PREFIX: &str = "state-";
+
+/// Write a snapshot of the state db to `dir`, returning its path.
+pub fn write_backup(kv: &Kv, dir: &Path) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let snapshot = kv.export_snapshot()?;
+    let path = dir.join(format!(
+        "{BACKUP_PREFIX}{}.json",
+        snapshot.exported_at.format("%Y%m%dT%H%M%SZ")
+    ));
+
+    // Write then rename, so a partial backup is never picked up
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(&snapshot)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+/// Backups in `dir`, oldest first.
+pub fn list_backups(dir: &Path) -> Vec<PathBuf> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .map(|n| n.to_string_lossy())
+                .is_some_and(|n| n.starts_with(BACKUP_PREFIX) && n.ends_with(".json"))
+        })
+        .collect();
+    backups.sort();
+    backups
+}
+
+/// Remove all but the newest `keep` backups.
+pub fn rotate(dir: &Path, keep: usize) {
+    let backups = list_backups(dir);
+    if backups.len() > keep {
+        for path in &backups[..backups.len() - keep] {
+            if let Err(error) = fs::remove_file(path) {
+                tracing::warn!("Failed to remove backup {}: {}", path.display(), error);
+            }
+        }
+    }
+}
+
+/// Back up the state db every `interval_secs`, keeping the newest `keep`.
+pub fn spawn_periodic(kv: Arc<Kv>, dir: PathBuf, settings: BackupSettings) {
+    if !settings.enable {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(settings.interval_secs)).await;
+
+            let kv = kv.clone();
+            let backup_dir = dir.clone();
+            let res = tokio::task::spawn_blocking(move || write_backup(&kv, &backup_dir)).await;
+            match res {
+                Ok(Ok(path)) => {
+                    tracing::info!("Backed up state to {}", path.display());
+                    rotate(&dir, settings.keep);
+                }
+                Ok(Err(error)) => tracing::warn!("State backup failed: {error:#}"),
+                Err(error) => tracing::warn!("State backup failed: {error}"),
+            }
+        }
+    });
+}
@@ -1,8 +1,14 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
-use serde_json::json;
+use serde_json::{Value, json};
+use tokio::io::AsyncWriteExt;
 
 use crate::downloader::DownloaderKind;
+use crate::init::InitArgs;
 use crate::player::SetPlaylistMode;
+use crate::playlist::export::{ExportFormat, ExportPaths};
+use crate::settings::Overrides;
 
 const DEFAULT_HOST: &str = "http://127.0.0.1:8371";
 
@@ -12,19 +18,41 @@ const DEFAULT_HOST: &str = "http://127.0.0.1:8371";
 pub struct Cli {
     #[command(subcommand)]
     pub cmd: Command,
+    /// API key of the daemon, for commands via HTTP API
+    #[arg(long, global = true, env = "MUSICD_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Run the daemon (web + player)
-    Start,
+    Start(StartArgs),
+    /// Write an initial settings.json
+    Init(InitArgs),
+    /// Run the daemon as a Windows service
+    #[cfg(windows)]
+    #[command(subcommand)]
+    Service(crate::service::ServiceCommand),
     /// Print current status via HTTP API
     Status {
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
+    /// Print the tracks played, newest first, via HTTP API
+    History {
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Skip this many of the newest tracks
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        #[arg(long, default_value = DEFAULT_HOST)]
+        host: String,
+    },
     /// Print jobs via HTTP API
     Jobs {
+        /// Print jobs file errors instead
+        #[arg(long)]
+        errors: bool,
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
@@ -38,6 +66,31 @@ pub enum Command {
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
+    /// Import an M3U/M3U8 or PLS playlist file or URL via HTTP API
+    Import {
+        file: String,
+        /// Defaults to the playlist name in the file, or the file name
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, value_enum)]
+        downloader: Option<DownloaderKind>,
+        #[arg(long, default_value = DEFAULT_HOST)]
+        host: String,
+    },
+    /// Export a playlist via HTTP API, to stdout or `--output`
+    Export {
+        id: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::M3u8)]
+        format: ExportFormat,
+        #[arg(long, value_enum, default_value_t = ExportPaths::Relative)]
+        paths: ExportPaths,
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value = DEFAULT_HOST)]
+        host: String,
+    },
+    /// Print the JSON Schema of `settings`, `jobs`, `playlist` or `manifest`
+    Schema { name: String },
     /// Clean unused files and directories
     Clean {
         #[arg(long, default_value = DEFAULT_HOST)]
@@ -63,9 +116,10 @@ pub enum Command {
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
-    /// Seek to position via HTTP API
+    /// Seek to position via HTTP API: `30`, relative `+30` / `-15`, or `50%`
     Seek {
-        secs: u64,
+        #[arg(allow_hyphen_values = true, value_parser = parse_seek)]
+        position: Value,
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
@@ -83,27 +137,130 @@ pub enum Command {
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
+    /// Rate the current track (1-5, 0 clears) via HTTP API
+    Rate {
+        rating: u8,
+        #[arg(long, default_value = DEFAULT_HOST)]
+        host: String,
+    },
     /// Switch to a track index via HTTP API
     Track {
         idx: String,
         #[arg(long, default_value = DEFAULT_HOST)]
         host: String,
     },
+    /// Save a full backup (tar.gz) of the data dir and settings via HTTP API
+    Backup {
+        file: PathBuf,
+        /// Include the track files of playlists
+        #[arg(long)]
+        audio: bool,
+        #[arg(long, default_value = DEFAULT_HOST)]
+        host: String,
+    },
+    /// Restore a full backup via HTTP API
+    Restore {
+        file: PathBuf,
+        #[arg(long, default_value = DEFAULT_HOST)]
+        host: String,
+    },
+}
+
+/// Overrides for settings files and environment variables
+#[derive(clap::Args, Debug, Clone)]
+pub struct StartArgs {
+    /// Settings file to use instead of `settings.json`
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[arg(long)]
+    host: Option<String>,
+    #[arg(long)]
+    port: Option<u16>,
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// Don't start playing on start
+    #[arg(long)]
+    no_autoplay: bool,
+}
+
+impl StartArgs {
+    pub fn overrides(&self) -> Overrides {
+        Overrides {
+            config: self.config.clone(),
+            host: self.host.clone(),
+            port: self.port,
+            data_dir: self.data_dir.clone(),
+            auto_play: self.no_autoplay.then_some(false),
+        }
+    }
+}
+
+/// The body of `/control/seek` for `30`, `+30`, `-15` or `50%`.
+fn parse_seek(position: &str) -> Result<Value, String> {
+    let invalid = || format!("invalid position {position:?}, expected eg. 30, +30, -15 or 50%");
+    if let Some(percent) = position.strip_suffix('%') {
+        let percent: f32 = percent.parse().map_err(|_| invalid())?;
+        if !(0.0..=100.0).contains(&percent) {
+            return Err("percent must be between 0 and 100".to_string());
+        }
+        return Ok(json!({"percent": percent}));
+    }
+    if position.starts_with(['+', '-']) {
+        let secs: i64 = position.parse().map_err(|_| invalid())?;
+        return Ok(json!({"relative_secs": secs}));
+    }
+    let secs: u64 = position.parse().map_err(|_| invalid())?;
+    Ok(json!({"secs": secs}))
+}
+
+/// HTTP client sending `api_key` as a bearer token.
+fn client(api_key: Option<&str>) -> anyhow::Result<reqwest::Client> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(key) = api_key {
+        let mut value = reqwest::header::HeaderValue::from_str(&format!("Bearer {key}"))?;
+        value.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, value);
+    }
+    Ok(reqwest::Client::builder().default_headers(headers).build()?)
 }
 
 impl Command {
-    pub async fn run(self) -> anyhow::Result<()> {
+    pub async fn run(self, api_key: Option<&str>) -> anyhow::Result<()> {
+        let c = client(api_key)?;
         match self {
-            Command::Start => Ok(()),
+            Command::Start(_) => Ok(()),
+            #[cfg(windows)]
+            Command::Service(_) => Ok(()),
+            Command::Init(args) => crate::init::run(&args),
+            Command::Schema { name } => {
+                let Some(data) = crate::schema::get(&name) else {
+                    anyhow::bail!(
+                        "unknown schema {name}, expected one of: {}",
+                        crate::schema::NAMES.join(", ")
+                    );
+                };
+                println!("{}", String::from_utf8_lossy(&data));
+                Ok(())
+            }
             Command::Status { host } => {
                 let url = format!("{host}/status");
-                let s = reqwest::get(url).await?.text().await?;
+                let s = c.get(url).send().await?.text().await?;
+                println!("{s}");
+                Ok(())
+            }
+            Command::History { limit, offset, host } => {
+                let url = format!("{host}/history?limit={limit}&offset={offset}");
+                let s = c.get(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
-            Command::Jobs { host } => {
-                let url = format!("{host}/jobs");
-                let s = reqwest::get(url).await?.text().await?;
+            Command::Jobs { errors, host } => {
+                let url = if errors {
+                    format!("{host}/jobs/errors")
+                } else {
+                    format!("{host}/jobs")
+                };
+                let s = c.get(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
@@ -114,58 +271,100 @@ impl Command {
                 host,
             } => {
                 let url = format!("{host}/publish");
-                let c = reqwest::Client::new();
                 let b = json!({"name": name, "source_urls": source_urls, "downloader": downloader});
                 let s = c.post(url).json(&b).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
+            Command::Import {
+                file,
+                name,
+                downloader,
+                host,
+            } => {
+                let b = if file.starts_with("http://") || file.starts_with("https://") {
+                    // Fetched by the daemon, which names it after the file's #PLAYLIST
+                    json!({"name": name, "url": file, "downloader": downloader})
+                } else {
+                    let file = PathBuf::from(file);
+                    let content = tokio::fs::read_to_string(&file).await?;
+                    let base_dir = std::path::absolute(&file)?.parent().map(|p| p.to_path_buf());
+                    let name = name.or_else(|| {
+                        let parsed = crate::playlist::import::parse(&content);
+                        parsed
+                            .name
+                            .or_else(|| file.file_stem().map(|s| s.to_string_lossy().to_string()))
+                    });
+                    json!({"name": name, "content": content, "base_dir": base_dir, "downloader": downloader})
+                };
+
+                let url = format!("{host}/playlists/import");
+                let s = c.post(url).json(&b).send().await?.text().await?;
+                println!("{s}");
+                Ok(())
+            }
+            Command::Export {
+                id,
+                format,
+                paths,
+                output,
+                host,
+            } => {
+                let url = format!("{host}/playlists/{id}/export");
+                let res = c
+                    .get(url)
+                    .query(&json!({"format": format, "paths": paths}))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                let body = res.bytes().await?;
+                match output {
+                    Some(file) => {
+                        tokio::fs::write(&file, &body).await?;
+                        println!("Saved playlist to {}", file.display());
+                    }
+                    None => print!("{}", String::from_utf8_lossy(&body)),
+                }
+                Ok(())
+            }
             Command::Clean { host } => {
                 let url = format!("{host}/clean");
-                let c = reqwest::Client::new();
                 let s = c.post(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
             Command::Play { host } => {
                 let url = format!("{host}/control/play");
-                let c = reqwest::Client::new();
                 let s = c.post(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
             Command::Pause { host } => {
                 let url = format!("{host}/control/pause");
-                let c = reqwest::Client::new();
                 let s = c.post(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
             Command::Prev { host } => {
                 let url = format!("{host}/control/prev");
-                let c = reqwest::Client::new();
                 let s = c.post(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
             Command::Next { host } => {
                 let url = format!("{host}/control/next");
-                let c = reqwest::Client::new();
                 let s = c.post(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
-            Command::Seek { secs, host } => {
+            Command::Seek { position, host } => {
                 let url = format!("{host}/control/seek");
-                let c = reqwest::Client::new();
-                let b = &json!({"secs": secs});
-                let s = c.post(url).json(&b).send().await?.text().await?;
+                let s = c.post(url).json(&position).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
             Command::Volume { value, host } => {
                 let url = format!("{host}/control/volume");
-                let c = reqwest::Client::new();
                 let b = json!({"value": value});
                 let s = c.post(url).json(&b).send().await?.text().await?;
                 println!("{s}");
@@ -173,19 +372,42 @@ impl Command {
             }
             Command::Playlist { id, mode, host } => {
                 let url = format!("{host}/control/playlist/{id}");
-                let c = reqwest::Client::new();
                 let b = json!({"mode": mode});
                 let s = c.post(url).json(&b).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
+            Command::Rate { rating, host } => {
+                let url = format!("{host}/control/rating");
+                let b = json!({"rating": (rating > 0).then_some(rating)});
+                let s = c.post(url).json(&b).send().await?.text().await?;
+                println!("{s}");
+                Ok(())
+            }
             Command::Track { idx, host } => {
                 let url = format!("{host}/control/track/{idx}");
-                let c = reqwest::Client::new();
                 let s = c.post(url).send().await?.text().await?;
                 println!("{s}");
                 Ok(())
             }
+            Command::Backup { file, audio, host } => {
+                let url = format!("{host}/admin/backup?audio={audio}");
+                let mut res = c.post(url).send().await?.error_for_status()?;
+                let mut out = tokio::fs::File::create(&file).await?;
+                while let Some(chunk) = res.chunk().await? {
+                    out.write_all(&chunk).await?;
+                }
+                out.flush().await?;
+                println!("Saved backup to {}", file.display());
+                Ok(())
+            }
+            Command::Restore { file, host } => {
+                let url = format!("{host}/admin/restore");
+                let body = tokio::fs::File::open(&file).await?;
+                let s = c.post(url).body(body).send().await?.text().await?;
+                println!("{s}");
+                Ok(())
+            }
         }
     }
 }
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{Value, json};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast;
+
+use crate::notifier::{Notification, Notifier};
+use crate::player::PlayerHandle;
+use crate::settings::DiscordSettings;
+
+/// Discord listens on the first free of `discord-ipc-0` to `discord-ipc-9`.
+const MAX_PIPES: u8 = 10;
+const RECONNECT_SECS: u64 = 15;
+/// Changes arriving within this are sent as one update, Discord limits how often presence can change.
+const DEBOUNCE_MS: u64 = 1000;
+/// Longest text Discord shows in `details` and `state`.
+const MAX_TEXT: usize = 128;
+/// Larger frames are not from Discord.
+const MAX_FRAME: u32 = 64 * 1024;
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const OP_CLOSE: u32 = 2;
+
+trait Ipc: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Ipc for T {}
+
+#[cfg(unix)]
+async fn connect() -> anyhow::Result<Box<dyn Ipc>> {
+    let dir = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(std::env::var_os)
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| "/tmp".into());
+    for i in 0..MAX_PIPES {
+        if let Ok(stream) = tokio::net::UnixStream::connect(dir.join(format!("discord-ipc-{i}"))).await {
+            return Ok(Box::new(stream));
+        }
+    }
+    anyhow::bail!("Discord is not running (no discord-ipc socket in {})", dir.display())
+}
+
+#[cfg(windows)]
+async fn connect() -> anyhow::Result<Box<dyn Ipc>> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    for i in 0..MAX_PIPES {
+        if let Ok(pipe) = ClientOptions::new().open(format!(r"\\.\pipe\discord-ipc-{i}")) {
+            return Ok(Box::new(pipe));
+        }
+    }
+    anyhow::bail!("Discord is not running (no discord-ipc pipe)")
+}
+
+async fn send(ipc: &mut Box<dyn Ipc>, op: u32, payload: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(payload)?;
+    let mut frame = Vec::with_capacity(8 + body.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&body);
+    ipc.write_all(&frame).await?;
+    Ok(())
+}
+
+async fn recv(ipc: &mut Box<dyn Ipc>) -> anyhow::Result<Value> {
+    let mut header = [0u8; 8];
+    ipc.read_exact(&mut header).await?;
+    let op = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if len > MAX_FRAME {
+        anyhow::bail!("Frame of {len} bytes from Discord");
+    }
+    let mut body = vec![0u8; len as usize];
+    ipc.read_exact(&mut body).await?;
+    let payload: Value = serde_json::from_slice(&body)?;
+    if op == OP_CLOSE {
+        anyhow::bail!("Discord closed the connection: {}", payload["message"]);
+    }
+    if payload["evt"] == "ERROR" {
+        anyhow::bail!("Discord refused the update: {}", payload["data"]["message"]);
+    }
+    Ok(payload)
+}
+
+/// Cut to what Discord shows, padded as it also refuses texts shorter than 2 characters.
+fn truncate(text: &str) -> String {
+    let text: String = text.chars().take(MAX_TEXT).collect();
+    if text.chars().count() < 2 {
+        format!("{text}  ")
+    } else {
+        text
+    }
+}
+
+/// The current track, or none when nothing is loaded.
+fn activity(player: &PlayerHandle) -> Value {
+    let Ok(status) = player.status() else {
+        return Value::Null;
+    };
+    let Some(track) = status.current_meta.map(|t| t.display_name()) else {
+        return Value::Null;
+    };
+
+    let playlist = status.playlist_name.unwrap_or_default();
+    let mut activity = json!({
+        "type": 2,
+        "details": truncate(&track),
+        "state": truncate(&playlist),
+    });
+    if status.is_paused == Some(true) {
+        activity["state"] = json!(truncate(&format!("Paused · {playlist}")));
+    } else if let Some(pos) = status.current_pos {
+        let now = chrono::Utc::now().timestamp_millis();
+        let start = now - pos.as_millis() as i64;
+        activity["timestamps"] = json!({"start": start});
+        if let Some(total) = status.total_duration {
+            activity["timestamps"]["end"] = json!(start + total.as_millis() as i64);
+        }
+    }
+    activity
+}
+
+async fn set_activity(ipc: &mut Box<dyn Ipc>, player: &PlayerHandle) -> anyhow::Result<()> {
+    let payload = json!({
+        "cmd": "SET_ACTIVITY",
+        "args": {"pid": std::process::id(), "activity": activity(player)},
+        "nonce": uuid::Uuid::new_v4().to_string(),
+    });
+    send(ipc, OP_FRAME, &payload).await?;
+    recv(ipc).await?;
+    Ok(())
+}
+
+async fn run(
+    client_id: &str,
+    player: &PlayerHandle,
+    rx: &mut broadcast::Receiver<crate::notifier::Event>,
+) -> anyhow::Result<()> {
+    let mut ipc = connect().await?;
+    send(&mut ipc, OP_HANDSHAKE, &json!({"v": 1, "client_id": client_id})).await?;
+    recv(&mut ipc).await.context("Handshake failed")?;
+    tracing::info!("Showing the current track on Discord");
+
+    loop {
+        set_activity(&mut ipc, player).await?;
+
+        match rx.recv().await {
+            Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        }
+        tokio::time::sleep(Duration::from_millis(DEBOUNCE_MS)).await;
+        // Skip what came in meanwhile, the activity is read fresh anyway
+        loop {
+            match rx.try_recv() {
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                Err(broadcast::error::TryRecvError::Empty) => break,
+                Err(broadcast::error::TryRecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Publish the current track and playlist to the local Discord app, reconnecting when it restarts.
+pub fn spawn(settings: &DiscordSettings, notifier: &Notifier, player: PlayerHandle) {
+    if !settings.enable {
+        return;
+    }
+    let Some(client_id) = settings.client_id.clone() else {
+        tracing::warn!("discord.enable is set without discord.client_id");
+        return;
+    };
+
+    let mut rx = notifier.subscribe_filtered(|n| {
+        matches!(
+            n,
+            Notification::Played
+                | Notification::Paused
+                | Notification::TrackChanged { .. }
+                | Notification::TrackDurationChanged { .. }
+                | Notification::PlaylistChanged(_)
+        )
+    });
+    tokio::spawn(async move {
+        let mut last_error = None;
+        loop {
+            match run(&client_id, &player, &mut rx).await {
+                Ok(()) => return,
+                Err(error) => {
+                    // Discord not running is the usual case, log each new reason once
+                    let message = format!("{error:#}");
+                    if last_error.as_ref() != Some(&message) {
+                        tracing::info!("Discord Rich Presence unavailable: {message}");
+                        last_error = Some(message);
+                    }
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(RECONNECT_SECS)).await;
+        }
+    });
+}
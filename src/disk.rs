@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::notifier::{Notification, Notifier, ProblemKind};
+use crate::settings::DiskSettings;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Fail when less than `min_free_bytes` are free on the disk holding `dir`, so downloads don't fill it up.
+pub fn ensure_free(dir: &Path, min_free_bytes: u64) -> anyhow::Result<()> {
+    match fs2::available_space(dir) {
+        Ok(free) if free < min_free_bytes => anyhow::bail!(
+            "Only {} MiB free on the disk holding {}, at least {} MiB are needed",
+            free / MIB,
+            dir.display(),
+            min_free_bytes / MIB
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Check the free space of `dir` periodically, notifying once each time it runs low.
+pub fn spawn_monitor(notifier: Notifier, dir: PathBuf, settings: DiskSettings) {
+    if settings.check_interval_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut low = false;
+        loop {
+            if let Ok(free_bytes) = fs2::available_space(&dir) {
+                let is_low = free_bytes < settings.low_free_bytes;
+                if is_low && !low {
+                    let message = format!("{} MiB free on the disk holding {}", free_bytes / MIB, dir.display());
+                    tracing::warn!("Disk almost full: {message}");
+                    notifier.notify(Notification::DiskSpaceLow {
+                        free_bytes,
+                        path: dir.clone(),
+                    });
+                    notifier.warning(ProblemKind::DiskAlmostFull, message);
+                }
+                low = is_low;
+            }
+
+            tokio::time::sleep(Duration::from_secs(settings.check_interval_secs)).await;
+        }
+    });
+}
@@ -0,0 +1,212 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::Url;
+use tokio::io::AsyncWriteExt;
+use tokio::{fs, process::Command};
+use tokio_util::sync::CancellationToken;
+
+use super::{Downloader, Progress, ProgressFn};
+
+use crate::playlist::names::normalize_file_name;
+use crate::playlist::{PlaylistMeta, TrackMeta, is_audio_file};
+use crate::settings::DownloaderSettings;
+
+const CONNECT_TIMEOUT_SECS: u64 = 15;
+
+/// Only network inputs for ffmpeg, a source must not read local files.
+const PROTOCOL_WHITELIST: &str = "http,https,tcp,tls,crypto";
+
+/// Downloads direct audio URLs: files with an audio extension over plain HTTP, anything else
+/// (streams, HLS, Icecast archives) recorded by ffmpeg into m4a.
+pub struct FfmpegDownloader;
+
+/// Normalized name of the last path segment of `url`.
+fn file_name(url: &Url) -> String {
+    let segment = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("track");
+    normalize_file_name(OsStr::new(segment))
+}
+
+/// Write the body of `url` to `path`, reporting the percent received when the length is known.
+async fn fetch(
+    client: &reqwest::Client,
+    url: Url,
+    path: &Path,
+    report: impl Fn(f32),
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let mut resp = client.get(url).send().await?.error_for_status()?;
+    let total = resp.content_length().filter(|&n| n > 0);
+    let mut file = fs::File::create(path).await?;
+    let mut received = 0u64;
+    let mut reported = None;
+    loop {
+        let chunk = tokio::select! {
+            chunk = resp.chunk() => chunk?,
+            _ = cancel.cancelled() => anyhow::bail!("Cancelled"),
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+        // Only whole percent changes
+        if let Some(total) = total {
+            let percent = received as f32 / total as f32 * 100.0;
+            if reported != Some(percent as u32) {
+                reported = Some(percent as u32);
+                report(percent);
+            }
+        }
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Record the audio of `url` to `path` with ffmpeg, at most `max_secs` of it.
+async fn record(
+    ffmpeg_path: &Path,
+    url: &str,
+    path: &Path,
+    max_secs: Option<u64>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let mut command = Command::new(ffmpeg_path);
+    command
+        .arg("-nostdin")
+        .arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-y")
+        .arg("-protocol_whitelist")
+        .arg(PROTOCOL_WHITELIST)
+        .arg("-i")
+        .arg(url);
+    if let Some(secs) = max_secs {
+        command.arg("-t").arg(secs.to_string());
+    }
+    let mut child = command
+        .arg("-vn")
+        .arg("-map_metadata")
+        .arg("0")
+        .arg("-c:a")
+        .arg("aac")
+        .arg("-b:a")
+        .arg("192k")
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to spawn ffmpeg from path: {:?}", ffmpeg_path))?;
+
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        _ = cancel.cancelled() => {
+            let _ = child.kill().await;
+            anyhow::bail!("Cancelled");
+        }
+    };
+    if !status.success() {
+        anyhow::bail!("ffmpeg failed with status {status}");
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Downloader for FfmpegDownloader {
+    #[tracing::instrument(name = "download", skip_all, fields(downloader = "ffmpeg", sources = sources.len()))]
+    async fn download_playlist(
+        &self,
+        sources: &[String],
+        dest_dir: &Path,
+        settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let urls = sources
+            .iter()
+            .map(|source| {
+                Url::parse(source)
+                    .ok()
+                    .filter(|u| matches!(u.scheme(), "http" | "https"))
+                    .with_context(|| format!("{source} is not an http(s) URL"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("musicd/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .build()?;
+
+        fs::create_dir_all(dest_dir).await?;
+        let mut tracks = vec![];
+        for (i, (source, url)) in sources.iter().zip(urls).enumerate() {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Cancelled");
+            }
+            let name = file_name(&url);
+            let overall = |percent: f32| Some((i as f32 + percent / 100.0) / sources.len() as f32 * 100.0);
+            progress(Progress {
+                percent: overall(0.0),
+                current: Some(name.clone()),
+            });
+
+            // Plain files as they are, anything else through ffmpeg
+            let direct = settings.ffmpeg.direct && is_audio_file(Path::new(&name));
+            let file = if direct {
+                format!("{:03}-{name}", i + 1)
+            } else {
+                let stem = Path::new(&name).file_stem().unwrap_or_default().to_string_lossy();
+                format!("{:03}-{stem}.m4a", i + 1)
+            };
+            let path = dest_dir.join(&file);
+            let res = if direct {
+                let report = |percent: f32| {
+                    progress(Progress {
+                        percent: overall(percent),
+                        current: Some(name.clone()),
+                    })
+                };
+                fetch(&client, url, &path, report, cancel).await
+            } else {
+                record(&settings.ffmpeg.path, source, &path, settings.ffmpeg.max_secs, cancel).await
+            };
+
+            if let Err(error) = res {
+                fs::remove_file(&path).await.ok();
+                if cancel.is_cancelled() {
+                    anyhow::bail!("Cancelled");
+                }
+                tracing::warn!("Failed to download {source}: {error:#}");
+                continue;
+            }
+            let mut track = TrackMeta::new(file);
+            track.source_url = Some(source.clone());
+            tracks.push(track);
+        }
+
+        if tracks.is_empty() {
+            anyhow::bail!("no audio tracks were downloaded");
+        }
+
+        // Caller rewrites id/name in finalize
+        let meta = PlaylistMeta {
+            id: "TBD".into(),
+            name: "TBD".into(),
+            created_at: chrono::Utc::now(),
+            sources: sources.to_vec(),
+            tracks,
+            ..Default::default()
+        };
+        meta.save_async(&dest_dir.join("playlist.json")).await
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use tokio::fs;
+use tokio_util::sync::CancellationToken;
+
+use super::{Downloader, Progress, ProgressFn};
+
+use crate::playlist::import::is_within_roots;
+use crate::playlist::merge::link_or_copy;
+use crate::playlist::{PlaylistMeta, TrackMeta, is_audio_file, watch};
+use crate::settings::DownloaderSettings;
+
+/// "Downloads" audio files already on disk: each source is a directory, searched recursively,
+/// or a single audio file. Sources must be within `roots` (`publish.local_roots`).
+pub struct LocalDirDownloader {
+    pub roots: Vec<PathBuf>,
+}
+
+impl LocalDirDownloader {
+    /// Audio files of all `sources`, in order.
+    fn files(&self, sources: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        for source in sources {
+            let path = PathBuf::from(source);
+            if !is_within_roots(&path, &self.roots) {
+                anyhow::bail!("{source} is not within publish.local_roots");
+            }
+            if path.is_dir() {
+                files.extend(watch::scan(&path));
+            } else if path.is_file() && is_audio_file(&path) {
+                files.push(path);
+            } else {
+                anyhow::bail!("{source} is not a directory or audio file");
+            }
+        }
+        Ok(files)
+    }
+}
+
+#[async_trait]
+impl Downloader for LocalDirDownloader {
+    #[tracing::instrument(name = "download", skip_all, fields(downloader = "local-dir", sources = sources.len()))]
+    async fn download_playlist(
+        &self,
+        sources: &[String],
+        dest_dir: &Path,
+        settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let files = self.files(sources)?;
+        if files.is_empty() {
+            anyhow::bail!("no audio files in {}", sources.join(", "));
+        }
+
+        fs::create_dir_all(dest_dir).await?;
+        let mut tracks = vec![];
+        for (i, path) in files.iter().enumerate() {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Cancelled");
+            }
+            progress(Progress {
+                percent: Some(i as f32 / files.len() as f32 * 100.0),
+                current: Some(path.to_string_lossy().to_string()),
+            });
+
+            let Some(file_name) = path.file_name() else {
+                continue;
+            };
+            // Files of different sources and subdirectories may share names, keep them apart and in order
+            let file = format!("{:03}-{}", i + 1, file_name.to_string_lossy());
+            link_or_copy(path, &dest_dir.join(&file), settings.local_dir.link)
+                .await
+                .with_context(|| format!("Failed to copy {}", path.display()))?;
+            tracks.push(TrackMeta::new(file));
+        }
+
+        // Caller rewrites id/name in finalize
+        let meta = PlaylistMeta {
+            id: "TBD".into(),
+            name: "TBD".into(),
+            created_at: chrono::Utc::now(),
+            sources: sources.to_vec(),
+            tracks,
+            ..Default::default()
+        };
+        meta.save_async(&dest_dir.join("playlist.json")).await
+    }
+}
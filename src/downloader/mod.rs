@@ -1,33 +1,120 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
 use crate::settings::DownloaderSettings;
 
+pub mod ffmpeg;
+pub mod local_dir;
+pub mod spotdl;
 pub mod yt_dlp;
 
+use ffmpeg::FfmpegDownloader;
+use local_dir::LocalDirDownloader;
+use spotdl::SpotDlDownloader;
+use yt_dlp::YtDlpDownloader;
+
+/// Progress of a download.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Overall percent of all sources, when known.
+    pub percent: Option<f32>,
+    /// Title of the track being downloaded.
+    pub current: Option<String>,
+}
+
+/// Called as a download progresses.
+pub type ProgressFn<'a> = dyn Fn(Progress) + Send + Sync + 'a;
+
 #[async_trait]
 pub trait Downloader: Send + Sync {
     /// Download a playlist into dest dir atomically (write into tmp then rename).
+    /// Stops and cleans up when `cancel` is cancelled.
     async fn download_playlist(
         &self,
         sources: &[String],
         dest_dir: &Path,
         settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
     ) -> anyhow::Result<()>;
+
+    /// Like `download_playlist`, but skipping the tracks whose `source_id` is in `known`.
+    /// Downloaders that can't tell download everything, which is then diffed by source url or file name.
+    async fn download_new(
+        &self,
+        sources: &[String],
+        dest_dir: &Path,
+        _known: &[String],
+        settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        self.download_playlist(sources, dest_dir, settings, progress, cancel)
+            .await
+    }
+
+    /// The `source_id` of every track in `sources` as they are now, or None when `download_new`
+    /// downloads everything anyway.
+    async fn list_ids(
+        &self,
+        _sources: &[String],
+        _settings: &DownloaderSettings,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<Option<HashSet<String>>> {
+        Ok(None)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, clap::ValueEnum)]
 pub enum DownloaderKind {
     #[serde(rename = "yt-dlp")]
     YtDlp,
+    /// Audio files already on disk, within `local_roots`.
+    #[serde(rename = "local-dir")]
+    LocalDir,
+    /// Direct audio file or stream URLs, downloaded over HTTP or recorded with ffmpeg.
+    #[serde(rename = "ffmpeg")]
+    Ffmpeg,
+    /// Spotify playlists, albums and tracks, found and downloaded by spotdl.
+    #[serde(rename = "spotdl")]
+    #[value(name = "spotdl")]
+    SpotDl,
+}
+
+/// The downloader for `sources` when none was asked for: spotdl for Spotify links, `default` otherwise.
+pub fn resolve(kind: Option<DownloaderKind>, sources: &[String], default: DownloaderKind) -> DownloaderKind {
+    kind.unwrap_or_else(|| {
+        if !sources.is_empty() && sources.iter().all(|s| s.starts_with(spotdl::SPOTIFY_PREFIX)) {
+            DownloaderKind::SpotDl
+        } else {
+            default
+        }
+    })
+}
+
+/// Create the downloader for a kind. Local sources are only read from within `local_roots`.
+pub fn create(kind: &DownloaderKind, local_roots: &[PathBuf]) -> Box<dyn Downloader> {
+    match kind {
+        DownloaderKind::YtDlp => Box::new(YtDlpDownloader),
+        DownloaderKind::LocalDir => Box::new(LocalDirDownloader {
+            roots: local_roots.to_vec(),
+        }),
+        DownloaderKind::Ffmpeg => Box::new(FfmpegDownloader),
+        DownloaderKind::SpotDl => Box::new(SpotDlDownloader),
+    }
 }
 
 impl DownloaderKind {
     pub fn as_str(&self) -> &'static str {
         match self {
             DownloaderKind::YtDlp => "yt-dlp",
+            DownloaderKind::LocalDir => "local-dir",
+            DownloaderKind::Ffmpeg => "ffmpeg",
+            DownloaderKind::SpotDl => "spotdl",
         }
     }
 }
@@ -38,6 +125,9 @@ impl TryFrom<String> for DownloaderKind {
     fn try_from(s: String) -> Result<Self, Self::Error> {
         match s.to_lowercase().as_str() {
             "yt-dlp" => Ok(Self::YtDlp),
+            "local-dir" => Ok(Self::LocalDir),
+            "ffmpeg" => Ok(Self::Ffmpeg),
+            "spotdl" => Ok(Self::SpotDl),
             other => Err(format!("{} is not a supported downloader.", other)),
         }
     }
@@ -0,0 +1,144 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::{fs, process::Command};
+use tokio_util::sync::CancellationToken;
+
+use super::{Downloader, Progress, ProgressFn};
+
+use crate::playlist::{PlaylistMeta, TrackMeta, is_audio_file};
+use crate::settings::DownloaderSettings;
+
+/// Sources must be links to Spotify tracks, albums or playlists.
+pub const SPOTIFY_PREFIX: &str = "https://open.spotify.com/";
+
+/// `001-03-Artist - Title.m4a`, the list position is zero padded by spotdl.
+const OUTPUT_TEMPLATE: &str = "{list-position}-{artists} - {title}.{output-ext}";
+
+/// What spotdl prints as it goes, with `--simple-tui`.
+enum Line<'a> {
+    /// `Found 25 songs in My Mix (Playlist)`
+    Found(usize),
+    /// `Downloaded "Artist - Title": https://music.youtube.com/...`, or skipped as it exists.
+    Done(&'a str),
+}
+
+fn parse_line(line: &str) -> Option<Line<'_>> {
+    let line = line.trim();
+    if let Some((count, rest)) = line.strip_prefix("Found ").and_then(|r| r.split_once(' '))
+        && rest.starts_with("song")
+    {
+        return count.parse().ok().map(Line::Found);
+    }
+    if let Some(rest) = line.strip_prefix("Downloaded \"") {
+        return rest.rsplit_once("\":").map(|(title, _)| Line::Done(title));
+    }
+    line.strip_prefix("Skipping ").map(Line::Done)
+}
+
+/// Mirrors Spotify playlists, albums and tracks with [spotdl](https://github.com/spotDL/spotify-downloader),
+/// which finds each track on YouTube Music and downloads it with its Spotify tags.
+pub struct SpotDlDownloader;
+
+#[async_trait]
+impl Downloader for SpotDlDownloader {
+    #[tracing::instrument(name = "download", skip_all, fields(downloader = "spotdl", sources = sources.len()))]
+    async fn download_playlist(
+        &self,
+        sources: &[String],
+        dest_dir: &Path,
+        settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        if let Some(source) = sources.iter().find(|s| !s.starts_with(SPOTIFY_PREFIX)) {
+            anyhow::bail!("{source} is not a Spotify link ({SPOTIFY_PREFIX}...)");
+        }
+
+        fs::create_dir_all(dest_dir).await?;
+        for (i, source) in sources.iter().enumerate() {
+            let output = dest_dir.join(format!("{:03}-{OUTPUT_TEMPLATE}", i + 1));
+            let mut child = Command::new(&settings.spotdl.path)
+                .arg("download")
+                .arg(source)
+                .arg("--output")
+                .arg(&output)
+                .arg("--format")
+                .arg("m4a")
+                .arg("--ffmpeg")
+                .arg(&settings.ffmpeg.path)
+                .arg("--simple-tui")
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit())
+                .kill_on_drop(true)
+                .spawn()
+                .with_context(|| format!("failed to spawn spotdl from path: {:?}", settings.spotdl.path))?;
+
+            // Report each finished track, pass the rest through
+            let stdout = child.stdout.take();
+            let report = async {
+                let Some(stdout) = stdout else {
+                    return;
+                };
+                let mut lines = BufReader::new(stdout).lines();
+                let (mut total, mut done) = (None, 0);
+                while let Ok(Some(line)) = lines.next_line().await {
+                    match parse_line(&line) {
+                        Some(Line::Found(n)) => total = Some(n.max(1)),
+                        Some(Line::Done(title)) => {
+                            done += 1;
+                            let percent = total.map(|n| done.min(n) as f32 / n as f32);
+                            progress(Progress {
+                                percent: percent.map(|p| (i as f32 + p) / sources.len() as f32 * 100.0),
+                                current: Some(title.to_string()),
+                            });
+                        }
+                        None => println!("{line}"),
+                    }
+                }
+            };
+            let cancelled = tokio::select! {
+                _ = report => false,
+                _ = cancel.cancelled() => true,
+            };
+            if cancelled {
+                let _ = child.kill().await;
+                anyhow::bail!("Cancelled");
+            }
+
+            let status = child.wait().await?;
+            if !status.success() {
+                tracing::warn!("spotdl failed with status {}", status);
+            }
+        }
+
+        let mut tracks = vec![];
+        let mut rd = fs::read_dir(dest_dir).await?;
+        while let Some(entry) = rd.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_file() && is_audio_file(&path) {
+                tracks.push(TrackMeta::new(entry.file_name().to_string_lossy().to_string()));
+            }
+        }
+        tracks.sort_by(|a, b| a.file.cmp(&b.file));
+
+        if tracks.is_empty() {
+            anyhow::bail!("no audio tracks were downloaded");
+        }
+
+        // Caller rewrites id/name in finalize
+        let meta = PlaylistMeta {
+            id: "TBD".into(),
+            name: "TBD".into(),
+            created_at: chrono::Utc::now(),
+            sources: sources.to_vec(),
+            tracks,
+            ..Default::default()
+        };
+        meta.save_async(&dest_dir.join("playlist.json")).await
+    }
+}
@@ -3,30 +3,146 @@ use std::{
     process::Stdio,
 };
 
+use std::collections::{HashMap, HashSet};
+
 use anyhow::Context;
 use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::{fs, process::Command};
+use tokio_util::sync::CancellationToken;
 
-use super::Downloader;
+use super::{Downloader, Progress, ProgressFn};
 
+use crate::playlist::{TrackMeta, is_audio_file};
 use crate::settings::DownloaderSettings;
 
+/// Written by yt-dlp with one `<filepath>\t<webpage_url>\t<archive id>` line per downloaded track.
+const SOURCES_FILE: &str = ".sources.tsv";
+
+/// yt-dlp download archive with the ids of the tracks already there, which it skips.
+const ARCHIVE_FILE: &str = ".archive.txt";
+
+/// `youtube dQw4w9WgXcQ`, the way yt-dlp writes its download archive.
+const ARCHIVE_ID_TEMPLATE: &str = "%(extractor_key)s %(id)s";
+
+/// Prefix of the progress lines printed with `--progress-template`.
+const PROGRESS_PREFIX: &str = "[musicd]\t";
+
+/// `<percent>\t<playlist index>\t<playlist count>\t<title>`, index and count are empty for single videos.
+const PROGRESS_TEMPLATE: &str =
+    "download:[musicd]\t%(progress._percent_str)s\t%(info.playlist_index|)s\t%(info.n_entries|)s\t%(info.title)s";
+
+/// Parse a progress line into the overall percent of source `i` of `n`, and the title.
+fn parse_progress(line: &str, i: usize, n: usize) -> Option<Progress> {
+    let mut fields = line.strip_prefix(PROGRESS_PREFIX)?.splitn(4, '\t');
+    let percent: Option<f32> = fields.next()?.trim().trim_end_matches('%').parse().ok();
+    let index: Option<usize> = fields.next()?.parse().ok();
+    let count: Option<usize> = fields.next()?.parse().ok();
+    let title = fields.next().map(|t| t.to_string()).filter(|t| !t.is_empty());
+
+    // Progress within the source, counting finished playlist entries
+    let source_percent = match (index, count) {
+        (Some(index), Some(count)) if count > 0 => {
+            percent.map(|p| (index.saturating_sub(1) as f32 + p / 100.0) / count as f32 * 100.0)
+        }
+        _ => percent,
+    };
+    Some(Progress {
+        percent: source_percent.map(|p| ((i as f32 + p / 100.0) / n as f32 * 100.0).clamp(0.0, 100.0)),
+        current: title,
+    })
+}
+
+/// Archive ids are lower case, as yt-dlp writes them.
+fn archive_id(id: &str) -> Option<String> {
+    let (extractor, id) = id.trim().split_once(' ')?;
+    (!extractor.is_empty() && extractor != "NA" && !id.is_empty()).then(|| format!("{} {id}", extractor.to_lowercase()))
+}
+
+fn yt_dlp_path(settings: &DownloaderSettings) -> PathBuf {
+    settings.yt_dlp.path.clone().unwrap_or("yt-dlp".into())
+}
+
 pub struct YtDlpDownloader;
 
 #[async_trait]
 impl Downloader for YtDlpDownloader {
+    #[tracing::instrument(name = "download", skip_all, fields(downloader = "yt-dlp", sources = sources.len()))]
     async fn download_playlist(
         &self,
         sources: &[String],
         dest_dir: &Path,
         settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        self.download(sources, dest_dir, None, settings, progress, cancel).await
+    }
+
+    #[tracing::instrument(name = "download", skip_all, fields(downloader = "yt-dlp", sources = sources.len(), known = known.len()))]
+    async fn download_new(
+        &self,
+        sources: &[String],
+        dest_dir: &Path,
+        known: &[String],
+        settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        self.download(sources, dest_dir, Some(known), settings, progress, cancel)
+            .await
+    }
+
+    /// Lists the playlists without downloading anything, with `--flat-playlist`.
+    async fn list_ids(
+        &self,
+        sources: &[String],
+        settings: &DownloaderSettings,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Option<HashSet<String>>> {
+        let yt_dlp_path = yt_dlp_path(settings);
+        let mut ids = HashSet::new();
+        for source in sources {
+            let output = Command::new(&yt_dlp_path)
+                .arg("--flat-playlist")
+                .arg("--yes-playlist")
+                .arg("--print")
+                .arg("%(ie_key,extractor_key)s %(id)s")
+                .arg(source)
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .kill_on_drop(true)
+                .output();
+            let output = tokio::select! {
+                output = output => output.context(format!("failed to spawn yt-dlp from path: {:?}", yt_dlp_path))?,
+                _ = cancel.cancelled() => anyhow::bail!("Cancelled"),
+            };
+            if !output.status.success() {
+                anyhow::bail!("yt-dlp failed to list {source} with status {}", output.status);
+            }
+            ids.extend(String::from_utf8_lossy(&output.stdout).lines().filter_map(archive_id));
+        }
+        Ok(Some(ids))
+    }
+}
+
+impl YtDlpDownloader {
+    /// Download `sources` into `dest_dir`, skipping the `known` archive ids when given.
+    async fn download(
+        &self,
+        sources: &[String],
+        dest_dir: &Path,
+        known: Option<&[String]>,
+        settings: &DownloaderSettings,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
     ) -> anyhow::Result<()> {
         if sources.is_empty() {
             return Ok(());
         }
 
         // Find path to yt-dlp
-        let yt_dlp_path = settings.yt_dlp.path.clone().unwrap_or("yt-dlp".into());
+        let yt_dlp_path = yt_dlp_path(settings);
 
         // We assume yt-dlp is installed & in PATH.
         // Strategy: use yt-dlp to extract audio files into dest_dir_tmp,
@@ -37,6 +153,15 @@ impl Downloader for YtDlpDownloader {
         }
         fs::create_dir_all(&tmp).await?;
 
+        let sources_file = tmp.join(SOURCES_FILE);
+        let sources_file_str = sources_file.to_string_lossy().to_string();
+        let archive_file = tmp.join(ARCHIVE_FILE);
+        if let Some(known) = known {
+            let mut archive = known.join("\n");
+            archive.push('\n');
+            fs::write(&archive_file, archive).await?;
+        }
+
         for (i, source) in sources.iter().enumerate() {
             // 001-song.m4a, 002-001-playlist-song.m4a
             let template = "%(playlist_index|)03d%(playlist_index&-|)s%(title).80s.%(ext)s";
@@ -44,43 +169,115 @@ impl Downloader for YtDlpDownloader {
             let out_template_str = out_template.to_string_lossy().to_string();
 
             // Download audio
-            let status = Command::new(&yt_dlp_path)
+            let mut command = Command::new(&yt_dlp_path);
+            if known.is_some() {
+                command.arg("--download-archive").arg(&archive_file);
+            }
+            // Thumbnails next to the tracks become their art when probed, not those of the playlists
+            if settings.yt_dlp.thumbnails {
+                command
+                    .arg("--write-thumbnail")
+                    .arg("--convert-thumbnails")
+                    .arg("jpg")
+                    .arg("--no-write-playlist-metafiles");
+            }
+            let mut child = command
                 .arg("-x")
                 .arg("--audio-format")
                 .arg("m4a")
+                .arg("--embed-metadata")
                 .arg("--yes-playlist")
-                .arg("--no-progress")
+                .arg("--newline")
+                .arg("--progress-template")
+                .arg(PROGRESS_TEMPLATE)
+                .arg("--print-to-file")
+                .arg(format!(
+                    "after_move:%(filepath)s\t%(webpage_url)s\t{ARCHIVE_ID_TEMPLATE}"
+                ))
+                .arg(&sources_file_str)
                 .arg("-o")
                 .arg(&out_template_str)
                 .arg(source)
-                .stdout(Stdio::inherit())
+                .stdout(Stdio::piped())
                 .stderr(Stdio::inherit())
-                .status()
-                .await
+                .spawn()
                 .context(format!("failed to spawn yt-dlp from path: {:?}", yt_dlp_path))?;
 
+            // Report progress lines, pass the rest through
+            let stdout = child.stdout.take();
+            let report = async {
+                let Some(stdout) = stdout else {
+                    return;
+                };
+                let mut lines = BufReader::new(stdout).lines();
+                let mut last: (Option<i32>, Option<String>) = (None, None);
+                while let Ok(Some(line)) = lines.next_line().await {
+                    match parse_progress(&line, i, sources.len()) {
+                        Some(p) => {
+                            // Only whole percent changes
+                            let key = (p.percent.map(|p| p as i32), p.current.clone());
+                            if key != last {
+                                last = key;
+                                progress(p);
+                            }
+                        }
+                        None => println!("{line}"),
+                    }
+                }
+            };
+            let cancelled = tokio::select! {
+                _ = report => false,
+                _ = cancel.cancelled() => true,
+            };
+            if cancelled {
+                let _ = child.kill().await;
+                fs::remove_dir_all(&tmp).await.ok();
+                anyhow::bail!("Cancelled");
+            }
+
+            let status = child.wait().await?;
             if !status.success() {
                 tracing::warn!("yt-dlp failed with status {}", status);
             }
         }
 
+        // Map downloaded file names to their source URL and archive id
+        let downloaded: HashMap<String, (String, Option<String>)> = fs::read_to_string(&sources_file)
+            .await
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.rsplitn(3, '\t');
+                let id = fields.next()?;
+                let url = fields.next()?;
+                let name = Path::new(fields.next()?).file_name()?.to_string_lossy().to_string();
+                Some((name, (url.to_string(), archive_id(id))))
+            })
+            .collect();
+        fs::remove_file(&sources_file).await.ok();
+        fs::remove_file(&archive_file).await.ok();
+
         // Build playlist.json
-        let mut tracks: Vec<String> = vec![];
+        let mut tracks: Vec<TrackMeta> = vec![];
         let mut rd = tokio::fs::read_dir(&tmp).await?;
         while let Some(e) = rd.next_entry().await? {
             if e.file_type().await?.is_file() {
                 let p = e.path();
-                if let Some(ext) = p.extension().and_then(|s| s.to_str())
-                    && matches!(ext, "m4a" | "mp3" | "ogg" | "flac" | "wav" | "aac" | "opus")
-                {
+                if is_audio_file(&p) {
                     let name = p.file_name().unwrap().to_string_lossy().to_string();
-                    tracks.push(name);
+                    let mut track = TrackMeta::new(name);
+                    if let Some((url, id)) = downloaded.get(&track.file) {
+                        track.source_url = Some(url.clone());
+                        track.source_id = id.clone();
+                    }
+                    tracks.push(track);
                 }
             }
         }
-        tracks.sort();
+        tracks.sort_by(|a, b| a.file.cmp(&b.file));
 
-        if tracks.is_empty() {
+        // Nothing new is fine when skipping the known tracks
+        if tracks.is_empty() && known.is_none() {
             anyhow::bail!("no audio tracks were downloaded");
         }
 
@@ -0,0 +1,388 @@
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::source::SeekError;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::EqualizerSettings;
+
+const MIN_BANDS: usize = 5;
+const MAX_BANDS: usize = 10;
+/// Gains are kept within this many dB either way.
+const MAX_GAIN_DB: f32 = 12.0;
+
+/// Played instead of bands that aren't 5 to 10 frequencies.
+const DEFAULT_BANDS_HZ: [f32; 5] = [60.0, 250.0, 1000.0, 4000.0, 12000.0];
+const DEFAULT_Q: f32 = 1.0;
+
+/// Built-in presets as gain (dB) over frequency (Hz), interpolated to the configured bands.
+const BUILTIN_PRESETS: &[(&str, &[(f32, f32)])] = &[
+    ("flat", &[]),
+    ("bass", &[(60.0, 6.0), (250.0, 3.0), (1000.0, 0.0)]),
+    ("treble", &[(2000.0, 0.0), (6000.0, 3.0), (12000.0, 6.0)]),
+    ("vocal", &[(100.0, -2.0), (1000.0, 2.0), (3000.0, 4.0), (8000.0, 0.0)]),
+    ("loudness", &[(60.0, 5.0), (1000.0, 0.0), (12000.0, 4.0)]),
+];
+
+/// The equalizer as played and reported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EqualizerState {
+    pub enabled: bool,
+    /// Preset the gains are from, unset once they were set by hand.
+    pub preset: Option<String>,
+    /// Center frequency of each band (Hz).
+    pub bands_hz: Vec<f32>,
+    /// Gain of each band (dB).
+    pub gains_db: Vec<f32>,
+}
+
+/// A change to the equalizer, anything unset stays.
+#[derive(Debug, Clone, Default)]
+pub struct EqualizerChange {
+    pub enable: Option<bool>,
+    pub preset: Option<String>,
+    pub gains_db: Option<Vec<f32>>,
+}
+
+struct Shared {
+    settings: RwLock<EqualizerSettings>,
+    state: RwLock<EqualizerState>,
+    /// Bumped on every change, so playing sources pick it up.
+    generation: AtomicU64,
+}
+
+/// Gains of the player's equalizer, shared with the sources playing so changes apply live.
+#[derive(Clone)]
+pub struct Equalizer {
+    shared: Arc<Shared>,
+}
+
+/// Gain of `curve` at `hz`, linear over log frequency between its points.
+fn curve_gain(curve: &[(f32, f32)], hz: f32) -> f32 {
+    let (Some(first), Some(last)) = (curve.first(), curve.last()) else {
+        return 0.0;
+    };
+    if hz <= first.0 {
+        return first.1;
+    }
+    curve
+        .windows(2)
+        .find(|w| hz <= w[1].0)
+        .map(|w| {
+            let t = (hz / w[0].0).ln() / (w[1].0 / w[0].0).ln();
+            w[0].1 + (w[1].1 - w[0].1) * t
+        })
+        .unwrap_or(last.1)
+}
+
+/// Gains of preset `name` for the bands of `settings`, configured presets first.
+fn preset_gains(settings: &EqualizerSettings, name: &str) -> Option<Vec<f32>> {
+    if let Some(gains) = settings.presets.get(name) {
+        // Missing bands are flat, extra gains are left out
+        return Some(
+            (0..settings.bands_hz.len())
+                .map(|i| gains.get(i).copied().unwrap_or(0.0).clamp(-MAX_GAIN_DB, MAX_GAIN_DB))
+                .collect(),
+        );
+    }
+    let (_, curve) = BUILTIN_PRESETS.iter().find(|(n, _)| *n == name)?;
+    Some(settings.bands_hz.iter().map(|&hz| curve_gain(curve, hz)).collect())
+}
+
+/// `settings` with 5 to 10 bands of valid frequencies, the default bands otherwise.
+fn checked(mut settings: EqualizerSettings) -> EqualizerSettings {
+    let valid = settings.bands_hz.iter().all(|hz| hz.is_finite() && *hz > 0.0);
+    if !valid || !(MIN_BANDS..=MAX_BANDS).contains(&settings.bands_hz.len()) {
+        tracing::warn!(
+            "Equalizer needs {MIN_BANDS} to {MAX_BANDS} bands above 0 Hz, not {:?}, using the default bands",
+            settings.bands_hz
+        );
+        settings.bands_hz = DEFAULT_BANDS_HZ.to_vec();
+    }
+    if !settings.q.is_finite() || settings.q <= 0.0 {
+        settings.q = DEFAULT_Q;
+    }
+    settings
+}
+
+/// The state `settings` start with.
+fn initial_state(settings: &EqualizerSettings) -> EqualizerState {
+    let gains = preset_gains(settings, &settings.preset);
+    if gains.is_none() {
+        tracing::warn!("Unknown equalizer preset {:?}, playing flat", settings.preset);
+    }
+    EqualizerState {
+        enabled: settings.enable,
+        preset: gains.as_ref().map(|_| settings.preset.clone()),
+        bands_hz: settings.bands_hz.clone(),
+        gains_db: gains.unwrap_or_else(|| vec![0.0; settings.bands_hz.len()]),
+    }
+}
+
+impl Equalizer {
+    pub fn new(settings: &EqualizerSettings) -> Self {
+        let settings = checked(settings.clone());
+        Self {
+            shared: Arc::new(Shared {
+                state: RwLock::new(initial_state(&settings)),
+                settings: RwLock::new(settings),
+                generation: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn state(&self) -> EqualizerState {
+        self.shared.state.read().unwrap().clone()
+    }
+
+    /// Names of the presets, built-in and configured.
+    pub fn presets(&self) -> Vec<String> {
+        let settings = self.shared.settings.read().unwrap();
+        let mut configured: Vec<String> = settings
+            .presets
+            .keys()
+            .filter(|name| BUILTIN_PRESETS.iter().all(|(builtin, _)| builtin != name))
+            .cloned()
+            .collect();
+        configured.sort();
+        BUILTIN_PRESETS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .chain(configured)
+            .collect()
+    }
+
+    /// Apply `change`, returning the new state.
+    pub fn change(&self, change: EqualizerChange) -> Result<EqualizerState, String> {
+        let settings = self.shared.settings.read().unwrap();
+        let mut state = self.shared.state.write().unwrap();
+        let mut next = state.clone();
+        match (change.preset, change.gains_db) {
+            (Some(_), Some(_)) => return Err("Set either a preset or gains, not both".into()),
+            (Some(preset), None) => {
+                next.gains_db = preset_gains(&settings, &preset).ok_or(format!("No equalizer preset {preset:?}"))?;
+                next.preset = Some(preset);
+            }
+            (None, Some(gains)) => {
+                if gains.len() != next.bands_hz.len() {
+                    return Err(format!("Expected {} gains, one per band", next.bands_hz.len()));
+                }
+                next.gains_db = gains.into_iter().map(|g| g.clamp(-MAX_GAIN_DB, MAX_GAIN_DB)).collect();
+                next.preset = None;
+            }
+            (None, None) => {}
+        }
+        if let Some(enable) = change.enable {
+            next.enabled = enable;
+        }
+
+        *state = next.clone();
+        self.shared.generation.fetch_add(1, Ordering::Relaxed);
+        Ok(next)
+    }
+
+    /// Take reloaded settings, starting over from them when they changed.
+    pub fn set_settings(&self, settings: &EqualizerSettings) {
+        let settings = checked(settings.clone());
+        let mut current = self.shared.settings.write().unwrap();
+        if *current == settings {
+            return;
+        }
+        *self.shared.state.write().unwrap() = initial_state(&settings);
+        *current = settings;
+        self.shared.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// `source` as equalized by this equalizer.
+    pub fn apply<S: Source>(&self, source: S) -> Equalized<S> {
+        Equalized {
+            input: source,
+            shared: self.shared.clone(),
+            // Picks up the gains on the first sample
+            generation: u64::MAX,
+            enabled: false,
+            preamp: 1.0,
+            filters: vec![],
+            channels: 0,
+            sample_rate: 0,
+            channel: 0,
+        }
+    }
+}
+
+/// Peaking biquad coefficients, normalized, after the RBJ audio EQ cookbook.
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn peaking(hz: f32, q: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * hz / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / a;
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: -2.0 * cos / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha / a) / a0,
+        }
+    }
+}
+
+/// One band on one channel, in transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    coefficients: Coefficients,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn process(&mut self, x: f32) -> f32 {
+        let c = &self.coefficients;
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+/// A source played through the equalizer, following its changes.
+pub struct Equalized<S> {
+    input: S,
+    shared: Arc<Shared>,
+    generation: u64,
+    enabled: bool,
+    /// Lowers the level by the largest boost, so boosted bands don't clip.
+    preamp: f32,
+    /// Filters of each channel, one per band that isn't flat.
+    filters: Vec<Vec<Biquad>>,
+    channels: u16,
+    sample_rate: u32,
+    /// Channel of the next sample.
+    channel: usize,
+}
+
+impl<S: Source> Equalized<S> {
+    /// Rebuild the filters for the current gains and format, keeping their state where the band stays.
+    fn update(&mut self) {
+        let settings = self.shared.settings.read().unwrap();
+        let state = self.shared.state.read().unwrap();
+        let (channels, sample_rate) = (self.input.channels(), self.input.sample_rate());
+        let nyquist = sample_rate as f32 / 2.0;
+
+        let bands: Vec<Coefficients> = state
+            .bands_hz
+            .iter()
+            .zip(&state.gains_db)
+            .filter(|(hz, gain)| **hz < nyquist && gain.abs() > 0.01)
+            .map(|(hz, gain)| Coefficients::peaking(*hz, settings.q, *gain, sample_rate))
+            .collect();
+        let reset = channels != self.channels
+            || sample_rate != self.sample_rate
+            || self.filters.first().is_none_or(|f| f.len() != bands.len());
+        if reset {
+            self.filters = vec![vec![]; channels as usize];
+        }
+        for filters in self.filters.iter_mut() {
+            if reset {
+                *filters = bands
+                    .iter()
+                    .map(|&coefficients| Biquad {
+                        coefficients,
+                        z1: 0.0,
+                        z2: 0.0,
+                    })
+                    .collect();
+            } else {
+                for (filter, &coefficients) in filters.iter_mut().zip(&bands) {
+                    filter.coefficients = coefficients;
+                }
+            }
+        }
+
+        let boost = state.gains_db.iter().fold(0f32, |max, g| max.max(*g));
+        self.enabled = state.enabled && !bands.is_empty();
+        self.preamp = 10f32.powf(-boost / 20.0);
+        self.channels = channels;
+        self.sample_rate = sample_rate;
+    }
+}
+
+impl<S: Source> Iterator for Equalized<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // Changes and new formats are picked up between frames
+        if self.channel == 0 {
+            let generation = self.shared.generation.load(Ordering::Relaxed);
+            if generation != self.generation
+                || self.input.channels() != self.channels
+                || self.input.sample_rate() != self.sample_rate
+            {
+                self.generation = generation;
+                self.update();
+            }
+        }
+
+        let sample = self.input.next()?;
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.channels.max(1) as usize;
+        if !self.enabled {
+            return Some(sample);
+        }
+
+        match self.filters.get_mut(channel) {
+            Some(filters) => Some(
+                filters
+                    .iter_mut()
+                    .fold(sample * self.preamp, |x, filter| filter.process(x)),
+            ),
+            None => Some(sample),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S: Source> Source for Equalized<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        // Start clean at the new position
+        for filter in self.filters.iter_mut().flatten() {
+            filter.z1 = 0.0;
+            filter.z2 = 0.0;
+        }
+        self.channel = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::fs::{self, File};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crate::player::PlayerHandle;
+use crate::settings::{GpioAction, GpioSettings};
+
+const SYSFS_GPIO: &str = "/sys/class/gpio";
+/// While the encoder turns, its pins are also sampled this often, in case edges come faster than they are read.
+const POLL_MS: u64 = 1;
+/// The encoder counts as turning for this long after its last step.
+const ENCODER_ACTIVE_MS: u64 = 250;
+/// Contacts bounce for a few milliseconds after a press.
+const DEBOUNCE_MS: u64 = 30;
+const STATUS_POLL_MS: u64 = 200;
+/// Wait for udev to hand out permissions on newly exported pins.
+const EXPORT_WAIT_MS: u64 = 1000;
+
+/// Encoder steps for (previous, current) states of pins A and B, as `(a << 1) | b`.
+const QUADRATURE: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+/// A detent of a typical encoder is 4 steps.
+const STEPS_PER_DETENT: i8 = 4;
+
+/// A pin exported through the sysfs GPIO interface. Inputs report changes either way, see [`wait`].
+struct Pin {
+    value: File,
+}
+
+impl Pin {
+    fn open(base: u32, pin: u32, output: bool, active_low: bool) -> anyhow::Result<Self> {
+        let number = base + pin;
+        let dir = PathBuf::from(SYSFS_GPIO).join(format!("gpio{number}"));
+        if !dir.exists() {
+            fs::write(PathBuf::from(SYSFS_GPIO).join("export"), number.to_string())
+                .with_context(|| format!("Failed to export GPIO {pin}"))?;
+        }
+
+        let start = Instant::now();
+        let direction = if output { "out" } else { "in" };
+        while let Err(error) = fs::write(dir.join("direction"), direction) {
+            if start.elapsed() > Duration::from_millis(EXPORT_WAIT_MS) {
+                return Err(anyhow::Error::from(error).context(format!("Failed to set direction of GPIO {pin}")));
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        fs::write(dir.join("active_low"), if active_low { "1" } else { "0" })
+            .with_context(|| format!("Failed to set active_low of GPIO {pin}"))?;
+        if !output {
+            fs::write(dir.join("edge"), "both")
+                .with_context(|| format!("Failed to set edge of GPIO {pin}, does it support interrupts?"))?;
+        }
+
+        let value = File::options()
+            .read(true)
+            .write(output)
+            .open(dir.join("value"))
+            .with_context(|| format!("Failed to open GPIO {pin}"))?;
+        Ok(Self { value })
+    }
+
+    fn is_high(&self) -> bool {
+        let mut buf = [0u8; 1];
+        matches!(self.value.read_at(&mut buf, 0), Ok(1) if buf[0] == b'1')
+    }
+
+    fn set(&self, high: bool) {
+        if let Err(error) = self.value.write_at(if high { b"1" } else { b"0" }, 0) {
+            tracing::warn!("Failed to set GPIO: {error}");
+        }
+    }
+}
+
+/// Block until one of `fds` changes, or `timeout` passes (`None` waits indefinitely). Reading a pin's value
+/// rearms it.
+fn wait(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> std::io::Result<()> {
+    // Rounded up, so a pending debounce doesn't spin on 0
+    let timeout = timeout.map_or(-1, |t| t.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32);
+    // SAFETY: `fds` is a valid slice for the duration of the call
+    if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } == -1 {
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Base of the first GPIO chip, newer kernels no longer number them from 0.
+fn chip_base(settings: &GpioSettings) -> u32 {
+    if let Some(base) = settings.chip_base {
+        return base;
+    }
+
+    fs::read_dir(SYSFS_GPIO)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("gpiochip"))
+        .filter_map(|e| fs::read_to_string(e.path().join("base")).ok())
+        .filter_map(|base| base.trim().parse().ok())
+        .min()
+        .unwrap_or(0)
+}
+
+fn volume_by(player: &PlayerHandle, delta: f32) {
+    // Without the current volume, a step could jump anywhere
+    let Some(volume) = player.status().ok().and_then(|s| s.volume) else {
+        tracing::debug!("Volume unknown, not changing it");
+        return;
+    };
+    player.set_volume(volume + delta);
+}
+
+fn run(player: &PlayerHandle, action: GpioAction, volume_step: f32) {
+    tracing::debug!("GPIO action {:?}", action);
+    match action {
+        GpioAction::Play => player.play(),
+        GpioAction::Pause => player.pause(),
+        GpioAction::PlayPause => {
+            if player.status().ok().and_then(|s| s.is_paused).unwrap_or(true) {
+                player.play();
+            } else {
+                player.pause();
+            }
+        }
+        GpioAction::Next => player.next(),
+        GpioAction::Prev => player.prev(),
+        GpioAction::VolumeUp => volume_by(player, volume_step),
+        GpioAction::VolumeDown => volume_by(player, -volume_step),
+    }
+}
+
+struct Button {
+    pin: Pin,
+    action: GpioAction,
+    pressed: bool,
+    changed_at: Instant,
+}
+
+struct Encoder {
+    a: Pin,
+    b: Pin,
+    state: u8,
+    steps: i8,
+    moved_at: Instant,
+}
+
+impl Encoder {
+    fn read(&self) -> u8 {
+        ((self.a.is_high() as u8) << 1) | self.b.is_high() as u8
+    }
+}
+
+/// Map buttons and a rotary encoder on GPIO pins to player commands, and reflect playback on a status pin.
+pub fn spawn(settings: &GpioSettings, player: PlayerHandle) -> anyhow::Result<()> {
+    if !settings.enable {
+        return Ok(());
+    }
+
+    let base = chip_base(settings);
+    let mut buttons = vec![];
+    for button in &settings.buttons {
+        buttons.push(Button {
+            pin: Pin::open(base, button.pin, false, button.active_low)?,
+            action: button.action,
+            pressed: false,
+            changed_at: Instant::now(),
+        });
+    }
+    let mut encoder = match &settings.encoder {
+        Some(encoder) => {
+            let mut encoder = Encoder {
+                a: Pin::open(base, encoder.a, false, false)?,
+                b: Pin::open(base, encoder.b, false, false)?,
+                state: 0,
+                steps: 0,
+                moved_at: Instant::now(),
+            };
+            encoder.state = encoder.read();
+            Some(encoder)
+        }
+        None => None,
+    };
+    let volume_step = settings.volume_step;
+
+    if !buttons.is_empty() || encoder.is_some() {
+        let player = player.clone();
+        let mut fds: Vec<_> = buttons
+            .iter()
+            .map(|b| &b.pin)
+            .chain(encoder.iter().flat_map(|e| [&e.a, &e.b]))
+            .map(|pin| libc::pollfd {
+                fd: pin.value.as_raw_fd(),
+                events: libc::POLLPRI | libc::POLLERR,
+                revents: 0,
+            })
+            .collect();
+        std::thread::spawn(move || {
+            loop {
+                // Wake up again for a press still bouncing, or the encoder still turning
+                let mut wake: Option<Instant> = None;
+                let now = Instant::now();
+
+                for button in &mut buttons {
+                    let pressed = button.pin.is_high();
+                    if pressed == button.pressed {
+                        continue;
+                    }
+                    let settled = button.changed_at + Duration::from_millis(DEBOUNCE_MS);
+                    if now >= settled {
+                        button.pressed = pressed;
+                        button.changed_at = now;
+                        if pressed {
+                            run(&player, button.action, volume_step);
+                        }
+                    } else {
+                        wake = Some(wake.map_or(settled, |w| w.min(settled)));
+                    }
+                }
+
+                if let Some(encoder) = &mut encoder {
+                    let state = encoder.read();
+                    if state != encoder.state {
+                        encoder.steps += QUADRATURE[((encoder.state << 2) | state) as usize];
+                        encoder.state = state;
+                        encoder.moved_at = now;
+                        if encoder.steps >= STEPS_PER_DETENT {
+                            encoder.steps = 0;
+                            run(&player, GpioAction::VolumeUp, volume_step);
+                        } else if encoder.steps <= -STEPS_PER_DETENT {
+                            encoder.steps = 0;
+                            run(&player, GpioAction::VolumeDown, volume_step);
+                        }
+                    }
+                    if encoder.moved_at.elapsed() < Duration::from_millis(ENCODER_ACTIVE_MS) {
+                        let sample = Instant::now() + Duration::from_millis(POLL_MS);
+                        wake = Some(wake.map_or(sample, |w| w.min(sample)));
+                    }
+                }
+
+                if let Err(error) = wait(&mut fds, wake.map(|w| w.saturating_duration_since(Instant::now()))) {
+                    tracing::error!("GPIO controls stopped, failed to wait for input: {error}");
+                    return;
+                }
+            }
+        });
+    }
+
+    if let Some(status) = &settings.status_pin {
+        let pin = Pin::open(base, status.pin, true, status.active_low)?;
+        std::thread::spawn(move || {
+            let mut playing = None;
+            loop {
+                let now = player.status().ok().map(|s| s.is_paused == Some(false));
+                if now.is_some() && now != playing {
+                    playing = now;
+                    pin.set(now.unwrap_or_default());
+                }
+                std::thread::sleep(Duration::from_millis(STATUS_POLL_MS));
+            }
+        });
+    }
+
+    tracing::info!(
+        "GPIO controls on {} buttons{}{}",
+        settings.buttons.len(),
+        if settings.encoder.is_some() { ", an encoder" } else { "" },
+        if settings.status_pin.is_some() {
+            " and a status pin"
+        } else {
+            ""
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::settings::{Environment, Overrides, Settings};
+
+const SYSTEMD_UNIT_PATH: &str = "/etc/systemd/system/musicd.service";
+
+/// Options of `musicd init`; anything not given is asked for when running in a terminal.
+#[derive(clap::Args, Debug, Clone)]
+pub struct InitArgs {
+    /// Settings file to write, defaults to `settings.json`
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    #[arg(long)]
+    host: Option<String>,
+    #[arg(long)]
+    port: Option<u16>,
+    /// Don't start playing on start
+    #[arg(long)]
+    no_autoplay: bool,
+    #[arg(long)]
+    yt_dlp_path: Option<PathBuf>,
+    /// Install a systemd unit running `musicd start` from the current directory
+    #[arg(long)]
+    systemd: bool,
+    /// Use defaults for anything not given instead of asking
+    #[arg(long, short)]
+    yes: bool,
+    /// Overwrite an existing settings file
+    #[arg(long)]
+    force: bool,
+}
+
+struct Prompt {
+    interactive: bool,
+}
+
+impl Prompt {
+    fn ask(&self, question: &str, default: &str) -> anyhow::Result<String> {
+        if !self.interactive {
+            return Ok(default.to_string());
+        }
+
+        print!("{question} [{default}]: ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        let line = line.trim();
+        Ok(if line.is_empty() { default } else { line }.to_string())
+    }
+
+    fn confirm(&self, question: &str, default: bool) -> anyhow::Result<bool> {
+        let answer = self.ask(question, if default { "Y/n" } else { "y/N" })?;
+        Ok(match answer.to_lowercase().as_str() {
+            "y" | "yes" => true,
+            "n" | "no" => false,
+            _ => default,
+        })
+    }
+}
+
+fn systemd_unit(working_dir: &Path, config: Option<&Path>) -> anyhow::Result<String> {
+    let exe = std::env::current_exe().context("Failed to locate the musicd binary")?;
+    let mut start = format!("{} start", exe.display());
+    if let Some(config) = config {
+        start += &format!(" --config {}", std::path::absolute(config)?.display());
+    }
+    Ok(format!(
+        "[Unit]
+Description=musicd
+After=network-online.target sound.target
+Wants=network-online.target
+
+[Service]
+WorkingDirectory={}
+ExecStart={}
+Restart=on-failure
+
+[Install]
+WantedBy=multi-user.target
+",
+        working_dir.display(),
+        start
+    ))
+}
+
+pub fn run(args: &InitArgs) -> anyhow::Result<()> {
+    let path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| Settings::files(&Overrides::default())[0].clone());
+    if path.exists() && !args.force {
+        anyhow::bail!("{} already exists, use --force to overwrite", path.display());
+    }
+
+    let prompt = Prompt {
+        interactive: !args.yes && std::io::stdin().is_terminal(),
+    };
+
+    let data_dir = match &args.data_dir {
+        Some(dir) => dir.to_string_lossy().to_string(),
+        None => prompt.ask("Data directory", Environment::detect().default_data_dir())?,
+    };
+    let host = match &args.host {
+        Some(host) => host.clone(),
+        None => prompt.ask("Listen on host", "0.0.0.0")?,
+    };
+    let port: u16 = match args.port {
+        Some(port) => port,
+        None => prompt.ask("Port", "8371")?.parse().context("Invalid port")?,
+    };
+    let auto_play = !args.no_autoplay && prompt.confirm("Start playing on start?", true)?;
+    let yt_dlp_path = match &args.yt_dlp_path {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => prompt.ask("Path to yt-dlp", "yt-dlp")?,
+    };
+
+    // Everything else keeps its default
+    let settings = json!({
+        "data_dir": data_dir,
+        "server": {
+            "host": host,
+            "port": port,
+        },
+        "player": {
+            "auto_play": auto_play,
+        },
+        "downloader": {
+            "yt_dlp": {
+                "path": yt_dlp_path,
+            },
+        },
+    });
+    std::fs::write(&path, serde_json::to_string_pretty(&settings)? + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote {}", path.display());
+
+    if args.systemd || (prompt.interactive && prompt.confirm("Install a systemd unit?", false)?) {
+        let working_dir = std::env::current_dir()?;
+        std::fs::write(SYSTEMD_UNIT_PATH, systemd_unit(&working_dir, args.config.as_deref())?)
+            .with_context(|| format!("Failed to write {SYSTEMD_UNIT_PATH} (try with sudo)"))?;
+        println!("Wrote {SYSTEMD_UNIT_PATH}, enable it with:");
+        println!("  systemctl daemon-reload && systemctl enable --now musicd");
+    }
+
+    Ok(())
+}
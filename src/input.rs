@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::player::PlayerHandle;
+use crate::settings::InputSettings;
+
+const INPUT_DIR: &str = "/dev/input";
+/// How often to look for newly plugged in devices.
+const RESCAN_SECS: u64 = 5;
+
+/// `struct input_event`: a `timeval` followed by type, code and value.
+const TIMEVAL_SIZE: usize = 2 * size_of::<usize>();
+const EVENT_SIZE: usize = TIMEVAL_SIZE + 8;
+
+const EV_KEY: u16 = 0x01;
+/// Key presses; releases are 0 and auto repeats 2.
+const KEY_PRESSED: i32 = 1;
+const KEY_REPEATED: i32 = 2;
+
+const KEY_MUTE: u16 = 113;
+const KEY_VOLUMEDOWN: u16 = 114;
+const KEY_VOLUMEUP: u16 = 115;
+const KEY_NEXTSONG: u16 = 163;
+const KEY_PLAYPAUSE: u16 = 164;
+const KEY_PREVIOUSSONG: u16 = 165;
+const KEY_STOPCD: u16 = 166;
+const KEY_PLAYCD: u16 = 200;
+const KEY_PAUSECD: u16 = 201;
+
+/// Volume before muting, restored by the next mute key.
+type Muted = Arc<Mutex<Option<f32>>>;
+
+fn handle_key(player: &PlayerHandle, settings: &InputSettings, muted: &Muted, code: u16, value: i32) {
+    let repeatable = matches!(code, KEY_VOLUMEUP | KEY_VOLUMEDOWN);
+    if value != KEY_PRESSED && !(repeatable && value == KEY_REPEATED) {
+        return;
+    }
+
+    // Without it, volume keys would step from (and mute would restore) a guessed volume
+    let Ok(status) = player.status() else {
+        tracing::debug!("Player status unavailable, ignoring media key {}", code);
+        return;
+    };
+    match code {
+        KEY_PLAYPAUSE => {
+            if status.is_paused.unwrap_or(true) {
+                player.play();
+            } else {
+                player.pause();
+            }
+        }
+        KEY_PLAYCD => player.play(),
+        KEY_PAUSECD | KEY_STOPCD => player.pause(),
+        KEY_NEXTSONG => player.next(),
+        KEY_PREVIOUSSONG => player.prev(),
+        KEY_VOLUMEUP | KEY_VOLUMEDOWN | KEY_MUTE => {
+            let Some(volume) = status.volume else {
+                return;
+            };
+            match code {
+                KEY_VOLUMEUP => player.set_volume(volume + settings.volume_step),
+                KEY_VOLUMEDOWN => player.set_volume(volume - settings.volume_step),
+                _ => {
+                    let mut muted = muted.lock().unwrap();
+                    match muted.take() {
+                        Some(before) => player.set_volume(before),
+                        None => {
+                            *muted = Some(volume);
+                            player.set_volume(0.0);
+                        }
+                    }
+                }
+            }
+        }
+        _ => return,
+    }
+    tracing::debug!("Media key {}", code);
+}
+
+/// Read key events from `path` until the device goes away.
+fn read_device(path: &Path, player: &PlayerHandle, settings: &InputSettings, muted: &Muted) -> std::io::Result<()> {
+    let mut file = File::open(path)?;
+    tracing::info!("Listening for media keys on {}", path.display());
+
+    let mut buf = [0u8; EVENT_SIZE];
+    loop {
+        file.read_exact(&mut buf)?;
+        let kind = u16::from_ne_bytes([buf[TIMEVAL_SIZE], buf[TIMEVAL_SIZE + 1]]);
+        let code = u16::from_ne_bytes([buf[TIMEVAL_SIZE + 2], buf[TIMEVAL_SIZE + 3]]);
+        let value = i32::from_ne_bytes(buf[TIMEVAL_SIZE + 4..].try_into().unwrap());
+        if kind == EV_KEY {
+            handle_key(player, settings, muted, code, value);
+        }
+    }
+}
+
+/// The configured devices, or every event device.
+fn devices(settings: &InputSettings) -> Vec<PathBuf> {
+    if !settings.devices.is_empty() {
+        return settings.devices.clone();
+    }
+
+    std::fs::read_dir(INPUT_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with("event")))
+        .collect()
+}
+
+/// Map media keys of input devices (eg. a USB remote or keyboard) to player commands.
+pub fn spawn(settings: &InputSettings, player: PlayerHandle) {
+    if !settings.enable {
+        return;
+    }
+
+    let settings = settings.clone();
+    std::thread::spawn(move || {
+        let reading: Arc<Mutex<HashSet<PathBuf>>> = Default::default();
+        let muted: Muted = Default::default();
+        let mut failed: HashSet<PathBuf> = HashSet::new();
+        loop {
+            for path in devices(&settings) {
+                if reading.lock().unwrap().contains(&path) || failed.contains(&path) {
+                    continue;
+                }
+
+                // Opened here to report permission errors once, then read on its own thread
+                if let Err(error) = File::open(&path) {
+                    tracing::warn!("Failed to open {}: {error}", path.display());
+                    failed.insert(path);
+                    continue;
+                }
+
+                reading.lock().unwrap().insert(path.clone());
+                let reading = reading.clone();
+                let player = player.clone();
+                let settings = settings.clone();
+                let muted = muted.clone();
+                std::thread::spawn(move || {
+                    if let Err(error) = read_device(&path, &player, &settings, &muted) {
+                        tracing::info!("Stopped listening on {}: {error}", path.display());
+                    }
+                    reading.lock().unwrap().remove(&path);
+                });
+            }
+
+            // Devices that went away may come back with the same name
+            failed.retain(|p| p.exists());
+            std::thread::sleep(Duration::from_secs(RESCAN_SECS));
+        }
+    });
+}
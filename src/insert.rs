@@ -0,0 +1,99 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use lofty::file::AudioFile;
+use serde::Serialize;
+
+use crate::playlist::is_audio_file;
+use crate::settings::InsertSettings;
+
+/// An insert clip in the inserts dir.
+#[derive(Debug, Clone, Serialize)]
+pub struct InsertClip {
+    pub file: String,
+    pub size: u64,
+    pub duration: Option<Duration>,
+}
+
+/// Audio files in `dir`, sorted by name.
+fn clip_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && is_audio_file(p))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Clips in `dir` with their size and duration.
+pub fn list(dir: &Path) -> Vec<InsertClip> {
+    clip_paths(dir)
+        .into_iter()
+        .filter_map(|path| {
+            Some(InsertClip {
+                file: path.file_name()?.to_string_lossy().to_string(),
+                size: fs::metadata(&path).ok()?.len(),
+                duration: lofty::read_from_path(&path).ok().map(|f| f.properties().duration()),
+            })
+        })
+        .collect()
+}
+
+/// Whether `file` can be the name of a clip: a plain audio file name.
+pub fn valid_name(file: &str) -> bool {
+    !file.starts_with('.')
+        && !file.contains(['/', '\\'])
+        && Path::new(file).file_name().is_some_and(|n| n == file)
+        && is_audio_file(Path::new(file))
+}
+
+/// Decides when an insert is due and which clip plays, in name order.
+pub struct InsertScheduler {
+    tracks: u32,
+    since: Instant,
+    next: usize,
+}
+
+impl InsertScheduler {
+    pub fn new() -> Self {
+        Self {
+            tracks: 0,
+            since: Instant::now(),
+            next: 0,
+        }
+    }
+
+    /// Count a track that played to its end, returning whether an insert is due now.
+    pub fn track_ended(&mut self, rules: &InsertSettings) -> bool {
+        let due = self.due_after_track(rules);
+        self.tracks += 1;
+        due
+    }
+
+    /// Whether an insert would be due if the current track ended now.
+    pub fn due_after_track(&self, rules: &InsertSettings) -> bool {
+        let by_tracks = rules.every_tracks.is_some_and(|n| self.tracks + 1 >= n);
+        let by_time = rules
+            .every_minutes
+            .is_some_and(|m| self.since.elapsed() >= Duration::from_secs(m * 60));
+        by_tracks || by_time
+    }
+
+    /// The clip to play next from `dir`, starting the count for the next insert over.
+    pub fn next_clip(&mut self, dir: &Path) -> Option<PathBuf> {
+        self.tracks = 0;
+        self.since = Instant::now();
+
+        let clips = clip_paths(dir);
+        if clips.is_empty() {
+            return None;
+        }
+        let clip = clips[self.next % clips.len()].clone();
+        self.next = (self.next + 1) % clips.len();
+        Some(clip)
+    }
+}
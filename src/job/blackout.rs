@@ -0,0 +1,111 @@
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// Max number of evaluations before giving up on finding an allowed time.
+pub const MAX_ITERATIONS: usize = 1000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlackoutAction {
+    /// Skip the run, moving on to the next repeat (if any).
+    #[default]
+    Skip,
+    /// Defer the run until the blackout ends.
+    Defer,
+}
+
+/// A blackout rule. All specified criteria must match (evaluated in local time).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Blackout {
+    /// Specific dates, eg. public holidays.
+    #[serde(default)]
+    pub dates: Vec<NaiveDate>,
+    /// Days of the week, eg. `["sun"]`.
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    /// Start of a daily time window, eg. `"22:00:00"`.
+    #[serde(default)]
+    pub from: Option<NaiveTime>,
+    /// End of a daily time window, may wrap past midnight.
+    #[serde(default)]
+    pub to: Option<NaiveTime>,
+}
+
+impl Blackout {
+    fn window(&self) -> Option<(NaiveTime, NaiveTime)> {
+        match (self.from, self.to) {
+            (None, None) => None,
+            (from, to) => Some((from.unwrap_or(NaiveTime::MIN), to.unwrap_or(NaiveTime::MIN))),
+        }
+    }
+
+    pub fn matches(&self, at: DateTime<Utc>) -> bool {
+        let window = self.window();
+        if self.dates.is_empty() && self.weekdays.is_empty() && window.is_none() {
+            return false;
+        }
+
+        let local = at.with_timezone(&Local);
+
+        if !self.dates.is_empty() && !self.dates.contains(&local.date_naive()) {
+            return false;
+        }
+
+        if !self.weekdays.is_empty() && !self.weekdays.contains(&local.weekday()) {
+            return false;
+        }
+
+        if let Some((from, to)) = window {
+            let time = local.time();
+            let inside = if from < to {
+                time >= from && time < to
+            } else {
+                // Wraps past midnight (or covers the whole day when equal)
+                time >= from || time < to
+            };
+            if !inside {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// When the blackout covering `at` is over.
+    fn end_after(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let local = at.with_timezone(&Local);
+        let date = local.date_naive();
+
+        let end = match self.window() {
+            Some((from, to)) if from < to || local.time() < to => date.and_time(to),
+            Some((_, to)) => next_day(date).and_time(to),
+            None => next_day(date).and_time(NaiveTime::MIN),
+        };
+
+        match Local.from_local_datetime(&end).earliest() {
+            Some(end) if end > local => end.with_timezone(&Utc),
+            // Fall back to an hour later on DST gaps
+            _ => at + chrono::Duration::hours(1),
+        }
+    }
+}
+
+fn next_day(date: NaiveDate) -> NaiveDate {
+    date.checked_add_days(Days::new(1)).unwrap_or(date)
+}
+
+pub fn is_blacked_out(rules: &[Blackout], at: DateTime<Utc>) -> bool {
+    rules.iter().any(|rule| rule.matches(at))
+}
+
+/// Push `at` forward until no rule applies.
+pub fn defer_until_allowed(rules: &[Blackout], at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut at = at;
+    for _ in 0..MAX_ITERATIONS {
+        match rules.iter().find(|rule| rule.matches(at)) {
+            Some(rule) => at = rule.end_after(at),
+            None => return Some(at),
+        }
+    }
+    None
+}
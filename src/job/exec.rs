@@ -0,0 +1,73 @@
+use std::{process::Stdio, time::Duration};
+
+use anyhow::Context;
+use tokio::process::Command;
+
+use super::Job;
+use crate::settings::ExecSettings;
+
+/// Max bytes of stdout/stderr kept per stream.
+const MAX_OUTPUT_BYTES: usize = 4096;
+
+/// Check that the job's command may be run under the exec settings.
+pub fn check_allowed(command: &str, settings: &ExecSettings) -> Result<(), String> {
+    if !settings.enable {
+        return Err("exec jobs are disabled (job.exec.enable)".into());
+    }
+
+    if !settings.allowlist.iter().any(|allowed| allowed == command) {
+        return Err(format!("command is not in job.exec.allowlist: {command}"));
+    }
+
+    Ok(())
+}
+
+fn truncate(bytes: &[u8]) -> String {
+    let s = String::from_utf8_lossy(bytes);
+    let s = s.trim();
+    if s.len() <= MAX_OUTPUT_BYTES {
+        return s.to_string();
+    }
+
+    let mut end = MAX_OUTPUT_BYTES;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &s[..end])
+}
+
+pub async fn run(job: &Job, settings: &ExecSettings) -> anyhow::Result<String> {
+    let command = job.command.as_deref().unwrap_or_default();
+    check_allowed(command, settings).map_err(anyhow::Error::msg)?;
+
+    let timeout = Duration::from_secs(job.timeout_secs.unwrap_or(settings.default_timeout_secs));
+
+    // Child is killed if the timeout drops the future
+    let output = Command::new(command)
+        .args(&job.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+
+    let output = tokio::time::timeout(timeout, output)
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {} seconds", timeout.as_secs()))?
+        .with_context(|| format!("failed to spawn command: {command}"))?;
+
+    let stdout = truncate(&output.stdout);
+    let stderr = truncate(&output.stderr);
+    if !stdout.is_empty() {
+        tracing::info!("Job [{}] stdout: {}", job.id, stdout);
+    }
+    if !stderr.is_empty() {
+        tracing::warn!("Job [{}] stderr: {}", job.id, stderr);
+    }
+
+    if !output.status.success() {
+        anyhow::bail!("command exited with {}: {}", output.status, stderr);
+    }
+
+    Ok(output.status.to_string())
+}
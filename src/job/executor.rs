@@ -0,0 +1,127 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::oneshot;
+
+/// Bounded executor that hands out slots by job priority (higher first, then FIFO).
+pub struct Executor {
+    max_concurrent: usize,
+    state: Mutex<ExecutorState>,
+}
+
+#[derive(Default)]
+struct ExecutorState {
+    running: usize,
+    seq: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+struct Waiter {
+    priority: i32,
+    seq: u64,
+    tx: oneshot::Sender<Permit>,
+}
+
+impl Waiter {
+    fn key(&self) -> (i32, Reverse<u64>) {
+        (self.priority, Reverse(self.seq))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Slot in the executor, released on drop.
+pub struct Permit {
+    executor: Option<Arc<Executor>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(executor) = self.executor.take() {
+            executor.release();
+        }
+    }
+}
+
+impl Executor {
+    pub fn new(max_concurrent: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max_concurrent: max_concurrent.max(1),
+            state: Mutex::new(ExecutorState::default()),
+        })
+    }
+
+    /// Whether all slots are taken.
+    pub fn is_full(&self) -> bool {
+        self.state.lock().unwrap().running >= self.max_concurrent
+    }
+
+    pub async fn acquire(self: &Arc<Self>, priority: i32) -> Permit {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.max_concurrent {
+                state.running += 1;
+                return Permit {
+                    executor: Some(self.clone()),
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            state.seq += 1;
+            let seq = state.seq;
+            state.waiting.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        // A permit sent to a dropped receiver is dropped with it, so slots never leak
+        rx.await.expect("executor dropped while waiting")
+    }
+
+    fn release(self: &Arc<Self>) {
+        loop {
+            let waiter = {
+                let mut state = self.state.lock().unwrap();
+                match state.waiting.pop() {
+                    Some(waiter) => waiter,
+                    None => {
+                        state.running -= 1;
+                        return;
+                    }
+                }
+            };
+
+            // Hand the slot over directly to the next waiter
+            let permit = Permit {
+                executor: Some(self.clone()),
+            };
+            match waiter.tx.send(permit) {
+                Ok(()) => return,
+                Err(mut permit) => {
+                    // Waiter is gone (eg. job aborted), try the next one without releasing twice
+                    permit.executor = None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,850 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Datelike, Duration, Local, Months, Utc, Weekday};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{task::JoinHandle, time::sleep_until};
+use tracing::Instrument;
+
+use crate::notifier::{Notification, Notifier, ProblemKind};
+use crate::settings::JobSettings;
+use crate::state::{JobRun, State as Kv};
+
+pub mod blackout;
+mod exec;
+mod executor;
+
+use blackout::{Blackout, BlackoutAction};
+use executor::Executor;
+
+const RETRY_DELAY_SECS: u64 = 5;
+/// Prefix of the ids of jobs from the remote manifest.
+pub const REMOTE_JOB_PREFIX: &str = "manifest:";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatTemplate {
+    Daily,
+    Weekdays,
+    Weekends,
+    Weekly,
+    Biweekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Repeat {
+    Template(RepeatTemplate),
+    Custom { frequency: RepeatFrequency, every: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum AlertTarget {
+    /// POST the alert as JSON to a URL.
+    Webhook { url: String },
+    /// Emit a `JOB_ALERT` notification.
+    Notification,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStoreKind {
+    /// Jobs are read from `jobs.json` and reloaded when it changes.
+    #[default]
+    File,
+    /// Jobs are kept in the state DB and managed via the API.
+    Sled,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobType {
+    /// Send an HTTP request.
+    #[default]
+    Http,
+    /// Run a local command (requires `job.exec.enable`).
+    Exec,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub run_at: DateTime<Utc>,
+    #[serde(default)]
+    pub repeat: Option<Repeat>,
+    #[serde(default)]
+    pub end_repeat: Option<DateTime<Utc>>,
+    #[serde(default, rename = "type")]
+    pub job_type: JobType,
+    #[serde(default)]
+    pub method: String,
+    #[serde(default)]
+    pub url: String,
+    pub body: Option<Value>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default)]
+    pub alert: Option<AlertTarget>,
+    #[serde(default)]
+    pub blackouts: Vec<Blackout>,
+    #[serde(default)]
+    pub blackout_action: BlackoutAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobFileError {
+    /// Position of the entry in the jobs file, if the error is entry specific.
+    pub index: Option<usize>,
+    /// Job id, if it could be read.
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl Job {
+    pub fn validate(&self, settings: &JobSettings) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("id must not be empty".into());
+        }
+
+        match self.job_type {
+            JobType::Http => {
+                if !matches!(self.method.to_uppercase().as_str(), "GET" | "POST" | "PUT" | "DELETE") {
+                    return Err(format!("unsupported method: {}", self.method));
+                }
+                if self.url.is_empty() {
+                    return Err("url must not be empty".into());
+                }
+            }
+            JobType::Exec => {
+                let command = self.command.as_deref().unwrap_or_default();
+                if command.is_empty() {
+                    return Err("command must not be empty".into());
+                }
+                exec::check_allowed(command, &settings.exec)?;
+            }
+        }
+
+        if let Some(Repeat::Custom { every: 0, .. }) = self.repeat {
+            return Err("repeat.every must be at least 1".into());
+        }
+
+        if let Some(end_repeat) = self.end_repeat
+            && end_repeat < self.run_at
+        {
+            return Err("end_repeat must not be before run_at".into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct JobManager {
+    pub notifier: Notifier,
+    pub kv: Arc<Kv>,
+    pub jobs: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    pub file_path: PathBuf,
+    pub current_jobs: Arc<Mutex<Vec<Job>>>,
+    pub errors: Arc<Mutex<Vec<JobFileError>>>,
+    pub settings: JobSettings,
+    executor: Arc<Executor>,
+    /// Jobs pushed by the remote manifest, scheduled next to the local ones.
+    remote_jobs: Arc<Mutex<Vec<Job>>>,
+}
+
+fn seconds_until(run_at: DateTime<Utc>) -> i64 {
+    let now = Utc::now();
+    let delta = run_at.signed_duration_since(now);
+    delta.num_seconds()
+}
+
+fn next_run_time(job: &Job, last_run: DateTime<Utc>, always_advance: bool) -> Option<DateTime<Utc>> {
+    let now = Utc::now();
+
+    fn days_until_next(last_run: DateTime<Utc>, now: DateTime<Utc>, every: i64, always_advance: bool) -> i64 {
+        let mut periods = (now.signed_duration_since(last_run).num_days() / every).max(0);
+        if always_advance || last_run <= now {
+            periods += 1;
+        }
+        periods
+    }
+
+    fn weeks_until_next(last_run: DateTime<Utc>, now: DateTime<Utc>, every: i64, always_advance: bool) -> i64 {
+        let mut periods = (now.signed_duration_since(last_run).num_weeks() / every).max(0);
+        if always_advance || last_run <= now {
+            periods += 1;
+        }
+        periods
+    }
+
+    fn months_until_next(last_run: DateTime<Utc>, now: DateTime<Utc>, every: i32, always_advance: bool) -> i32 {
+        let mut months = (now.year() - last_run.year()) * 12 + (now.month() as i32 - last_run.month() as i32);
+        months /= every;
+        if always_advance || last_run.checked_add_months(Months::new((months * every) as u32)) <= Some(now) {
+            months += 1;
+        }
+        months
+    }
+
+    fn next_weekday_after(
+        mut dt: DateTime<Utc>,
+        now: DateTime<Utc>,
+        condition: impl Fn(Weekday) -> bool,
+    ) -> DateTime<Utc> {
+        loop {
+            dt += Duration::days(1);
+            if condition(dt.weekday()) && dt > now {
+                break dt;
+            }
+        }
+    }
+
+    match &job.repeat {
+        Some(Repeat::Template(template)) => match template {
+            RepeatTemplate::Daily => Some(last_run + Duration::days(days_until_next(last_run, now, 1, always_advance))),
+            RepeatTemplate::Weekdays => Some(next_weekday_after(last_run, now, |w| {
+                w != Weekday::Sat && w != Weekday::Sun
+            })),
+            RepeatTemplate::Weekends => Some(next_weekday_after(last_run, now, |w| {
+                w == Weekday::Sat || w == Weekday::Sun
+            })),
+            RepeatTemplate::Weekly => {
+                Some(last_run + Duration::weeks(weeks_until_next(last_run, now, 1, always_advance)))
+            }
+            RepeatTemplate::Biweekly => {
+                Some(last_run + Duration::weeks(weeks_until_next(last_run, now, 2, always_advance) * 2))
+            }
+            RepeatTemplate::Monthly => {
+                let months = months_until_next(last_run, now, 1, always_advance);
+                last_run.checked_add_months(Months::new(months as u32))
+            }
+            RepeatTemplate::Yearly => {
+                let months = months_until_next(last_run, now, 12, always_advance);
+                last_run.checked_add_months(Months::new(months as u32))
+            }
+        },
+        Some(Repeat::Custom { frequency, every }) => match frequency {
+            RepeatFrequency::Daily => Some(
+                last_run
+                    + Duration::days(days_until_next(last_run, now, *every as i64, always_advance) * *every as i64),
+            ),
+            RepeatFrequency::Weekly => Some(
+                last_run
+                    + Duration::weeks(weeks_until_next(last_run, now, *every as i64, always_advance) * *every as i64),
+            ),
+            RepeatFrequency::Monthly => {
+                let months = months_until_next(last_run, now, *every as i32, always_advance) * *every as i32;
+                last_run.checked_add_months(Months::new(months as u32))
+            }
+            RepeatFrequency::Yearly => {
+                let months = months_until_next(last_run, now, *every as i32 * 12, always_advance) * *every as i32 * 12;
+                last_run.checked_add_months(Months::new(months as u32))
+            }
+        },
+        None => None,
+    }
+}
+
+/// Parse entries individually so one malformed job doesn't drop the whole file.
+fn parse_jobs(data: &str, settings: &JobSettings) -> (Vec<Job>, Vec<JobFileError>) {
+    match serde_json::from_str::<Vec<Value>>(data) {
+        Ok(entries) => parse_job_values(entries, settings),
+        Err(error) => {
+            let error = JobFileError {
+                index: None,
+                id: None,
+                message: format!("Failed to parse jobs file: {error}"),
+            };
+            (Vec::new(), vec![error])
+        }
+    }
+}
+
+fn parse_job_values(entries: Vec<Value>, settings: &JobSettings) -> (Vec<Job>, Vec<JobFileError>) {
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut errors = Vec::new();
+
+    for (index, entry) in entries.into_iter().enumerate() {
+        let id = entry.get("id").and_then(|v| v.as_str()).map(str::to_string);
+
+        let result = serde_json::from_value::<Job>(entry)
+            .map_err(|error| error.to_string())
+            .and_then(|job| job.validate(settings).map(|_| job));
+
+        match result {
+            Ok(job) if jobs.iter().any(|j| j.id == job.id) => errors.push(JobFileError {
+                index: Some(index),
+                id,
+                message: format!("duplicate id: {}", job.id),
+            }),
+            Ok(job) => jobs.push(job),
+            Err(message) => errors.push(JobFileError {
+                index: Some(index),
+                id,
+                message,
+            }),
+        }
+    }
+
+    (jobs, errors)
+}
+
+async fn execute_job(job: &Job, settings: &JobSettings) -> anyhow::Result<String> {
+    match job.job_type {
+        JobType::Http => execute_http(job).await,
+        JobType::Exec => exec::run(job, &settings.exec).await,
+    }
+}
+
+async fn execute_http(job: &Job) -> anyhow::Result<String> {
+    let client = reqwest::Client::new();
+
+    let request = match job.method.to_uppercase().as_str() {
+        "GET" => client.get(&job.url),
+        "POST" => {
+            if let Some(ref b) = job.body {
+                client.post(&job.url).json(&b)
+            } else {
+                client.post(&job.url)
+            }
+        }
+        "PUT" => {
+            if let Some(ref b) = job.body {
+                client.put(&job.url).json(&b)
+            } else {
+                client.put(&job.url)
+            }
+        }
+        "DELETE" => client.delete(&job.url),
+        _ => anyhow::bail!("unsupported method: {}", job.method),
+    };
+
+    // Continue the trace of the job in the service it calls
+    let request = match crate::telemetry::traceparent() {
+        Some(traceparent) => request.header("traceparent", traceparent),
+        None => request,
+    };
+
+    // The error keeps the status for the run history
+    let resp = request.send().await?.error_for_status()?;
+    Ok(resp.status().to_string())
+}
+
+impl JobManager {
+    pub fn new(notifier: Notifier, kv: Arc<Kv>, file_path: &Path, settings: JobSettings) -> Self {
+        Self {
+            notifier,
+            kv,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            file_path: file_path.to_path_buf(),
+            current_jobs: Arc::new(Mutex::new(Vec::new())),
+            errors: Arc::new(Mutex::new(Vec::new())),
+            executor: Executor::new(settings.max_concurrent),
+            settings,
+            remote_jobs: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn load_jobs(&self) -> (Vec<Job>, Vec<JobFileError>) {
+        if self.settings.store == JobStoreKind::Sled {
+            return match self.kv.list_jobs() {
+                Ok(entries) => parse_job_values(entries, &self.settings),
+                Err(error) => {
+                    let error = JobFileError {
+                        index: None,
+                        id: None,
+                        message: format!("Failed to read jobs from store: {error:#}"),
+                    };
+                    (Vec::new(), vec![error])
+                }
+            };
+        }
+
+        // Skip if file does not exist
+        if !self.file_path.try_exists().unwrap_or_else(|error| {
+            tracing::warn!("Could not check if jobs file exists: {:?}", error);
+            false
+        }) {
+            return Default::default();
+        }
+
+        let data = fs::read_to_string(&self.file_path).unwrap_or_default();
+        if data.trim().is_empty() {
+            return Default::default();
+        }
+
+        parse_jobs(&data, &self.settings)
+    }
+
+    /// On first boot with the sled store, import an existing jobs file.
+    pub fn init_store(&self) -> anyhow::Result<()> {
+        if self.settings.store != JobStoreKind::Sled || self.kv.jobs_imported()? {
+            return Ok(());
+        }
+
+        if self.file_path.exists() {
+            let data = fs::read_to_string(&self.file_path)?;
+            let entries: Vec<Value> = serde_json::from_str(&data)?;
+            let errors = self.import_jobs(entries)?;
+            tracing::info!(
+                "Imported jobs file into store ({} errors): {}",
+                errors.len(),
+                self.file_path.display()
+            );
+        }
+
+        self.kv.set_jobs_imported()
+    }
+
+    fn ensure_store(&self) -> anyhow::Result<()> {
+        if self.settings.store != JobStoreKind::Sled {
+            anyhow::bail!("Jobs are managed by the jobs file, set job.store to \"sled\" to edit them via the API");
+        }
+        Ok(())
+    }
+
+    /// Create or replace a job in the store, then reschedule.
+    pub fn upsert_job(&self, job: &Job) -> anyhow::Result<()> {
+        self.ensure_store()?;
+        job.validate(&self.settings).map_err(anyhow::Error::msg)?;
+        self.kv.put_job(&job.id, &serde_json::to_value(job)?)?;
+        self.schedule_jobs();
+        Ok(())
+    }
+
+    /// Remove a job from the store, then reschedule. Returns whether it existed.
+    pub fn delete_job(&self, id: &str) -> anyhow::Result<bool> {
+        self.ensure_store()?;
+        let existed = self.kv.remove_job(id)?;
+        self.kv.remove_job_runs(id)?;
+        self.schedule_jobs();
+        Ok(existed)
+    }
+
+    /// Atomically replace all jobs in the store with the valid entries, then reschedule.
+    pub fn import_jobs(&self, entries: Vec<Value>) -> anyhow::Result<Vec<JobFileError>> {
+        self.ensure_store()?;
+        let (jobs, errors) = parse_job_values(entries, &self.settings);
+        let entries = jobs
+            .iter()
+            .map(|job| Ok((job.id.clone(), serde_json::to_value(job)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.kv.replace_jobs(&entries)?;
+        self.schedule_jobs();
+        Ok(errors)
+    }
+
+    /// Replace the jobs of the remote manifest, rescheduling only when they changed.
+    /// Ids are prefixed with `manifest:` so they never clash with local jobs.
+    pub fn set_remote_jobs(&self, entries: Vec<Value>) -> Vec<JobFileError> {
+        let (jobs, mut errors) = parse_job_values(entries, &self.settings);
+        let mut remote = vec![];
+        for mut job in jobs {
+            if job.job_type == JobType::Exec {
+                errors.push(JobFileError {
+                    index: None,
+                    id: Some(job.id),
+                    message: "exec jobs can't come from the manifest".into(),
+                });
+                continue;
+            }
+            job.id = format!("{REMOTE_JOB_PREFIX}{}", job.id);
+            remote.push(job);
+        }
+
+        let changed = {
+            let mut current = self.remote_jobs.lock().unwrap();
+            let changed = serde_json::to_value(&*current).ok() != serde_json::to_value(&remote).ok();
+            *current = remote;
+            changed
+        };
+        if changed {
+            tracing::info!("Jobs from manifest changed, rescheduling");
+            self.schedule_jobs();
+        }
+        errors
+    }
+
+    pub fn schedule_jobs(&self) {
+        let (mut jobs, errors) = self.load_jobs();
+        jobs.extend(self.remote_jobs.lock().unwrap().iter().cloned());
+
+        for error in &errors {
+            tracing::warn!(
+                "Invalid job (index: {:?}, id: {:?}): {}",
+                error.index,
+                error.id,
+                error.message
+            );
+        }
+
+        // Keep errors for jobs API
+        {
+            let mut current = self.errors.lock().unwrap();
+            *current = errors.clone();
+        }
+
+        // Keep a copy for jobs API
+        {
+            let mut current = self.current_jobs.lock().unwrap();
+            *current = jobs.clone();
+        }
+
+        {
+            let mut handles = self.jobs.lock().unwrap();
+
+            // Cancel all old jobs
+            for (_, handle) in handles.drain() {
+                handle.abort();
+            }
+        }
+
+        for job in jobs {
+            // Skip expired jobs
+            if job.repeat.is_none() {
+                let delay_secs = seconds_until(job.run_at);
+                let expire_secs = -(self.settings.max_late_secs as i64);
+
+                if delay_secs < expire_secs {
+                    tracing::info!(
+                        "Skipping job [{}]: expired (scheduled {}, now {}, max_late_secs {})",
+                        job.id,
+                        job.run_at.with_timezone(&Local),
+                        Utc::now().with_timezone(&Local),
+                        self.settings.max_late_secs
+                    );
+                    continue;
+                }
+            }
+
+            self.spawn_job(job.clone());
+        }
+
+        // Notify
+        self.notifier.notify(Notification::JobsUpdated);
+        if !errors.is_empty() {
+            self.notifier.notify(Notification::JobsFileInvalid { errors });
+        }
+    }
+
+    fn spawn_job(&self, mut job: Job) {
+        let fire_at = match self.next_fire(&mut job) {
+            Ok(fire_at) => fire_at,
+            Err(reason) => {
+                tracing::info!("Job [{}] {}, skipping..", job.id, reason);
+                return;
+            }
+        };
+
+        if job.repeat.is_some() || fire_at != job.run_at {
+            tracing::info!("Job [{}] next run will be at {}", job.id, fire_at.with_timezone(&Local));
+        }
+
+        let manager = self.clone();
+        let notifier = self.notifier.clone();
+        let id = job.id.clone();
+
+        let handle = tokio::spawn(async move {
+            // Schedule the job
+            let delay_secs = seconds_until(fire_at);
+            let delay = if delay_secs > 0 { delay_secs as u64 } else { 0 };
+            let when = std::time::Instant::now() + std::time::Duration::from_secs(delay);
+
+            let id = job.id.clone();
+
+            // Sleep
+            tracing::trace!("Job [{}] idle for {} seconds", id, delay);
+            sleep_until(when.into()).await;
+            tracing::info!("Running job {}", id);
+
+            // Alert if we woke up outside of the expected window (eg. after a suspend)
+            let late_secs = -seconds_until(fire_at);
+            if late_secs > manager.settings.max_late_secs as i64 {
+                manager
+                    .alert(
+                        &job,
+                        format!(
+                            "missed scheduled run at {} by {} seconds",
+                            fire_at.with_timezone(&Local),
+                            late_secs
+                        ),
+                    )
+                    .await;
+            }
+
+            let mut attempt = 0;
+            loop {
+                // Wait for a free slot, highest priority first
+                if manager.executor.is_full() {
+                    tracing::info!("Job [{}] queued (priority {})", id, job.priority);
+                }
+                let permit = manager.executor.acquire(job.priority).await;
+
+                // Notify
+                notifier.notify(Notification::RunningJob { id: id.clone() });
+
+                let span = tracing::info_span!("job", id, attempt, error = tracing::field::Empty);
+                let started_at = Utc::now();
+                let started = std::time::Instant::now();
+                let result = execute_job(&job, &manager.settings).instrument(span.clone()).await;
+                drop(permit);
+                if let Err(error) = &result {
+                    span.record("error", format!("{error:#}"));
+                }
+                let run = JobRun {
+                    started_at,
+                    duration: started.elapsed(),
+                    attempt,
+                    status: match &result {
+                        Ok(status) => Some(status.clone()),
+                        Err(error) => error
+                            .downcast_ref::<reqwest::Error>()
+                            .and_then(|e| e.status())
+                            .map(|s| s.to_string()),
+                    },
+                    error: result.as_ref().err().map(|error| format!("{error:#}")),
+                };
+                if let Err(error) = manager.kv.append_job_run(&id, &run) {
+                    tracing::warn!("Failed to record run of job [{}]: {:#}", id, error);
+                }
+                let finished = result.is_ok() || attempt >= job.retries;
+                if finished {
+                    notifier.notify(Notification::JobFinished { id: id.clone(), run });
+                }
+
+                match result {
+                    Ok(status) => {
+                        tracing::info!("Job [{}] executed -> {}", id, status);
+                        break;
+                    }
+                    Err(error) if attempt < job.retries => {
+                        attempt += 1;
+                        tracing::warn!(
+                            "Job [{}] failed: {:#}, retrying ({}/{})",
+                            id,
+                            error,
+                            attempt,
+                            job.retries
+                        );
+                        tokio::time::sleep(std::time::Duration::from_secs(RETRY_DELAY_SECS)).await;
+                    }
+                    Err(error) => {
+                        tracing::warn!("Job [{}] failed: {:#}", id, error);
+                        notifier.error(
+                            ProblemKind::JobFailed,
+                            format!("Job {} failed after {} attempts: {:#}", id, attempt + 1, error),
+                        );
+                        manager
+                            .alert(&job, format!("failed after {} attempts: {:#}", attempt + 1, error))
+                            .await;
+                        break;
+                    }
+                }
+            }
+
+            // Schedule next run if repeating
+            if let Some(next) = next_run_time(&job, job.run_at, true)
+                && job.end_repeat.is_none_or(|end| next <= end)
+            {
+                let mut next_job = job.clone();
+                next_job.run_at = next;
+
+                tracing::info!(
+                    "Job [{}] will be repeated again at {}",
+                    job.id,
+                    next.with_timezone(&Local)
+                );
+
+                manager.spawn_job(next_job);
+            }
+        });
+
+        let mut handles = self.jobs.lock().unwrap();
+        if let Some(prev) = handles.insert(id.clone(), handle) {
+            // Stop previous run
+            prev.abort();
+        }
+    }
+
+    /// Returns when the job should fire next, or why it won't.
+    /// `job.run_at` is advanced to the repeat occurrence being fired.
+    fn next_fire(&self, job: &mut Job) -> Result<DateTime<Utc>, String> {
+        if job.repeat.is_some() {
+            let now = Utc::now();
+
+            // Determine the next valid run time if first run is in the past
+            if job.run_at <= now
+                && let Some(next) = next_run_time(job, job.run_at, false)
+            {
+                // Set next run
+                job.run_at = next;
+            }
+
+            // If there is an end_repeat and it's passed, stop scheduling
+            if let Some(end_repeat) = job.end_repeat
+                && (job.run_at > end_repeat || now >= end_repeat)
+            {
+                return Err(format!("end_repeat at {} reached", end_repeat.with_timezone(&Local)));
+            }
+        }
+
+        self.resolve_blackouts(job)
+    }
+
+    /// For skipped repeats `job.run_at` is advanced to the next allowed occurrence.
+    fn resolve_blackouts(&self, job: &mut Job) -> Result<DateTime<Utc>, String> {
+        let rules: Vec<Blackout> = self.settings.blackouts.iter().chain(&job.blackouts).cloned().collect();
+        if !blackout::is_blacked_out(&rules, job.run_at) {
+            return Ok(job.run_at);
+        }
+
+        match job.blackout_action {
+            BlackoutAction::Defer => {
+                blackout::defer_until_allowed(&rules, job.run_at).ok_or_else(|| "is always in a blackout".into())
+            }
+            BlackoutAction::Skip => {
+                for _ in 0..blackout::MAX_ITERATIONS {
+                    let Some(next) = next_run_time(job, job.run_at, true) else {
+                        break;
+                    };
+                    if job.end_repeat.is_some_and(|end| next > end) {
+                        break;
+                    }
+
+                    job.run_at = next;
+                    if !blackout::is_blacked_out(&rules, next) {
+                        return Ok(next);
+                    }
+                }
+
+                Err("is in a blackout".into())
+            }
+        }
+    }
+
+    /// Compute the next `count` run times without scheduling anything.
+    pub fn preview(&self, job: &Job, count: usize) -> Vec<DateTime<Utc>> {
+        let mut job = job.clone();
+        let mut runs = Vec::new();
+
+        // Expired one-off jobs never run
+        if job.repeat.is_none() && seconds_until(job.run_at) < -(self.settings.max_late_secs as i64) {
+            return runs;
+        }
+
+        while runs.len() < count {
+            let Ok(fire_at) = self.next_fire(&mut job) else {
+                break;
+            };
+            runs.push(fire_at);
+
+            match next_run_time(&job, job.run_at, true) {
+                Some(next) if job.end_repeat.is_none_or(|end| next <= end) => job.run_at = next,
+                _ => break,
+            }
+        }
+
+        runs
+    }
+
+    async fn alert(&self, job: &Job, reason: String) {
+        let Some(target) = job.alert.as_ref().or(self.settings.alert.as_ref()) else {
+            return;
+        };
+
+        tracing::warn!("Job [{}] alert: {}", job.id, reason);
+
+        match target {
+            AlertTarget::Notification => {
+                self.notifier.notify(Notification::JobAlert {
+                    id: job.id.clone(),
+                    reason,
+                });
+            }
+            AlertTarget::Webhook { url } => {
+                let body = serde_json::json!({
+                    "id": job.id,
+                    "reason": reason,
+                    "at": Utc::now(),
+                });
+                let res = reqwest::Client::new()
+                    .post(url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .and_then(|resp| resp.error_for_status());
+                if let Err(error) = res {
+                    tracing::warn!("Job [{}] alert webhook failed: {}", job.id, error);
+                }
+            }
+        }
+    }
+
+    pub fn watch(&self) {
+        // Store changes go through the API, which reschedules directly
+        if self.settings.store == JobStoreKind::Sled {
+            return;
+        }
+
+        let mgr = self.clone();
+        let dir = self
+            .file_path
+            .parent()
+            .unwrap_or_else(|| std::path::Path::new("."))
+            .to_path_buf();
+        let file_name = self.file_path.file_name().unwrap().to_os_string();
+
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+                let mut watcher: RecommendedWatcher = RecommendedWatcher::new(
+                    move |res| {
+                        let _ = tx.blocking_send(res);
+                    },
+                    notify::Config::default(),
+                )
+                .unwrap();
+
+                watcher.watch(&dir, RecursiveMode::NonRecursive).unwrap();
+
+                while let Some(Ok(event)) = rx.recv().await {
+                    // Any create/modify/remove event in the directory
+                    if dir.join(&file_name).exists()
+                        && matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+                    {
+                        tracing::info!("Jobs file changed or created, reloading jobs...");
+                        mgr.schedule_jobs();
+                    }
+                }
+            });
+        });
+    }
+}
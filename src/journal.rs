@@ -0,0 +1,81 @@
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::notifier::{Event, Notifier};
+use crate::settings::JournalSettings;
+
+/// A journaled event, as `{"seq": ..., "at": ..., "type": ..., "payload": ...}`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The previous half of the journal, kept when it's rotated.
+fn rotated(path: &Path) -> PathBuf {
+    path.with_extension("1.jsonl")
+}
+
+fn append(path: &Path, max_bytes: u64, line: &[u8]) -> anyhow::Result<()> {
+    // Rotate at half the cap, so both files together stay under it
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    if size > 0 && size + line.len() as u64 > max_bytes / 2 {
+        fs::rename(path, rotated(path))?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line)?;
+    Ok(())
+}
+
+/// Journaled notifications at or after `since`, oldest first, at most `limit`.
+pub fn read(path: &Path, since: Option<DateTime<Utc>>, limit: usize) -> Vec<JournalEntry> {
+    [rotated(path), path.to_path_buf()]
+        .iter()
+        .filter_map(|p| fs::File::open(p).ok())
+        .flat_map(|file| BufReader::new(file).lines().map_while(Result::ok))
+        .filter_map(|line| serde_json::from_str::<JournalEntry>(&line).ok())
+        .filter(|entry| since.is_none_or(|since| entry.at >= since))
+        .take(limit)
+        .collect()
+}
+
+/// Append notifications to the journal at `path`.
+pub fn spawn(notifier: &Notifier, path: PathBuf, settings: JournalSettings) {
+    if !settings.enable {
+        return;
+    }
+
+    let mut rx = notifier.subscribe_with_zones(|n| !n.is_noisy());
+    tokio::spawn(async move {
+        loop {
+            let event: Event = match rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("Journal missed {} notifications", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            let Ok(mut line) = serde_json::to_vec(&event) else {
+                continue;
+            };
+            line.push(b'\n');
+
+            let path = path.clone();
+            let max_bytes = settings.max_bytes;
+            match tokio::task::spawn_blocking(move || append(&path, max_bytes, &line)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => tracing::warn!("Failed to write event journal: {error:#}"),
+                Err(error) => tracing::warn!("Failed to write event journal: {error}"),
+            }
+        }
+    });
+}
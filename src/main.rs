@@ -1,25 +1,57 @@
+mod alarm;
 mod api;
+mod archive;
+mod backup;
 mod cli;
+mod discord;
+mod disk;
 mod downloader;
+mod equalizer;
+#[cfg(feature = "gpio")]
+mod gpio;
+mod init;
+mod input;
+mod insert;
 mod job;
+mod journal;
+mod manifest;
+mod mqtt;
 mod notifier;
+mod output;
 mod player;
 mod playlist;
 mod publisher;
+mod rate_limit;
+mod reload;
+mod schema;
+#[cfg(windows)]
+mod service;
 mod settings;
+mod snapcast;
 mod state;
+mod station;
+mod stats;
+mod streamer;
+mod telemetry;
+mod transcode;
 mod utils;
+mod webhook;
+mod zone;
 
 use crate::{
     notifier::Notifier,
     player::PlayerConfig,
-    settings::{DownloaderSettings, Settings},
+    settings::{Environment, Overrides, Settings, TlsSettings},
+    telemetry::Telemetry,
 };
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
-use tracing_subscriber::{EnvFilter, fmt};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -27,20 +59,54 @@ async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
 
     // Init logging
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info,tower_http=info".into());
-    fmt().with_env_filter(filter).init();
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| Environment::detect().default_log_filter().into());
+    let telemetry = telemetry::init(filter);
 
     // Init CLI
     let cli = cli::Cli::parse();
 
+    // Init writes the settings, so don't load them
+    if let cli::Command::Init(_) = cli.cmd {
+        return cli.cmd.run(cli.api_key.as_deref()).await;
+    }
+
+    // Started and stopped by the service control manager instead
+    #[cfg(windows)]
+    if let cli::Command::Service(cmd) = cli.cmd {
+        return service::run(cmd, telemetry);
+    }
+
+    // Other commands use HTTP API and exit
+    let cli::Command::Start(args) = &cli.cmd else {
+        let settings = Settings::load_or_init(&Default::default())?;
+        settings.ensure_dirs()?;
+        let api_key = cli.api_key.as_deref().or(settings.server.api_key.as_deref());
+        return cli.cmd.run(api_key).await;
+    };
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            shutdown.cancel();
+        }
+    });
+    start(args.overrides(), telemetry, shutdown).await
+}
+
+/// Run the daemon until `shutdown` is cancelled, then save the playback state.
+async fn start(overrides: Overrides, mut telemetry: Telemetry, shutdown: CancellationToken) -> anyhow::Result<()> {
+    // Taken first, before other threads that could read the environment it clears
+    let activated = systemd_listener()?;
+
     // Init settings
-    let settings = Settings::load_or_init()?;
+    let settings = Settings::load_or_init(&overrides)?;
     let paths = settings.ensure_dirs()?;
 
-    // Other commands use HTTP API and exit
-    if let cli::Command::Start = cli.cmd {
-    } else {
-        return cli.cmd.run().await;
+    if !Settings::files(&overrides).iter().any(|f| f.exists()) {
+        tracing::warn!("No settings file found, using defaults. Run `musicd init` to create one.");
     }
 
     tracing::info!(
@@ -49,32 +115,97 @@ async fn main() -> anyhow::Result<()> {
         paths.root.display()
     );
 
+    telemetry.start(&settings.telemetry);
+
     let notifier = Notifier::new();
+    webhook::spawn(&notifier, settings.notifier.webhooks.clone());
+    notifier.spawn_heartbeat(settings.notifier.heartbeat_interval_secs);
+    journal::spawn(&notifier, paths.journal.clone(), settings.notifier.journal.clone());
 
     let kv = Arc::new(state::State::open(&paths.db)?);
+
+    // Prune old listening history
+    let history_before = chrono::Utc::now() - chrono::Duration::days(stats::HISTORY_RETENTION_DAYS);
+    match kv.prune_history(&history_before) {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("Pruned {} history entries", n),
+        Err(error) => tracing::warn!("Failed to prune history: {error:#}"),
+    }
+    // Streams what the main player plays, at the rate of its output
+    let streamer = if settings.streamer.enable {
+        let sample_rate = if settings.snapcast.enable {
+            settings.snapcast.sample_rate
+        } else {
+            output::SAMPLE_RATE
+        };
+        Some(streamer::Streamer::new(settings.streamer.clone(), sample_rate)?)
+    } else {
+        None
+    };
     let player = player::PlayerHandle::new(
         notifier.clone(),
-        PlayerConfig {
-            auto_play: settings.player.auto_play,
-            default_audio_effects: settings.player.default_audio_effects,
+        PlayerConfig::from(&settings.player),
+        kv.clone(),
+        if settings.snapcast.enable {
+            player::PlayerOutput::Snapcast(settings.snapcast.clone())
+        } else {
+            match &settings.player.output_device {
+                Some(device) => player::PlayerOutput::Device(device.clone()),
+                None => player::PlayerOutput::DefaultDevice,
+            }
         },
+        None,
+        paths.inserts.clone(),
+        streamer.as_ref().map(|s| s.tap()),
     )?;
 
+    // Periodic state backups
+    backup::spawn_periodic(kv.clone(), paths.backups.clone(), settings.backup.clone());
+
+    // Warn before downloads start failing
+    disk::spawn_monitor(notifier.clone(), paths.root.clone(), settings.disk.clone());
+
     // Job manager
-    let job_manager = job::JobManager::new(notifier.clone(), &paths.jobs, settings.job.max_late_secs);
+    let job_manager = job::JobManager::new(notifier.clone(), kv.clone(), &paths.jobs, settings.job.clone());
+    if let Err(error) = job_manager.init_store() {
+        tracing::warn!("Failed to import jobs file into store: {error:#}");
+    }
     job_manager.schedule_jobs();
     job_manager.watch();
 
     // Publisher
-    let publisher = publisher::Publisher::new(
-        paths.clone(),
-        kv.clone(),
+    let publisher = publisher::Publisher::new(paths.clone(), kv.clone(), notifier.clone(), player.clone(), &settings);
+
+    // Discord Rich Presence
+    discord::spawn(&settings.discord, &notifier, player.clone());
+
+    // Media keys
+    input::spawn(&settings.input, player.clone());
+
+    // GPIO controls
+    #[cfg(feature = "gpio")]
+    if let Err(error) = gpio::spawn(&settings.gpio, player.clone()) {
+        tracing::warn!("Failed to set up GPIO controls: {error:#}");
+    }
+    #[cfg(not(feature = "gpio"))]
+    if settings.gpio.enable {
+        tracing::warn!("gpio.enable is set, but musicd was built without the gpio feature");
+    }
+
+    // Measure loudness of tracks that have none yet
+    playlist::loudness::spawn(&settings.loudness, paths.playlists.clone(), player.clone());
+
+    // Lyrics lines over the websocket
+    playlist::lyrics::spawn(
+        &settings.lyrics,
+        paths.playlists.clone(),
         notifier.clone(),
         player.clone(),
-        settings.publish.clone(),
-        settings.downloader.clone(),
     );
 
+    // Mirror watched folders
+    playlist::watch::watch_folders(&settings.watch, &paths.playlists, notifier.clone(), player.clone());
+
     // On boot, try to restore last playlist
     if let Some(id) = kv.get_current_playlist_id()? {
         if let Ok(items) = playlist::get_playlists(&paths.playlists)
@@ -92,25 +223,54 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    // Periodic (optional) manifest checker — if manifest url provided, and it indicates a new playlist,
-    // your own service can return a JSON { "id": "...", "name": "...", "source_urls": "..." }.
-    if settings.manifest.enable
-        && let Some(url) = settings.manifest.url.clone()
+    // Extra players, back on their last playlists
+    let zones = zone::spawn(&settings.zones, &settings.player, &paths, kv.clone(), &notifier);
+
+    // MQTT events and commands, for the main player and the zones
+    mqtt::spawn(&settings.mqtt, &notifier, player.clone(), zones.clone());
+
+    // Back on the station if one was playing
+    if let Some(id) = kv.get_current_status(None).ok().flatten().and_then(|c| c.station_id)
+        && let Some(station) = kv.get_station(&id)?
     {
-        let paths2 = paths.clone();
-        let kv2 = kv.clone();
-        let player2 = player.clone();
-        let downloader_settings = settings.downloader.clone();
-        tokio::spawn(async move {
-            loop {
-                if let Err(error) = check_manifest_once(&url, &paths2, &kv2, &player2, &downloader_settings).await {
-                    tracing::warn!("manifest check failed: {error:#}");
-                }
-                tokio::time::sleep(Duration::from_secs(settings.manifest.check_interval_secs)).await;
-            }
-        });
+        player.set_station(station);
     }
 
+    // Periodic (optional) manifest checker
+    let manifest_settings = Arc::new(RwLock::new(settings.manifest.clone()));
+    let manifest_status = Arc::new(Mutex::new(manifest::ManifestStatus::default()));
+    manifest::ManifestChecker {
+        status: manifest_status.clone(),
+        settings: manifest_settings.clone(),
+        paths: paths.clone(),
+        kv: kv.clone(),
+        player: player.clone(),
+        publisher: publisher.clone(),
+        job_manager: job_manager.clone(),
+        notifier: notifier.clone(),
+        min_free_bytes: settings.disk.min_free_bytes,
+    }
+    .spawn();
+
+    // Refresh playlists with a refresh interval
+    publisher.spawn_scheduled_refresh();
+
+    // Settings files go into full backups
+    let settings_files = Settings::files(&overrides);
+
+    // Reload settings on change
+    reload::watch(
+        settings.clone(),
+        overrides,
+        reload::Reloadable {
+            notifier: notifier.clone(),
+            player: player.clone(),
+            zones: zones.clone(),
+            publisher: publisher.clone(),
+            manifest: manifest_settings,
+        },
+    );
+
     // Web API
     let app = api::router(api::AppCtx {
         paths: paths.clone(),
@@ -119,59 +279,186 @@ async fn main() -> anyhow::Result<()> {
         publisher: publisher.clone(),
         player: player.clone(),
         job_manager: job_manager.clone(),
+        usage: playlist::usage::UsageCache::new(),
+        library: playlist::library::LibraryCache::new(),
+        backup: settings.backup.clone(),
+        api_key: settings.server.api_key.clone(),
+        settings_files,
+        journal: settings.notifier.journal.clone(),
+        min_free_bytes: settings.disk.min_free_bytes,
+        manifest_status,
+        alarm: alarm::Alarm::new(
+            settings.alarm.clone(),
+            paths.playlists.clone(),
+            kv.clone(),
+            player.clone(),
+            notifier.clone(),
+        ),
+        lyrics: settings.lyrics.clone(),
+        zones,
+        streamer,
+        rate_limiter: rate_limit::RateLimiter::new(settings.server.rate_limit.clone()),
     });
 
     let host = &settings.server.host;
     let port = settings.server.port;
+    let tls = tls_config(&settings.server.tls).await?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
 
-    // Start server
-    let addr: SocketAddr = format!("{host}:{port}")
-        .parse()
-        .unwrap_or_else(|_| panic!("Failed to parse address (host: {host}, port: {port})"));
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    tracing::info!("Listening on http://{addr}");
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+    // Start server, on the socket passed by systemd if socket activated
+    let listener = match activated {
+        Some(listener) => {
+            listener.set_nonblocking(true)?;
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            tracing::info!("Listening on {scheme}://{} (socket activated)", listener.local_addr()?);
+            listener
+        }
+        None => {
+            let addr: SocketAddr = format!("{host}:{port}")
+                .parse()
+                .unwrap_or_else(|_| panic!("Failed to parse address (host: {host}, port: {port})"));
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to listen on {addr}"))?;
+            tracing::info!("Listening on {scheme}://{addr}");
+            listener
+        }
+    };
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(config) => {
+            // Open connections, eg. WebSocket clients, get the same grace as over plain HTTP
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                let shutdown = shutdown.clone();
+                async move {
+                    shutdown.cancelled().await;
+                    handle.graceful_shutdown(Some(Duration::from_secs(SHUTDOWN_GRACE_SECS)));
+                }
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, config)
+                .handle(handle)
+                .serve(app)
+                .await?;
+        }
+        None => {
+            let serve = axum::serve(listener, app).with_graceful_shutdown(shutdown.clone().cancelled_owned());
+            // WebSocket clients stay connected, don't wait on them for long
+            tokio::select! {
+                res = serve => res?,
+                _ = async {
+                    shutdown.cancelled().await;
+                    tokio::time::sleep(Duration::from_secs(SHUTDOWN_GRACE_SECS)).await;
+                } => tracing::info!("Closing remaining connections"),
+            }
+        }
+    }
+
+    // Save where playback was, so it resumes there
+    tracing::info!("Shutting down");
+    let player2 = player.clone();
+    tokio::task::spawn_blocking(move || player2.checkpoint(Duration::from_secs(SHUTDOWN_CHECKPOINT_SECS))).await?;
+    kv.flush()?;
+    telemetry.flush(Duration::from_secs(SHUTDOWN_GRACE_SECS)).await;
 
     Ok(())
 }
 
-#[derive(serde::Deserialize)]
-struct RemoteManifest {
-    id: String,
-    name: String,
-    source_urls: Vec<String>,
+/// Longest wait for open requests on shutdown.
+const SHUTDOWN_GRACE_SECS: u64 = 3;
+/// Longest wait for the player to save its position on shutdown.
+const SHUTDOWN_CHECKPOINT_SECS: u64 = 2;
+
+/// The certificate and key of `settings` to serve HTTPS with, unset to serve plain HTTP.
+async fn tls_config(settings: &TlsSettings) -> anyhow::Result<Option<RustlsConfig>> {
+    let (cert, key) = match (&settings.cert_path, &settings.key_path) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("server.tls needs both cert_path and key_path"),
+    };
+    // The same crypto as reqwest, so rustls doesn't have to choose
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let config = RustlsConfig::from_pem_file(cert, key).await.with_context(|| {
+        format!(
+            "Failed to load TLS certificate {} and key {}",
+            cert.display(),
+            key.display()
+        )
+    })?;
+    Ok(Some(config))
+}
+
+/// Resolves on Ctrl+C, or SIGTERM (eg. `systemctl stop`).
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Failed to listen for Ctrl+C: {error}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(error) => {
+                tracing::warn!("Failed to listen for SIGTERM: {error}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
 
-async fn check_manifest_once(
-    url: &str,
-    paths: &settings::Paths,
-    kv: &state::State,
-    player: &player::PlayerHandle,
-    downloader_settings: &DownloaderSettings,
-) -> anyhow::Result<()> {
-    let m: RemoteManifest = reqwest::get(url).await?.json().await?;
-    // If id differs from current, fetch new
-    if kv.get_current_playlist_id()? != Some(m.id.clone()) {
-        use downloader::yt_dlp::YtDlpDownloader;
-        use downloader::{Downloader, DownloaderKind};
-        let dl: Box<dyn Downloader> = match DownloaderKind::YtDlp {
-            DownloaderKind::YtDlp => Box::new(YtDlpDownloader),
-        };
-        let tmp_dir = paths.tmp.join(format!("remote_{}", m.id));
-        tokio::fs::create_dir_all(&tmp_dir).await?;
-        dl.download_playlist(&m.source_urls, &tmp_dir, downloader_settings)
-            .await?;
-        // fix meta
-        let meta_path = tmp_dir.join("playlist.json");
-        let mut meta: crate::playlist::PlaylistMeta = serde_json::from_slice(&tokio::fs::read(&meta_path).await?)?;
-        meta.id = m.id.clone();
-        meta.name = m.name.clone();
-        tokio::fs::write(&meta_path, serde_json::to_vec_pretty(&meta)?).await?;
-        let final_path = paths.playlists.join(meta.dir_name());
-        tokio::fs::rename(&tmp_dir, &final_path).await?;
-        kv.set_current_playlist_id(&meta.id)?;
-        player.set_playlist_dir(final_path, player::SetPlaylistMode::Queue);
-        tracing::info!("updated from manifest to '{}'", meta.name);
+/// The listening socket passed by systemd socket activation (`LISTEN_FDS`), if any.
+#[cfg(unix)]
+fn systemd_listener() -> anyhow::Result<Option<std::net::TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    /// First passed fd, after stdin, stdout and stderr.
+    const SD_LISTEN_FDS_START: i32 = 3;
+
+    let fds: usize = match std::env::var("LISTEN_FDS") {
+        Ok(fds) => fds.parse().unwrap_or(0),
+        Err(_) => return Ok(None),
+    };
+    // The fds are meant for this process, not for children it forks
+    let for_us = std::env::var("LISTEN_PID").is_ok_and(|pid| pid == std::process::id().to_string());
+    if fds == 0 || !for_us {
+        return Ok(None);
     }
-    Ok(())
+    if fds > 1 {
+        tracing::warn!("systemd passed {fds} sockets, only the first one is used");
+    }
+
+    // SAFETY: systemd hands this fd over to us, and nothing else in the process owns it
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    listener
+        .local_addr()
+        .context("The socket passed by systemd is not a TCP socket")?;
+    // Not inherited by hooks and players this process runs, which would keep the port open
+    // SAFETY: a valid fd, owned by `listener`
+    if unsafe { libc::fcntl(SD_LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to set close-on-exec on the systemd socket");
+    }
+    // Nor are the variables saying it's theirs
+    for var in ["LISTEN_FDS", "LISTEN_PID", "LISTEN_FDNAMES"] {
+        // SAFETY: called at the start of `start`, before the threads that could read the environment
+        unsafe { std::env::remove_var(var) };
+    }
+    Ok(Some(listener))
+}
+
+#[cfg(not(unix))]
+fn systemd_listener() -> anyhow::Result<Option<std::net::TcpListener>> {
+    Ok(None)
 }
@@ -0,0 +1,287 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Local, Utc};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::job::JobManager;
+use crate::notifier::{Notifier, ProblemKind};
+use crate::player::{PlayerHandle, SetPlaylistMode};
+use crate::playlist::{PlaylistMeta, find_playlist, names};
+use crate::publisher::Publisher;
+use crate::settings::{DownloaderSettings, ManifestSettings, Paths};
+use crate::state::State as Kv;
+use crate::{disk, downloader};
+
+const TIMEOUT_SECS: u64 = 30;
+
+/// Served at `manifest.url`, either a single playlist or a list of scheduled ones.
+#[derive(Debug, Deserialize)]
+struct RemoteManifest {
+    #[serde(flatten)]
+    single: Option<ManifestPlaylist>,
+    #[serde(default)]
+    playlists: Vec<ManifestPlaylist>,
+    /// Jobs scheduled next to the local ones, replacing those of the previous check.
+    #[serde(default)]
+    jobs: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestPlaylist {
+    id: String,
+    name: String,
+    source_urls: Vec<String>,
+    /// Active during any of these windows; without any it's the fallback.
+    #[serde(default)]
+    active: Vec<ActiveWindow>,
+}
+
+/// Days of the year a playlist is active, eg. `12-01` to `12-26`. Both days are included,
+/// and `to` before `from` wraps past new year.
+#[derive(Debug, Clone, Deserialize)]
+struct ActiveWindow {
+    from: MonthDay,
+    to: MonthDay,
+}
+
+/// A `MM-DD` day of the year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+struct MonthDay {
+    month: u32,
+    day: u32,
+}
+
+impl TryFrom<String> for MonthDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid day {s:?}, expected MM-DD");
+        let (month, day) = s.split_once('-').ok_or_else(invalid)?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        let day: u32 = day.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(invalid());
+        }
+        Ok(Self { month, day })
+    }
+}
+
+impl ActiveWindow {
+    fn matches(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&Local);
+        let today = MonthDay {
+            month: local.month(),
+            day: local.day(),
+        };
+        if self.from <= self.to {
+            self.from <= today && today <= self.to
+        } else {
+            today >= self.from || today <= self.to
+        }
+    }
+}
+
+impl RemoteManifest {
+    fn into_playlists(self) -> Vec<ManifestPlaylist> {
+        self.single.into_iter().chain(self.playlists).collect()
+    }
+}
+
+/// The playlist to play at `at`: the first one with a matching window, otherwise the first without windows.
+fn select(playlists: &[ManifestPlaylist], at: DateTime<Utc>) -> Option<&ManifestPlaylist> {
+    playlists
+        .iter()
+        .find(|p| p.active.iter().any(|w| w.matches(at)))
+        .or_else(|| playlists.iter().find(|p| p.active.is_empty()))
+}
+
+/// Download a manifest playlist into `paths.playlists`.
+async fn download(
+    playlist: &ManifestPlaylist,
+    paths: &Paths,
+    downloader_settings: &DownloaderSettings,
+    min_free_bytes: u64,
+) -> anyhow::Result<()> {
+    disk::ensure_free(&paths.root, min_free_bytes)?;
+    let dl = downloader::create(&downloader::DownloaderKind::YtDlp, &[]);
+    let tmp_dir = paths.tmp.join(format!("remote_{}", playlist.id));
+    tokio::fs::create_dir_all(&tmp_dir).await?;
+    dl.download_playlist(
+        &playlist.source_urls,
+        &tmp_dir,
+        downloader_settings,
+        &|_| {},
+        &CancellationToken::new(),
+    )
+    .await?;
+    // fix meta
+    let meta_path = tmp_dir.join("playlist.json");
+    let mut meta: PlaylistMeta = serde_json::from_slice(&tokio::fs::read(&meta_path).await?)?;
+    meta.id = playlist.id.clone();
+    meta.name = playlist.name.clone();
+    names::normalize_dir(&tmp_dir, &mut meta)?;
+    meta.probe_tracks_async(&tmp_dir).await;
+    tokio::fs::write(&meta_path, serde_json::to_vec_pretty(&meta)?).await?;
+    let final_path = paths.playlists.join(meta.dir_name());
+    tokio::fs::rename(&tmp_dir, &final_path).await?;
+    tracing::info!("downloaded '{}' from manifest", meta.name);
+    Ok(())
+}
+
+/// Client for the manifest request, with the configured auth, headers and proxy.
+fn client(settings: &ManifestSettings) -> anyhow::Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = &settings.bearer_token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}")).context("Invalid manifest.bearer_token")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    for (name, value) in &settings.headers {
+        let name =
+            HeaderName::from_bytes(name.as_bytes()).with_context(|| format!("Invalid manifest header {name}"))?;
+        let value = HeaderValue::from_str(value).with_context(|| format!("Invalid value of manifest header {name}"))?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .timeout(Duration::from_secs(TIMEOUT_SECS));
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).context("Invalid manifest.proxy")?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Outcome of the recent manifest checks, for `GET /manifest/status`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ManifestStatus {
+    pub enabled: bool,
+    pub last_check: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub next_check: Option<DateTime<Utc>>,
+    /// Id of the manifest playlist scheduled at the last successful check.
+    pub manifest_id: Option<String>,
+}
+
+/// Delay before the next check: the interval, doubled for every failure in a row up to `max_backoff_secs`.
+fn next_delay(settings: &ManifestSettings, failures: u32) -> Duration {
+    let secs = settings
+        .check_interval_secs
+        .saturating_mul(2u64.saturating_pow(failures))
+        .min(settings.max_backoff_secs.max(settings.check_interval_secs));
+    Duration::from_secs(secs)
+}
+
+/// Periodic (optional) manifest checker — if manifest url provided, and it indicates a new playlist,
+/// your own service can return a JSON { "id": "...", "name": "...", "source_urls": "..." },
+/// or `{ "playlists": [...] }` with activation windows, plus optional `jobs`.
+/// Settings are read on every check, so they can be reloaded.
+#[derive(Clone)]
+pub struct ManifestChecker {
+    pub status: Arc<Mutex<ManifestStatus>>,
+    pub settings: Arc<RwLock<ManifestSettings>>,
+    pub paths: Paths,
+    pub kv: Arc<Kv>,
+    pub player: PlayerHandle,
+    pub publisher: Publisher,
+    pub job_manager: JobManager,
+    pub notifier: Notifier,
+    pub min_free_bytes: u64,
+}
+
+impl ManifestChecker {
+    /// Returns the id of the scheduled manifest playlist.
+    #[tracing::instrument(name = "manifest_check", skip_all, fields(url))]
+    async fn check_once(&self, settings: &ManifestSettings, url: &str) -> anyhow::Result<Option<String>> {
+        let mut request = client(settings)?.get(url);
+        if let Some(traceparent) = crate::telemetry::traceparent() {
+            request = request.header("traceparent", traceparent);
+        }
+        let m: RemoteManifest = request.send().await?.error_for_status()?.json().await?;
+
+        for error in self.job_manager.set_remote_jobs(m.jobs.clone()) {
+            tracing::warn!("Invalid job from manifest (id: {:?}): {}", error.id, error.message);
+        }
+
+        let playlists = m.into_playlists();
+        if playlists.is_empty() {
+            anyhow::bail!("manifest lists no playlists");
+        }
+
+        // Download every playlist not there yet, so switching later doesn't wait for it
+        let downloader_settings = self.publisher.downloader_settings();
+        for playlist in &playlists {
+            if find_playlist(&self.paths.playlists, &playlist.id).is_none() {
+                download(playlist, &self.paths, &downloader_settings, self.min_free_bytes).await?;
+            }
+        }
+
+        // Switch when the scheduled playlist differs from current
+        let Some(selected) = select(&playlists, Utc::now()) else {
+            return Ok(None);
+        };
+        if self.kv.get_current_playlist_id()? != Some(selected.id.clone()) {
+            let Some((folder, meta)) = find_playlist(&self.paths.playlists, &selected.id) else {
+                anyhow::bail!("playlist {} from manifest is missing", selected.id);
+            };
+            self.kv.set_current_playlist_id(&meta.id)?;
+            self.player
+                .set_playlist_dir(self.paths.playlists.join(folder), SetPlaylistMode::Queue);
+            tracing::info!("updated from manifest to '{}'", meta.name);
+        }
+        Ok(Some(selected.id.clone()))
+    }
+
+    pub fn spawn(&self) {
+        let checker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let manifest = checker.settings.read().unwrap().clone();
+                let enabled = manifest.enable && manifest.url.is_some();
+                checker.status.lock().unwrap().enabled = enabled;
+
+                if enabled && let Some(url) = &manifest.url {
+                    let res = checker.check_once(&manifest, url).await;
+                    let mut status = checker.status.lock().unwrap();
+                    let now = Utc::now();
+                    status.last_check = Some(now);
+                    match res {
+                        Ok(id) => {
+                            status.last_success = Some(now);
+                            status.last_error = None;
+                            status.consecutive_failures = 0;
+                            status.manifest_id = id;
+                        }
+                        Err(error) => {
+                            status.last_error = Some(format!("{error:#}"));
+                            status.consecutive_failures += 1;
+                            tracing::warn!(
+                                "manifest check failed ({} in a row): {error:#}",
+                                status.consecutive_failures
+                            );
+                            if status.consecutive_failures == manifest.warn_after_failures {
+                                checker.notifier.warning(
+                                    ProblemKind::ManifestCheckFailed,
+                                    format!("{} checks in a row failed: {error:#}", status.consecutive_failures),
+                                );
+                            }
+                        }
+                    }
+                }
+
+                let failures = checker.status.lock().unwrap().consecutive_failures;
+                let delay = next_delay(&manifest, failures);
+                checker.status.lock().unwrap().next_check =
+                    Some(Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default()).filter(|_| enabled);
+                tokio::time::sleep(delay).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,293 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::broadcast;
+
+use crate::notifier::{Event, Notifier};
+use crate::player::PlayerHandle;
+use crate::settings::MqttSettings;
+use crate::zone::Zones;
+
+const DEFAULT_PORT: u16 = 1883;
+const RECONNECT_SECS: u64 = 15;
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Larger packets from the broker are not commands.
+const MAX_PACKET: usize = 64 * 1024;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xc0;
+const DISCONNECT: u8 = 0xe0;
+
+/// Payload of `{prefix}/command`, named like the WebSocket commands.
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Command {
+    Play,
+    Pause,
+    Prev,
+    Next,
+    SetVolume { value: f32 },
+}
+
+/// Append the MQTT variable length of a packet's remainder.
+fn put_len(buf: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s);
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(body.len() + 5);
+    buf.push(header);
+    put_len(&mut buf, body.len());
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// MQTT 3.1.1 CONNECT, with a retained `offline` status as the last will.
+fn connect_packet(settings: &MqttSettings, status_topic: &str) -> Vec<u8> {
+    let mut flags = 0x02 | 0x04 | 0x20; // Clean session, will, will retained
+    if settings.username.is_some() {
+        flags |= 0x80;
+    }
+    if settings.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = vec![];
+    put_str(&mut body, b"MQTT");
+    body.push(4);
+    body.push(flags);
+    body.extend_from_slice(&settings.keep_alive_secs.to_be_bytes());
+    put_str(&mut body, settings.client_id.as_bytes());
+    put_str(&mut body, status_topic.as_bytes());
+    put_str(&mut body, b"offline");
+    if let Some(username) = &settings.username {
+        put_str(&mut body, username.as_bytes());
+    }
+    if let Some(password) = &settings.password {
+        put_str(&mut body, password.as_bytes());
+    }
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = vec![];
+    put_str(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(PUBLISH | retain as u8, &body)
+}
+
+fn subscribe_packet(filters: &[String]) -> Vec<u8> {
+    let mut body = 1u16.to_be_bytes().to_vec();
+    for filter in filters {
+        put_str(&mut body, filter.as_bytes());
+        body.push(0); // At most once
+    }
+    packet(SUBSCRIBE, &body)
+}
+
+/// Read the next packet, as its type and flags byte and its body.
+async fn read_packet(reader: &mut (impl AsyncRead + Unpin)) -> anyhow::Result<(u8, Vec<u8>)> {
+    let header = reader.read_u8().await?;
+    let mut len = 0usize;
+    for i in 0..4 {
+        let byte = reader.read_u8().await?;
+        len |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if len > MAX_PACKET {
+        anyhow::bail!("Packet of {len} bytes from the broker");
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).await?;
+    Ok((header, body))
+}
+
+/// Topic and payload of a PUBLISH body.
+fn parse_publish(header: u8, body: &[u8]) -> Option<(&str, &[u8])> {
+    let len = u16::from_be_bytes(body.get(..2)?.try_into().ok()?) as usize;
+    let topic = std::str::from_utf8(body.get(2..2 + len)?).ok()?;
+    // Packet id, only with QoS 1 and 2
+    let start = 2 + len + if header & 0x06 != 0 { 2 } else { 0 };
+    Some((topic, body.get(start..)?))
+}
+
+/// Broker host and port from `mqtt://host:port`.
+fn broker(url: &str) -> anyhow::Result<(String, u16)> {
+    let url = Url::parse(url).with_context(|| format!("Invalid mqtt.url {url:?}"))?;
+    if url.scheme() != "mqtt" {
+        anyhow::bail!("Only mqtt:// brokers are supported, not {}://", url.scheme());
+    }
+    let host = url.host_str().context("mqtt.url has no host")?;
+    Ok((host.to_string(), url.port().unwrap_or(DEFAULT_PORT)))
+}
+
+/// Run `payload` of `topic` on the player it is for.
+fn handle_command(prefix: &str, topic: &str, payload: &[u8], player: &PlayerHandle, zones: &Zones) {
+    let Some(rest) = topic.strip_prefix(prefix).and_then(|t| t.strip_prefix('/')) else {
+        return;
+    };
+    let player = match rest.strip_suffix("/command").and_then(|t| t.strip_prefix("zones/")) {
+        Some(zone) => match zones.get(zone) {
+            Some(zone) => &zone.player,
+            None => {
+                tracing::warn!("MQTT command for unknown zone {zone:?}");
+                return;
+            }
+        },
+        None if rest == "command" => player,
+        None => return,
+    };
+
+    match serde_json::from_slice(payload) {
+        Ok(Command::Play) => player.play(),
+        Ok(Command::Pause) => player.pause(),
+        Ok(Command::Prev) => player.prev(),
+        Ok(Command::Next) => player.next(),
+        Ok(Command::SetVolume { value }) => player.set_volume(value),
+        Err(error) => tracing::warn!("Invalid MQTT command on {topic}: {error}"),
+    }
+}
+
+/// `{prefix}/event/{TYPE}`, or `{prefix}/zones/{zone}/event/{TYPE}` for the events of a zone.
+fn event_topic(prefix: &str, event: &Event) -> String {
+    match &event.zone {
+        Some(zone) => format!("{prefix}/zones/{zone}/event/{}", event.notification.kind()),
+        None => format!("{prefix}/event/{}", event.notification.kind()),
+    }
+}
+
+/// Play the commands published to the subscribed topics, until the connection is lost.
+async fn take_commands(
+    mut reader: OwnedReadHalf,
+    prefix: &str,
+    player: &PlayerHandle,
+    zones: &Zones,
+) -> anyhow::Result<()> {
+    loop {
+        let (header, body) = read_packet(&mut reader).await?;
+        if header & 0xf0 == PUBLISH
+            && let Some((topic, payload)) = parse_publish(header, &body)
+        {
+            handle_command(prefix, topic, payload, player, zones);
+        }
+    }
+}
+
+async fn run(
+    settings: &MqttSettings,
+    player: &PlayerHandle,
+    zones: &Zones,
+    rx: &mut broadcast::Receiver<Event>,
+) -> anyhow::Result<()> {
+    let (host, port) = broker(&settings.url)?;
+    let prefix = settings.topic_prefix.trim_end_matches('/').to_string();
+    let status_topic = format!("{prefix}/status");
+
+    let stream = tokio::time::timeout(
+        Duration::from_secs(CONNECT_TIMEOUT_SECS),
+        TcpStream::connect((host.as_str(), port)),
+    )
+    .await
+    .context("Timed out connecting to the broker")??;
+    stream.set_nodelay(true)?;
+    let (mut reader, mut writer) = stream.into_split();
+
+    writer.write_all(&connect_packet(settings, &status_topic)).await?;
+    let (header, body) = read_packet(&mut reader).await?;
+    match (header & 0xf0, body.get(1)) {
+        (CONNACK, Some(0)) => {}
+        (CONNACK, Some(5)) => anyhow::bail!("Broker refused the credentials"),
+        (CONNACK, code) => anyhow::bail!("Broker refused the connection ({code:?})"),
+        _ => anyhow::bail!("Expected CONNACK from the broker"),
+    }
+
+    let filters = [format!("{prefix}/command"), format!("{prefix}/zones/+/command")];
+    writer.write_all(&subscribe_packet(&filters)).await?;
+    writer
+        .write_all(&publish_packet(&status_topic, b"online", true))
+        .await?;
+    tracing::info!("Connected to MQTT broker {host}:{port}");
+
+    // Commands are taken while events are published
+    let commands = take_commands(reader, &prefix, player, zones);
+    tokio::pin!(commands);
+
+    let mut ping = tokio::time::interval(Duration::from_secs(settings.keep_alive_secs.max(2) as u64 / 2));
+    loop {
+        tokio::select! {
+            res = &mut commands => return res.context("Lost the broker"),
+            _ = ping.tick() => writer.write_all(&[PINGREQ, 0]).await?,
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    let Ok(body) = serde_json::to_vec(&event) else {
+                        continue;
+                    };
+                    writer.write_all(&publish_packet(&event_topic(&prefix, &event), &body, false)).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => tracing::warn!("MQTT missed {} notifications", n),
+                Err(broadcast::error::RecvError::Closed) => {
+                    disconnect(&mut writer, &status_topic).await;
+                    return Ok(());
+                }
+            },
+        }
+    }
+}
+
+/// Leave as `offline`, the last will only goes out when the connection drops.
+async fn disconnect(writer: &mut OwnedWriteHalf, status_topic: &str) {
+    let _ = writer.write_all(&publish_packet(status_topic, b"offline", true)).await;
+    let _ = writer.write_all(&[DISCONNECT, 0]).await;
+}
+
+/// Publish notifications to the MQTT broker and play commands from it, reconnecting when it goes away.
+pub fn spawn(settings: &MqttSettings, notifier: &Notifier, player: PlayerHandle, zones: Zones) {
+    if !settings.enable {
+        return;
+    }
+
+    let settings = settings.clone();
+    let mut rx = notifier.subscribe_with_zones(|n| !n.is_noisy());
+    tokio::spawn(async move {
+        let mut last_error = None;
+        loop {
+            match run(&settings, &player, &zones, &mut rx).await {
+                Ok(()) => return,
+                Err(error) => {
+                    // Log each new reason once while the broker is away
+                    let message = format!("{error:#}");
+                    if last_error.as_ref() != Some(&message) {
+                        tracing::warn!("MQTT unavailable: {message}");
+                        last_error = Some(message);
+                    }
+                }
+            }
+            tokio::time::sleep(Duration::from_secs(RECONNECT_SECS)).await;
+        }
+    });
+}
@@ -1,41 +1,432 @@
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 
+use crate::equalizer::EqualizerState;
+use crate::job::JobFileError;
+use crate::player::QueuedTrack;
+use crate::playlist::PlaylistMeta;
+use crate::state::JobRun;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Notification {
     Played,
     Paused,
-    TrackChanged { idx: usize, name: String },
-    TrackDurationChanged { duration: Option<Duration> },
-    PlaylistChanged { id: String, name: String },
-    PlaylistPublished { id: String, name: String },
-    SeekPositionChanged { duration: Duration },
-    VolumeChanged { value: f32 },
+    TrackChanged {
+        idx: usize,
+        name: String,
+    },
+    TrackDurationChanged {
+        duration: Option<Duration>,
+    },
+    PlaylistChanged(PlaylistSummary),
+    /// A radio station started playing instead of the playlist.
+    StationChanged {
+        id: String,
+        name: String,
+    },
+    PlaylistPublished(PlaylistSummary),
+    PlaylistUpdated(PlaylistSummary),
+    PlaylistRemoved(PlaylistSummary),
+    PublishStarted {
+        job_id: String,
+        name: String,
+    },
+    DownloadProgress {
+        job_id: String,
+        percent: Option<f32>,
+        current: Option<String>,
+    },
+    PublishCompleted {
+        job_id: String,
+        #[serde(flatten)]
+        playlist: PlaylistSummary,
+    },
+    PublishFailed {
+        job_id: String,
+        name: String,
+        error: String,
+    },
+    PublishCancelled {
+        job_id: String,
+        name: String,
+    },
+    SeekPositionChanged {
+        duration: Duration,
+    },
+    VolumeChanged {
+        value: f32,
+    },
+    /// The equalizer was turned on or off, or its gains changed.
+    EqualizerChanged(EqualizerState),
     JobsUpdated,
-    RunningJob { id: String },
+    JobsFileInvalid {
+        errors: Vec<JobFileError>,
+    },
+    RunningJob {
+        id: String,
+    },
+    /// A job succeeded, or failed after all its retries.
+    JobFinished {
+        id: String,
+        #[serde(flatten)]
+        run: JobRun,
+    },
+    JobAlert {
+        id: String,
+        reason: String,
+    },
+    SettingsReloaded,
+    /// Sent periodically; `seq` is the last numbered event, heartbeats don't take a number.
+    Heartbeat {
+        uptime: Duration,
+        seq: u64,
+    },
+    /// Less than `disk.low_free_bytes` are free on the disk holding `path`.
+    DiskSpaceLow {
+        free_bytes: u64,
+        path: PathBuf,
+    },
+    /// An alarm started ramping up, at first or after a snooze.
+    AlarmRinging {
+        playlist_id: Option<String>,
+    },
+    AlarmSnoozed {
+        until: DateTime<Utc>,
+    },
+    AlarmStopped,
+    /// The queue changed: a track was queued, one of them started playing, or it was cleared.
+    QueueChanged {
+        /// Queued track playing now.
+        playing: Option<QueuedTrack>,
+        upcoming: Vec<QueuedTrack>,
+    },
+    /// An insert clip played between tracks, or was cut short.
+    InsertPlayed {
+        file: String,
+        played: Duration,
+        skipped: bool,
+    },
+    /// A playlist track was passed over without playing.
+    TrackSkipped {
+        idx: usize,
+        name: String,
+        reason: SkipReason,
+    },
+    /// A synced lyrics line of the current track was reached.
+    LyricLine {
+        index: usize,
+        time_ms: u64,
+        text: String,
+    },
+    /// Something broke and needs attention.
+    Error {
+        kind: ProblemKind,
+        message: String,
+    },
+    /// Something may break soon, or failed and will be retried.
+    Warning {
+        kind: ProblemKind,
+        message: String,
+    },
+}
+
+/// A playlist as told by publish and playlist notifications, so clients don't need to fetch it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaylistSummary {
+    pub id: String,
+    pub name: String,
+    pub tracks: usize,
+    /// Total of the known track durations.
+    pub duration: Duration,
+    /// Number of sources it was published from, eg. urls.
+    pub sources: usize,
+}
+
+impl From<&PlaylistMeta> for PlaylistSummary {
+    fn from(meta: &PlaylistMeta) -> Self {
+        Self {
+            id: meta.id.clone(),
+            name: meta.name.clone(),
+            tracks: meta.tracks.len(),
+            duration: meta.tracks.iter().filter_map(|t| t.duration).sum(),
+            sources: meta.sources.len(),
+        }
+    }
+}
+
+/// Why a `TRACK_SKIPPED` track didn't play.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SkipReason {
+    /// Shorter than `player.min_track_secs`.
+    TooShort,
+    /// Longer than `player.max_track_secs`.
+    TooLong,
+}
+
+/// What an `ERROR` or `WARNING` notification is about.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ProblemKind {
+    ManifestCheckFailed,
+    JobFailed,
+    DiskAlmostFull,
+    AudioDeviceLost,
+    PlaylistMetaUnreadable,
+    StationUnavailable,
+}
+
+impl Notification {
+    /// The `type` this notification is serialized with, eg. `TRACK_CHANGED`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::Played => "PLAYED",
+            Notification::Paused => "PAUSED",
+            Notification::TrackChanged { .. } => "TRACK_CHANGED",
+            Notification::TrackDurationChanged { .. } => "TRACK_DURATION_CHANGED",
+            Notification::PlaylistChanged(_) => "PLAYLIST_CHANGED",
+            Notification::StationChanged { .. } => "STATION_CHANGED",
+            Notification::PlaylistPublished(_) => "PLAYLIST_PUBLISHED",
+            Notification::PlaylistUpdated(_) => "PLAYLIST_UPDATED",
+            Notification::PlaylistRemoved(_) => "PLAYLIST_REMOVED",
+            Notification::PublishStarted { .. } => "PUBLISH_STARTED",
+            Notification::DownloadProgress { .. } => "DOWNLOAD_PROGRESS",
+            Notification::PublishCompleted { .. } => "PUBLISH_COMPLETED",
+            Notification::PublishFailed { .. } => "PUBLISH_FAILED",
+            Notification::PublishCancelled { .. } => "PUBLISH_CANCELLED",
+            Notification::SeekPositionChanged { .. } => "SEEK_POSITION_CHANGED",
+            Notification::VolumeChanged { .. } => "VOLUME_CHANGED",
+            Notification::EqualizerChanged(_) => "EQUALIZER_CHANGED",
+            Notification::JobsUpdated => "JOBS_UPDATED",
+            Notification::JobsFileInvalid { .. } => "JOBS_FILE_INVALID",
+            Notification::RunningJob { .. } => "RUNNING_JOB",
+            Notification::JobFinished { .. } => "JOB_FINISHED",
+            Notification::JobAlert { .. } => "JOB_ALERT",
+            Notification::SettingsReloaded => "SETTINGS_RELOADED",
+            Notification::Heartbeat { .. } => "HEARTBEAT",
+            Notification::DiskSpaceLow { .. } => "DISK_SPACE_LOW",
+            Notification::AlarmRinging { .. } => "ALARM_RINGING",
+            Notification::AlarmSnoozed { .. } => "ALARM_SNOOZED",
+            Notification::AlarmStopped => "ALARM_STOPPED",
+            Notification::QueueChanged { .. } => "QUEUE_CHANGED",
+            Notification::InsertPlayed { .. } => "INSERT_PLAYED",
+            Notification::TrackSkipped { .. } => "TRACK_SKIPPED",
+            Notification::LyricLine { .. } => "LYRIC_LINE",
+            Notification::Error { .. } => "ERROR",
+            Notification::Warning { .. } => "WARNING",
+        }
+    }
+
+    /// Sent every half second or every lyrics line while playing, or periodically;
+    /// webhooks and the journal skip them unless asked for.
+    pub fn is_noisy(&self) -> bool {
+        matches!(
+            self,
+            Notification::SeekPositionChanged { .. } | Notification::Heartbeat { .. } | Notification::LyricLine { .. }
+        )
+    }
+}
+
+/// Number of recent events kept for clients catching up after a reconnect.
+const REPLAY_CAPACITY: usize = 500;
+
+/// Events buffered per subscriber before a slow one lags.
+const CHANNEL_CAPACITY: usize = 1000;
+
+/// A notification with its sequence number, which increases by one for every notification, and the time it was sent.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    /// Zone whose player sent it, unset for the main player and the rest of the daemon.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+    #[serde(flatten)]
+    pub notification: Notification,
+}
+
+type Filter = Box<dyn Fn(&Notification) -> bool + Send + Sync>;
+
+/// A subscriber to some kinds of notifications, with a channel of its own.
+struct Filtered {
+    filter: Filter,
+    tx: broadcast::Sender<Event>,
+    /// Also gets the events of zones.
+    zones: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    seq: u64,
+    events: VecDeque<Event>,
+    filtered: Vec<Filtered>,
 }
 
-/// Wrapper around a broadcast channel
+/// Wrapper around a broadcast channel of every notification, and filtered ones for subscribers that
+/// only want some, so they don't lag behind on frequent notifications they'd skip anyway.
 #[derive(Clone)]
 pub struct Notifier {
-    pub tx: broadcast::Sender<Notification>,
+    tx: broadcast::Sender<Event>,
+    shared: Arc<Mutex<Shared>>,
+    /// For a zone, its name and the daemon's notifier its events are passed on to.
+    upstream: Option<Arc<(String, Notifier)>>,
 }
 
 impl Notifier {
     pub fn new() -> Self {
-        let (tx, _rx) = broadcast::channel(1000);
-        Self { tx }
+        let (tx, _rx) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            tx,
+            shared: Arc::new(Mutex::new(Shared::default())),
+            upstream: None,
+        }
     }
 
-    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
-        self.tx.subscribe()
+    /// A notifier for the player of `zone`, with its own events and replay for `/zones/{zone}/ws`.
+    /// Its events are also passed on to the subscribers of this one that want zones, marked with the zone.
+    pub fn for_zone(&self, zone: &str) -> Self {
+        Self {
+            upstream: Some(Arc::new((zone.to_string(), self.clone()))),
+            ..Self::new()
+        }
+    }
+
+    /// Subscribe to new events, also returning the kept events after `since_seq`.
+    /// Events older than the kept ones are lost; clients can tell by a gap in `seq`.
+    pub fn subscribe(&self, since_seq: Option<u64>) -> (Vec<Event>, broadcast::Receiver<Event>) {
+        // Hold the lock so no event is both missed and replayed
+        let shared = self.shared.lock().unwrap();
+        let rx = self.tx.subscribe();
+        let missed = match since_seq {
+            Some(since) => shared.events.iter().filter(|e| e.seq > since).cloned().collect(),
+            None => vec![],
+        };
+        (missed, rx)
+    }
+
+    /// Subscribe to new events of the notifications `filter` accepts.
+    pub fn subscribe_filtered(
+        &self,
+        filter: impl Fn(&Notification) -> bool + Send + Sync + 'static,
+    ) -> broadcast::Receiver<Event> {
+        self.add_filtered(Box::new(filter), false)
+    }
+
+    /// Like `subscribe_filtered`, also getting the events of zones, eg. for webhooks.
+    pub fn subscribe_with_zones(
+        &self,
+        filter: impl Fn(&Notification) -> bool + Send + Sync + 'static,
+    ) -> broadcast::Receiver<Event> {
+        self.add_filtered(Box::new(filter), true)
+    }
+
+    fn add_filtered(&self, filter: Filter, zones: bool) -> broadcast::Receiver<Event> {
+        let (tx, rx) = broadcast::channel(CHANNEL_CAPACITY);
+        self.shared
+            .lock()
+            .unwrap()
+            .filtered
+            .push(Filtered { filter, tx, zones });
+        rx
     }
 
     pub fn notify(&self, notification: Notification) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.seq += 1;
+        let event = Event {
+            seq: shared.seq,
+            at: Utc::now(),
+            zone: self.upstream.as_ref().map(|upstream| upstream.0.clone()),
+            notification,
+        };
+
+        if shared.events.len() == REPLAY_CAPACITY {
+            shared.events.pop_front();
+        }
+        shared.events.push_back(event.clone());
+
+        if let Some(upstream) = &self.upstream {
+            upstream.1.pass_on(&event);
+        }
+        self.send(&mut shared, event);
+    }
+
+    /// Send an event of a zone to the subscribers that want zones. It keeps the zone's `seq`,
+    /// and isn't kept for replay nor sent to `/ws`, which only has the main player's events.
+    fn pass_on(&self, event: &Event) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.filtered.retain(|f| f.tx.receiver_count() > 0);
+        for filtered in shared.filtered.iter().filter(|f| f.zones) {
+            if (filtered.filter)(&event.notification) {
+                let _ = filtered.tx.send(event.clone());
+            }
+        }
+    }
+
+    /// Send to every subscriber that wants `event`, while holding the lock so they get events in order.
+    fn send(&self, shared: &mut Shared, event: Event) {
+        // Forget filtered subscribers that are gone
+        shared.filtered.retain(|f| f.tx.receiver_count() > 0);
+        for filtered in &shared.filtered {
+            if (filtered.filter)(&event.notification) {
+                let _ = filtered.tx.send(event.clone());
+            }
+        }
+
         // Ignore error if there are no active subscribers
-        let _ = self.tx.send(notification);
+        let _ = self.tx.send(event);
+    }
+
+    /// Send a heartbeat to current subscribers only, it isn't kept for replay.
+    fn heartbeat(&self, uptime: Duration) {
+        let mut shared = self.shared.lock().unwrap();
+        let seq = shared.seq;
+        let event = Event {
+            seq,
+            at: Utc::now(),
+            zone: None,
+            notification: Notification::Heartbeat { uptime, seq },
+        };
+        self.send(&mut shared, event);
+    }
+
+    /// Send a heartbeat every `interval_secs`, so clients can tell an idle daemon from a dead connection.
+    pub fn spawn_heartbeat(&self, interval_secs: u64) {
+        if interval_secs == 0 {
+            return;
+        }
+
+        let notifier = self.clone();
+        let started = Instant::now();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            // The first tick is immediate
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                notifier.heartbeat(started.elapsed());
+            }
+        });
+    }
+
+    pub fn error(&self, kind: ProblemKind, message: impl Into<String>) {
+        self.notify(Notification::Error {
+            kind,
+            message: message.into(),
+        });
+    }
+
+    pub fn warning(&self, kind: ProblemKind, message: impl Into<String>) {
+        self.notify(Notification::Warning {
+            kind,
+            message: message.into(),
+        });
     }
 }
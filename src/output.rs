@@ -0,0 +1,246 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::mixer::{Mixer, MixerSource};
+use rodio::{OutputStream, OutputStreamBuilder, Source, cpal};
+use serde::Serialize;
+
+use crate::notifier::{Notifier, ProblemKind};
+use crate::streamer::SampleTap;
+
+const CHANNELS: u16 = 2;
+/// Sample rate of the player's mixer, and of the samples tapped for streaming.
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Without a device, samples are pulled in chunks of this length.
+const SILENT_CHUNK_MS: u32 = 20;
+/// Behind by more than this without a device, the clock starts over instead of catching up.
+const MAX_LAG_MS: u64 = 1000;
+/// Wait for the output thread to open a device.
+const SWITCH_TIMEOUT_SECS: u64 = 10;
+
+/// An audio output device of this machine.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputDevice {
+    pub name: String,
+    /// Whether it's the system's default output device.
+    pub default: bool,
+}
+
+/// Output devices of the default host, by name.
+pub fn devices() -> anyhow::Result<Vec<OutputDevice>> {
+    let host = cpal::default_host();
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let mut devices: Vec<OutputDevice> = host
+        .output_devices()?
+        .filter_map(|d| d.name().ok())
+        .map(|name| OutputDevice {
+            default: default.as_ref() == Some(&name),
+            name,
+        })
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices.dedup_by(|a, b| a.name == b.name);
+    Ok(devices)
+}
+
+/// Open the output device named `device`, the default device when unset.
+fn open_stream(
+    device: Option<&str>,
+    on_error: impl FnMut(cpal::StreamError) + Send + Clone + 'static,
+) -> anyhow::Result<OutputStream> {
+    match device {
+        Some(name) => {
+            let device = cpal::default_host()
+                .output_devices()?
+                .find(|d| d.name().is_ok_and(|n| n == name))
+                .with_context(|| format!("No output device named {name:?}"))?;
+            Ok(OutputStreamBuilder::from_device(device)?
+                .with_error_callback(on_error)
+                .open_stream_or_fallback()?)
+        }
+        None => Ok(OutputStreamBuilder::from_default_device()
+            .and_then(|b| b.with_error_callback(on_error).open_stream_or_fallback())
+            .or_else(|_| OutputStreamBuilder::open_default_stream())?),
+    }
+}
+
+/// The player's mixer as played by one device. Ends once another device took over.
+struct Attached {
+    source: Arc<Mutex<MixerSource>>,
+    generation: Arc<AtomicU64>,
+    attached: u64,
+    tap: Option<SampleTap>,
+}
+
+impl Iterator for Attached {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.generation.load(Ordering::Relaxed) != self.attached {
+            return None;
+        }
+        let sample = self.source.lock().unwrap().next();
+        if let (Some(tap), Some(sample)) = (&mut self.tap, sample) {
+            tap.push(sample);
+        }
+        sample
+    }
+}
+
+impl Source for Attached {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// The mixer's output, played by one device at a time.
+struct Shared {
+    source: Arc<Mutex<MixerSource>>,
+    generation: Arc<AtomicU64>,
+    tap: Option<SampleTap>,
+}
+
+impl Shared {
+    /// The output for a new device; the previous one stops pulling samples.
+    fn attach(&self) -> Attached {
+        Attached {
+            source: self.source.clone(),
+            generation: self.generation.clone(),
+            attached: self.generation.fetch_add(1, Ordering::SeqCst) + 1,
+            tap: self.tap.clone(),
+        }
+    }
+}
+
+type SwitchRequest = (Option<String>, crossbeam_channel::Sender<anyhow::Result<()>>);
+
+/// Switches the device a mixer of `open` plays on.
+#[derive(Clone)]
+pub struct DeviceOutput {
+    tx: crossbeam_channel::Sender<SwitchRequest>,
+    device: Arc<Mutex<Option<String>>>,
+}
+
+impl DeviceOutput {
+    /// Play on the device named `device`, the default device when unset.
+    /// Playback goes on where it was; the previous device stays when the new one fails to open.
+    pub fn switch(&self, device: Option<String>) -> anyhow::Result<()> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        self.tx
+            .send((device, tx))
+            .map_err(|_| anyhow::anyhow!("Audio output stopped"))?;
+        rx.recv_timeout(Duration::from_secs(SWITCH_TIMEOUT_SECS))
+            .context("Timed out opening the output device")?
+    }
+
+    /// Name of the device played on, unset for the default device.
+    pub fn device(&self) -> Option<String> {
+        self.device.lock().unwrap().clone()
+    }
+}
+
+fn on_stream_error(notifier: Notifier) -> impl FnMut(cpal::StreamError) + Send + Clone + 'static {
+    move |error: cpal::StreamError| {
+        tracing::error!("Audio stream error: {error}");
+        if matches!(error, cpal::StreamError::DeviceNotAvailable) {
+            notifier.error(ProblemKind::AudioDeviceLost, error.to_string());
+        }
+    }
+}
+
+/// A mixer for the player's sinks, played on `device` (the default device when unset).
+/// The stream owns the OS device and lives on its own thread, which rebuilds it on a switch.
+/// What's played is also handed to `tap`; with a tap and no device, it plays in real time into nothing.
+pub fn open(
+    device: Option<String>,
+    notifier: Notifier,
+    tap: Option<SampleTap>,
+) -> anyhow::Result<(Mixer, DeviceOutput)> {
+    let (mixer, source) = rodio::mixer::mixer(CHANNELS, SAMPLE_RATE);
+    // Keeps the mixer going while nothing plays
+    mixer.add(rodio::source::Zero::new(CHANNELS, SAMPLE_RATE));
+
+    let (tx, rx) = crossbeam_channel::unbounded::<SwitchRequest>();
+    let output = DeviceOutput {
+        tx,
+        device: Arc::new(Mutex::new(device.clone())),
+    };
+    let current = output.device.clone();
+    let shared = Shared {
+        source: Arc::new(Mutex::new(source)),
+        generation: Arc::new(AtomicU64::new(0)),
+        tap,
+    };
+
+    thread::Builder::new().name("musicd-output".into()).spawn(move || {
+        let mut _stream = match open_stream(device.as_deref(), on_stream_error(notifier.clone())) {
+            Ok(stream) => {
+                stream.mixer().add(shared.attach());
+                Some(stream)
+            }
+            Err(error) => {
+                eprintln!("Audio init error: {error:?}");
+                notifier.error(ProblemKind::AudioDeviceLost, format!("No audio output: {error:#}"));
+                None
+            }
+        };
+
+        // Without a device, samples are pulled here at the sample rate, so the stream goes on
+        let mut silent = (_stream.is_none() && shared.tap.is_some()).then(|| shared.attach());
+        let frames = (SAMPLE_RATE * SILENT_CHUNK_MS / 1000) as u64;
+        let mut start = Instant::now();
+        let mut pulled = 0u64;
+        loop {
+            let request = match silent.as_mut() {
+                Some(source) => {
+                    source.by_ref().take(frames as usize * CHANNELS as usize).for_each(drop);
+                    pulled += frames;
+                    let due = start + Duration::from_micros(pulled * 1_000_000 / SAMPLE_RATE as u64);
+                    let now = Instant::now();
+                    if now > due + Duration::from_millis(MAX_LAG_MS) {
+                        start = now;
+                        pulled = 0;
+                    }
+                    match rx.recv_timeout(due.saturating_duration_since(now)) {
+                        Ok(request) => request,
+                        Err(crossbeam_channel::RecvTimeoutError::Timeout) => continue,
+                        Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match rx.recv() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                },
+            };
+
+            let (device, reply) = request;
+            let res = open_stream(device.as_deref(), on_stream_error(notifier.clone())).map(|new| {
+                new.mixer().add(shared.attach());
+                // Dropping the old stream releases its device
+                _stream = Some(new);
+                silent = None;
+                tracing::info!("Playing on output device {}", device.as_deref().unwrap_or("(default)"));
+                *current.lock().unwrap() = device;
+            });
+            let _ = reply.send(res);
+        }
+    })?;
+
+    Ok((mixer, output))
+}
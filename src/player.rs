@@ -1,4 +1,5 @@
 use std::{
+    collections::VecDeque,
     fs::File,
     io::BufReader,
     path::{Path, PathBuf},
@@ -7,11 +8,19 @@ use std::{
     time::{Duration, Instant},
 };
 
-use rodio::{OutputStreamBuilder, Sink, Source, decoder::DecoderBuilder, source::LimitSettings};
+use chrono::{DateTime, Utc};
+use rodio::mixer::Mixer;
+use rodio::{Sink, Source, decoder::DecoderBuilder, source::LimitSettings};
 use serde::{Deserialize, Serialize};
 
-use crate::notifier::{Notification, Notifier};
-use crate::playlist::PlaylistMeta;
+use crate::equalizer::{Equalizer, EqualizerChange, EqualizerState};
+use crate::insert::InsertScheduler;
+use crate::notifier::{Notification, Notifier, ProblemKind, SkipReason};
+use crate::output::DeviceOutput;
+use crate::playlist::{PlaylistMeta, PlaylistSettings, TrackMeta, loudness, names};
+use crate::settings::{EqualizerSettings, InsertSettings, PlayerSettings, SnapcastSettings};
+use crate::state::{CurrentStatus, HistoryEntry, State as Kv, Station, TrackProgress};
+use crate::streamer::SampleTap;
 
 #[derive(Clone)]
 pub struct PlayerHandle {
@@ -22,6 +31,51 @@ pub struct PlayerHandle {
 pub struct PlayerConfig {
     pub auto_play: bool,
     pub default_audio_effects: bool,
+    pub gapless: bool,
+    pub skip_below_rating: Option<u8>,
+    pub min_track_secs: Option<u64>,
+    pub max_track_secs: Option<u64>,
+    pub loudness_target: Option<f32>,
+    pub ffmpeg_path: PathBuf,
+    pub inserts: InsertSettings,
+    pub equalizer: EqualizerSettings,
+}
+
+impl From<&PlayerSettings> for PlayerConfig {
+    fn from(settings: &PlayerSettings) -> Self {
+        Self {
+            auto_play: settings.auto_play,
+            default_audio_effects: settings.default_audio_effects,
+            gapless: settings.gapless,
+            skip_below_rating: settings.skip_below_rating,
+            min_track_secs: settings.min_track_secs,
+            max_track_secs: settings.max_track_secs,
+            loudness_target: settings.loudness_target,
+            ffmpeg_path: settings.ffmpeg_path.clone(),
+            inserts: settings.inserts.clone(),
+            equalizer: settings.equalizer.clone(),
+        }
+    }
+}
+
+impl PlayerConfig {
+    /// Why `track` is skipped for its duration, None when it's within bounds or its duration isn't known.
+    fn duration_skip(&self, track: &TrackMeta) -> Option<SkipReason> {
+        let duration = track.duration?;
+        if self
+            .min_track_secs
+            .is_some_and(|min| duration < Duration::from_secs(min))
+        {
+            Some(SkipReason::TooShort)
+        } else if self
+            .max_track_secs
+            .is_some_and(|max| duration > Duration::from_secs(max))
+        {
+            Some(SkipReason::TooLong)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, clap::ValueEnum)]
@@ -32,23 +86,117 @@ pub enum SetPlaylistMode {
     Skip,
 }
 
+/// Where to seek to in the current track.
+#[derive(Debug, Clone, Copy)]
+pub enum SeekTarget {
+    /// Seconds from the start.
+    Secs(u64),
+    /// Seconds forward, or back when negative, from the current position.
+    Relative(i64),
+    /// Percent of the track's duration.
+    Percent(f32),
+}
+
+impl SeekTarget {
+    /// The position to seek to from `pos`, none for a percent of a track of unknown duration.
+    fn position(&self, pos: Duration, total: Option<Duration>) -> Option<Duration> {
+        let target = match *self {
+            SeekTarget::Secs(secs) => Duration::from_secs(secs),
+            SeekTarget::Relative(secs) if secs < 0 => pos.saturating_sub(Duration::from_secs(secs.unsigned_abs())),
+            SeekTarget::Relative(secs) => pos + Duration::from_secs(secs as u64),
+            SeekTarget::Percent(percent) => total?.mul_f32(percent.clamp(0.0, 100.0) / 100.0),
+        };
+        // Stay within the track, when its duration is known
+        Some(total.map_or(target, |total| target.min(total)))
+    }
+}
+
 enum PlayerCommand {
     Play,
     Pause,
     Prev,
     Next,
-    Seek(u64),
+    Seek(SeekTarget),
     SetVolume(f32),
     SetIndex(usize),
+    Reload,
+    SetConfig(PlayerConfig),
+    /// Stop the current track to play a station.
+    Switch,
+    /// Save the position now, replying once saved.
+    Checkpoint(crossbeam_channel::Sender<()>),
+}
+
+impl PlayerCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            PlayerCommand::Play => "play",
+            PlayerCommand::Pause => "pause",
+            PlayerCommand::Prev => "prev",
+            PlayerCommand::Next => "next",
+            PlayerCommand::Seek(_) => "seek",
+            PlayerCommand::SetVolume(_) => "set_volume",
+            PlayerCommand::SetIndex(_) => "set_index",
+            PlayerCommand::Reload => "reload",
+            PlayerCommand::SetConfig(_) => "set_config",
+            PlayerCommand::Switch => "switch",
+            PlayerCommand::Checkpoint(_) => "checkpoint",
+        }
+    }
 }
 
 struct PlayerInner {
     /// Path to active playlist dir
     playlist_dir: RwLock<Option<PathBuf>>,
+    /// Station playing instead of the playlist
+    station: RwLock<Option<Station>>,
+    /// Tracks lined up to play next
+    queue: Mutex<VecDeque<QueuedTrack>>,
     /// Current index state exposed for status
     status: Mutex<PlayerStatus>,
     /// Signal channels
     tx: crossbeam_channel::Sender<PlayerCommand>,
+    notifier: Notifier,
+    /// Switches the output device, unless playing into Snapcast
+    device_output: Option<DeviceOutput>,
+    /// Applied to every track and station played
+    equalizer: Equalizer,
+}
+
+impl PlayerInner {
+    fn upcoming(&self) -> Vec<QueuedTrack> {
+        match self.queue.lock() {
+            Ok(queue) => queue.iter().cloned().collect(),
+            Err(error) => {
+                tracing::warn!("Failed to obtain queue lock: {:?}", error);
+                vec![]
+            }
+        }
+    }
+
+    fn pop_queued(&self) -> Option<QueuedTrack> {
+        self.queue.lock().ok()?.pop_front()
+    }
+
+    fn notify_queue(&self, playing: Option<QueuedTrack>) {
+        self.notifier.notify(Notification::QueueChanged {
+            playing,
+            upcoming: self.upcoming(),
+        });
+    }
+}
+
+/// A track lined up to play after the current one, from any playlist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedTrack {
+    pub playlist_id: String,
+    pub playlist_name: String,
+    pub file: String,
+    pub title: String,
+    pub duration: Option<Duration>,
+    /// Dir of the playlist.
+    #[serde(skip)]
+    pub dir: PathBuf,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -57,42 +205,655 @@ pub struct PlayerStatus {
     pub playlist_name: Option<String>,
     pub current_index: usize,
     pub current_track: Option<String>,
+    /// Tags and duration of the current track, as probed at publish time.
+    pub current_meta: Option<TrackMeta>,
     pub current_pos: Option<Duration>,
     pub total_duration: Option<Duration>,
     pub is_paused: Option<bool>,
     pub volume: Option<f32>,
+    pub station: Option<Station>,
+    /// Insert clip playing between tracks.
+    pub insert: Option<String>,
+    /// Track of the queue playing instead of the playlist.
+    pub queued: Option<QueuedTrack>,
+    /// Tracks lined up to play next.
+    pub queue: Vec<QueuedTrack>,
 }
 
 const RETRY_DURATION_S: u64 = 2;
 const TICK_DURATION_MS: u64 = 100;
 const POSITION_UPDATE_DURATION_MS: u64 = 500;
+const CHECKPOINT_DURATION_S: u64 = 5;
+/// The next track is lined up this long before the current one ends, for gapless playback.
+const PRELOAD_DURATION_S: u64 = 5;
+
+fn record_history(
+    kv: &Kv,
+    zone: Option<&str>,
+    meta: &PlaylistMeta,
+    track: &TrackMeta,
+    started_at: DateTime<Utc>,
+    listened: Duration,
+    skipped: bool,
+) {
+    let entry = HistoryEntry {
+        at: Utc::now(),
+        started_at: Some(started_at),
+        zone: zone.map(str::to_string),
+        playlist_id: meta.id.clone(),
+        playlist_name: meta.name.clone(),
+        file: track.file.clone(),
+        title: track.display_name(),
+        listened,
+        skipped,
+    };
+    if let Err(error) = kv.append_history(&entry) {
+        tracing::warn!("Failed to record history: {:?}", error);
+    }
+}
+
+fn save_checkpoint(kv: &Kv, zone: Option<&str>, meta: &PlaylistMeta, idx: usize, track: &TrackMeta, sink: &Sink) {
+    let status = CurrentStatus {
+        playlist_id: Some(meta.id.clone()),
+        track_path: Some(track.file.clone()),
+        track_index: idx,
+        position: Some(sink.get_pos()),
+        is_paused: sink.is_paused(),
+        volume: Some(sink.volume()),
+        station_id: None,
+    };
+    if let Err(error) = kv.set_current_status(zone, &status) {
+        tracing::warn!("Failed to save player checkpoint: {:?}", error);
+    }
+}
+
+/// Remember the position within `track`, for long-form playlists only.
+fn save_progress(kv: &Kv, meta: &PlaylistMeta, track: &TrackMeta, position: Duration, finished: bool) {
+    if !meta.long_form {
+        return;
+    }
+    let progress = TrackProgress {
+        position,
+        updated_at: Utc::now(),
+        finished,
+    };
+    if let Err(error) = kv.set_progress(&meta.id, &track.file, &progress) {
+        tracing::warn!("Failed to save track progress: {:?}", error);
+    }
+}
+
+/// Append `source` of `track` to `sink`, normalized to the loudness target, through the `equalizer` and with
+/// the audio effects of `config`, as adjusted by the `settings` of its playlist.
+fn append_track(
+    sink: &Sink,
+    source: impl Source + Send + 'static,
+    track: &TrackMeta,
+    config: &PlayerConfig,
+    settings: &PlaylistSettings,
+    equalizer: &Equalizer,
+) {
+    // Loudness normalization, for tracks that were measured
+    let gain_db = config
+        .loudness_target
+        .zip(track.loudness.zip(track.true_peak))
+        .map(|(target, (loudness, true_peak))| loudness::gain_db(loudness, true_peak, target))
+        .unwrap_or(0.0)
+        + settings.volume_offset_db.unwrap_or(0.0);
+    let source = equalizer.apply(source.amplify(10f32.powf(gain_db / 20.0)));
+
+    // Audio effects
+    if settings.audio_effects.unwrap_or(config.default_audio_effects) {
+        let limit_settings = LimitSettings::default()
+            .with_threshold(-1.0) // Higher threshold (less limiting)
+            .with_knee_width(8.0) // Wide knee (softer)
+            .with_attack(Duration::from_millis(20)) // Slower attack
+            .with_release(Duration::from_millis(200)); // Slower release
+        let mixed_source = source.automatic_gain_control(1.0, 4.0, 0.1, 5.0).limit(limit_settings);
+        sink.append(mixed_source);
+    } else {
+        sink.append(source);
+    }
+}
+
+/// The playlist track appended to the sink of the current one, to follow it without a gap.
+struct Preloaded {
+    file: String,
+    total_duration: Option<Duration>,
+    /// Its own sink, paused until the crossfade starts, when the playlist crossfades.
+    sink: Option<Sink>,
+}
+
+/// Append the track after `idx` of `meta` to `sink`, skipping low rated ones as the playlist does.
+/// Tracks skipped for their duration aren't lined up, leaving them to the playlist to skip and tell.
+/// With a crossfade, it goes into a sink of its own instead, fading in once played.
+/// Nothing is appended when something else is due in between: a queued track, another playlist or a station.
+fn preload_next(
+    ctx: &PlaybackContext,
+    dir: &Path,
+    meta: &PlaylistMeta,
+    idx: usize,
+    sink: &Sink,
+    config: &PlayerConfig,
+) -> Option<Preloaded> {
+    if !ctx.inner.queue.lock().ok()?.is_empty()
+        || ctx.inner.station.try_read().ok()?.is_some()
+        || ctx.inner.playlist_dir.try_read().ok()?.as_deref() != Some(dir)
+    {
+        return None;
+    }
+
+    let len = meta.tracks.len();
+    let track = (1..=len).map(|i| &meta.tracks[(idx + i) % len]).find(|t| {
+        config.skip_below_rating.is_none_or(|min| {
+            ctx.kv
+                .get_rating(&meta.id, &t.file)
+                .ok()
+                .flatten()
+                .is_none_or(|r| r >= min)
+        })
+    })?;
+    if config.duration_skip(track).is_some() {
+        return None;
+    }
+    let path = names::resolve_track(dir, track).unwrap_or_else(|| dir.join(&track.file));
+    let source = match File::open(&path).map_err(anyhow::Error::from).and_then(|f| {
+        Ok(DecoderBuilder::new()
+            .with_data(BufReader::new(f))
+            .with_seekable(true)
+            .build()?)
+    }) {
+        Ok(source) => source,
+        Err(error) => {
+            tracing::warn!("Failed to preload {}: {error:#}", path.display());
+            return None;
+        }
+    };
+
+    tracing::info!("Preload next track {}", track.file);
+    let total_duration = source.total_duration();
+    let own_sink = match meta.settings.crossfade() {
+        Some(crossfade) => {
+            let own_sink = Sink::connect_new(ctx.mixer);
+            own_sink.pause();
+            append_track(
+                &own_sink,
+                source.fade_in(crossfade),
+                track,
+                config,
+                &meta.settings,
+                &ctx.inner.equalizer,
+            );
+            Some(own_sink)
+        }
+        None => {
+            append_track(sink, source, track, config, &meta.settings, &ctx.inner.equalizer);
+            None
+        }
+    };
+    Some(Preloaded {
+        file: track.file.clone(),
+        total_duration,
+        sink: own_sink,
+    })
+}
+
+/// Play `entry` of the queue between tracks. Commands apply to it as to playlist tracks and next skips it;
+/// prev, set index and the like end it and are handled by the playlist.
+/// Returns whether the queue goes on, `false` when such a command ended it.
+fn play_queued(entry: &QueuedTrack, ctx: &PlaybackContext, config: &mut PlayerConfig, volume: &mut f32) -> bool {
+    let meta_path = entry.dir.join("playlist.json");
+    let meta = match PlaylistMeta::load(&meta_path) {
+        Ok(meta) => meta,
+        Err(error) => {
+            tracing::warn!("Failed to read {}: {error:#}", meta_path.display());
+            return true;
+        }
+    };
+    let Some(track) = meta.tracks.iter().find(|t| t.file == entry.file).cloned() else {
+        tracing::warn!("Queued track {} is no longer in '{}'", entry.file, meta.name);
+        return true;
+    };
+    let path = names::resolve_track(&entry.dir, &track).unwrap_or_else(|| entry.dir.join(&track.file));
+    let source = match File::open(&path).map_err(anyhow::Error::from).and_then(|f| {
+        Ok(DecoderBuilder::new()
+            .with_data(BufReader::new(f))
+            .with_seekable(true)
+            .build()?)
+    }) {
+        Ok(source) => source,
+        Err(error) => {
+            tracing::warn!("Failed to play queued track {}: {error:#}", path.display());
+            return true;
+        }
+    };
+
+    tracing::info!("Play queued track {}", track.file);
+    let total_duration = source.total_duration().or(track.duration);
+    ctx.inner.notify_queue(Some(entry.clone()));
+    ctx.notifier.notify(Notification::TrackDurationChanged {
+        duration: source.total_duration(),
+    });
+    if let Ok(mut s) = ctx.inner.status.try_lock() {
+        s.queued = Some(entry.clone());
+        s.current_track = Some(track.file.clone());
+        s.current_meta = Some(track.clone());
+        s.total_duration = source.total_duration();
+    }
+
+    let sink = Sink::connect_new(ctx.mixer);
+    append_track(&sink, source, &track, config, &meta.settings, &ctx.inner.equalizer);
+    sink.set_volume(*volume);
+    if !config.auto_play {
+        sink.pause();
+        ctx.notifier.notify(Notification::Paused);
+    }
+    let started_at = Utc::now();
+
+    let position_update_duration = Duration::from_millis(POSITION_UPDATE_DURATION_MS);
+    let mut last_position_update_time = Instant::now();
+    let mut skipped = false;
+    let mut goes_on = true;
+    while !sink.empty() {
+        if let Ok(mut s) = ctx.inner.status.try_lock() {
+            s.current_pos = Some(sink.get_pos());
+            s.is_paused = Some(sink.is_paused());
+            s.volume = Some(sink.volume());
+        }
+        if last_position_update_time.elapsed() >= position_update_duration {
+            ctx.notifier.notify(Notification::SeekPositionChanged {
+                duration: sink.get_pos(),
+            });
+            last_position_update_time = Instant::now();
+        }
+
+        match ctx.rx.try_recv() {
+            Ok(PlayerCommand::Play) => {
+                sink.play();
+                ctx.notifier.notify(Notification::Played);
+            }
+            Ok(PlayerCommand::Pause) => {
+                sink.pause();
+                ctx.notifier.notify(Notification::Paused);
+            }
+            Ok(PlayerCommand::Seek(target)) => match target.position(sink.get_pos(), total_duration) {
+                Some(duration) => match sink.try_seek(duration) {
+                    Ok(()) => ctx.notifier.notify(Notification::SeekPositionChanged { duration }),
+                    Err(error) => tracing::warn!("Seek error: {:?}", error),
+                },
+                None => tracing::warn!("Cannot seek to {target:?}, the track's duration is unknown"),
+            },
+            Ok(PlayerCommand::SetVolume(value)) => {
+                let value = value.clamp(0.0, 1.0);
+                sink.set_volume(value);
+                *volume = value;
+                ctx.notifier.notify(Notification::VolumeChanged { value });
+            }
+            Ok(PlayerCommand::SetConfig(new_config)) => *config = new_config,
+            Ok(PlayerCommand::Checkpoint(done)) => {
+                let _ = done.send(());
+            }
+            Ok(PlayerCommand::Next) => {
+                skipped = true;
+                break;
+            }
+            Ok(command) => {
+                // For the playlist
+                skipped = true;
+                goes_on = false;
+                let _ = ctx.inner.tx.send(command);
+                break;
+            }
+            Err(crossbeam_channel::TryRecvError::Empty) => {}
+            Err(error) => tracing::warn!("Player command channel recv error: {:?}", error),
+        }
+        thread::sleep(Duration::from_millis(TICK_DURATION_MS));
+    }
+
+    let listened = if skipped {
+        sink.get_pos()
+    } else {
+        track.duration.unwrap_or(sink.get_pos())
+    };
+    sink.stop();
+    record_history(ctx.kv, ctx.zone, &meta, &track, started_at, listened, skipped);
+    save_progress(ctx.kv, &meta, &track, listened, !skipped);
+    if let Ok(mut s) = ctx.inner.status.try_lock() {
+        s.queued = None;
+    }
+    goes_on
+}
+
+/// Play the insert clip at `path` between tracks. Play, pause and volume apply to it and next skips it;
+/// other commands end it and are handled by the track after it.
+fn play_insert(
+    path: &Path,
+    inner: &PlayerInner,
+    rx: &crossbeam_channel::Receiver<PlayerCommand>,
+    mixer: &Mixer,
+    notifier: &Notifier,
+    config: &mut PlayerConfig,
+    volume: &mut f32,
+) {
+    let file = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let source = match File::open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|f| Ok(DecoderBuilder::new().with_data(BufReader::new(f)).build()?))
+    {
+        Ok(source) => source,
+        Err(error) => {
+            tracing::warn!("Failed to play insert {}: {error:#}", path.display());
+            return;
+        }
+    };
+
+    tracing::info!("Play insert {}", file);
+    let sink = Sink::connect_new(mixer);
+    sink.append(source);
+    sink.set_volume(*volume);
+    if let Ok(mut s) = inner.status.try_lock() {
+        s.insert = Some(file.clone());
+    }
+
+    let mut skipped = false;
+    while !sink.empty() {
+        if let Ok(mut s) = inner.status.try_lock() {
+            s.is_paused = Some(sink.is_paused());
+        }
+        match rx.try_recv() {
+            Ok(PlayerCommand::Play) => {
+                sink.play();
+                notifier.notify(Notification::Played);
+            }
+            Ok(PlayerCommand::Pause) => {
+                sink.pause();
+                notifier.notify(Notification::Paused);
+            }
+            Ok(PlayerCommand::SetVolume(value)) => {
+                let value = value.clamp(0.0, 1.0);
+                sink.set_volume(value);
+                *volume = value;
+                notifier.notify(Notification::VolumeChanged { value });
+            }
+            Ok(PlayerCommand::SetConfig(new_config)) => *config = new_config,
+            Ok(PlayerCommand::Checkpoint(done)) => {
+                let _ = done.send(());
+            }
+            Ok(PlayerCommand::Next) => {
+                skipped = true;
+                break;
+            }
+            Ok(command) => {
+                // For the next track
+                skipped = true;
+                let _ = inner.tx.send(command);
+                break;
+            }
+            Err(crossbeam_channel::TryRecvError::Empty) => {}
+            Err(error) => tracing::warn!("Player command channel recv error: {:?}", error),
+        }
+        thread::sleep(Duration::from_millis(TICK_DURATION_MS));
+    }
+
+    let played = sink.get_pos();
+    sink.stop();
+    if let Ok(mut s) = inner.status.try_lock() {
+        s.insert = None;
+    }
+    notifier.notify(Notification::InsertPlayed { file, played, skipped });
+}
+
+fn save_station_checkpoint(kv: &Kv, zone: Option<&str>, station: &Station, paused: bool, volume: f32) {
+    let status = CurrentStatus {
+        playlist_id: None,
+        track_path: None,
+        track_index: 0,
+        position: None,
+        is_paused: paused,
+        volume: Some(volume),
+        station_id: Some(station.id.clone()),
+    };
+    if let Err(error) = kv.set_current_status(zone, &status) {
+        tracing::warn!("Failed to save player checkpoint: {:?}", error);
+    }
+}
+
+/// What the player thread shares with `play_station` and `play_queued`.
+struct PlaybackContext<'a> {
+    inner: &'a PlayerInner,
+    rx: &'a crossbeam_channel::Receiver<PlayerCommand>,
+    mixer: &'a Mixer,
+    notifier: &'a Notifier,
+    kv: &'a Kv,
+    zone: Option<&'a str>,
+}
+
+/// Play `station` until another station or a playlist is set, reconnecting when the stream drops.
+/// Pausing disconnects, so playing again is live rather than behind.
+fn play_station(
+    station: &Station,
+    ctx: &PlaybackContext,
+    config: &mut PlayerConfig,
+    volume: &mut f32,
+    resume: Option<CurrentStatus>,
+) {
+    let retry_duration = Duration::from_secs(RETRY_DURATION_S);
+    let tick_duration = Duration::from_millis(TICK_DURATION_MS);
+    let checkpoint_duration = Duration::from_secs(CHECKPOINT_DURATION_S);
+
+    tracing::info!("Play station '{}'", station.name);
+    ctx.notifier.notify(Notification::StationChanged {
+        id: station.id.clone(),
+        name: station.name.clone(),
+    });
+    if let Ok(mut s) = ctx.inner.status.try_lock() {
+        *s = PlayerStatus {
+            station: Some(station.clone()),
+            ..PlayerStatus::default()
+        };
+    }
+
+    let mut paused = !config.auto_play
+        || resume.is_some_and(|c| c.station_id.as_deref() == Some(station.id.as_str()) && c.is_paused);
+    let mut sink: Option<Sink> = None;
+    let mut last_attempt: Option<Instant> = None;
+    // Failures are warned about once until the station plays again
+    let mut failing = false;
+    let mut last_checkpoint_time = Instant::now();
+    let mut checkpoint_now = true;
+
+    loop {
+        let current = ctx.inner.station.try_read().ok().and_then(|s| s.clone());
+        if current.as_ref() != Some(station) {
+            if let Some(sink) = sink.take() {
+                sink.stop();
+            }
+            return;
+        }
+
+        if !paused
+            && sink.as_ref().is_none_or(|s| s.empty())
+            && last_attempt.is_none_or(|at| at.elapsed() >= retry_duration)
+        {
+            if sink.take().is_some() {
+                tracing::warn!("Station '{}' stream ended, reconnecting", station.name);
+            }
+            last_attempt = Some(Instant::now());
+            match crate::station::open(&config.ffmpeg_path, &station.url) {
+                Ok(source) => {
+                    let new_sink = Sink::connect_new(ctx.mixer);
+                    new_sink.append(ctx.inner.equalizer.apply(source));
+                    new_sink.set_volume(*volume);
+                    sink = Some(new_sink);
+                    failing = false;
+                }
+                Err(error) => {
+                    tracing::warn!("Failed to play station '{}': {error:#}", station.name);
+                    if !failing {
+                        ctx.notifier
+                            .warning(ProblemKind::StationUnavailable, format!("{}: {error:#}", station.name));
+                        failing = true;
+                    }
+                }
+            }
+        }
+
+        if let Ok(mut s) = ctx.inner.status.try_lock() {
+            s.current_pos = sink.as_ref().map(|s| s.get_pos());
+            s.is_paused = Some(paused);
+            s.volume = Some(*volume);
+        }
+
+        if checkpoint_now || last_checkpoint_time.elapsed() >= checkpoint_duration {
+            save_station_checkpoint(ctx.kv, ctx.zone, station, paused, *volume);
+            last_checkpoint_time = Instant::now();
+            checkpoint_now = false;
+        }
+
+        let command = ctx.rx.try_recv();
+        if command.is_ok() {
+            checkpoint_now = true;
+        }
+        match command {
+            Ok(PlayerCommand::Play) => {
+                tracing::info!("Play");
+                paused = false;
+                ctx.notifier.notify(Notification::Played);
+            }
+            Ok(PlayerCommand::Pause) => {
+                tracing::info!("Pause");
+                paused = true;
+                if let Some(sink) = sink.take() {
+                    sink.stop();
+                }
+                ctx.notifier.notify(Notification::Paused);
+            }
+            Ok(PlayerCommand::SetVolume(value)) => {
+                let value = value.clamp(0.0, 1.0);
+                tracing::info!("Volume: {:?}", value);
+                if let Some(sink) = &sink {
+                    sink.set_volume(value);
+                }
+                *volume = value;
+                ctx.notifier.notify(Notification::VolumeChanged { value });
+            }
+            Ok(PlayerCommand::SetConfig(new_config)) => {
+                tracing::info!("Player config: {:?}", new_config);
+                *config = new_config;
+            }
+            Ok(PlayerCommand::Checkpoint(done)) => {
+                save_station_checkpoint(ctx.kv, ctx.zone, station, paused, *volume);
+                let _ = done.send(());
+            }
+            Ok(PlayerCommand::Switch | PlayerCommand::Reload) => {}
+            Ok(command) => tracing::info!("Ignoring {} while playing a station", command.name()),
+            Err(crossbeam_channel::TryRecvError::Empty) => {}
+            Err(error) => tracing::warn!("Player command channel recv error: {:?}", error),
+        }
+
+        thread::sleep(tick_duration);
+    }
+}
+
+/// Where a player plays into.
+#[derive(Debug, Clone)]
+pub enum PlayerOutput {
+    DefaultDevice,
+    /// The output device with this name.
+    Device(String),
+    Snapcast(SnapcastSettings),
+}
 
 impl PlayerHandle {
-    pub fn new(notifier: Notifier, config: PlayerConfig) -> anyhow::Result<Self> {
+    /// Plays into `output`, also handing what's played to `tap`. The position is saved per `zone`, the main player has none.
+    pub fn new(
+        notifier: Notifier,
+        mut config: PlayerConfig,
+        kv: Arc<Kv>,
+        output: PlayerOutput,
+        zone: Option<String>,
+        inserts_dir: PathBuf,
+        tap: Option<SampleTap>,
+    ) -> anyhow::Result<Self> {
+        // Device outputs play on their own thread, so the device can be switched while playing
+        let (device_mixer, device_output, snapcast_tap) = match &output {
+            PlayerOutput::Snapcast(_) => (None, None, tap),
+            PlayerOutput::DefaultDevice => {
+                let (mixer, device_output) = crate::output::open(None, notifier.clone(), tap)?;
+                (Some(mixer), Some(device_output), None)
+            }
+            PlayerOutput::Device(name) => {
+                let (mixer, device_output) = crate::output::open(Some(name.clone()), notifier.clone(), tap)?;
+                (Some(mixer), Some(device_output), None)
+            }
+        };
+
         let (_tx, _rx) = crossbeam_channel::unbounded::<PlayerCommand>();
         let inner = Arc::new(PlayerInner {
             playlist_dir: RwLock::new(None),
+            station: RwLock::new(None),
+            queue: Mutex::new(VecDeque::new()),
             status: Mutex::new(PlayerStatus::default()),
             tx: _tx.clone(),
+            notifier: notifier.clone(),
+            device_output,
+            equalizer: Equalizer::new(&config.equalizer),
         });
 
         let self_inner = inner.clone();
         thread::Builder::new().name("musicd-player".into()).spawn(move || {
-            // Audio stream owns OS device; keep it inside the thread.
-            let stream_handle = match OutputStreamBuilder::open_default_stream() {
-                Ok(v) => v,
-                Err(error) => {
-                    eprintln!("Audio init error: {error:?}");
-                    return;
-                }
+            let mixer = match device_mixer {
+                Some(mixer) => mixer,
+                None => match &output {
+                    PlayerOutput::Snapcast(snapcast) => match crate::snapcast::output(snapcast, snapcast_tap) {
+                        Ok(mixer) => mixer,
+                        Err(error) => {
+                            tracing::error!("Snapcast output error: {error:?}");
+                            notifier.error(ProblemKind::AudioDeviceLost, format!("No snapcast output: {error:#}"));
+                            return;
+                        }
+                    },
+                    _ => unreachable!("device outputs are opened with the player"),
+                },
             };
 
             // Cache durations
             let retry_duration = Duration::from_secs(RETRY_DURATION_S);
             let position_update_duration = Duration::from_millis(POSITION_UPDATE_DURATION_MS);
             let tick_duration = Duration::from_millis(TICK_DURATION_MS);
+            let checkpoint_duration = Duration::from_secs(CHECKPOINT_DURATION_S);
+            let preload_duration = Duration::from_secs(PRELOAD_DURATION_S);
+
+            // Resume from the last checkpoint on the first playlist load
+            let mut resume = kv.get_current_status(zone.as_deref()).ok().flatten();
+            let mut volume = resume.as_ref().and_then(|c| c.volume).unwrap_or(1.0);
+
+            // Inserts between tracks
+            let mut inserts = InsertScheduler::new();
+            let mut insert_due = false;
+
+            // Queued tracks play once the current track ended or was skipped
+            let mut queue_due = false;
+
+            // Last playlist.json reported as unreadable, to warn once
+            let mut unreadable: Option<PathBuf> = None;
+
+            let playback = PlaybackContext {
+                inner: &self_inner,
+                rx: &_rx,
+                mixer: &mixer,
+                notifier: &notifier,
+                kv: &kv,
+                zone: zone.as_deref(),
+            };
 
             loop {
+                // A station plays until it's cleared
+                let station = self_inner.station.try_read().ok().and_then(|s| s.clone());
+                if let Some(station) = station {
+                    play_station(&station, &playback, &mut config, &mut volume, resume.take());
+                    continue;
+                }
+
                 // Reload playlist dir
                 let pdir = {
                     match self_inner.playlist_dir.try_read() {
@@ -106,22 +867,27 @@ impl PlayerHandle {
                 if let Some(dir) = pdir {
                     // Load meta
                     let meta_path = dir.join("playlist.json");
-                    let meta = match std::fs::read_to_string(&meta_path)
-                        .ok()
-                        .and_then(|s| serde_json::from_str::<PlaylistMeta>(&s).ok())
-                    {
-                        Some(m) => m,
-                        None => {
+                    let mut meta = match PlaylistMeta::load(&meta_path) {
+                        Ok(m) => m,
+                        Err(error) => {
+                            if unreadable.as_ref() != Some(&meta_path) {
+                                tracing::warn!("Failed to read {}: {error:#}", meta_path.display());
+                                notifier.warning(
+                                    ProblemKind::PlaylistMetaUnreadable,
+                                    format!("{}: {error:#}", meta_path.display()),
+                                );
+                                unreadable = Some(meta_path.clone());
+                            }
                             thread::sleep(retry_duration);
                             continue;
                         }
                     };
+                    unreadable = None;
+
+                    meta.apply_sort();
 
                     // Notify
-                    notifier.notify(Notification::PlaylistChanged {
-                        id: meta.id.clone(),
-                        name: meta.name.clone(),
-                    });
+                    notifier.notify(Notification::PlaylistChanged((&meta).into()));
 
                     let mut idx = {
                         match self_inner.status.try_lock() {
@@ -130,10 +896,12 @@ impl PlayerHandle {
                                 s.playlist_name = Some(meta.name.clone());
                                 s.current_index = 0;
                                 s.current_track = None;
+                                s.current_meta = None;
                                 s.current_pos = None;
                                 s.total_duration = None;
                                 s.is_paused = None;
                                 s.volume = None;
+                                s.station = None;
                                 s.current_index
                             }
                             Err(error) => {
@@ -150,24 +918,102 @@ impl PlayerHandle {
                         continue;
                     }
 
+                    let mut resume_at = resume
+                        .take()
+                        .filter(|c| c.playlist_id.as_deref() == Some(meta.id.as_str()));
+                    if let Some(checkpoint) = &resume_at {
+                        idx = checkpoint
+                            .track_path
+                            .as_ref()
+                            .and_then(|f| meta.tracks.iter().position(|t| &t.file == f))
+                            .unwrap_or(checkpoint.track_index);
+                        tracing::info!("Resume from checkpoint at track {}", idx);
+                    }
+
+                    // Skip state for low rated tracks
+                    let mut skipped = 0;
+                    let mut picked = false;
+                    let mut backwards = false;
+
+                    // Sink already playing the track after a gapless end
+                    let mut following: Option<(Sink, Option<Duration>)> = None;
+
                     loop {
                         // Loop to first track
                         if idx >= meta.tracks.len() {
                             idx = 0;
                         }
 
-                        let track = &meta.tracks[idx];
+                        // Queued tracks, before the playlist goes on
+                        if queue_due {
+                            queue_due = false;
+                            let mut played = false;
+                            while let Some(entry) = self_inner.pop_queued() {
+                                played = true;
+                                if !play_queued(&entry, &playback, &mut config, &mut volume) {
+                                    break;
+                                }
+                            }
+                            if played {
+                                self_inner.notify_queue(None);
+                            }
+                        }
+
+                        let track = meta.tracks[idx].clone();
+
+                        // Skip low rated tracks and those too short or long, unless picked or every track is skipped
+                        let duration_skip = config.duration_skip(&track);
+                        if !picked
+                            && skipped < meta.tracks.len()
+                            && (duration_skip.is_some()
+                                || config.skip_below_rating.is_some_and(|min| {
+                                    kv.get_rating(&meta.id, &track.file)
+                                        .ok()
+                                        .flatten()
+                                        .is_some_and(|r| r < min)
+                                }))
+                        {
+                            match duration_skip {
+                                Some(reason) => {
+                                    tracing::info!("Skip track {:?}: {}", reason, track.file);
+                                    notifier.notify(Notification::TrackSkipped {
+                                        idx,
+                                        name: track.file.clone(),
+                                        reason,
+                                    });
+                                }
+                                None => tracing::info!("Skip low rated track: {}", track.file),
+                            }
+                            skipped += 1;
+                            if backwards {
+                                idx = idx.checked_sub(1).unwrap_or(meta.tracks.len() - 1);
+                            } else {
+                                idx += 1;
+                            }
+                            continue;
+                        }
+                        skipped = 0;
+                        picked = false;
+
+                        if insert_due {
+                            insert_due = false;
+                            if let Some(clip) = inserts.next_clip(&inserts_dir) {
+                                play_insert(&clip, &self_inner, &_rx, &mixer, &notifier, &mut config, &mut volume);
+                            }
+                        }
+
                         {
                             // Notify
                             notifier.notify(Notification::TrackChanged {
                                 idx,
-                                name: track.to_string(),
+                                name: track.file.clone(),
                             });
 
                             match self_inner.status.try_lock() {
                                 Ok(mut s) => {
                                     s.current_index = idx;
-                                    s.current_track = Some(track.clone());
+                                    s.current_track = Some(track.file.clone());
+                                    s.current_meta = Some(track.clone());
                                 }
                                 Err(error) => {
                                     tracing::warn!("Failed to obtain status lock: {:?}", error);
@@ -177,9 +1023,25 @@ impl PlayerHandle {
                             }
                         }
 
-                        let fp = dir.join(track);
-                        let sink = Sink::connect_new(stream_handle.mixer());
-                        if let Ok(file) = File::open(&fp)
+                        let fp = names::resolve_track(&dir, &track).unwrap_or_else(|| dir.join(&track.file));
+                        let mut loaded = false;
+                        let mut total_duration = None;
+                        let sink = if let Some((sink, duration)) = following.take() {
+                            // Already playing, lined up in the sink of the previous track
+                            loaded = true;
+                            total_duration = duration.or(track.duration);
+                            if let Ok(mut s) = self_inner.status.try_lock() {
+                                s.total_duration = duration;
+                            }
+
+                            // Notify
+                            notifier.notify(Notification::TrackDurationChanged { duration });
+                            sink
+                        } else {
+                            Sink::connect_new(&mixer)
+                        };
+                        if !loaded
+                            && let Ok(file) = File::open(&fp)
                             && let Ok(source) = DecoderBuilder::new()
                                 .with_data(BufReader::new(file))
                                 .with_seekable(true)
@@ -201,39 +1063,56 @@ impl PlayerHandle {
                                 duration: source.total_duration(),
                             });
 
-                            // Audio effects
-                            if config.default_audio_effects {
-                                let limit_settings = LimitSettings::default()
-                                    .with_threshold(-1.0) // Higher threshold (less limiting)
-                                    .with_knee_width(8.0) // Wide knee (softer)
-                                    .with_attack(Duration::from_millis(20)) // Slower attack
-                                    .with_release(Duration::from_millis(200)); // Slower release                            
-                                let mixed_source =
-                                    source.automatic_gain_control(1.0, 4.0, 0.1, 5.0).limit(limit_settings);
-                                sink.append(mixed_source);
-                            } else {
-                                sink.append(source);
-                            }
+                            total_duration = source.total_duration().or(track.duration);
+                            append_track(&sink, source, &track, &config, &meta.settings, &self_inner.equalizer);
+                            sink.set_volume(volume);
+                            loaded = true;
 
                             // Auto play
-                            if !config.auto_play {
+                            if !config.auto_play || resume_at.as_ref().is_some_and(|c| c.is_paused) {
                                 sink.pause();
 
                                 // Notify
                                 notifier.notify(Notification::Paused);
                             }
+
+                            // Resume position, from the checkpoint or where the track was left
+                            let position = resume_at.take().and_then(|c| c.position).or_else(|| {
+                                meta.long_form
+                                    .then(|| kv.get_progress(&meta.id, &track.file).ok().flatten())
+                                    .flatten()
+                                    .filter(|p| !p.finished)
+                                    .map(|p| p.position)
+                            });
+                            if let Some(position) = position
+                                && let Err(error) = sink.try_seek(position)
+                            {
+                                tracing::warn!("Resume seek error: {:?}", error);
+                            }
                         }
+                        resume_at = None;
+
+                        save_checkpoint(&kv, zone.as_deref(), &meta, idx, &track, &sink);
+                        let started_at = Utc::now();
 
                         // Keep track of position updates for notification
                         let mut last_position_update_time = Instant::now();
+                        let mut last_checkpoint_time = Instant::now();
+                        let mut checkpoint_now = false;
+
+                        // Gapless playback, the next track is lined up once
+                        let mut preloaded: Option<Preloaded> = None;
+                        let mut preload_tried = false;
+                        let mut ended = false;
 
                         // Ticks - Wait for end or skip signal
                         loop {
+                            let last_pos = sink.get_pos();
                             match self_inner.status.try_lock() {
                                 Ok(mut s) => {
-                                    s.current_pos = Some(sink.get_pos());
+                                    s.current_pos = Some(last_pos);
                                     s.is_paused = Some(sink.is_paused());
-                                    s.volume = Some(sink.volume());
+                                    s.volume = Some(volume);
                                 }
                                 Err(error) => {
                                     tracing::warn!("Failed to obtain status lock: {:?}", error);
@@ -252,15 +1131,75 @@ impl PlayerHandle {
                                 last_position_update_time = Instant::now();
                             }
 
-                            // End
-                            if sink.empty() {
+                            if checkpoint_now || last_checkpoint_time.elapsed() >= checkpoint_duration {
+                                save_checkpoint(&kv, zone.as_deref(), &meta, idx, &track, &sink);
+                                if loaded {
+                                    save_progress(&kv, &meta, &track, last_pos, false);
+                                }
+                                last_checkpoint_time = Instant::now();
+                                checkpoint_now = false;
+                            }
+
+                            // Line up the next track shortly before the end, unless an insert plays in between
+                            let crossfade = meta.settings.crossfade();
+                            let remaining = total_duration.map(|d| d.saturating_sub(last_pos));
+                            if (config.gapless || crossfade.is_some())
+                                && loaded
+                                && !preload_tried
+                                && !meta.long_form
+                                && remaining.is_some_and(|r| r <= preload_duration.max(crossfade.unwrap_or_default()))
+                                && !inserts.due_after_track(&config.inserts)
+                            {
+                                preload_tried = true;
+                                preloaded = preload_next(&playback, &dir, &meta, idx, &sink, &config);
+                            }
+
+                            // Crossfade, the current track fades out as the next one fades in
+                            if let Some(next_sink) = preloaded.as_ref().and_then(|p| p.sink.as_ref())
+                                && let (Some(crossfade), Some(remaining)) = (crossfade, remaining)
+                                && remaining <= crossfade
+                                && !sink.is_paused()
+                            {
+                                next_sink.set_volume(volume);
+                                next_sink.play();
+                                sink.set_volume(volume * remaining.as_secs_f32() / crossfade.as_secs_f32());
+                            }
+
+                            // End, or the lined up track took over
+                            let lined_up = preloaded.as_ref().is_some_and(|p| p.sink.is_none());
+                            if sink.empty() || (lined_up && sink.len() <= 1) {
                                 tracing::info!("Seek empty");
+                                if loaded {
+                                    record_history(
+                                        &kv,
+                                        zone.as_deref(),
+                                        &meta,
+                                        &track,
+                                        started_at,
+                                        track.duration.unwrap_or(last_pos),
+                                        false,
+                                    );
+                                    save_progress(&kv, &meta, &track, track.duration.unwrap_or(last_pos), true);
+                                }
+                                insert_due = inserts.track_ended(&config.inserts);
+                                queue_due = true;
                                 idx += 1;
+                                backwards = false;
+                                ended = true;
                                 break;
                             }
 
                             // Commands
-                            match _rx.try_recv() {
+                            let command = _rx.try_recv();
+                            if command.is_ok() {
+                                // Checkpoint on the next tick
+                                checkpoint_now = true;
+                            }
+                            let span = command
+                                .as_ref()
+                                .ok()
+                                .map(|c| tracing::info_span!("player_command", command = c.name()).entered());
+                            match command {
                                 Ok(PlayerCommand::Play) => {
                                     tracing::info!("Play");
                                     sink.play();
@@ -271,37 +1210,77 @@ impl PlayerHandle {
                                 Ok(PlayerCommand::Pause) => {
                                     tracing::info!("Pause");
                                     sink.pause();
+                                    if let Some(next_sink) = preloaded.as_ref().and_then(|p| p.sink.as_ref()) {
+                                        next_sink.pause();
+                                    }
 
                                     // Notify
                                     notifier.notify(Notification::Paused);
                                 }
-                                Ok(PlayerCommand::Seek(secs)) => {
-                                    let duration = Duration::from_secs(secs);
-                                    match sink.try_seek(duration) {
-                                        Ok(()) => {
-                                            tracing::info!("Seek to position: {:?}", secs);
-
-                                            // Notify
-                                            notifier.notify(Notification::SeekPositionChanged { duration });
+                                Ok(PlayerCommand::Seek(target)) => {
+                                    // A crossfade under way starts over at the new position
+                                    if preloaded.as_ref().is_some_and(|p| p.sink.is_some()) {
+                                        preloaded = None;
+                                        preload_tried = false;
+                                        sink.set_volume(volume);
+                                    }
+                                    match target.position(sink.get_pos(), total_duration) {
+                                        Some(duration) => match sink.try_seek(duration) {
+                                            Ok(()) => {
+                                                tracing::info!("Seek to position: {:?}", duration);
+
+                                                // Notify
+                                                notifier.notify(Notification::SeekPositionChanged { duration });
+                                            }
+                                            Err(error) => tracing::warn!("Seek error: {:?}", error),
+                                        },
+                                        None => {
+                                            tracing::warn!("Cannot seek to {target:?}, the track's duration is unknown")
                                         }
-                                        Err(error) => tracing::warn!("Seek error: {:?}", error),
                                     }
                                 }
                                 Ok(PlayerCommand::Prev) => {
                                     tracing::info!("Prev");
+                                    if loaded {
+                                        record_history(
+                                            &kv,
+                                            zone.as_deref(),
+                                            &meta,
+                                            &track,
+                                            started_at,
+                                            sink.get_pos(),
+                                            true,
+                                        );
+                                        save_progress(&kv, &meta, &track, sink.get_pos(), false);
+                                    }
 
                                     if idx == 0 {
                                         idx = meta.tracks.len() - 1;
                                     } else {
                                         idx -= 1;
                                     }
+                                    backwards = true;
                                     sink.stop();
                                     break;
                                 }
                                 Ok(PlayerCommand::Next) => {
                                     tracing::info!("Next");
+                                    if loaded {
+                                        record_history(
+                                            &kv,
+                                            zone.as_deref(),
+                                            &meta,
+                                            &track,
+                                            started_at,
+                                            sink.get_pos(),
+                                            true,
+                                        );
+                                        save_progress(&kv, &meta, &track, sink.get_pos(), false);
+                                    }
 
                                     idx += 1;
+                                    backwards = false;
+                                    queue_due = true;
                                     sink.stop();
                                     break;
                                 }
@@ -309,6 +1288,7 @@ impl PlayerHandle {
                                     let value = value.clamp(0.0, 1.0);
                                     tracing::info!("Volume: {:?}", value);
                                     sink.set_volume(value);
+                                    volume = value;
 
                                     // Notify
                                     notifier.notify(Notification::VolumeChanged { value });
@@ -316,20 +1296,110 @@ impl PlayerHandle {
                                 Ok(PlayerCommand::SetIndex(index)) => {
                                     tracing::info!("Set Index: {:?}", index);
                                     if index != idx {
+                                        if loaded {
+                                            record_history(
+                                                &kv,
+                                                zone.as_deref(),
+                                                &meta,
+                                                &track,
+                                                started_at,
+                                                sink.get_pos(),
+                                                true,
+                                            );
+                                            save_progress(&kv, &meta, &track, sink.get_pos(), false);
+                                        }
                                         idx = index;
+                                        picked = true;
                                         sink.stop();
                                         break;
                                     }
                                 }
+                                Ok(PlayerCommand::Reload) => {
+                                    // Pick up edits to playlist.json without interrupting the current track
+                                    match PlaylistMeta::load(&meta_path) {
+                                        Ok(mut new_meta) if !new_meta.tracks.is_empty() => {
+                                            tracing::info!("Reload playlist");
+                                            new_meta.apply_sort();
+                                            idx = match new_meta.tracks.iter().position(|t| t.file == track.file) {
+                                                Some(pos) => pos,
+                                                // Removed; step back so the track after it plays next
+                                                None => idx
+                                                    .min(new_meta.tracks.len())
+                                                    .checked_sub(1)
+                                                    .unwrap_or(new_meta.tracks.len() - 1),
+                                            };
+                                            meta = new_meta;
+                                        }
+                                        Ok(_) => tracing::warn!("Reload playlist: no tracks left"),
+                                        Err(error) => {
+                                            tracing::warn!("Reload playlist error: {:?}", error);
+                                            notifier.warning(
+                                                ProblemKind::PlaylistMetaUnreadable,
+                                                format!("{}: {error:#}", meta_path.display()),
+                                            );
+                                        }
+                                    }
+                                }
+                                Ok(PlayerCommand::SetConfig(new_config)) => {
+                                    // Audio effects apply from the next track
+                                    tracing::info!("Player config: {:?}", new_config);
+                                    config = new_config;
+                                }
+                                Ok(PlayerCommand::Switch) => {
+                                    if loaded {
+                                        record_history(
+                                            &kv,
+                                            zone.as_deref(),
+                                            &meta,
+                                            &track,
+                                            started_at,
+                                            sink.get_pos(),
+                                            true,
+                                        );
+                                        save_progress(&kv, &meta, &track, sink.get_pos(), false);
+                                    }
+                                    sink.stop();
+                                    break;
+                                }
+                                Ok(PlayerCommand::Checkpoint(done)) => {
+                                    save_checkpoint(&kv, zone.as_deref(), &meta, idx, &track, &sink);
+                                    if loaded {
+                                        save_progress(&kv, &meta, &track, sink.get_pos(), false);
+                                    }
+                                    let _ = done.send(());
+                                }
                                 Err(error) => match error {
                                     crossbeam_channel::TryRecvError::Empty => {}
                                     _ => tracing::warn!("Player command channel recv error: {:?}", error),
                                 },
                             }
+                            drop(span);
 
                             thread::sleep(tick_duration);
                         }
 
+                        // The lined up track plays on in this sink, unless something got due before it meanwhile
+                        if ended
+                            && let Some(next) = preloaded
+                            && !insert_due
+                            && self_inner.queue.lock().is_ok_and(|q| q.is_empty())
+                            && let Some(pos) = meta.tracks.iter().position(|t| t.file == next.file)
+                        {
+                            idx = pos;
+                            picked = true;
+                            queue_due = false;
+                            // Crossfading, the next track plays on in its own sink
+                            let sink = match next.sink {
+                                Some(next_sink) => {
+                                    next_sink.set_volume(volume);
+                                    next_sink.play();
+                                    next_sink
+                                }
+                                None => sink,
+                            };
+                            following = Some((sink, next.total_duration));
+                        }
+
                         // Check if playlist changed
                         let now_dir = {
                             match self_inner.playlist_dir.try_read() {
@@ -340,7 +1410,8 @@ impl PlayerHandle {
                                 }
                             }
                         };
-                        if now_dir.as_deref() != Some(&dir) {
+                        let has_station = self_inner.station.try_read().is_ok_and(|s| s.is_some());
+                        if now_dir.as_deref() != Some(&dir) || has_station {
                             // Reload
                             break;
                         }
@@ -365,6 +1436,11 @@ impl PlayerHandle {
             }
         };
 
+        // Back from a station to playlists
+        if let Ok(mut station) = self.inner.station.try_write() {
+            *station = None;
+        }
+
         if let Ok(mut dir) = self.inner.playlist_dir.try_write() {
             let new_dir = playlist_dir.as_ref().to_path_buf();
             *dir = Some(new_dir.clone());
@@ -381,9 +1457,49 @@ impl PlayerHandle {
         }
     }
 
+    /// Play `station` live instead of the playlist, until another playlist or station is set.
+    pub fn set_station(&self, station: Station) {
+        match self.inner.station.try_write() {
+            Ok(mut current) => *current = Some(station),
+            Err(error) => {
+                tracing::warn!("Failed to obtain station lock: {:?}", error);
+                return;
+            }
+        }
+        let _ = self.inner.tx.send(PlayerCommand::Switch);
+    }
+
+    /// Stop playing `id` if it's the current station, eg. when it was removed.
+    /// The playlist plays again.
+    pub fn clear_station(&self, id: &str) {
+        if let Ok(mut station) = self.inner.station.try_write()
+            && station.as_ref().is_some_and(|s| s.id == id)
+        {
+            *station = None;
+        }
+    }
+
+    /// Reload `playlist.json` if `playlist_dir` is the active playlist.
+    pub fn reload_if_current(&self, playlist_dir: impl AsRef<Path>) {
+        let is_current = match self.inner.playlist_dir.try_read() {
+            Ok(dir) => dir.as_deref() == Some(playlist_dir.as_ref()),
+            Err(error) => {
+                tracing::warn!("Failed to obtain playlist_dir lock: {:?}", error);
+                false
+            }
+        };
+
+        if is_current {
+            let _ = self.inner.tx.send(PlayerCommand::Reload);
+        }
+    }
+
     pub fn status(&self) -> anyhow::Result<PlayerStatus> {
         match self.inner.status.try_lock() {
-            Ok(s) => Ok(s.clone()),
+            Ok(s) => Ok(PlayerStatus {
+                queue: self.inner.upcoming(),
+                ..s.clone()
+            }),
             Err(error) => {
                 tracing::warn!("Failed to obtain status lock: {:?}", error);
                 Err(anyhow::anyhow!("Failed to obtain status lock: {:?}", error))
@@ -391,6 +1507,30 @@ impl PlayerHandle {
         }
     }
 
+    /// Line up `track` to play after the current track, behind the tracks queued before.
+    pub fn enqueue(&self, track: QueuedTrack) {
+        match self.inner.queue.lock() {
+            Ok(mut queue) => queue.push_back(track),
+            Err(error) => {
+                tracing::warn!("Failed to obtain queue lock: {:?}", error);
+                return;
+            }
+        }
+        self.inner.notify_queue(self.status().ok().and_then(|s| s.queued));
+    }
+
+    pub fn clear_queue(&self) {
+        if let Ok(mut queue) = self.inner.queue.lock() {
+            queue.clear();
+        }
+        self.inner.notify_queue(self.status().ok().and_then(|s| s.queued));
+    }
+
+    /// Tracks lined up to play next, in order.
+    pub fn queue(&self) -> Vec<QueuedTrack> {
+        self.inner.upcoming()
+    }
+
     pub fn play(&self) {
         let _ = self.inner.tx.send(PlayerCommand::Play);
     }
@@ -407,8 +1547,8 @@ impl PlayerHandle {
         let _ = self.inner.tx.send(PlayerCommand::Next);
     }
 
-    pub fn seek(&self, secs: u64) {
-        let _ = self.inner.tx.send(PlayerCommand::Seek(secs));
+    pub fn seek(&self, target: SeekTarget) {
+        let _ = self.inner.tx.send(PlayerCommand::Seek(target));
     }
 
     pub fn set_volume(&self, value: f32) {
@@ -418,4 +1558,48 @@ impl PlayerHandle {
     pub fn set_index(&self, index: usize) {
         let _ = self.inner.tx.send(PlayerCommand::SetIndex(index));
     }
+
+    /// Name of the output device played on, unset for the default device or Snapcast.
+    pub fn output_device(&self) -> Option<String> {
+        self.inner.device_output.as_ref().and_then(|o| o.device())
+    }
+
+    /// Switch to the output device named `device`, the default device when unset.
+    pub fn set_output_device(&self, device: Option<String>) -> anyhow::Result<()> {
+        match &self.inner.device_output {
+            Some(output) => output.switch(device),
+            None => anyhow::bail!("Playing into Snapcast, not an output device"),
+        }
+    }
+
+    pub fn set_config(&self, config: PlayerConfig) {
+        self.inner.equalizer.set_settings(&config.equalizer);
+        let _ = self.inner.tx.send(PlayerCommand::SetConfig(config));
+    }
+
+    pub fn equalizer(&self) -> EqualizerState {
+        self.inner.equalizer.state()
+    }
+
+    /// Names of the equalizer presets, built-in and configured.
+    pub fn equalizer_presets(&self) -> Vec<String> {
+        self.inner.equalizer.presets()
+    }
+
+    /// Change the equalizer of the tracks playing and to come.
+    pub fn set_equalizer(&self, change: EqualizerChange) -> Result<EqualizerState, String> {
+        let state = self.inner.equalizer.change(change)?;
+        self.inner
+            .notifier
+            .notify(Notification::EqualizerChanged(state.clone()));
+        Ok(state)
+    }
+
+    /// Save the playback position now, waiting up to `timeout` for it to be saved.
+    pub fn checkpoint(&self, timeout: Duration) {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        if self.inner.tx.send(PlayerCommand::Checkpoint(tx)).is_ok() && rx.recv_timeout(timeout).is_err() {
+            tracing::debug!("Player didn't save its position in time");
+        }
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{ART_DIR, PlaylistMeta, names};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupeBy {
+    /// Same file contents.
+    #[default]
+    Hash,
+    /// Same normalized artist and title.
+    Title,
+    /// Either of the above.
+    Any,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateTrack {
+    pub playlist_id: String,
+    pub folder: String,
+    pub file: String,
+    pub title: String,
+}
+
+/// Tracks considered the same; the first one is kept when removing.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub key: String,
+    pub tracks: Vec<DuplicateTrack>,
+}
+
+/// Lowercased alphanumeric words of the display name, eg. `artist track`.
+fn normalize_title(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Size and hash of the contents of `path`.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+    }
+    Ok(format!("{size:x}-{:016x}", hasher.finish()))
+}
+
+/// Find duplicate tracks in `playlists` (folder name and meta, relative to `root`), in the given order.
+pub fn find_duplicates(root: &Path, playlists: &[(String, PlaylistMeta)], by: DedupeBy) -> Vec<DuplicateGroup> {
+    // Union of tracks sharing any key, keyed by the first key seen
+    let mut groups: Vec<DuplicateGroup> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (folder, meta) in playlists {
+        let dir = root.join(folder);
+        for track in &meta.tracks {
+            let mut keys = vec![];
+            if matches!(by, DedupeBy::Hash | DedupeBy::Any) {
+                let path = names::resolve_track(&dir, track).unwrap_or_else(|| dir.join(&track.file));
+                match hash_file(&path) {
+                    Ok(hash) => keys.push(format!("hash:{hash}")),
+                    Err(error) => tracing::warn!("Failed to hash {}: {}", track.file, error),
+                }
+            }
+            if matches!(by, DedupeBy::Title | DedupeBy::Any) {
+                let title = normalize_title(&track.display_name());
+                if !title.is_empty() {
+                    keys.push(format!("title:{title}"));
+                }
+            }
+            if keys.is_empty() {
+                continue;
+            }
+
+            let item = DuplicateTrack {
+                playlist_id: meta.id.clone(),
+                folder: folder.clone(),
+                file: track.file.clone(),
+                title: track.display_name(),
+            };
+            let group = match keys.iter().find_map(|k| index.get(k)) {
+                Some(&i) => {
+                    groups[i].tracks.push(item);
+                    i
+                }
+                None => {
+                    groups.push(DuplicateGroup {
+                        key: keys[0].clone(),
+                        tracks: vec![item],
+                    });
+                    groups.len() - 1
+                }
+            };
+            for key in keys {
+                index.entry(key).or_insert(group);
+            }
+        }
+    }
+
+    groups.retain(|g| g.tracks.len() > 1);
+    groups
+}
+
+/// Files to remove per playlist folder: every track of a group but the first.
+pub fn removals(groups: &[DuplicateGroup]) -> HashMap<String, HashSet<String>> {
+    let mut out: HashMap<String, HashSet<String>> = HashMap::new();
+    for group in groups {
+        for track in group.tracks.iter().skip(1) {
+            out.entry(track.folder.clone()).or_default().insert(track.file.clone());
+        }
+    }
+    out
+}
+
+/// Drop `files` from the playlist in `dir`, saving `playlist.json` before deleting anything.
+pub fn remove_tracks(dir: &Path, files: &HashSet<String>) -> anyhow::Result<usize> {
+    let meta_path = dir.join("playlist.json");
+    let mut meta = PlaylistMeta::load(&meta_path)?;
+
+    let mut removed: Vec<(String, Option<String>)> = vec![];
+    meta.tracks.retain(|t| {
+        let keep = !files.contains(&t.file);
+        if !keep {
+            removed.push((t.file.clone(), t.art.clone()));
+        }
+        keep
+    });
+    if removed.is_empty() {
+        return Ok(0);
+    }
+
+    meta.save_atomic(&meta_path)?;
+
+    for (file, art) in &removed {
+        // Files of a mirrored folder are left in place
+        let mut paths: Vec<PathBuf> = vec![];
+        if Path::new(file).is_relative() {
+            paths.push(dir.join(file));
+        }
+        if let Some(art) = art
+            && art.starts_with(ART_DIR)
+            && !meta.tracks.iter().any(|t| t.art.as_ref() == Some(art))
+        {
+            paths.push(dir.join(art));
+        }
+        for path in paths {
+            if let Err(error) = std::fs::remove_file(&path) {
+                tracing::warn!("Failed to remove {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    Ok(removed.len())
+}
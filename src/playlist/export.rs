@@ -0,0 +1,114 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{PlaylistMeta, TrackMeta};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Extended M3U (UTF-8).
+    #[default]
+    #[serde(alias = "m3u")]
+    #[value(alias = "m3u")]
+    M3u8,
+    /// PLS version 2.
+    Pls,
+    /// The playlist's `playlist.json` as it's stored.
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::M3u8 => "m3u8",
+            ExportFormat::Pls => "pls",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::M3u8 => "audio/x-mpegurl; charset=utf-8",
+            ExportFormat::Pls => "audio/x-scpls; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportPaths {
+    /// File names relative to the playlist dir.
+    #[default]
+    Relative,
+    /// Absolute paths on the daemon host.
+    Absolute,
+    /// Original source URLs, falling back to relative paths.
+    Source,
+}
+
+fn location(track: &TrackMeta, dir: &Path, paths: &ExportPaths) -> String {
+    match paths {
+        ExportPaths::Relative => track.file.clone(),
+        ExportPaths::Absolute => std::path::absolute(dir.join(&track.file))
+            .unwrap_or_else(|_| dir.join(&track.file))
+            .to_string_lossy()
+            .to_string(),
+        ExportPaths::Source => track.source_url.clone().unwrap_or_else(|| track.file.clone()),
+    }
+}
+
+fn art_location(art: &str, dir: &Path, paths: &ExportPaths) -> String {
+    match paths {
+        ExportPaths::Absolute => std::path::absolute(dir.join(art))
+            .unwrap_or_else(|_| dir.join(art))
+            .to_string_lossy()
+            .to_string(),
+        ExportPaths::Relative | ExportPaths::Source => art.to_string(),
+    }
+}
+
+fn length_secs(track: &TrackMeta) -> i64 {
+    track.duration.map(|d| d.as_secs() as i64).unwrap_or(-1)
+}
+
+/// Render a playlist located at `dir` in the given format. `paths` doesn't apply to JSON.
+pub fn export(meta: &PlaylistMeta, dir: &Path, format: &ExportFormat, paths: &ExportPaths) -> String {
+    let mut out = String::new();
+
+    match format {
+        ExportFormat::M3u8 => {
+            let _ = writeln!(out, "#EXTM3U");
+            let _ = writeln!(out, "#PLAYLIST:{}", meta.name);
+            if let Some(cover) = &meta.cover {
+                let _ = writeln!(out, "#EXTALBUMARTURL:{}", art_location(cover, dir, paths));
+            }
+            for track in &meta.tracks {
+                let _ = writeln!(out, "#EXTINF:{},{}", length_secs(track), track.display_name());
+                if let Some(art) = &track.art {
+                    let _ = writeln!(out, "#EXTIMG:{}", art_location(art, dir, paths));
+                }
+                let _ = writeln!(out, "{}", location(track, dir, paths));
+            }
+        }
+        ExportFormat::Pls => {
+            let _ = writeln!(out, "[playlist]");
+            for (i, track) in meta.tracks.iter().enumerate() {
+                let n = i + 1;
+                let _ = writeln!(out, "File{n}={}", location(track, dir, paths));
+                let _ = writeln!(out, "Title{n}={}", track.display_name());
+                let _ = writeln!(out, "Length{n}={}", length_secs(track));
+            }
+            let _ = writeln!(out, "NumberOfEntries={}", meta.tracks.len());
+            let _ = writeln!(out, "Version=2");
+        }
+        ExportFormat::Json => {
+            out = serde_json::to_string_pretty(meta).unwrap_or_default();
+            out.push('\n');
+        }
+    }
+
+    out
+}
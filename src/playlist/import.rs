@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Largest playlist file fetched from a URL.
+const MAX_FETCH_BYTES: usize = 1024 * 1024;
+const FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// A single entry read from an imported playlist file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportEntry {
+    /// Path or URL as written in the file.
+    pub location: String,
+    pub title: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+/// Where an entry's audio comes from.
+pub enum EntrySource {
+    Remote(String),
+    Local(PathBuf),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParsedPlaylist {
+    pub name: Option<String>,
+    pub entries: Vec<ImportEntry>,
+}
+
+impl ImportEntry {
+    pub fn resolve(&self, base_dir: Option<&Path>) -> EntrySource {
+        let location = self.location.trim();
+        let lower = location.to_ascii_lowercase();
+
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            return EntrySource::Remote(location.to_string());
+        }
+
+        let path = match location.strip_prefix("file://") {
+            Some(rest) => PathBuf::from(percent_decode(rest)),
+            None => PathBuf::from(location),
+        };
+
+        match base_dir {
+            Some(base) if path.is_relative() => EntrySource::Local(base.join(path)),
+            _ => EntrySource::Local(path),
+        }
+    }
+}
+
+/// Parse an M3U/M3U8 or PLS playlist, telling them apart by the `[playlist]` header of PLS.
+pub fn parse(content: &str) -> ParsedPlaylist {
+    let is_pls = content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .is_some_and(|l| l.eq_ignore_ascii_case("[playlist]"));
+    if is_pls { parse_pls(content) } else { parse_m3u(content) }
+}
+
+/// Parse a PLS playlist: `FileN`, `TitleN` and `LengthN` keys, in the order of N.
+pub fn parse_pls(content: &str) -> ParsedPlaylist {
+    let mut entries: BTreeMap<u32, ImportEntry> = BTreeMap::new();
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+        let Some((field, n)) = ["file", "title", "length"]
+            .into_iter()
+            .find_map(|field| Some((field, key.strip_prefix(field)?.parse::<u32>().ok()?)))
+        else {
+            continue;
+        };
+
+        let entry = entries.entry(n).or_insert_with(|| ImportEntry {
+            location: String::new(),
+            title: None,
+            duration: None,
+        });
+        match field {
+            "file" => entry.location = value.to_string(),
+            "title" => entry.title = Some(value.to_string()).filter(|t| !t.is_empty()),
+            // -1 for streams of unknown length
+            _ => {
+                entry.duration = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| *secs > 0.0)
+                    .map(Duration::from_secs_f64)
+            }
+        }
+    }
+
+    ParsedPlaylist {
+        name: None,
+        entries: entries.into_values().filter(|e| !e.location.is_empty()).collect(),
+    }
+}
+
+/// Fetch the playlist file at `url`. Relative entries of it are made absolute against `url`,
+/// so they're downloaded from the same server.
+pub async fn fetch(url: &str) -> anyhow::Result<ParsedPlaylist> {
+    let base = reqwest::Url::parse(url)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .with_context(|| format!("{url} is not an http(s) URL"))?;
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
+        .build()?;
+    let mut resp = client.get(base.clone()).send().await?.error_for_status()?;
+    let mut data = vec![];
+    while let Some(chunk) = resp.chunk().await? {
+        data.extend_from_slice(&chunk);
+        if data.len() > MAX_FETCH_BYTES {
+            anyhow::bail!("Playlist is larger than {} KiB", MAX_FETCH_BYTES / 1024);
+        }
+    }
+
+    let mut parsed = parse(&String::from_utf8_lossy(&data));
+    for entry in &mut parsed.entries {
+        if let Ok(absolute) = base.join(entry.location.trim())
+            && matches!(absolute.scheme(), "http" | "https")
+        {
+            entry.location = absolute.to_string();
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parse a (possibly extended) M3U/M3U8 playlist.
+pub fn parse_m3u(content: &str) -> ParsedPlaylist {
+    let mut parsed = ParsedPlaylist::default();
+    let mut pending: Option<(Option<Duration>, Option<String>)> = None;
+
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<secs>[ attrs],<title>
+            let (length, title) = info.split_once(',').unwrap_or((info, ""));
+            let duration = length
+                .split_whitespace()
+                .next()
+                .and_then(|secs| secs.parse::<f64>().ok())
+                .filter(|secs| *secs > 0.0)
+                .map(Duration::from_secs_f64);
+            let title = Some(title.trim().to_string()).filter(|t| !t.is_empty());
+            pending = Some((duration, title));
+        } else if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+            parsed.name = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+        } else if line.starts_with('#') {
+            // Other directives and comments
+        } else {
+            let (duration, title) = pending.take().unwrap_or_default();
+            parsed.entries.push(ImportEntry {
+                location: line.to_string(),
+                title,
+                duration,
+            });
+        }
+    }
+
+    parsed
+}
+
+/// Whether `path` is inside one of the allowed `roots`.
+pub fn is_within_roots(path: &Path, roots: &[PathBuf]) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+
+    roots
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| path.starts_with(root))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let Ok(hex) = std::str::from_utf8(&bytes[i + 1..i + 3])
+            && let Ok(b) = u8::from_str_radix(hex, 16)
+        {
+            out.push(b);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
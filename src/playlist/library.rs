@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::merge::MergeTrack;
+use super::{PlaylistMeta, TrackMeta, dedupe, names};
+
+/// A track of the library; the same file in several playlists is one track.
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryTrack {
+    /// Content hash, used to pick tracks for a new playlist.
+    pub key: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Playlists the track is in, in the order they were listed.
+    pub playlists: Vec<LibraryRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryRef {
+    pub playlist_id: String,
+    pub playlist_name: String,
+    pub file: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LibraryFilter {
+    /// Words that must all appear in the title, artist, album or file name.
+    pub q: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Only tracks of this playlist.
+    pub playlist_id: Option<String>,
+    /// Only tracks of playlists with this tag.
+    pub tag: Option<String>,
+}
+
+impl LibraryFilter {
+    fn matches(&self, track: &LibraryTrack, tags: &HashMap<String, Vec<String>>) -> bool {
+        let contains = |value: &Option<String>, needle: &str| {
+            value
+                .as_ref()
+                .is_some_and(|v| v.to_lowercase().contains(&needle.to_lowercase()))
+        };
+        if let Some(artist) = &self.artist
+            && !contains(&track.artist, artist)
+        {
+            return false;
+        }
+        if let Some(album) = &self.album
+            && !contains(&track.album, album)
+        {
+            return false;
+        }
+        if let Some(id) = &self.playlist_id
+            && !track.playlists.iter().any(|p| &p.playlist_id == id)
+        {
+            return false;
+        }
+        if let Some(tag) = &self.tag
+            && !track
+                .playlists
+                .iter()
+                .any(|p| tags.get(&p.playlist_id).is_some_and(|t| t.contains(tag)))
+        {
+            return false;
+        }
+        if let Some(q) = &self.q {
+            let haystack = [
+                Some(track.title.clone()),
+                track.artist.clone(),
+                track.album.clone(),
+                Some(
+                    track
+                        .playlists
+                        .iter()
+                        .map(|p| p.file.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+            if !q.to_lowercase().split_whitespace().all(|w| haystack.contains(w)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Size, modification time and content hash of a file.
+type HashEntry = (u64, SystemTime, String);
+
+/// Content hashes keyed by path, recomputed when the size or modification time of the file changes.
+#[derive(Clone, Default)]
+pub struct LibraryCache {
+    hashes: Arc<Mutex<HashMap<PathBuf, HashEntry>>>,
+}
+
+impl LibraryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn hash(&self, path: &Path) -> std::io::Result<String> {
+        let meta = fs::metadata(path)?;
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        if let Some((size, at, hash)) = self.hashes.lock().unwrap().get(path)
+            && *size == meta.len()
+            && *at == modified
+        {
+            return Ok(hash.clone());
+        }
+
+        let hash = dedupe::hash_file(path)?;
+        self.hashes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (meta.len(), modified, hash.clone()));
+        Ok(hash)
+    }
+
+    /// Every track of `playlists` (folder name and meta, relative to `root`), sorted by title.
+    /// Hashing reads every file not seen before, so this blocks for a while on first use.
+    pub fn tracks(&self, root: &Path, playlists: &[(String, PlaylistMeta)]) -> Vec<LibraryTrack> {
+        let mut tracks: Vec<LibraryTrack> = vec![];
+        let mut index: HashMap<String, usize> = HashMap::new();
+
+        for (folder, meta) in playlists {
+            let dir = root.join(folder);
+            for track in &meta.tracks {
+                let path = names::resolve_track(&dir, track).unwrap_or_else(|| dir.join(&track.file));
+                let key = match self.hash(&path) {
+                    Ok(hash) => hash,
+                    Err(error) => {
+                        tracing::debug!("Skipping {} in the library: {}", path.display(), error);
+                        continue;
+                    }
+                };
+
+                let item = LibraryRef {
+                    playlist_id: meta.id.clone(),
+                    playlist_name: meta.name.clone(),
+                    file: track.file.clone(),
+                };
+                match index.get(&key) {
+                    Some(&i) => tracks[i].playlists.push(item),
+                    None => {
+                        index.insert(key.clone(), tracks.len());
+                        tracks.push(LibraryTrack {
+                            key,
+                            title: track.display_name(),
+                            artist: track.artist.clone(),
+                            album: track.album.clone(),
+                            duration: track.duration,
+                            size: track.size,
+                            playlists: vec![item],
+                        });
+                    }
+                }
+            }
+        }
+
+        tracks.sort_by_cached_key(|t| t.title.to_lowercase());
+        tracks
+    }
+
+    /// Tracks of the library passing `filter`.
+    pub fn search(
+        &self,
+        root: &Path,
+        playlists: &[(String, PlaylistMeta)],
+        filter: &LibraryFilter,
+    ) -> Vec<LibraryTrack> {
+        let tags: HashMap<String, Vec<String>> =
+            playlists.iter().map(|(_, m)| (m.id.clone(), m.tags.clone())).collect();
+        self.tracks(root, playlists)
+            .into_iter()
+            .filter(|t| filter.matches(t, &tags))
+            .collect()
+    }
+
+    /// The tracks of `keys`, in that order, from the first playlist each is in.
+    /// Returns the keys not found as the error.
+    pub fn pick(
+        &self,
+        root: &Path,
+        playlists: &[(String, PlaylistMeta)],
+        keys: &[String],
+    ) -> Result<Vec<MergeTrack>, Vec<String>> {
+        let tracks = self.tracks(root, playlists);
+        let by_key: HashMap<&str, &LibraryTrack> = tracks.iter().map(|t| (t.key.as_str(), t)).collect();
+
+        let mut picked = vec![];
+        let mut missing = vec![];
+        for key in keys {
+            let found = by_key
+                .get(key.as_str())
+                .and_then(|t| t.playlists.first())
+                .and_then(|r| {
+                    let (folder, meta) = playlists.iter().find(|(_, m)| m.id == r.playlist_id)?;
+                    let track: &TrackMeta = meta.tracks.iter().find(|t| t.file == r.file)?;
+                    Some(MergeTrack {
+                        dir: root.join(folder),
+                        track: track.clone(),
+                    })
+                });
+            match found {
+                Some(track) => picked.push(track),
+                None => missing.push(key.clone()),
+            }
+        }
+
+        if missing.is_empty() { Ok(picked) } else { Err(missing) }
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio_util::sync::CancellationToken;
+
+use super::{PlaylistMeta, get_playlists, names};
+use crate::downloader::{Progress, ProgressFn};
+use crate::player::PlayerHandle;
+use crate::settings::LoudnessSettings;
+
+/// Peaks are kept this far below full scale when normalizing (dBTP).
+const MAX_TRUE_PEAK: f32 = -1.0;
+
+/// Integrated loudness (LUFS) and true peak (dBTP) of the file at `path`,
+/// measured by the ffmpeg `loudnorm` filter in its first (measurement) pass.
+pub fn measure(ffmpeg_path: &Path, path: &Path) -> anyhow::Result<(f32, f32)> {
+    let output = Command::new(ffmpeg_path)
+        .arg("-nostdin")
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-i")
+        .arg(path)
+        .arg("-map")
+        .arg("0:a:0")
+        .arg("-af")
+        .arg("loudnorm=print_format=json")
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to spawn ffmpeg from path: {:?}", ffmpeg_path))?;
+    if !output.status.success() {
+        anyhow::bail!("ffmpeg failed with status {}", output.status);
+    }
+
+    // The measurement is the last JSON object written to stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let json = stderr
+        .rfind('{')
+        .and_then(|start| Some(&stderr[start..=start + stderr[start..].find('}')?]))
+        .context("No loudnorm output")?;
+    let stats: serde_json::Value = serde_json::from_str(json).context("Invalid loudnorm output")?;
+    let value = |key: &str| -> anyhow::Result<f32> {
+        let value = stats[key].as_str().unwrap_or_default();
+        value
+            .parse::<f32>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .with_context(|| format!("Invalid {key} {value:?}, is the track silent?"))
+    };
+    Ok((value("input_i")?, value("input_tp")?))
+}
+
+/// Gain (dB) bringing a track measured at `loudness` and `true_peak` to `target` (LUFS),
+/// reduced so its peaks stay below `MAX_TRUE_PEAK`.
+pub fn gain_db(loudness: f32, true_peak: f32, target: f32) -> f32 {
+    (target - loudness).min(MAX_TRUE_PEAK - true_peak)
+}
+
+/// Measure the tracks of `meta` in `dir` that have no loudness yet, eg. while publishing.
+/// Tracks that can't be measured are left for the background analysis.
+pub async fn measure_tracks(
+    dir: &Path,
+    meta: &mut PlaylistMeta,
+    settings: &LoudnessSettings,
+    progress: &ProgressFn<'_>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let total = meta.tracks.len();
+    for (i, track) in meta.tracks.iter_mut().enumerate() {
+        if cancel.is_cancelled() {
+            anyhow::bail!("Cancelled");
+        }
+        if track.loudness.is_some() {
+            continue;
+        }
+        progress(Progress {
+            percent: Some(i as f32 / total as f32 * 100.0),
+            current: Some(track.display_name()),
+        });
+
+        let path = names::resolve_track(dir, track).unwrap_or_else(|| dir.join(&track.file));
+        let ffmpeg_path = settings.ffmpeg_path.clone();
+        let measure_path = path.clone();
+        match tokio::task::spawn_blocking(move || measure(&ffmpeg_path, &measure_path))
+            .await
+            .context("Loudness task failed")?
+        {
+            Ok((loudness, true_peak)) => {
+                track.loudness = Some(loudness);
+                track.true_peak = Some(true_peak);
+            }
+            Err(error) => tracing::warn!("Loudness analysis of {} failed: {error:#}", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Measure the tracks of all playlists that have no loudness yet, saving each playlist as it's done.
+/// Files in `failed` are skipped, and files failing now are added to it.
+/// Returns the dirs of the updated playlists.
+fn analyze_all(root: &Path, settings: &LoudnessSettings, failed: &mut HashSet<PathBuf>) -> Vec<PathBuf> {
+    let playlists = match get_playlists(root) {
+        Ok(playlists) => playlists,
+        Err(error) => {
+            tracing::warn!("Loudness analysis failed to list playlists: {error:#}");
+            return vec![];
+        }
+    };
+
+    let mut updated = vec![];
+    for (folder, meta) in playlists {
+        let dir = root.join(&folder);
+        let mut measured = HashMap::new();
+        for track in meta.tracks.iter().filter(|t| t.loudness.is_none()) {
+            let path = names::resolve_track(&dir, track).unwrap_or_else(|| dir.join(&track.file));
+            if failed.contains(&path) {
+                continue;
+            }
+            match measure(&settings.ffmpeg_path, &path) {
+                Ok(values) => {
+                    measured.insert(track.file.clone(), values);
+                }
+                Err(error) => {
+                    tracing::warn!("Loudness analysis of {} failed: {error:#}", path.display());
+                    failed.insert(path);
+                }
+            }
+        }
+        if measured.is_empty() {
+            continue;
+        }
+
+        // Load again, the playlist may have changed while measuring
+        let meta_path = dir.join("playlist.json");
+        let mut meta = match PlaylistMeta::load(&meta_path) {
+            Ok(meta) => meta,
+            Err(error) => {
+                tracing::warn!("Failed to load {}: {error:#}", meta_path.display());
+                continue;
+            }
+        };
+        for track in meta.tracks.iter_mut() {
+            if let Some(&(loudness, true_peak)) = measured.get(&track.file) {
+                track.loudness = Some(loudness);
+                track.true_peak = Some(true_peak);
+            }
+        }
+        match meta.save_atomic(&meta_path) {
+            Ok(()) => {
+                tracing::info!("Measured loudness of {} tracks of '{}'", measured.len(), meta.name);
+                updated.push(dir);
+            }
+            Err(error) => tracing::warn!("Failed to save loudness of '{}': {error:#}", meta.name),
+        }
+    }
+    updated
+}
+
+/// Measure tracks without loudness every `interval_secs`, starting right away,
+/// so playlists published before the analysis was enabled are normalized too.
+pub fn spawn(settings: &LoudnessSettings, root: PathBuf, player: PlayerHandle) {
+    if !settings.enable {
+        return;
+    }
+
+    let settings = settings.clone();
+    tokio::spawn(async move {
+        let mut failed = HashSet::new();
+        loop {
+            let (pass_root, pass_settings) = (root.clone(), settings.clone());
+            let res = tokio::task::spawn_blocking(move || {
+                let updated = analyze_all(&pass_root, &pass_settings, &mut failed);
+                (updated, failed)
+            })
+            .await;
+            match res {
+                Ok((updated, still_failed)) => {
+                    failed = still_failed;
+                    for dir in updated {
+                        player.reload_if_current(dir);
+                    }
+                }
+                Err(error) => {
+                    tracing::warn!("Loudness analysis failed: {error}");
+                    failed = HashSet::new();
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(settings.interval_secs)).await;
+        }
+    });
+}
@@ -0,0 +1,322 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use lofty::file::TaggedFileExt;
+use lofty::tag::ItemKey;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::{TrackMeta, file_stem, find_playlist, names};
+use crate::notifier::{Notification, Notifier};
+use crate::player::PlayerHandle;
+use crate::settings::LyricsSettings;
+
+/// Directory inside a playlist where fetched lyrics are cached.
+pub const LYRICS_DIR: &str = "lyrics";
+
+/// Tracks without lyrics are looked up online again after this long.
+const NOT_FOUND_RETRY_DAYS: i64 = 7;
+const TIMEOUT_SECS: u64 = 10;
+/// How often the position is checked for the next line while playing.
+const TICK_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LyricsSource {
+    /// Embedded in the tags of the track.
+    Tag,
+    Lrclib,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LyricLine {
+    pub time_ms: u64,
+    pub text: String,
+}
+
+/// Lyrics of a track, as cached in the playlist's lyrics dir.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lyrics {
+    /// Where the lyrics came from, unset when none were found.
+    pub source: Option<LyricsSource>,
+    pub plain: Option<String>,
+    /// Timed lines, when the lyrics are synced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub synced: Vec<LyricLine>,
+    #[serde(default)]
+    pub instrumental: bool,
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl Lyrics {
+    fn none() -> Self {
+        Self {
+            source: None,
+            plain: None,
+            synced: vec![],
+            instrumental: false,
+            fetched_at: Utc::now(),
+        }
+    }
+
+    /// From lyrics text, synced when it's in LRC format.
+    fn from_text(text: &str, source: LyricsSource) -> Self {
+        let synced = parse_lrc(text);
+        let plain = if synced.is_empty() {
+            text.trim().to_string()
+        } else {
+            synced.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n")
+        };
+        Self {
+            source: Some(source),
+            plain: Some(plain),
+            synced,
+            ..Self::none()
+        }
+    }
+}
+
+/// Timed lines of LRC lyrics (`[mm:ss.xx]text`), sorted by time. Empty if there are none.
+pub fn parse_lrc(text: &str) -> Vec<LyricLine> {
+    let mut lines = vec![];
+    for line in text.lines() {
+        // A line can have several timestamps, eg. for a repeated chorus
+        let mut rest = line.trim();
+        let mut times = vec![];
+        while let Some(tail) = rest.strip_prefix('[')
+            && let Some((stamp, after)) = tail.split_once(']')
+        {
+            match parse_timestamp(stamp) {
+                Some(time_ms) => times.push(time_ms),
+                // Tags like [ar:Artist]
+                None => break,
+            }
+            rest = after;
+        }
+        for time_ms in times {
+            lines.push(LyricLine {
+                time_ms,
+                text: rest.trim().to_string(),
+            });
+        }
+    }
+    lines.sort_by_key(|l| l.time_ms);
+    lines
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` in milliseconds.
+fn parse_timestamp(stamp: &str) -> Option<u64> {
+    let (minutes, seconds) = stamp.split_once(':')?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let (secs, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+    let secs: u64 = secs.parse().ok()?;
+    if secs >= 60 || fraction.is_empty() || fraction.len() > 3 {
+        return None;
+    }
+    let fraction_ms = fraction.parse::<u64>().ok()? * 10u64.pow(3 - fraction.len() as u32);
+    Some((minutes * 60 + secs) * 1000 + fraction_ms)
+}
+
+fn cache_path(dir: &Path, track: &TrackMeta) -> PathBuf {
+    dir.join(LYRICS_DIR).join(format!("{}.json", file_stem(&track.file)))
+}
+
+/// Lyrics embedded in the tags of the track file.
+fn read_tag(dir: &Path, track: &TrackMeta) -> Option<String> {
+    let path = names::resolve_track(dir, track).unwrap_or_else(|| dir.join(&track.file));
+    let tagged_file = lofty::read_from_path(&path).ok()?;
+    tagged_file
+        .tags()
+        .iter()
+        .find_map(|tag| tag.get_string(ItemKey::Lyrics))
+        .map(|text| text.to_string())
+        .filter(|text| !text.trim().is_empty())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibResp {
+    #[serde(default)]
+    instrumental: bool,
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+/// Look the track up on LRCLIB by artist, title, album and duration. `None` if it isn't known.
+async fn fetch_lrclib(url: &str, track: &TrackMeta) -> anyhow::Result<Option<Lyrics>> {
+    let (Some(artist), Some(title)) = (&track.artist, &track.title) else {
+        return Ok(None);
+    };
+
+    let mut query = vec![("artist_name", artist.clone()), ("track_name", title.clone())];
+    if let Some(album) = &track.album {
+        query.push(("album_name", album.clone()));
+    }
+    if let Some(duration) = track.duration {
+        query.push(("duration", duration.as_secs().to_string()));
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .user_agent(concat!(
+            "musicd/",
+            env!("CARGO_PKG_VERSION"),
+            " (",
+            env!("CARGO_PKG_REPOSITORY"),
+            ")"
+        ))
+        .build()?;
+    let res = client
+        .get(format!("{}/api/get", url.trim_end_matches('/')))
+        .query(&query)
+        .send()
+        .await
+        .context("LRCLIB request failed")?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let resp: LrclibResp = res
+        .error_for_status()?
+        .json()
+        .await
+        .context("Invalid LRCLIB response")?;
+
+    let lyrics = match (resp.synced_lyrics, resp.plain_lyrics) {
+        (Some(synced), _) if !synced.trim().is_empty() => Lyrics::from_text(&synced, LyricsSource::Lrclib),
+        (_, Some(plain)) if !plain.trim().is_empty() => Lyrics::from_text(&plain, LyricsSource::Lrclib),
+        _ => Lyrics {
+            source: Some(LyricsSource::Lrclib),
+            ..Lyrics::none()
+        },
+    };
+    Ok(Some(Lyrics {
+        instrumental: resp.instrumental,
+        ..lyrics
+    }))
+}
+
+/// Lyrics of `track` in the playlist `dir`: cached ones, from its tags, or from LRCLIB when enabled.
+/// Tracks not found online are cached too, and only looked up again after a while.
+pub async fn get(dir: &Path, track: &TrackMeta, settings: &LyricsSettings) -> Lyrics {
+    let cache = cache_path(dir, track);
+    let online = settings.enable && settings.lrclib_url.is_some();
+    if let Some(lyrics) = tokio::fs::read(&cache)
+        .await
+        .ok()
+        .and_then(|data| serde_json::from_slice::<Lyrics>(&data).ok())
+        && (lyrics.source.is_some() || Utc::now() - lyrics.fetched_at < chrono::Duration::days(NOT_FOUND_RETRY_DAYS))
+    {
+        return lyrics;
+    }
+
+    let (tag_dir, tag_track) = (dir.to_path_buf(), track.clone());
+    let tag = tokio::task::spawn_blocking(move || read_tag(&tag_dir, &tag_track))
+        .await
+        .ok()
+        .flatten();
+    let lyrics = match (tag, &settings.lrclib_url) {
+        (Some(text), _) => Lyrics::from_text(&text, LyricsSource::Tag),
+        (None, Some(url)) if settings.enable => match fetch_lrclib(url, track).await {
+            Ok(lyrics) => lyrics.unwrap_or_else(Lyrics::none),
+            // Not cached, to try again next time
+            Err(error) => {
+                tracing::warn!("Failed to fetch lyrics of '{}': {error:#}", track.display_name());
+                return Lyrics::none();
+            }
+        },
+        (None, _) => Lyrics::none(),
+    };
+
+    // Reading the tags again is cheap, only remember online lookups
+    if lyrics.source.is_none() && !online {
+        return lyrics;
+    }
+    let res = match tokio::fs::create_dir_all(dir.join(LYRICS_DIR)).await {
+        Ok(()) => tokio::fs::write(&cache, serde_json::to_vec_pretty(&lyrics).unwrap_or_default()).await,
+        Err(error) => Err(error),
+    };
+    if let Err(error) = res {
+        tracing::warn!("Failed to cache lyrics of {}: {}", track.file, error);
+    }
+    lyrics
+}
+
+/// Synced lines of the track `file` of playlist `id`.
+async fn synced_lines(root: &Path, id: &str, file: &str, settings: &LyricsSettings) -> Vec<LyricLine> {
+    let Some((folder, meta)) = find_playlist(root, id) else {
+        return vec![];
+    };
+    let Some(track) = meta.tracks.iter().find(|t| t.file == file) else {
+        return vec![];
+    };
+    get(&root.join(folder), track, settings).await.synced
+}
+
+/// Send a `LYRIC_LINE` as each synced line of the current track is reached.
+pub fn spawn(settings: &LyricsSettings, root: PathBuf, notifier: Notifier, player: PlayerHandle) {
+    if !settings.enable {
+        return;
+    }
+
+    let settings = settings.clone();
+    let mut rx = notifier.subscribe_filtered(|n| {
+        matches!(
+            n,
+            Notification::Played
+                | Notification::TrackChanged { .. }
+                | Notification::PlaylistChanged(_)
+                | Notification::SeekPositionChanged { .. }
+                | Notification::QueueChanged { .. }
+        )
+    });
+    tokio::spawn(async move {
+        let mut current: Option<(String, String)> = None;
+        let mut lines: Vec<LyricLine> = vec![];
+        let mut last: Option<usize> = None;
+        loop {
+            let mut playing = false;
+            if let Ok(status) = player.status() {
+                let key = match &status.queued {
+                    Some(queued) => Some((queued.playlist_id.clone(), queued.file.clone())),
+                    None => status.playlist_id.clone().zip(status.current_track.clone()),
+                };
+                if key != current {
+                    current = key;
+                    last = None;
+                    lines = match &current {
+                        Some((id, file)) => synced_lines(&root, id, file, &settings).await,
+                        None => vec![],
+                    };
+                }
+
+                playing = status.is_paused == Some(false) && !lines.is_empty();
+                if let Some(pos) = status.current_pos.filter(|_| playing) {
+                    let line = lines.iter().rposition(|l| l.time_ms <= pos.as_millis() as u64);
+                    if line != last {
+                        last = line;
+                        if let Some(index) = line {
+                            notifier.notify(Notification::LyricLine {
+                                index,
+                                time_ms: lines[index].time_ms,
+                                text: lines[index].text.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+
+            // Wait for the next line while playing, otherwise for playback to change
+            if playing {
+                tokio::time::sleep(Duration::from_millis(TICK_MS)).await;
+                while let Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) = rx.try_recv() {}
+            } else {
+                match rx.recv().await {
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{PlaylistMeta, TrackMeta};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeOrder {
+    /// Tracks of each playlist in the given order, one playlist after another.
+    #[default]
+    Concat,
+    /// One track from each playlist in turn.
+    Interleave,
+    /// Sorted by display name.
+    Title,
+}
+
+/// Track to be merged, along with the dir of the playlist it comes from.
+pub struct MergeTrack {
+    pub dir: PathBuf,
+    pub track: TrackMeta,
+}
+
+/// Combine the tracks of `playlists` in `order`, dropping duplicates.
+pub fn merge_tracks(playlists: &[(PathBuf, PlaylistMeta)], order: &MergeOrder) -> Vec<MergeTrack> {
+    let mut merged = vec![];
+    match order {
+        MergeOrder::Concat | MergeOrder::Title => {
+            for (dir, meta) in playlists {
+                for track in &meta.tracks {
+                    merged.push(MergeTrack {
+                        dir: dir.clone(),
+                        track: track.clone(),
+                    });
+                }
+            }
+        }
+        MergeOrder::Interleave => {
+            let longest = playlists.iter().map(|(_, m)| m.tracks.len()).max().unwrap_or(0);
+            for i in 0..longest {
+                for (dir, meta) in playlists {
+                    if let Some(track) = meta.tracks.get(i) {
+                        merged.push(MergeTrack {
+                            dir: dir.clone(),
+                            track: track.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Keep the first occurrence of each track
+    let mut seen = HashSet::new();
+    merged.retain(|m| seen.insert(dedupe_key(&m.track)));
+
+    if let MergeOrder::Title = order {
+        merged.sort_by_cached_key(|m| m.track.display_name().to_lowercase());
+    }
+
+    merged
+}
+
+/// Source URL when known, otherwise the normalized display name.
+fn dedupe_key(track: &TrackMeta) -> String {
+    match &track.source_url {
+        Some(url) => url.clone(),
+        None => track.display_name().to_lowercase(),
+    }
+}
+
+/// Strip index prefixes (eg. `001-`) added by earlier publishes, so they don't pile up.
+pub fn strip_index_prefix(file: &str) -> &str {
+    let mut rest = file;
+    while let Some((prefix, tail)) = rest.split_once('-') {
+        if prefix.len() != 3 || !prefix.bytes().all(|b| b.is_ascii_digit()) || tail.is_empty() {
+            break;
+        }
+        rest = tail;
+    }
+    rest
+}
+
+/// Hardlink `from` to `to` when asked, falling back to a copy (eg. across filesystems).
+pub async fn link_or_copy(from: &Path, to: &Path, link: bool) -> std::io::Result<()> {
+    if link && tokio::fs::hard_link(from, to).await.is_ok() {
+        return Ok(());
+    }
+    tokio::fs::copy(from, to).await.map(|_| ())
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::picture::PictureType;
+use lofty::tag::Accessor;
+use serde::{Deserialize, Deserializer, Serialize};
+use sort::TrackSort;
+
+pub mod dedupe;
+pub mod export;
+pub mod import;
+pub mod library;
+pub mod loudness;
+pub mod lyrics;
+pub mod merge;
+pub mod names;
+pub mod peaks;
+pub mod retention;
+pub mod sort;
+pub mod usage;
+pub mod versions;
+pub mod watch;
+
+/// Highest track rating.
+pub const MAX_RATING: u8 = 5;
+
+/// Directory inside a playlist where per-track art is stored.
+pub const ART_DIR: &str = "art";
+
+/// File extensions recognized as images (covers and thumbnails).
+pub const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+/// File extensions recognized as audio tracks.
+pub const AUDIO_EXTENSIONS: &[&str] = &["m4a", "mp3", "ogg", "flac", "wav", "aac", "opus"];
+
+pub fn is_audio_file(p: &Path) -> bool {
+    p.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlaylistMeta {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub sources: Vec<String>, // e.g., url, or "uploaded"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Free-form fields for manifests and UIs, kept as is.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    #[serde(deserialize_with = "deserialize_tracks")]
+    pub tracks: Vec<TrackMeta>,
+    /// Cover image relative to the playlist dir, eg. `cover.jpg`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// Order tracks are listed and played in.
+    #[serde(default, skip_serializing_if = "TrackSort::is_original")]
+    pub sort: TrackSort,
+    /// Seed for the `shuffle-seed` sort.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_seed: Option<u64>,
+    /// Free-form labels, eg. `morning` or `lobby`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Local folder mirrored by this playlist; tracks are absolute paths into it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<PathBuf>,
+    /// Podcasts and audiobooks: remember the position within each track.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub long_form: bool,
+    /// Never removed by the retention policy.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Refresh from the sources this often (seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval_secs: Option<u64>,
+    /// Last successful refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refreshed_at: Option<DateTime<Utc>>,
+    /// How the player plays this playlist, over the player settings.
+    #[serde(default, skip_serializing_if = "PlaylistSettings::is_empty")]
+    pub settings: PlaylistSettings,
+}
+
+/// Most a playlist is made louder or quieter (dB).
+pub const MAX_VOLUME_OFFSET_DB: f32 = 12.0;
+
+/// Longest crossfade between tracks.
+pub const MAX_CROSSFADE_SECS: f32 = 12.0;
+
+/// Playback settings of a playlist; unset ones follow the player settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistSettings {
+    /// Gain (dB) on top of loudness normalization, eg. -6 for a quieter playlist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_offset_db: Option<f32>,
+    /// Play in a shuffled order, kept between loads with `sort_seed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shuffle: Option<bool>,
+    /// Overrides `player.default_audio_effects`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_effects: Option<bool>,
+    /// Fade each track into the next over this many seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crossfade_secs: Option<f32>,
+}
+
+impl PlaylistSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Within the supported ranges; a crossfade of 0 is none.
+    pub fn clamped(self) -> Self {
+        Self {
+            volume_offset_db: self
+                .volume_offset_db
+                .map(|db| db.clamp(-MAX_VOLUME_OFFSET_DB, MAX_VOLUME_OFFSET_DB)),
+            crossfade_secs: self
+                .crossfade_secs
+                .map(|secs| secs.min(MAX_CROSSFADE_SECS))
+                .filter(|secs| *secs > 0.0),
+            ..self
+        }
+    }
+
+    pub fn crossfade(&self) -> Option<Duration> {
+        self.crossfade_secs
+            .filter(|secs| *secs > 0.0)
+            .map(|secs| Duration::from_secs_f32(secs.min(MAX_CROSSFADE_SECS)))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrackMeta {
+    /// File name relative to the playlist dir.
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    /// What the downloader knows the track as, eg. `youtube dQw4w9WgXcQ` as in a yt-dlp download archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<String>,
+    /// File size in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Track art relative to the playlist dir, eg. `art/001-Track.jpg`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub art: Option<String>,
+    /// Inode of the file, used to find it again if renamed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    /// Integrated loudness (LUFS), measured by the loudness analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<f32>,
+    /// True peak (dBTP), measured along with the loudness.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub true_peak: Option<f32>,
+    /// Rating from 1 to 5. Kept in the state db and only filled in for listings.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+}
+
+/// Accept both track objects and legacy file name strings.
+fn deserialize_tracks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<TrackMeta>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Track {
+        Legacy(String),
+        Meta(Box<TrackMeta>),
+    }
+
+    Ok(Vec::<Track>::deserialize(deserializer)?
+        .into_iter()
+        .map(|track| match track {
+            Track::Legacy(file) => TrackMeta::new(file),
+            Track::Meta(meta) => *meta,
+        })
+        .collect())
+}
+
+impl TrackMeta {
+    pub fn new(file: impl Into<String>) -> Self {
+        Self {
+            file: file.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Fill in size, tags, duration and art from the file in `dir`, keeping existing values where missing.
+    pub fn probe(&mut self, dir: &Path) {
+        let path = dir.join(&self.file);
+
+        if let Ok(metadata) = fs::metadata(&path) {
+            self.size = Some(metadata.len());
+        }
+        self.inode = names::inode(&path).or(self.inode);
+
+        // Prefer a thumbnail next to the track over embedded art
+        if self.art.as_ref().is_none_or(|art| !dir.join(art).exists()) {
+            self.art = find_sidecar_image(dir, &self.file).and_then(|image| store_art(dir, &self.file, &image));
+        }
+
+        let tagged_file = match lofty::read_from_path(&path) {
+            Ok(f) => f,
+            Err(error) => {
+                tracing::debug!("Failed to read tags of {}: {}", path.display(), error);
+                return;
+            }
+        };
+
+        let duration = tagged_file.properties().duration();
+        if !duration.is_zero() {
+            self.duration = Some(duration);
+        }
+
+        if let Some(tag) = tagged_file.primary_tag().or_else(|| tagged_file.first_tag()) {
+            let non_empty =
+                |s: Option<std::borrow::Cow<'_, str>>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+            self.title = non_empty(tag.title()).or(self.title.take());
+            self.artist = non_empty(tag.artist()).or(self.artist.take());
+            self.album = non_empty(tag.album()).or(self.album.take());
+
+            if self.art.is_none()
+                && let Some(picture) = tag
+                    .get_picture_type(PictureType::CoverFront)
+                    .or_else(|| tag.pictures().first())
+            {
+                let ext = picture.mime_type().and_then(|m| m.ext()).unwrap_or("jpg");
+                let art = format!("{ART_DIR}/{}.{ext}", file_stem(&self.file));
+                let res = fs::create_dir_all(dir.join(ART_DIR)).and_then(|_| fs::write(dir.join(&art), picture.data()));
+                match res {
+                    Ok(()) => self.art = Some(art),
+                    Err(error) => tracing::warn!("Failed to write art of {}: {}", path.display(), error),
+                }
+            }
+        }
+    }
+
+    /// Title for display, falling back to the file name without extension.
+    pub fn display_name(&self) -> String {
+        match &self.title {
+            Some(title) => match &self.artist {
+                Some(artist) => format!("{artist} - {title}"),
+                None => title.clone(),
+            },
+            None => file_stem(&self.file),
+        }
+    }
+}
+
+fn file_stem(file: &str) -> String {
+    Path::new(file)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.to_string())
+}
+
+/// Image with the same stem as a track, eg. a thumbnail written by the downloader.
+pub fn find_sidecar_image(dir: &Path, file: &str) -> Option<PathBuf> {
+    let stem = file_stem(file);
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{stem}.{ext}")))
+        .find(|p| p.is_file())
+}
+
+/// Move a sidecar image into the art dir, returning its relative path.
+fn store_art(dir: &Path, file: &str, image: &Path) -> Option<String> {
+    let ext = image.extension()?.to_string_lossy().to_lowercase();
+    let art = format!("{ART_DIR}/{}.{ext}", file_stem(file));
+    let res = fs::create_dir_all(dir.join(ART_DIR)).and_then(|_| fs::rename(image, dir.join(&art)));
+    match res {
+        Ok(()) => Some(art),
+        Err(error) => {
+            tracing::warn!("Failed to store art {}: {}", image.display(), error);
+            None
+        }
+    }
+}
+
+impl PlaylistMeta {
+    /// Whether the tracks can be downloaded again, ie. it was published from urls.
+    pub fn can_refresh(&self) -> bool {
+        self.folder.is_none()
+            && !self.sources.is_empty()
+            && self
+                .sources
+                .iter()
+                .all(|s| s.starts_with("http://") || s.starts_with("https://"))
+    }
+
+    pub fn load(p: &Path) -> anyhow::Result<Self> {
+        let s = fs::read_to_string(p)?;
+        Ok(serde_json::from_str(&s)?)
+    }
+
+    pub async fn load_async(p: &Path) -> Option<Self> {
+        tokio::fs::read_to_string(&p)
+            .await
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+    }
+
+    #[allow(dead_code)]
+    pub fn save(&self, p: &Path) -> anyhow::Result<()> {
+        fs::write(p, serde_json::to_vec_pretty(&self).unwrap())?;
+        Ok(())
+    }
+
+    /// Write to a temp file and rename over `p`, so readers never see a partial file.
+    /// The previous version is kept as a snapshot.
+    pub fn save_atomic(&self, p: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = p.parent()
+            && let Err(error) = versions::snapshot(dir)
+        {
+            tracing::warn!("Failed to snapshot {}: {error:#}", p.display());
+        }
+
+        let tmp = p.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self)?)?;
+        fs::rename(&tmp, p)?;
+        Ok(())
+    }
+
+    pub async fn save_async(&self, p: &Path) -> anyhow::Result<()> {
+        tokio::fs::write(&p, serde_json::to_vec_pretty(&self).unwrap()).await?;
+        Ok(())
+    }
+
+    /// Probe all tracks in `dir` for metadata and art, then pick a cover.
+    pub fn probe_tracks(&mut self, dir: &Path) {
+        for track in self.tracks.iter_mut() {
+            track.probe(dir);
+        }
+        self.ensure_cover(dir);
+    }
+
+    /// Same as `probe_tracks`, with blocking IO moved off the runtime.
+    pub async fn probe_tracks_async(&mut self, dir: &Path) {
+        let dir = dir.to_path_buf();
+        let mut meta = self.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            meta.probe_tracks(&dir);
+            meta
+        })
+        .await;
+
+        match res {
+            Ok(meta) => *self = meta,
+            Err(error) => tracing::warn!("Failed to probe tracks: {error}"),
+        }
+    }
+
+    /// Add normalized `tags`, skipping empty and existing ones.
+    pub fn add_tags(&mut self, tags: &[String]) {
+        for tag in tags.iter().map(|t| normalize_tag(t)) {
+            if !tag.is_empty() && !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+
+    /// Reorder tracks by `sort`, or shuffled with `settings.shuffle`. Only for listing and playback,
+    /// the stored order stays the original.
+    pub fn apply_sort(&mut self) {
+        let sort = match self.settings.shuffle {
+            Some(true) => TrackSort::ShuffleSeed,
+            _ => self.sort,
+        };
+        sort.apply(&mut self.tracks, self.sort_seed.unwrap_or_default());
+    }
+
+    /// Fill in track ratings (keyed by file) for listings.
+    pub fn apply_ratings(&mut self, ratings: &HashMap<String, u8>) {
+        for track in self.tracks.iter_mut() {
+            track.rating = ratings.get(&track.file).copied();
+        }
+    }
+
+    /// Whether any track is missing its precomputed duration (eg. playlists from older versions).
+    pub fn needs_probe(&self) -> bool {
+        self.tracks.iter().any(|t| t.duration.is_none())
+    }
+
+    /// Sum of all known track durations.
+    pub fn total_duration(&self) -> Duration {
+        self.tracks.iter().filter_map(|t| t.duration).sum()
+    }
+
+    /// Use an existing `cover.*` in `dir`, or copy the first track art as the cover.
+    pub fn ensure_cover(&mut self, dir: &Path) {
+        if self.cover.as_ref().is_some_and(|cover| dir.join(cover).is_file()) {
+            return;
+        }
+
+        self.cover = find_sidecar_image(dir, "cover").and_then(|p| Some(p.file_name()?.to_string_lossy().to_string()));
+        if self.cover.is_some() {
+            return;
+        }
+
+        if let Some(art) = self.tracks.iter().find_map(|t| t.art.as_ref()) {
+            let ext = Path::new(art)
+                .extension()
+                .map(|e| e.to_string_lossy().to_string())
+                .unwrap_or("jpg".into());
+            let cover = format!("cover.{ext}");
+            match fs::copy(dir.join(art), dir.join(&cover)) {
+                Ok(_) => self.cover = Some(cover),
+                Err(error) => tracing::warn!("Failed to write cover: {}", error),
+            }
+        }
+    }
+
+    pub fn dir_name(&self) -> String {
+        // "2025-08-name_id"
+        format!(
+            "{}-{}_{:.8}",
+            self.created_at.format("%Y-%m"),
+            safe(&self.name),
+            &self.id
+        )
+    }
+}
+
+/// Tags are matched case-insensitively and without surrounding whitespace.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+fn safe(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+pub fn get_playlists(root: &Path) -> anyhow::Result<Vec<(String, PlaylistMeta)>> {
+    let mut out = vec![];
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let meta = entry.path().join("playlist.json");
+            if meta.exists()
+                && let Ok(p) = PlaylistMeta::load(&meta)
+            {
+                out.push((entry.file_name().to_string_lossy().to_string(), p));
+            }
+        }
+    }
+    out.sort_by_key(|(_, m)| m.created_at);
+    out.reverse();
+    Ok(out)
+}
+
+pub fn find_playlist(root: &Path, id: &str) -> Option<(String, PlaylistMeta)> {
+    get_playlists(root).ok()?.into_iter().find(|(_, m)| m.id == id)
+}
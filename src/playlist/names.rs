@@ -0,0 +1,144 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{PlaylistMeta, TrackMeta, is_audio_file};
+
+/// Longest file name kept, in bytes (most filesystems allow 255).
+const MAX_FILE_NAME_BYTES: usize = 200;
+
+/// Make a file name safe to round-trip through `playlist.json`: valid UTF-8, no control
+/// characters or separators, no surrounding spaces or dots, and not too long.
+pub fn normalize_file_name(name: &OsStr) -> String {
+    let name: String = name
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_control() || c == '/' || c == '\\' || c == char::REPLACEMENT_CHARACTER {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    let path = Path::new(&name);
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().trim().to_string())
+        .filter(|e| !e.is_empty());
+    let stem = match &ext {
+        Some(_) => path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        None => name.clone(),
+    };
+    let stem = stem.trim_matches(|c: char| c.is_whitespace() || c == '.');
+
+    let max_stem = MAX_FILE_NAME_BYTES - ext.as_ref().map_or(0, |e| e.len() + 1);
+    let mut end = stem.len().min(max_stem);
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    let stem = match stem[..end].trim_end() {
+        "" => "track",
+        s => s,
+    };
+
+    match ext {
+        Some(ext) => format!("{stem}.{ext}"),
+        None => stem.to_string(),
+    }
+}
+
+/// Rename audio files in `dir` to normalized names and update the tracks of `meta` to match.
+pub fn normalize_dir(dir: &Path, meta: &mut PlaylistMeta) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let raw = entry.file_name();
+        let normalized = normalize_file_name(&raw);
+        if !path.is_file() || !is_audio_file(Path::new(&normalized)) || raw.as_os_str() == OsStr::new(&normalized) {
+            continue;
+        }
+
+        // Keep names unique
+        let mut target = normalized.clone();
+        let mut n = 2;
+        while dir.join(&target).exists() {
+            let p = Path::new(&normalized);
+            let stem = p
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            target = match p.extension() {
+                Some(ext) => format!("{stem}-{n}.{}", ext.to_string_lossy()),
+                None => format!("{stem}-{n}"),
+            };
+            n += 1;
+        }
+
+        fs::rename(&path, dir.join(&target))?;
+        tracing::info!("Renamed {:?} to {}", raw, target);
+
+        let lossy = raw.to_string_lossy();
+        for track in meta.tracks.iter_mut().filter(|t| t.file == lossy) {
+            track.file = target.clone();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn inode(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| m.ino())
+}
+
+#[cfg(not(unix))]
+pub fn inode(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Path of `track` in `dir`. When the name doesn't match exactly (eg. a playlist synced from
+/// another machine), fall back to the same normalized name, the same inode, then the only
+/// audio file with the same size.
+pub fn resolve_track(dir: &Path, track: &TrackMeta) -> Option<PathBuf> {
+    let exact = dir.join(&track.file);
+    if exact.exists() {
+        return Some(exact);
+    }
+
+    let candidates: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|e| is_audio_file(Path::new(&normalize_file_name(&e.file_name()))))
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+
+    let normalized = normalize_file_name(OsStr::new(&track.file));
+    let found = candidates
+        .iter()
+        .find(|p| p.file_name().is_some_and(|n| normalize_file_name(n) == normalized))
+        .or_else(|| {
+            let ino = track.inode?;
+            candidates.iter().find(|p| inode(p) == Some(ino))
+        })
+        .or_else(|| {
+            let size = track.size?;
+            let mut same_size = candidates
+                .iter()
+                .filter(|p| fs::metadata(p).is_ok_and(|m| m.len() == size));
+            match (same_size.next(), same_size.next()) {
+                (Some(p), None) => Some(p),
+                _ => None,
+            }
+        })
+        .cloned();
+
+    if let Some(path) = &found {
+        tracing::warn!("Resolved track {} to {}", track.file, path.display());
+    }
+    found
+}
@@ -0,0 +1,106 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::Context;
+use rodio::Source;
+use rodio::decoder::DecoderBuilder;
+use serde::{Deserialize, Serialize};
+
+use super::{TrackMeta, file_stem, names};
+
+/// Directory inside a playlist where waveform peaks are cached.
+pub const PEAKS_DIR: &str = "peaks";
+
+pub const DEFAULT_PEAKS: usize = 800;
+pub const MAX_PEAKS: usize = 4000;
+
+/// Samples are first reduced to the peak of every this many milliseconds.
+const BLOCK_MS: u64 = 10;
+
+/// Waveform of a track for drawing a seek bar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Peaks {
+    /// Size of the track file the peaks were generated from, they're regenerated when it changes.
+    pub size: u64,
+    /// Length of the track the peaks span, in milliseconds.
+    pub duration_ms: u64,
+    /// Highest absolute sample of each equally long part of the track, from 0 to 1.
+    pub peaks: Vec<f32>,
+}
+
+/// Peaks of `track` in the playlist `dir`, `count` of them, read from the cache or generated.
+/// Decoding the whole track is slow, so call this off the runtime.
+pub fn load_or_generate(dir: &Path, track: &TrackMeta, count: usize) -> anyhow::Result<Peaks> {
+    let path = names::resolve_track(dir, track).unwrap_or_else(|| dir.join(&track.file));
+    let size = fs::metadata(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .len();
+
+    let cache = dir
+        .join(PEAKS_DIR)
+        .join(format!("{}-{count}.json", file_stem(&track.file)));
+    if let Some(peaks) = fs::read(&cache)
+        .ok()
+        .and_then(|data| serde_json::from_slice::<Peaks>(&data).ok())
+        .filter(|p| p.size == size)
+    {
+        return Ok(peaks);
+    }
+
+    let peaks = generate(&path, size, count)?;
+    let res = fs::create_dir_all(dir.join(PEAKS_DIR)).and_then(|_| fs::write(&cache, serde_json::to_vec(&peaks)?));
+    if let Err(error) = res {
+        tracing::warn!("Failed to cache peaks of {}: {}", path.display(), error);
+    }
+    Ok(peaks)
+}
+
+fn generate(path: &Path, size: u64, count: usize) -> anyhow::Result<Peaks> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let source = DecoderBuilder::new()
+        .with_data(BufReader::new(file))
+        .build()
+        .with_context(|| format!("Failed to decode {}", path.display()))?;
+    let channels = source.channels() as u64;
+    let sample_rate = source.sample_rate() as u64;
+    let block_len = (sample_rate * channels * BLOCK_MS / 1000).max(1) as usize;
+
+    // Peak of each block, so long tracks don't have to be kept in memory
+    let mut blocks = vec![];
+    let mut peak = 0f32;
+    let mut samples = 0u64;
+    for sample in source {
+        peak = peak.max(sample.abs());
+        samples += 1;
+        if samples.is_multiple_of(block_len as u64) {
+            blocks.push(peak);
+            peak = 0.0;
+        }
+    }
+    if !samples.is_multiple_of(block_len as u64) {
+        blocks.push(peak);
+    }
+
+    let peaks = if blocks.is_empty() {
+        vec![]
+    } else {
+        (0..count)
+            .map(|i| {
+                let start = i * blocks.len() / count;
+                let end = ((i + 1) * blocks.len() / count).max(start + 1).min(blocks.len());
+                let peak = blocks[start.min(blocks.len() - 1)..end]
+                    .iter()
+                    .fold(0f32, |a, &b| a.max(b));
+                // Two decimals are plenty for drawing
+                (peak.min(1.0) * 100.0).round() / 100.0
+            })
+            .collect()
+    };
+
+    Ok(Peaks {
+        size,
+        duration_ms: samples * 1000 / (sample_rate * channels).max(1),
+        peaks,
+    })
+}
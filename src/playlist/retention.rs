@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use super::{PlaylistMeta, usage};
+use crate::settings::RetentionSettings;
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Playlists to remove from `root` to satisfy `settings`, oldest first.
+/// `playlists` are newest first, as listed by `get_playlists`. The `active` ones (current or playing),
+/// pinned ones and mirrored folders are never picked, but count towards the limits.
+pub fn select(
+    root: &Path,
+    playlists: &[(String, PlaylistMeta)],
+    active: &[String],
+    settings: &RetentionSettings,
+) -> Vec<(String, PlaylistMeta)> {
+    if settings.keep_last.is_none() && settings.max_total_gb.is_none() {
+        return vec![];
+    }
+
+    let sizes: Vec<u64> = match settings.max_total_gb {
+        Some(_) => playlists
+            .iter()
+            .map(|(folder, _)| usage::dir_size(&root.join(folder)))
+            .collect(),
+        None => vec![0; playlists.len()],
+    };
+    let max_bytes = settings.max_total_gb.map(|gb| (gb * GB) as u64);
+
+    let mut count = playlists.len();
+    let mut total: u64 = sizes.iter().sum();
+    let mut removed = vec![];
+
+    for ((folder, meta), size) in playlists.iter().zip(sizes).rev() {
+        let too_many = settings.keep_last.is_some_and(|keep| count > keep);
+        let too_big = max_bytes.is_some_and(|max| total > max);
+        if !too_many && !too_big {
+            break;
+        }
+        if meta.pinned || meta.folder.is_some() || active.contains(&meta.id) {
+            continue;
+        }
+
+        count -= 1;
+        total -= size;
+        removed.push((folder.clone(), meta.clone()));
+    }
+
+    removed
+}
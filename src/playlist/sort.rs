@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use super::TrackMeta;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrackSort {
+    /// Order of `playlist.json`.
+    #[default]
+    Original,
+    /// By display name.
+    Title,
+    /// Shortest first, unknown durations last.
+    Duration,
+    /// Shuffled with the playlist `sort_seed`, so the order is stable between loads.
+    ShuffleSeed,
+}
+
+impl TrackSort {
+    pub fn is_original(&self) -> bool {
+        *self == TrackSort::Original
+    }
+
+    pub fn apply(&self, tracks: &mut [TrackMeta], seed: u64) {
+        match self {
+            TrackSort::Original => {}
+            TrackSort::Title => tracks.sort_by_cached_key(|t| t.display_name().to_lowercase()),
+            TrackSort::Duration => tracks.sort_by_key(|t| (t.duration.is_none(), t.duration)),
+            TrackSort::ShuffleSeed => shuffle(tracks, seed),
+        }
+    }
+}
+
+/// Fisher-Yates with splitmix64, deterministic for a given seed.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Seed for a new shuffle when none is given.
+pub fn new_seed() -> u64 {
+    uuid::Uuid::new_v4().as_u64_pair().0
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use super::{PlaylistMeta, names};
+
+/// Sum of the track files stored in the playlist `dir`.
+/// Tracks of mirrored folders live outside the data dir and are not counted.
+pub fn tracks_size(dir: &Path, meta: &PlaylistMeta) -> u64 {
+    meta.tracks
+        .iter()
+        .filter(|t| Path::new(&t.file).is_relative())
+        .filter_map(|t| fs::metadata(names::resolve_track(dir, t)?).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Size of everything under `dir`, recursively.
+pub fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            match entry.metadata() {
+                Ok(m) if m.is_dir() => stack.push(entry.path()),
+                Ok(m) => total += m.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    total
+}
+
+/// Playlist sizes keyed by folder, recomputed when `playlist.json` changes.
+#[derive(Clone, Default)]
+pub struct UsageCache {
+    sizes: Arc<Mutex<HashMap<String, (SystemTime, u64)>>>,
+}
+
+impl UsageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn size(&self, root: &Path, folder: &str, meta: &PlaylistMeta) -> u64 {
+        let dir = root.join(folder);
+        let modified = fs::metadata(dir.join("playlist.json"))
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        if let Some((at, size)) = self.sizes.lock().unwrap().get(folder)
+            && *at == modified
+        {
+            return *size;
+        }
+
+        let size = tracks_size(&dir, meta);
+        self.sizes.lock().unwrap().insert(folder.to_string(), (modified, size));
+        size
+    }
+}
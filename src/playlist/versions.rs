@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+
+use super::PlaylistMeta;
+
+/// Directory inside a playlist where snapshots of `playlist.json` are kept.
+pub const VERSIONS_DIR: &str = ".versions";
+
+/// Number of snapshots kept per playlist, oldest are removed first.
+const MAX_VERSIONS: usize = 20;
+
+const VERSION_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub version: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub name: String,
+    pub tracks: usize,
+}
+
+fn versions_dir(dir: &Path) -> PathBuf {
+    dir.join(VERSIONS_DIR)
+}
+
+/// Copy the current `playlist.json` of `dir` into the versions dir, if there is one.
+pub fn snapshot(dir: &Path) -> anyhow::Result<Option<String>> {
+    let current = dir.join("playlist.json");
+    if !current.is_file() {
+        return Ok(None);
+    }
+
+    let versions = versions_dir(dir);
+    fs::create_dir_all(&versions)?;
+
+    let version = Utc::now().format(VERSION_FORMAT).to_string();
+    fs::copy(&current, versions.join(format!("{version}.json")))?;
+
+    // Prune
+    let names = version_names(dir);
+    if names.len() > MAX_VERSIONS {
+        for name in &names[..names.len() - MAX_VERSIONS] {
+            fs::remove_file(versions.join(format!("{name}.json"))).ok();
+        }
+    }
+
+    Ok(Some(version))
+}
+
+/// Version names, oldest first.
+fn version_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(versions_dir(dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            name.strip_suffix(".json").map(|n| n.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Snapshots of the playlist in `dir`, newest first.
+pub fn list(dir: &Path) -> Vec<VersionInfo> {
+    let mut out: Vec<VersionInfo> = version_names(dir)
+        .into_iter()
+        .filter_map(|version| {
+            let meta = load(dir, &version).ok()?;
+            let created_at = NaiveDateTime::parse_from_str(&version, VERSION_FORMAT)
+                .ok()
+                .map(|t| t.and_utc());
+            Some(VersionInfo {
+                version,
+                created_at,
+                name: meta.name,
+                tracks: meta.tracks.len(),
+            })
+        })
+        .collect();
+    out.reverse();
+    out
+}
+
+pub fn load(dir: &Path, version: &str) -> anyhow::Result<PlaylistMeta> {
+    // Versions are file names, never paths
+    if version.is_empty() || version.contains(['/', '\\', '.']) {
+        anyhow::bail!("invalid version {version}");
+    }
+    PlaylistMeta::load(&versions_dir(dir).join(format!("{version}.json")))
+}
+
+/// Restore `version` as the current `playlist.json`, returning the files it lists that no longer exist.
+pub fn rollback(dir: &Path, version: &str) -> anyhow::Result<(PlaylistMeta, Vec<String>)> {
+    let current = PlaylistMeta::load(&dir.join("playlist.json"))?;
+    let mut meta = load(dir, version)?;
+
+    // Keep the identity of the playlist
+    meta.id = current.id;
+    meta.folder = current.folder;
+
+    let missing = meta
+        .tracks
+        .iter()
+        .filter(|t| !dir.join(&t.file).exists())
+        .map(|t| t.file.clone())
+        .collect();
+
+    meta.save_atomic(&dir.join("playlist.json"))?;
+    Ok((meta, missing))
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::{PlaylistMeta, TrackMeta, get_playlists, is_audio_file};
+use crate::notifier::{Notification, Notifier};
+use crate::player::PlayerHandle;
+use crate::settings::{WatchFolder, WatchSettings};
+
+/// Wait for more events before syncing, eg. while a folder is being copied.
+const DEBOUNCE_MS: u64 = 1000;
+
+/// Audio files under `root`, sorted by path.
+pub fn scan(root: &Path) -> Vec<PathBuf> {
+    let mut out = vec![];
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => stack.push(path),
+                Ok(t) if t.is_file() && is_audio_file(&path) => out.push(path),
+                _ => {}
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+/// Create or update the playlist mirroring `folder`, returning its dir when anything changed.
+pub fn sync_folder(
+    playlists_root: &Path,
+    folder: &WatchFolder,
+    notifier: &Notifier,
+) -> anyhow::Result<Option<PathBuf>> {
+    let existing = get_playlists(playlists_root)?
+        .into_iter()
+        .find(|(_, m)| m.folder.as_deref() == Some(folder.path.as_path()));
+
+    let is_new = existing.is_none();
+    let (dir, mut meta) = match existing {
+        Some((dir_name, meta)) => (playlists_root.join(dir_name), meta),
+        None => {
+            let name = folder.name.clone().unwrap_or_else(|| {
+                folder
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "Folder".to_string())
+            });
+            let meta = PlaylistMeta {
+                id: uuid::Uuid::new_v4().to_string(),
+                name,
+                created_at: chrono::Utc::now(),
+                sources: vec![folder.path.to_string_lossy().to_string()],
+                folder: Some(folder.path.clone()),
+                ..Default::default()
+            };
+            let dir = playlists_root.join(meta.dir_name());
+            fs::create_dir_all(&dir)?;
+            (dir, meta)
+        }
+    };
+
+    // Tracks reference the files in place, keep what was already probed
+    let mut known: HashMap<String, TrackMeta> = meta.tracks.drain(..).map(|t| (t.file.clone(), t)).collect();
+    let mut changed = is_new;
+    for path in scan(&folder.path) {
+        let file = path.to_string_lossy().to_string();
+        let track = match known.remove(&file) {
+            Some(track) => track,
+            None => {
+                changed = true;
+                let mut track = TrackMeta::new(file);
+                track.probe(&dir);
+                track
+            }
+        };
+        meta.tracks.push(track);
+    }
+    changed |= !known.is_empty();
+
+    if !changed {
+        return Ok(None);
+    }
+
+    meta.ensure_cover(&dir);
+    meta.save_atomic(&dir.join("playlist.json"))?;
+
+    tracing::info!(
+        "Synced playlist '{}' with folder {} ({} tracks)",
+        meta.name,
+        folder.path.display(),
+        meta.tracks.len()
+    );
+
+    // Notify
+    if is_new {
+        notifier.notify(Notification::PlaylistPublished((&meta).into()));
+    } else {
+        notifier.notify(Notification::PlaylistUpdated((&meta).into()));
+    }
+
+    Ok(Some(dir))
+}
+
+/// Keep a playlist in sync with each configured folder, picking folders up again when (re)mounted.
+pub fn watch_folders(settings: &WatchSettings, playlists_root: &Path, notifier: Notifier, player: PlayerHandle) {
+    for folder in settings.folders.clone() {
+        let playlists_root = playlists_root.to_path_buf();
+        let notifier = notifier.clone();
+        let player = player.clone();
+        let rescan = Duration::from_secs(settings.rescan_secs);
+
+        std::thread::spawn(move || {
+            let sync = || match sync_folder(&playlists_root, &folder, &notifier) {
+                Ok(Some(dir)) => player.reload_if_current(dir),
+                Ok(None) => {}
+                Err(error) => tracing::warn!("Failed to sync folder {}: {error:#}", folder.path.display()),
+            };
+
+            let (tx, rx) = mpsc::channel();
+            let mut watcher: Option<RecommendedWatcher> = None;
+
+            loop {
+                if watcher.is_none() && folder.path.is_dir() {
+                    let tx = tx.clone();
+                    let res = RecommendedWatcher::new(
+                        move |res| {
+                            let _ = tx.send(res);
+                        },
+                        notify::Config::default(),
+                    )
+                    .and_then(|mut w| w.watch(&folder.path, RecursiveMode::Recursive).map(|_| w));
+
+                    match res {
+                        Ok(w) => {
+                            tracing::info!("Watching folder {}", folder.path.display());
+                            watcher = Some(w);
+                            sync();
+                        }
+                        Err(error) => tracing::warn!("Failed to watch folder {}: {error}", folder.path.display()),
+                    }
+                }
+
+                match rx.recv_timeout(rescan) {
+                    Ok(Ok(event)) => {
+                        if matches!(
+                            event.kind,
+                            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                        ) {
+                            // Drain the burst before syncing
+                            while rx.recv_timeout(Duration::from_millis(DEBOUNCE_MS)).is_ok() {}
+                            sync();
+                        }
+                    }
+                    Ok(Err(error)) => tracing::warn!("Folder watch error: {error}"),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        // Unmounted; watch again once it is back
+                        if !folder.path.is_dir() {
+                            watcher = None;
+                        } else if watcher.is_some() {
+                            sync();
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+    }
+}
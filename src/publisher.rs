@@ -1,14 +1,25 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use tokio::fs;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
-use crate::downloader::yt_dlp::YtDlpDownloader;
-use crate::downloader::{Downloader, DownloaderKind};
+use crate::downloader::{self, Downloader, DownloaderKind, Progress, ProgressFn};
 use crate::notifier::{Notification, Notifier};
 use crate::player::{PlayerHandle, SetPlaylistMode};
-use crate::playlist::PlaylistMeta;
-use crate::settings::{DownloaderSettings, Paths, PublishSettings};
+use crate::playlist::import::{self, EntrySource, ImportEntry};
+use crate::playlist::merge::{self, MergeOrder, MergeTrack};
+use crate::playlist::{PlaylistMeta, TrackMeta, find_sidecar_image, get_playlists, is_audio_file};
+use crate::playlist::{loudness, names, retention, versions, watch};
+use crate::settings::{DownloaderSettings, LoudnessSettings, Paths, PublishSettings, Settings};
 use crate::state::State as Kv;
+use crate::transcode;
 
 #[derive(Clone)]
 pub struct Publisher {
@@ -16,42 +27,93 @@ pub struct Publisher {
     pub kv: Arc<Kv>,
     pub notifier: Notifier,
     pub player: PlayerHandle,
-    pub publish_settings: PublishSettings,
-    pub downloader_settings: DownloaderSettings,
+    publish_settings: Arc<RwLock<PublishSettings>>,
+    downloader_settings: Arc<RwLock<DownloaderSettings>>,
+    loudness_settings: Arc<RwLock<LoudnessSettings>>,
+    jobs: PublishJobs,
+    /// Running publishes are aborted below this much free disk space (bytes).
+    min_free_bytes: u64,
 }
 
 impl Publisher {
-    pub fn new(
-        paths: Paths,
-        kv: Arc<Kv>,
-        notifier: Notifier,
-        player: PlayerHandle,
-        publish_settings: PublishSettings,
-        downloader_settings: DownloaderSettings,
-    ) -> Self {
+    /// A publisher with the publish, downloader, loudness and disk `settings`.
+    pub fn new(paths: Paths, kv: Arc<Kv>, notifier: Notifier, player: PlayerHandle, settings: &Settings) -> Self {
         Self {
             paths,
             kv,
             notifier,
             player,
-            publish_settings,
-            downloader_settings,
+            publish_settings: Arc::new(RwLock::new(settings.publish.clone())),
+            downloader_settings: Arc::new(RwLock::new(settings.downloader.clone())),
+            loudness_settings: Arc::new(RwLock::new(settings.loudness.clone())),
+            jobs: PublishJobs::default(),
+            min_free_bytes: settings.disk.min_free_bytes,
         }
     }
 
-    pub fn publish_in_background(&self, name: &str, source_urls: &[String], downloader_kind: Option<DownloaderKind>) {
-        // Resolve downloader
-        let downloader_kind = downloader_kind.unwrap_or(self.downloader_settings.default.clone());
-        let downloader: Box<dyn Downloader> = match downloader_kind {
-            DownloaderKind::YtDlp => Box::new(YtDlpDownloader),
-        };
+    pub fn publish_settings(&self) -> PublishSettings {
+        self.publish_settings.read().unwrap().clone()
+    }
 
-        // Temp dir for target; we’ll write to final folder after we have id/name
-        let provisional_name = format!(
+    pub fn downloader_settings(&self) -> DownloaderSettings {
+        self.downloader_settings.read().unwrap().clone()
+    }
+
+    /// Apply reloaded settings to publishes started from now on.
+    pub fn set_settings(
+        &self,
+        publish_settings: PublishSettings,
+        downloader_settings: DownloaderSettings,
+        loudness_settings: LoudnessSettings,
+    ) {
+        *self.publish_settings.write().unwrap() = publish_settings;
+        *self.downloader_settings.write().unwrap() = downloader_settings;
+        *self.loudness_settings.write().unwrap() = loudness_settings;
+    }
+
+    /// Active publishes and the most recent finished ones, newest first.
+    pub fn jobs(&self) -> Vec<PublishJob> {
+        self.jobs.list()
+    }
+
+    pub fn job(&self, id: &str) -> Option<PublishJob> {
+        self.jobs.get(id)
+    }
+
+    /// A running publish downloading the same `sources`, or refreshing `playlist_id`.
+    pub fn active_job(&self, sources: &[String], playlist_id: Option<&str>) -> Option<PublishJob> {
+        self.jobs.list().into_iter().find(|job| {
+            !job.state.is_finished()
+                && ((!sources.is_empty() && job.sources == sources)
+                    || playlist_id.is_some_and(|id| job.playlist_id.as_deref() == Some(id)))
+        })
+    }
+
+    fn provisional_name(name: &str) -> String {
+        format!(
             "{}_{}",
             chrono::Utc::now().format("%Y%m%d%H%M%S"),
             name.replace(' ', "_")
-        );
+        )
+    }
+
+    pub fn publish_in_background(
+        &self,
+        name: &str,
+        source_urls: &[String],
+        downloader_kind: Option<DownloaderKind>,
+    ) -> String {
+        if let Some(job) = self.active_job(source_urls, None) {
+            tracing::info!("Already publishing {} ({}), not downloading it twice", job.name, job.id);
+            return job.id;
+        }
+
+        // Resolve downloader
+        let downloader_kind = downloader::resolve(downloader_kind, source_urls, self.downloader_settings().default);
+        let downloader = downloader::create(&downloader_kind, &self.publish_settings().local_roots);
+
+        // Temp dir for target; we’ll write to final folder after we have id/name
+        let provisional_name = Self::provisional_name(name);
 
         // Will be renamed after meta is fixed
         let final_dir = self.paths.playlists.join(&provisional_name);
@@ -63,70 +125,1170 @@ impl Publisher {
             final_dir
         );
 
-        // Perform download in background (fire-and-forget)
+        // Perform download in background
+        let (job_id, cancel) = self.start_job(name, PublishKind::Publish, source_urls);
+        let span = job_span(&job_id, name, PublishKind::Publish);
         let name = name.to_string();
         let sources = source_urls.to_vec();
-        let paths = self.paths.clone();
-        let kv = self.kv.clone();
-        let player = self.player.clone();
-        let publish_settings = self.publish_settings.clone();
-        let downloader_settings = self.downloader_settings.clone();
-        let notifier = self.notifier.clone();
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            let tmp_dir = publisher.paths.tmp.join(&provisional_name);
+            let _ = fs::remove_dir_all(&tmp_dir).await;
+            let _ = fs::create_dir_all(&tmp_dir).await;
+
+            publisher.jobs.set_state(&id, PublishState::Downloading);
+            let progress = publisher.progress_fn(&id);
+            let res = match downloader
+                .download_playlist(&sources, &tmp_dir, &publisher.downloader_settings(), &progress, &cancel)
+                .await
+            {
+                Ok(()) if cancel.is_cancelled() => Err(anyhow::anyhow!("Cancelled")),
+                Ok(()) => {
+                    publisher.jobs.set_state(&id, PublishState::PostProcessing);
+                    publisher.finalize(&id, &tmp_dir, &name, sources, &cancel).await
+                }
+                Err(error) => Err(error.context("Download failed")),
+            };
+            publisher.finish_job(&id, &name, &tmp_dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Build a playlist from imported entries, downloading remote ones and copying local ones.
+    pub fn import_in_background(
+        &self,
+        name: &str,
+        entries: Vec<ImportEntry>,
+        base_dir: Option<PathBuf>,
+        downloader_kind: Option<DownloaderKind>,
+    ) -> String {
+        let downloader_kind = downloader_kind.unwrap_or(self.downloader_settings().default);
+        let downloader = downloader::create(&downloader_kind, &self.publish_settings().local_roots);
+        let provisional_name = Self::provisional_name(name);
+
+        tracing::info!(
+            "Importing playlist {} with {} entries (downloader: {:?})",
+            name,
+            entries.len(),
+            downloader_kind
+        );
+
+        let locations: Vec<String> = entries.iter().map(|e| e.location.clone()).collect();
+        let (job_id, cancel) = self.start_job(name, PublishKind::Import, &locations);
+        let span = job_span(&job_id, name, PublishKind::Import);
+        let name = name.to_string();
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            let tmp_dir = publisher.paths.tmp.join(&provisional_name);
+            let _ = fs::remove_dir_all(&tmp_dir).await;
+            let _ = fs::create_dir_all(&tmp_dir).await;
+
+            publisher.jobs.set_state(&id, PublishState::Downloading);
+            let progress = publisher.progress_fn(&id);
+            let res = match publisher
+                .import_entries(
+                    &entries,
+                    base_dir.as_deref(),
+                    &tmp_dir,
+                    downloader.as_ref(),
+                    &progress,
+                    &cancel,
+                )
+                .await
+            {
+                Ok(()) if cancel.is_cancelled() => Err(anyhow::anyhow!("Cancelled")),
+                Ok(()) => {
+                    publisher.jobs.set_state(&id, PublishState::PostProcessing);
+                    let sources = entries.into_iter().map(|e| e.location).collect();
+                    publisher.finalize(&id, &tmp_dir, &name, sources, &cancel).await
+                }
+                Err(error) => Err(error.context("Import failed")),
+            };
+            publisher.finish_job(&id, &name, &tmp_dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Build a playlist from the audio files under the server directory `dir`,
+    /// copying them or, with `link`, symlinking them in place.
+    pub fn publish_local_in_background(&self, name: &str, dir: PathBuf, link: bool) -> String {
+        let provisional_name = Self::provisional_name(name);
+
+        tracing::info!("Publishing playlist {} from {} (link: {})", name, dir.display(), link);
+
+        let (job_id, cancel) = self.start_job(name, PublishKind::Local, &[dir.to_string_lossy().to_string()]);
+        let span = job_span(&job_id, name, PublishKind::Local);
+        let name = name.to_string();
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            let tmp_dir = publisher.paths.tmp.join(&provisional_name);
+            let _ = fs::remove_dir_all(&tmp_dir).await;
+            let _ = fs::create_dir_all(&tmp_dir).await;
+
+            publisher.jobs.set_state(&id, PublishState::Downloading);
+            let progress = publisher.progress_fn(&id);
+            let res = match publisher
+                .copy_local_files(&dir, &tmp_dir, link, &progress, &cancel)
+                .await
+            {
+                Ok(()) if cancel.is_cancelled() => Err(anyhow::anyhow!("Cancelled")),
+                Ok(()) => {
+                    publisher.jobs.set_state(&id, PublishState::PostProcessing);
+                    let sources = vec![dir.to_string_lossy().to_string()];
+                    publisher.finalize(&id, &tmp_dir, &name, sources, &cancel).await
+                }
+                Err(error) => Err(error.context("Copy failed")),
+            };
+            publisher.finish_job(&id, &name, &tmp_dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Publish the audio `files` uploaded into `dir` as a new playlist, in order.
+    /// `dir` is the job's tmp dir from here on and removed when it fails.
+    pub fn publish_upload_in_background(&self, name: &str, dir: PathBuf, files: Vec<String>) -> String {
+        tracing::info!("Publishing playlist {} from {} uploaded files", name, files.len());
+
+        let (job_id, cancel) = self.start_job(name, PublishKind::Upload, &[]);
+        let span = job_span(&job_id, name, PublishKind::Upload);
+        let name = name.to_string();
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            publisher.jobs.set_state(&id, PublishState::PostProcessing);
+            // Caller rewrites id/name in finalize
+            let meta = PlaylistMeta {
+                id: "TBD".into(),
+                name: "TBD".into(),
+                created_at: chrono::Utc::now(),
+                sources: vec![],
+                tracks: files.into_iter().map(TrackMeta::new).collect(),
+                ..Default::default()
+            };
+            let res = match meta.save_async(&dir.join("playlist.json")).await {
+                Ok(()) => publisher.finalize(&id, &dir, &name, vec![], &cancel).await,
+                Err(error) => Err(error),
+            };
+            publisher.finish_job(&id, &name, &dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Download the tracks that are new in the sources of the playlist in `dir` and add them,
+    /// keeping its id, name and settings. With `remove_missing`, tracks no longer in the sources are removed.
+    pub fn refresh_in_background(
+        &self,
+        dir: PathBuf,
+        meta: PlaylistMeta,
+        downloader_kind: Option<DownloaderKind>,
+        remove_missing: bool,
+    ) -> String {
+        if let Some(job) = self.active_job(&meta.sources, Some(&meta.id)) {
+            tracing::info!("Already refreshing {} ({}), not downloading it twice", job.name, job.id);
+            return job.id;
+        }
+
+        let downloader_kind = downloader::resolve(downloader_kind, &meta.sources, self.downloader_settings().default);
+        let downloader = downloader::create(&downloader_kind, &self.publish_settings().local_roots);
+        let provisional_name = Self::provisional_name(&meta.name);
+
+        tracing::info!(
+            "Refreshing playlist {} from {} sources (downloader: {:?})",
+            meta.name,
+            meta.sources.len(),
+            downloader_kind
+        );
+
+        let (job_id, cancel) = self.start_job(&meta.name, PublishKind::Refresh, &meta.sources);
+        let span = job_span(&job_id, &meta.name, PublishKind::Refresh);
+        self.jobs.update(&job_id, |job| job.playlist_id = Some(meta.id.clone()));
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            let tmp_dir = publisher.paths.tmp.join(&provisional_name);
+            let _ = fs::remove_dir_all(&tmp_dir).await;
+            let _ = fs::create_dir_all(&tmp_dir).await;
+
+            publisher.jobs.set_state(&id, PublishState::Downloading);
+            let progress = publisher.progress_fn(&id);
+            let name = meta.name.clone();
+            let settings = publisher.downloader_settings();
+            let known: Vec<String> = meta.tracks.iter().filter_map(|t| t.source_id.clone()).collect();
+            let prune = match remove_missing {
+                false => Ok(Prune::Nothing),
+                true => match downloader.list_ids(&meta.sources, &settings, &cancel).await {
+                    Ok(Some(ids)) => Ok(Prune::NotListed(ids)),
+                    Ok(None) => Ok(Prune::NotDownloaded),
+                    Err(error) => Err(error.context("Listing the sources failed")),
+                },
+            };
+            let res = match prune {
+                Ok(prune) => match downloader
+                    .download_new(&meta.sources, &tmp_dir, &known, &settings, &progress, &cancel)
+                    .await
+                {
+                    Ok(()) if cancel.is_cancelled() => Err(anyhow::anyhow!("Cancelled")),
+                    Ok(()) => {
+                        publisher.jobs.set_state(&id, PublishState::PostProcessing);
+                        publisher.swap_in(&id, &tmp_dir, &dir, meta, prune, &cancel).await
+                    }
+                    Err(error) => Err(error.context("Download failed")),
+                },
+                Err(error) => Err(error),
+            };
+            publisher.finish_job(&id, &name, &tmp_dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Add the tracks downloaded into `tmp_dir` that aren't in the playlist in `dir` yet, remove the ones
+    /// `prune` says are gone, and swap the result in.
+    async fn swap_in(
+        &self,
+        job_id: &str,
+        tmp_dir: &Path,
+        dir: &Path,
+        current: PlaylistMeta,
+        prune: Prune,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<PlaylistMeta> {
+        let meta_path = tmp_dir.join("playlist.json");
+        let Some(mut fresh) = PlaylistMeta::load_async(&meta_path).await else {
+            anyhow::bail!("Missing playlist.json");
+        };
+        if let Err(error) = names::normalize_dir(tmp_dir, &mut fresh) {
+            tracing::warn!("Normalize file names failed: {error:#}");
+        }
+        fresh.probe_tracks_async(tmp_dir).await;
+
+        // Everything but the tracks stays
+        let mut meta = current;
+        meta.refreshed_at = Some(chrono::Utc::now());
+
+        // Tracks downloaded again are dropped, the current ones learn the id they're known by
+        let mut seen = vec![false; meta.tracks.len()];
+        let mut added = vec![];
+        for track in std::mem::take(&mut fresh.tracks) {
+            match meta.tracks.iter().position(|t| same_source(t, &track)) {
+                Some(i) => {
+                    seen[i] = true;
+                    meta.tracks[i].source_id = meta.tracks[i].source_id.take().or(track.source_id);
+                    fs::remove_file(tmp_dir.join(&track.file)).await.ok();
+                    if let Some(art) = &track.art {
+                        fs::remove_file(tmp_dir.join(art)).await.ok();
+                    }
+                }
+                None => added.push(track),
+            }
+        }
+        fresh.tracks = added;
+        self.transcode(job_id, tmp_dir, &mut fresh, cancel).await?;
+        self.measure_loudness(job_id, tmp_dir, &mut fresh, cancel).await?;
+
+        let mut kept: Vec<TrackMeta> = meta
+            .tracks
+            .iter()
+            .zip(seen)
+            .filter(|(track, seen)| match &prune {
+                Prune::Nothing => true,
+                Prune::NotDownloaded => *seen,
+                Prune::NotListed(ids) => *seen || track.source_id.as_ref().is_some_and(|id| ids.contains(id)),
+            })
+            .map(|(track, _)| track.clone())
+            .collect();
+        if kept.is_empty() && !meta.tracks.is_empty() {
+            // Rather an unavailable source than a playlist that was emptied
+            tracing::warn!(
+                "None of the tracks of '{}' are in its sources anymore, keeping them",
+                meta.name
+            );
+            kept = meta.tracks.clone();
+        }
+        let removed = meta.tracks.len() - kept.len();
+        if fresh.tracks.is_empty() && removed == 0 {
+            // Only the refresh time and the learned ids change, no need to swap
+            tracing::info!("Nothing new in the sources of playlist '{}'", meta.name);
+            meta.tracks = kept;
+            meta.save_async(&dir.join("playlist.json")).await?;
+            fs::remove_dir_all(tmp_dir).await.ok();
+            return Ok(meta);
+        }
+
+        // Bring the kept tracks along, new ones make way when their names are taken
+        for track in fresh.tracks.iter_mut() {
+            if kept.iter().any(|t| t.file == track.file) {
+                let file = free_name(tmp_dir, &kept, &track.file);
+                fs::rename(tmp_dir.join(&track.file), tmp_dir.join(&file)).await?;
+                if let Some(art) = track.art.take() {
+                    fs::remove_file(tmp_dir.join(art)).await.ok();
+                }
+                track.file = file;
+            }
+        }
+        let mut keep: Vec<String> = kept
+            .iter()
+            .flat_map(|t| std::iter::once(t.file.clone()).chain(t.art.clone()))
+            .collect();
+        match fresh.cover.take() {
+            Some(cover) => meta.cover = Some(cover),
+            None => keep.extend(meta.cover.clone()),
+        }
+        for file in &keep {
+            let to = tmp_dir.join(file);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            merge::link_or_copy(&dir.join(file), &to, true)
+                .await
+                .with_context(|| format!("Keep {file} failed"))?;
+        }
+        let added = fresh.tracks.len();
+        meta.tracks = kept;
+        meta.tracks.append(&mut fresh.tracks);
+
+        // The current tracks become the latest version
+        if let Err(error) = versions::snapshot(dir) {
+            tracing::warn!("Failed to snapshot {}: {error:#}", dir.display());
+        }
+        meta.save_async(&meta_path).await?;
+
+        // Swap the dirs, bring the version history along, then drop the old one
+        let old_dir = tmp_dir.with_extension("old");
+        fs::rename(dir, &old_dir)
+            .await
+            .context("Move current playlist failed")?;
+        if let Err(error) = fs::rename(tmp_dir, dir).await {
+            fs::rename(&old_dir, dir).await.ok();
+            return Err(anyhow::Error::from(error).context("Rename refreshed playlist failed"));
+        }
+        let versions = old_dir.join(versions::VERSIONS_DIR);
+        if versions.is_dir()
+            && let Err(error) = fs::rename(&versions, dir.join(versions::VERSIONS_DIR)).await
+        {
+            tracing::warn!("Failed to keep the versions of {}: {error}", dir.display());
+        }
+        if let Err(error) = fs::remove_dir_all(&old_dir).await {
+            tracing::warn!("Failed to remove {}: {error}", old_dir.display());
+        }
+
+        tracing::info!(
+            "Refreshed playlist '{}' ({} tracks, {added} added, {removed} removed)",
+            meta.name,
+            meta.tracks.len()
+        );
+
+        self.player.reload_if_current(dir);
+        self.notifier.notify(Notification::PlaylistUpdated((&meta).into()));
+
+        Ok(meta)
+    }
+
+    /// Refresh playlists with a `refresh_interval_secs` once it has passed since their last refresh.
+    pub fn spawn_scheduled_refresh(&self) {
+        let publisher = self.clone();
         tokio::spawn(async move {
-            let tmp_dir = paths.tmp.join(&provisional_name);
+            // Last attempt per playlist id, so failing refreshes wait for the next interval too
+            let mut attempted: HashMap<String, DateTime<Utc>> = HashMap::new();
+            loop {
+                tokio::time::sleep(Duration::from_secs(REFRESH_CHECK_SECS)).await;
+
+                let root = publisher.paths.playlists.clone();
+                let playlists = match tokio::task::spawn_blocking(move || get_playlists(&root)).await {
+                    Ok(Ok(playlists)) => playlists,
+                    Ok(Err(error)) => {
+                        tracing::warn!("Scheduled refresh failed to list playlists: {error:#}");
+                        continue;
+                    }
+                    Err(error) => {
+                        tracing::warn!("Scheduled refresh failed to list playlists: {error}");
+                        continue;
+                    }
+                };
+
+                let now = chrono::Utc::now();
+                for (folder, meta) in playlists {
+                    let Some(interval) = meta.refresh_interval_secs else {
+                        continue;
+                    };
+                    let last = [meta.refreshed_at, attempted.get(&meta.id).copied()]
+                        .into_iter()
+                        .flatten()
+                        .max()
+                        .unwrap_or(meta.created_at);
+                    if !meta.can_refresh() || now < last + chrono::Duration::seconds(interval as i64) {
+                        continue;
+                    }
+                    if publisher.active_job(&meta.sources, Some(&meta.id)).is_some() {
+                        continue;
+                    }
+                    if let Err(error) = crate::disk::ensure_free(&publisher.paths.root, publisher.min_free_bytes) {
+                        tracing::warn!("Skipping scheduled refresh of '{}': {error:#}", meta.name);
+                        continue;
+                    }
+
+                    tracing::info!("Scheduled refresh of playlist '{}'", meta.name);
+                    attempted.insert(meta.id.clone(), now);
+                    publisher.refresh_in_background(publisher.paths.playlists.join(folder), meta, None, false);
+                }
+            }
+        });
+    }
+
+    /// Combine existing playlists into a new one, copying (or hardlinking) their files.
+    pub fn merge_in_background(
+        &self,
+        name: &str,
+        playlists: Vec<(PathBuf, PlaylistMeta)>,
+        order: MergeOrder,
+        link: bool,
+    ) -> String {
+        let provisional_name = Self::provisional_name(name);
+
+        tracing::info!("Merging {} playlists into {}", playlists.len(), name);
+
+        let (job_id, cancel) = self.start_job(name, PublishKind::Merge, &[]);
+        let span = job_span(&job_id, name, PublishKind::Merge);
+        let name = name.to_string();
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            let tmp_dir = publisher.paths.tmp.join(&provisional_name);
             let _ = fs::remove_dir_all(&tmp_dir).await;
             let _ = fs::create_dir_all(&tmp_dir).await;
 
-            let res = downloader
-                .download_playlist(&sources, &tmp_dir, &downloader_settings)
-                .await;
-            if let Err(error) = res {
-                tracing::error!("Download failed: {error:#}");
-                let _ = fs::remove_dir_all(&tmp_dir).await;
-                return;
+            publisher.jobs.set_state(&id, PublishState::Downloading);
+            let tracks = merge::merge_tracks(&playlists, &order);
+            let res = match Self::copy_tracks(tracks, link, &tmp_dir, &cancel).await {
+                Ok(()) if cancel.is_cancelled() => Err(anyhow::anyhow!("Cancelled")),
+                Ok(()) => {
+                    let mut sources: Vec<String> = vec![];
+                    for (_, meta) in &playlists {
+                        for source in &meta.sources {
+                            if !sources.contains(source) {
+                                sources.push(source.clone());
+                            }
+                        }
+                    }
+                    publisher.jobs.set_state(&id, PublishState::PostProcessing);
+                    publisher.finalize(&id, &tmp_dir, &name, sources, &cancel).await
+                }
+                Err(error) => Err(error.context("Merge failed")),
+            };
+            publisher.finish_job(&id, &name, &tmp_dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Publish a playlist of library tracks, and play it right away with `play`.
+    pub fn library_in_background(&self, name: &str, tracks: Vec<MergeTrack>, link: bool, play: bool) -> String {
+        let provisional_name = Self::provisional_name(name);
+
+        tracing::info!("Publishing {} library tracks as {}", tracks.len(), name);
+
+        let (job_id, cancel) = self.start_job(name, PublishKind::Library, &[]);
+        let span = job_span(&job_id, name, PublishKind::Library);
+        let name = name.to_string();
+        let publisher = self.clone();
+        let id = job_id.clone();
+        let task = async move {
+            let tmp_dir = publisher.paths.tmp.join(&provisional_name);
+            let _ = fs::remove_dir_all(&tmp_dir).await;
+            let _ = fs::create_dir_all(&tmp_dir).await;
+
+            publisher.jobs.set_state(&id, PublishState::Downloading);
+            let res = match Self::copy_tracks(tracks, link, &tmp_dir, &cancel).await {
+                Ok(()) if cancel.is_cancelled() => Err(anyhow::anyhow!("Cancelled")),
+                Ok(()) => {
+                    publisher.jobs.set_state(&id, PublishState::PostProcessing);
+                    publisher.finalize(&id, &tmp_dir, &name, vec![], &cancel).await
+                }
+                Err(error) => Err(error.context("Copying library tracks failed")),
+            };
+
+            // Skip to it, even if publish.auto_set_playlist already queued it
+            if play && let Ok(meta) = &res {
+                if let Err(error) = publisher.kv.set_current_playlist_id(&meta.id) {
+                    tracing::warn!("Set current playlist failed: {error:#}");
+                }
+                publisher
+                    .player
+                    .set_playlist_dir(publisher.paths.playlists.join(meta.dir_name()), SetPlaylistMode::Skip);
+            }
+            publisher.finish_job(&id, &name, &tmp_dir, res).await;
+        };
+        self.spawn_job(&job_id, task.instrument(span));
+
+        job_id
+    }
+
+    /// Copy `tracks` into `tmp_dir` as a new playlist, numbered in order.
+    async fn copy_tracks(
+        merged_tracks: Vec<MergeTrack>,
+        link: bool,
+        tmp_dir: &Path,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let mut tracks = vec![];
+
+        for (i, merged) in merged_tracks.into_iter().enumerate() {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Cancelled");
+            }
+            let mut track = merged.track;
+            let from = names::resolve_track(&merged.dir, &track).unwrap_or_else(|| merged.dir.join(&track.file));
+            let file = format!("{:03}-{}", i + 1, merge::strip_index_prefix(&track.file));
+            merge::link_or_copy(&from, &tmp_dir.join(&file), link)
+                .await
+                .with_context(|| format!("Failed to copy {}", from.display()))?;
+
+            // Bring the art along as a sidecar, it is moved into art/ when probed
+            if let Some(art) = track.art.take() {
+                let art_path = merged.dir.join(&art);
+                if let (Some(stem), Some(ext)) = (Path::new(&file).file_stem(), art_path.extension()) {
+                    let sidecar = format!("{}.{}", stem.to_string_lossy(), ext.to_string_lossy());
+                    merge::link_or_copy(&art_path, &tmp_dir.join(sidecar), link).await.ok();
+                }
+            }
+
+            track.file = file;
+            tracks.push(track);
+        }
+
+        if tracks.is_empty() {
+            anyhow::bail!("no tracks to copy");
+        }
+
+        // Caller rewrites id/name in finalize
+        let meta = PlaylistMeta {
+            id: "TBD".into(),
+            name: "TBD".into(),
+            created_at: chrono::Utc::now(),
+            sources: vec![],
+            tracks,
+            ..Default::default()
+        };
+        meta.save_async(&tmp_dir.join("playlist.json")).await
+    }
+
+    async fn import_entries(
+        &self,
+        entries: &[ImportEntry],
+        base_dir: Option<&Path>,
+        tmp_dir: &Path,
+        downloader: &dyn Downloader,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let mut tracks = vec![];
+
+        for (i, entry) in entries.iter().enumerate() {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Cancelled");
             }
+            progress(Progress {
+                percent: Some(i as f32 / entries.len() as f32 * 100.0),
+                current: entry.title.clone().or_else(|| Some(entry.location.clone())),
+            });
+
+            // Keep the order of the imported file
+            let prefix = format!("{:03}-", i + 1);
+
+            match entry.resolve(base_dir) {
+                EntrySource::Remote(url) => {
+                    let entry_dir = tmp_dir.join(format!("entry-{:03}", i + 1));
+                    // Progress of this entry within all entries
+                    let entry_progress = move |p: Progress| {
+                        progress(Progress {
+                            percent: p.percent.map(|p| (i as f32 + p / 100.0) / entries.len() as f32 * 100.0),
+                            ..p
+                        })
+                    };
+                    let res = downloader
+                        .download_playlist(
+                            std::slice::from_ref(&url),
+                            &entry_dir,
+                            &self.downloader_settings(),
+                            &entry_progress,
+                            cancel,
+                        )
+                        .await;
+                    if let Err(error) = res {
+                        tracing::warn!("Skipping import entry {}: {error:#}", url);
+                        let _ = fs::remove_dir_all(&entry_dir).await;
+                        continue;
+                    }
+
+                    let Some(meta) = PlaylistMeta::load_async(&entry_dir.join("playlist.json")).await else {
+                        tracing::warn!("Skipping import entry {}: missing playlist.json", url);
+                        continue;
+                    };
+
+                    for mut track in meta.tracks {
+                        let file = format!("{prefix}{}", track.file);
+                        fs::rename(entry_dir.join(&track.file), tmp_dir.join(&file)).await?;
+
+                        // Keep thumbnails next to the renamed track
+                        if let Some(image) = find_sidecar_image(&entry_dir, &track.file)
+                            && let Some(image_name) = image.file_name()
+                        {
+                            let image_name = format!("{prefix}{}", image_name.to_string_lossy());
+                            fs::rename(&image, tmp_dir.join(image_name)).await.ok();
+                        }
+
+                        track.file = file;
+                        track.source_url.get_or_insert_with(|| url.clone());
+                        if track.title.is_none() {
+                            track.title = entry.title.clone();
+                        }
+                        tracks.push(track);
+                    }
 
-            // Fix playlist.json with id/name and move atomically into playlists/
-            let meta_path = tmp_dir.join("playlist.json");
-            let mut meta = match PlaylistMeta::load_async(&meta_path).await {
-                Some(m) => m,
-                None => {
-                    tracing::error!("Missing playlist.json");
-                    let _ = fs::remove_dir_all(&tmp_dir).await;
-                    return;
+                    let _ = fs::remove_dir_all(&entry_dir).await;
                 }
+                EntrySource::Local(path) => {
+                    if !import::is_within_roots(&path, &self.publish_settings().local_roots) {
+                        tracing::warn!(
+                            "Skipping import entry {}: not within publish.local_roots",
+                            path.display()
+                        );
+                        continue;
+                    }
+
+                    if !is_audio_file(&path) {
+                        tracing::warn!("Skipping import entry {}: not an audio file", path.display());
+                        continue;
+                    }
+
+                    let Some(file_name) = path.file_name() else {
+                        continue;
+                    };
+                    let file = format!("{prefix}{}", file_name.to_string_lossy());
+                    fs::copy(&path, tmp_dir.join(&file))
+                        .await
+                        .with_context(|| format!("Failed to copy {}", path.display()))?;
+
+                    let mut track = TrackMeta::new(file);
+                    track.title = entry.title.clone();
+                    track.duration = entry.duration;
+                    tracks.push(track);
+                }
+            }
+        }
+
+        if tracks.is_empty() {
+            anyhow::bail!("no tracks could be imported");
+        }
+
+        // Caller rewrites id/name in finalize
+        let meta = PlaylistMeta {
+            id: "TBD".into(),
+            name: "TBD".into(),
+            created_at: chrono::Utc::now(),
+            sources: vec![],
+            tracks,
+            ..Default::default()
+        };
+        meta.save_async(&tmp_dir.join("playlist.json")).await
+    }
+
+    async fn copy_local_files(
+        &self,
+        dir: &Path,
+        tmp_dir: &Path,
+        link: bool,
+        progress: &ProgressFn<'_>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let scan_dir = dir.to_path_buf();
+        let files = tokio::task::spawn_blocking(move || watch::scan(&scan_dir))
+            .await
+            .context("Scan task failed")?;
+        if files.is_empty() {
+            anyhow::bail!("no audio files in {}", dir.display());
+        }
+
+        let mut tracks = vec![];
+        for (i, path) in files.iter().enumerate() {
+            if cancel.is_cancelled() {
+                anyhow::bail!("Cancelled");
+            }
+            progress(Progress {
+                percent: Some(i as f32 / files.len() as f32 * 100.0),
+                current: Some(path.to_string_lossy().to_string()),
+            });
+
+            let Some(file_name) = path.file_name() else {
+                continue;
             };
-            meta.id = uuid::Uuid::new_v4().to_string();
-            meta.name = name.clone();
-            meta.sources = sources.clone();
-            if let Err(error) = meta.save_async(&meta_path).await {
-                tracing::error!("Write meta failed: {error:#}");
+            // Files of subdirectories may share names, keep them apart and in order
+            let file = format!("{:03}-{}", i + 1, file_name.to_string_lossy());
+            let target = tmp_dir.join(&file);
+            if link {
+                #[cfg(unix)]
+                let linked = fs::symlink(path, &target).await;
+                #[cfg(windows)]
+                let linked = fs::symlink_file(path, &target).await;
+                linked.with_context(|| format!("Failed to link {}", path.display()))?;
+            } else {
+                fs::copy(path, &target)
+                    .await
+                    .with_context(|| format!("Failed to copy {}", path.display()))?;
+            }
+            tracks.push(TrackMeta::new(file));
+        }
+
+        // Caller rewrites id/name in finalize
+        let meta = PlaylistMeta {
+            id: "TBD".into(),
+            name: "TBD".into(),
+            created_at: chrono::Utc::now(),
+            sources: vec![],
+            tracks,
+            ..Default::default()
+        };
+        meta.save_async(&tmp_dir.join("playlist.json")).await
+    }
+
+    /// Fix playlist.json with id/name and move atomically into playlists/
+    async fn finalize(
+        &self,
+        job_id: &str,
+        tmp_dir: &Path,
+        name: &str,
+        sources: Vec<String>,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<PlaylistMeta> {
+        let meta_path = tmp_dir.join("playlist.json");
+        let Some(mut meta) = PlaylistMeta::load_async(&meta_path).await else {
+            anyhow::bail!("Missing playlist.json");
+        };
+        meta.id = uuid::Uuid::new_v4().to_string();
+        meta.name = name.to_string();
+        meta.sources = sources;
+        if let Err(error) = names::normalize_dir(tmp_dir, &mut meta) {
+            tracing::warn!("Normalize file names failed: {error:#}");
+        }
+        meta.probe_tracks_async(tmp_dir).await;
+        self.transcode(job_id, tmp_dir, &mut meta, cancel).await?;
+        self.measure_loudness(job_id, tmp_dir, &mut meta, cancel).await?;
+        if let Err(error) = meta.save_async(&meta_path).await {
+            tracing::error!("Write meta failed: {error:#}");
+        }
+
+        let final_folder = meta.dir_name();
+        let final_path = self.paths.playlists.join(&final_folder);
+        tokio::fs::rename(&tmp_dir, &final_path)
+            .await
+            .context("Rename final failed")?;
+
+        tracing::info!("Published playlist '{}'", meta.name);
+
+        // Notify
+        self.notifier.notify(Notification::PlaylistPublished((&meta).into()));
+
+        // Switch current to the new playlist
+        if self.publish_settings().auto_set_playlist {
+            tracing::info!("Setting playlist after publish");
+
+            if let Err(error) = self.kv.set_current_playlist_id(&meta.id) {
+                tracing::warn!("Set current playlist failed: {error:#}");
+            }
+            self.player.set_playlist_dir(&final_path, SetPlaylistMode::Queue);
+        }
+
+        Ok(meta)
+    }
+
+    /// Convert the tracks in `tmp_dir` when `publish.transcode` is enabled, after they were probed
+    /// so embedded art and tags are kept.
+    async fn transcode(
+        &self,
+        job_id: &str,
+        tmp_dir: &Path,
+        meta: &mut PlaylistMeta,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let settings = self.publish_settings().transcode;
+        if !settings.enable {
+            return Ok(());
+        }
+
+        tracing::info!("Transcoding {} tracks to {:?}", meta.tracks.len(), settings.format);
+        let progress = self.progress_fn(job_id);
+        transcode::transcode_dir(tmp_dir, meta, &settings, &progress, cancel).await
+    }
+
+    /// Measure the loudness of the tracks in `tmp_dir` when `loudness.on_publish` is set.
+    async fn measure_loudness(
+        &self,
+        job_id: &str,
+        tmp_dir: &Path,
+        meta: &mut PlaylistMeta,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let settings = self.loudness_settings.read().unwrap().clone();
+        if !settings.on_publish {
+            return Ok(());
+        }
+
+        tracing::info!("Measuring loudness of {} tracks", meta.tracks.len());
+        let progress = self.progress_fn(job_id);
+        loudness::measure_tracks(tmp_dir, meta, &settings, &progress, cancel).await
+    }
+
+    /// Remove the oldest playlists beyond `publish.retention`, never the one just `published`.
+    async fn enforce_retention(&self, published: &str) {
+        let settings = self.publish_settings().retention;
+        let root = self.paths.playlists.clone();
+        let mut active = vec![published.to_string()];
+        active.extend(self.kv.get_current_playlist_id().ok().flatten());
+        active.extend(self.kv.list_zone_playlist_ids().unwrap_or_default());
+        active.extend(self.player.status().ok().and_then(|s| s.playlist_id));
+
+        let res = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<PlaylistMeta>> {
+            let playlists = crate::playlist::get_playlists(&root)?;
+            let mut removed = vec![];
+            for (folder, meta) in retention::select(&root, &playlists, &active, &settings) {
+                match std::fs::remove_dir_all(root.join(&folder)) {
+                    Ok(()) => removed.push(meta),
+                    Err(error) => tracing::warn!("Failed to remove playlist {}: {error}", folder),
+                }
+            }
+            Ok(removed)
+        })
+        .await;
+
+        match res {
+            Ok(Ok(removed)) => {
+                for meta in removed {
+                    tracing::info!("Removed playlist '{}' ({}) by retention policy", meta.name, meta.id);
+                    self.notifier.notify(Notification::PlaylistRemoved((&meta).into()));
+                }
+            }
+            Ok(Err(error)) => tracing::warn!("Retention failed: {error:#}"),
+            Err(error) => tracing::warn!("Retention failed: {error}"),
+        }
+    }
+
+    /// Register a publish, import or merge, returning the id its progress is reported with
+    /// and the token cancelling it.
+    fn start_job(&self, name: &str, kind: PublishKind, sources: &[String]) -> (String, CancellationToken) {
+        let job_id = uuid::Uuid::new_v4().to_string();
+        let cancel = CancellationToken::new();
+        let now = chrono::Utc::now();
+        self.jobs.insert(PublishJob {
+            id: job_id.clone(),
+            name: name.to_string(),
+            kind,
+            state: PublishState::Queued,
+            percent: None,
+            current: None,
+            sources: sources.to_vec(),
+            created_at: now,
+            updated_at: now,
+            finished_at: None,
+            playlist_id: None,
+            error: None,
+            cancel: cancel.clone(),
+        });
+        self.notifier.notify(Notification::PublishStarted {
+            job_id: job_id.clone(),
+            name: name.to_string(),
+        });
+        self.guard_disk_space(&job_id, &cancel);
+        (job_id, cancel)
+    }
+
+    /// Abort the publish `job_id` if free disk space drops below `min_free_bytes` while it runs.
+    fn guard_disk_space(&self, job_id: &str, cancel: &CancellationToken) {
+        let jobs = self.jobs.clone();
+        let root = self.paths.root.clone();
+        let min_free_bytes = self.min_free_bytes;
+        let job_id = job_id.to_string();
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(DISK_CHECK_SECS)) => {}
+                    _ = cancel.cancelled() => break,
+                }
+                if jobs.get(&job_id).is_none_or(|job| job.state.is_finished()) {
+                    break;
+                }
+                if let Err(error) = crate::disk::ensure_free(&root, min_free_bytes) {
+                    tracing::warn!("Aborting publish {}: {error:#}", job_id);
+                    jobs.update(&job_id, |job| job.error = Some(format!("{error:#}")));
+                    cancel.cancel();
+                    break;
+                }
             }
+        });
+    }
 
-            let final_folder = meta.dir_name();
-            let final_path = paths.playlists.join(&final_folder);
-            if let Err(error) = tokio::fs::rename(&tmp_dir, &final_path).await {
-                tracing::error!("Rename final failed: {error:#}");
+    /// Run the task of the publish `job_id`, which is marked failed should the task panic
+    /// instead of staying active for good.
+    fn spawn_job(&self, job_id: &str, task: impl Future<Output = ()> + Send + 'static) {
+        let handle = tokio::spawn(task);
+        let jobs = self.jobs.clone();
+        let notifier = self.notifier.clone();
+        let job_id = job_id.to_string();
+        tokio::spawn(async move {
+            let Err(error) = handle.await else {
                 return;
+            };
+            tracing::error!("Publish {} stopped: {error}", job_id);
+            let error = format!("Publish stopped unexpectedly: {error}");
+            jobs.update(&job_id, |job| {
+                job.state = PublishState::Failed;
+                job.error = Some(error.clone());
+            });
+            if let Some(job) = jobs.get(&job_id) {
+                notifier.notify(Notification::PublishFailed {
+                    job_id,
+                    name: job.name,
+                    error,
+                });
             }
+        });
+    }
 
-            tracing::info!("Published playlist '{}'", meta.name);
+    /// Stop a running publish; it's marked cancelled once its downloads are stopped and cleaned up.
+    /// `None` if there's no such publish, `Some(false)` if it already finished.
+    pub fn cancel(&self, id: &str) -> Option<bool> {
+        let job = self.jobs.get(id)?;
+        if job.state.is_finished() {
+            return Some(false);
+        }
+        tracing::info!("Cancelling publish {} ({})", job.name, id);
+        job.cancel.cancel();
+        Some(true)
+    }
 
-            // Notify
-            notifier.notify(Notification::PlaylistPublished {
-                id: meta.id.clone(),
-                name: meta.name.clone(),
+    fn progress_fn(&self, job_id: &str) -> impl Fn(Progress) + Send + Sync + use<> {
+        let notifier = self.notifier.clone();
+        let jobs = self.jobs.clone();
+        let job_id = job_id.to_string();
+        move |p: Progress| {
+            jobs.update(&job_id, |job| {
+                job.percent = p.percent;
+                job.current = p.current.clone();
             });
+            notifier.notify(Notification::DownloadProgress {
+                job_id: job_id.clone(),
+                percent: p.percent,
+                current: p.current,
+            })
+        }
+    }
+
+    async fn finish_job(&self, job_id: &str, name: &str, tmp_dir: &Path, res: anyhow::Result<PlaylistMeta>) {
+        match res {
+            Ok(meta) => {
+                self.jobs.update(job_id, |job| {
+                    job.state = PublishState::Done;
+                    job.playlist_id = Some(meta.id.clone());
+                });
+                self.notifier.notify(Notification::PublishCompleted {
+                    job_id: job_id.to_string(),
+                    playlist: (&meta).into(),
+                });
+                self.enforce_retention(&meta.id).await;
+            }
+            // Aborted, with the reason already set
+            Err(_) if let Some(reason) = self.jobs.get(job_id).and_then(|job| job.error) => {
+                let _ = fs::remove_dir_all(tmp_dir).await;
+                self.jobs.set_state(job_id, PublishState::Failed);
+                self.notifier.notify(Notification::PublishFailed {
+                    job_id: job_id.to_string(),
+                    name: name.to_string(),
+                    error: reason,
+                });
+            }
+            Err(_) if self.jobs.get(job_id).is_some_and(|job| job.cancel.is_cancelled()) => {
+                tracing::info!("Cancelled publish {} ({})", name, job_id);
+                let _ = fs::remove_dir_all(tmp_dir).await;
+                self.jobs.set_state(job_id, PublishState::Cancelled);
+                self.notifier.notify(Notification::PublishCancelled {
+                    job_id: job_id.to_string(),
+                    name: name.to_string(),
+                });
+            }
+            Err(error) => {
+                tracing::error!("{error:#}");
+                let _ = fs::remove_dir_all(tmp_dir).await;
+                self.jobs.update(job_id, |job| {
+                    job.state = PublishState::Failed;
+                    job.error = Some(format!("{error:#}"));
+                });
+                self.notifier.notify(Notification::PublishFailed {
+                    job_id: job_id.to_string(),
+                    name: name.to_string(),
+                    error: format!("{error:#}"),
+                });
+            }
+        }
+    }
+}
 
-            // Switch current to the new playlist
-            if publish_settings.auto_set_playlist {
-                tracing::info!("Setting playlist after publish");
+/// Finished publishes kept for `GET /publish`.
+const RECENT_JOBS: usize = 50;
 
-                if let Err(error) = kv.set_current_playlist_id(&meta.id) {
-                    tracing::warn!("Set current playlist failed: {error:#}");
-                }
-                player.set_playlist_dir(&final_path, SetPlaylistMode::Queue);
+/// How often playlists are checked for a due scheduled refresh.
+const REFRESH_CHECK_SECS: u64 = 60;
+
+/// How often free disk space is checked while publishing.
+const DISK_CHECK_SECS: u64 = 5;
+
+/// Span of a background job, for its logs and traces.
+fn job_span(job_id: &str, name: &str, kind: PublishKind) -> tracing::Span {
+    tracing::info_span!("publish", job_id, name, kind = ?kind)
+}
+
+/// Which of the current tracks a refresh removes.
+enum Prune {
+    /// None, new tracks are only added.
+    Nothing,
+    /// The ones not downloaded again, as the downloader downloads everything.
+    NotDownloaded,
+    /// The ones whose `source_id` isn't listed in the sources anymore.
+    NotListed(HashSet<String>),
+}
+
+/// Whether two tracks came from the same source entry, by id or url, or else by file name.
+fn same_source(a: &TrackMeta, b: &TrackMeta) -> bool {
+    if let (Some(a), Some(b)) = (&a.source_id, &b.source_id) {
+        return a == b;
+    }
+    match (&a.source_url, &b.source_url) {
+        (Some(a), Some(b)) => a == b,
+        (None, None) => a.file == b.file,
+        _ => false,
+    }
+}
+
+/// `file` numbered as `name-2.ext`, `name-3.ext`... until neither a track of `tracks` nor a file in `dir` has it.
+fn free_name(dir: &Path, tracks: &[TrackMeta], file: &str) -> String {
+    let path = Path::new(file);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| format!("{stem}-{n}{ext}"))
+        .find(|name| !dir.join(name).exists() && tracks.iter().all(|t| &t.file != name))
+        .unwrap()
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishKind {
+    Publish,
+    Import,
+    Merge,
+    Refresh,
+    Local,
+    Library,
+    Upload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublishState {
+    Queued,
+    Downloading,
+    PostProcessing,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl PublishState {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            PublishState::Done | PublishState::Failed | PublishState::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublishJob {
+    pub id: String,
+    pub name: String,
+    pub kind: PublishKind,
+    pub state: PublishState,
+    /// Overall download percent, when known.
+    pub percent: Option<f32>,
+    /// Title of the track being downloaded.
+    pub current: Option<String>,
+    /// What's downloaded or copied, empty for uploads, merges and library picks.
+    pub sources: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The published playlist, once done.
+    pub playlist_id: Option<String>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    cancel: CancellationToken,
+}
+
+/// Shared registry of publishes, oldest first.
+#[derive(Clone, Default)]
+struct PublishJobs(Arc<Mutex<VecDeque<PublishJob>>>);
+
+impl PublishJobs {
+    fn insert(&self, job: PublishJob) {
+        let mut jobs = self.0.lock().unwrap();
+        jobs.push_back(job);
+
+        // Forget the oldest finished ones
+        let finished = jobs.iter().filter(|j| j.state.is_finished()).count();
+        let mut excess = finished.saturating_sub(RECENT_JOBS);
+        jobs.retain(|j| {
+            if excess > 0 && j.state.is_finished() {
+                excess -= 1;
+                return false;
             }
+            true
         });
     }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut PublishJob)) {
+        if let Some(job) = self.0.lock().unwrap().iter_mut().find(|j| j.id == id) {
+            f(job);
+            job.updated_at = Utc::now();
+            if job.state.is_finished() && job.finished_at.is_none() {
+                job.finished_at = Some(job.updated_at);
+            }
+        }
+    }
+
+    fn set_state(&self, id: &str, state: PublishState) {
+        self.update(id, |job| job.state = state);
+    }
+
+    fn get(&self, id: &str) -> Option<PublishJob> {
+        self.0.lock().unwrap().iter().find(|j| j.id == id).cloned()
+    }
+
+    fn list(&self) -> Vec<PublishJob> {
+        self.0.lock().unwrap().iter().rev().cloned().collect()
+    }
 }
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::Method;
+
+use crate::settings::RateLimitSettings;
+
+/// Requests are counted in fixed windows of this length.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Clients whose window is over are forgotten once this many are tracked.
+const MAX_CLIENTS: usize = 1024;
+
+/// What a request counts against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Bucket {
+    Publish,
+    Control,
+}
+
+impl Bucket {
+    /// The bucket of a request, None for requests that aren't limited.
+    pub fn of(method: &Method, path: &str) -> Option<Self> {
+        if method != Method::POST {
+            return None;
+        }
+        if path == "/publish" || path == "/publish/upload" {
+            return Some(Bucket::Publish);
+        }
+        let zone_control = path
+            .strip_prefix("/zones/")
+            .and_then(|rest| rest.split_once('/'))
+            .is_some_and(|(_, rest)| rest.starts_with("control/"));
+        (path.starts_with("/control/") || zone_control).then_some(Bucket::Control)
+    }
+}
+
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+/// Counts the requests of each client IP, per bucket and minute.
+#[derive(Clone)]
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    windows: Arc<Mutex<HashMap<(IpAddr, Bucket), Window>>>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            windows: Default::default(),
+        }
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.settings.max_body_bytes
+    }
+
+    /// Count a request of `ip`, or how long until it may try again when it's over the limit.
+    pub fn check(&self, ip: IpAddr, bucket: Bucket) -> Result<(), Duration> {
+        let limit = match bucket {
+            Bucket::Publish => self.settings.publish_per_minute,
+            Bucket::Control => self.settings.control_per_minute,
+        };
+        if limit == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= MAX_CLIENTS {
+            windows.retain(|_, w| now.duration_since(w.started) < WINDOW);
+        }
+
+        let window = windows.entry((ip, bucket)).or_insert(Window { started: now, count: 0 });
+        let elapsed = now.duration_since(window.started);
+        if elapsed >= WINDOW {
+            *window = Window { started: now, count: 0 };
+        } else if window.count >= limit {
+            return Err(WINDOW - elapsed);
+        }
+        window.count += 1;
+        Ok(())
+    }
+}
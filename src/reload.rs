@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::notifier::{Notification, Notifier};
+use crate::player::{PlayerConfig, PlayerHandle};
+use crate::publisher::Publisher;
+use crate::settings::{ManifestSettings, Overrides, Settings};
+use crate::zone::Zones;
+
+/// Wait for writes to settle before reloading, editors often save in several steps.
+const DEBOUNCE_MS: u64 = 500;
+
+/// Parts of the running daemon that pick up reloaded settings.
+#[derive(Clone)]
+pub struct Reloadable {
+    pub notifier: Notifier,
+    pub player: PlayerHandle,
+    pub zones: Zones,
+    pub publisher: Publisher,
+    pub manifest: Arc<RwLock<ManifestSettings>>,
+}
+
+/// Apply the hot-safe sections of `new`; the rest need a restart.
+fn apply(current: &Settings, new: &Settings, targets: &Reloadable) {
+    targets.player.set_config(PlayerConfig::from(&new.player));
+    for zone in targets.zones.values() {
+        zone.player.set_config(PlayerConfig::from(&new.player));
+    }
+    targets
+        .publisher
+        .set_settings(new.publish.clone(), new.downloader.clone(), new.loudness.clone());
+    *targets.manifest.write().unwrap() = new.manifest.clone();
+
+    if current.server.api_key != new.server.api_key {
+        tracing::warn!("API key changed, restart to apply");
+    }
+    if current.server.host != new.server.host || current.server.port != new.server.port {
+        tracing::warn!(
+            "Server address changed to {}:{}, restart to apply",
+            new.server.host,
+            new.server.port
+        );
+    }
+    if current.server.tls != new.server.tls {
+        tracing::warn!("TLS settings changed, restart to apply");
+    }
+    if current.player.output_device != new.player.output_device
+        && let Err(error) = targets.player.set_output_device(new.player.output_device.clone())
+    {
+        tracing::warn!("Failed to switch output device: {error:#}");
+    }
+    if current.zones != new.zones {
+        tracing::warn!("Zones changed, restart to apply");
+    }
+    if current.data_dir != new.data_dir {
+        tracing::warn!("Data dir changed to {}, restart to apply", new.data_dir.display());
+    }
+
+    tracing::info!("Settings reloaded");
+    targets.notifier.notify(Notification::SettingsReloaded);
+}
+
+/// Reload the settings files when they change. Command line `overrides` keep applying.
+pub fn watch(settings: Settings, overrides: Overrides, targets: Reloadable) {
+    let files = Settings::files(&overrides);
+    let mut dirs: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|f| f.parent().map(|p| p.to_path_buf()))
+        .collect();
+    dirs.dedup();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+
+            let mut watcher: RecommendedWatcher = match RecommendedWatcher::new(
+                move |res| {
+                    let _ = tx.blocking_send(res);
+                },
+                notify::Config::default(),
+            ) {
+                Ok(w) => w,
+                Err(error) => {
+                    tracing::warn!("Failed to watch settings: {error}");
+                    return;
+                }
+            };
+
+            for dir in &dirs {
+                if let Err(error) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    tracing::warn!("Failed to watch {}: {error}", dir.display());
+                }
+            }
+
+            let mut current = settings;
+            while let Some(res) = rx.recv().await {
+                let Ok(event) = res else {
+                    continue;
+                };
+                if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+                    || !event.paths.iter().any(|p| files.contains(p))
+                {
+                    continue;
+                }
+
+                // Debounce
+                tokio::time::sleep(Duration::from_millis(DEBOUNCE_MS)).await;
+                while rx.try_recv().is_ok() {}
+
+                match Settings::load_or_init(&overrides) {
+                    Ok(new) => {
+                        apply(&current, &new, &targets);
+                        current = new;
+                    }
+                    Err(error) => tracing::warn!("Settings reload failed, keeping current settings: {error:#}"),
+                }
+            }
+        });
+    });
+}
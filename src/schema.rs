@@ -0,0 +1,370 @@
+use std::borrow::Cow;
+
+use rust_embed::Embed;
+
+/// JSON Schemas of the files musicd reads, kept next to the structs they describe.
+#[derive(Embed)]
+#[folder = "schema"]
+struct Schemas;
+
+/// Schema names, eg. `settings` for `settings.json`.
+pub const NAMES: &[&str] = &["settings", "jobs", "playlist", "manifest"];
+
+pub fn get(name: &str) -> Option<Cow<'static, [u8]>> {
+    let name = name.strip_suffix(".json").unwrap_or(name);
+    if !NAMES.contains(&name) {
+        return None;
+    }
+    Schemas::get(&format!("{name}.json")).map(|f| f.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+    use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+    use serde_json::Value;
+
+    use super::get;
+    use crate::job::Job;
+    use crate::playlist::{PlaylistMeta, TrackMeta};
+    use crate::settings::Settings;
+
+    /// Field names by path, eg. `server.tls`, with `[]` for list items.
+    type Fields = BTreeMap<String, BTreeSet<String>>;
+
+    /// Tried in turn for a string until it deserializes, eg. as a date or an environment name.
+    const STRINGS: &[&str] = &["", "1970-01-01T00:00:00Z", "1970-01-01", "00:00:00", "mon"];
+
+    #[derive(Default)]
+    struct Trace {
+        fields: Fields,
+        /// Values only typed at runtime (untagged enums, `serde_json::Value`), left out on the next try.
+        skipped: BTreeSet<String>,
+        /// Index into `STRINGS` by path.
+        strings: BTreeMap<String, usize>,
+        last_string: Option<String>,
+    }
+
+    /// Deserializes a made up value of any type, recording the fields of every struct on the way.
+    struct Recorder<'a> {
+        path: String,
+        trace: &'a RefCell<Trace>,
+    }
+
+    impl<'a> Recorder<'a> {
+        fn at(&self, path: String) -> Self {
+            Self {
+                path,
+                trace: self.trace,
+            }
+        }
+
+        fn items(&self) -> SeqDeserializer<std::vec::IntoIter<Recorder<'a>>, Error> {
+            let path = format!("{}[]", self.path);
+            let items = if self.trace.borrow().skipped.contains(&path) {
+                vec![]
+            } else {
+                vec![self.at(path)]
+            };
+            SeqDeserializer::new(items.into_iter())
+        }
+    }
+
+    impl<'de> IntoDeserializer<'de, Error> for Recorder<'_> {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self {
+            self
+        }
+    }
+
+    /// Deserializer methods that make up their value like `$to`.
+    macro_rules! forward {
+        ($($($method:ident),+ => $to:ident;)+) => {
+            $($(
+                fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                    self.$to(visitor)
+                }
+            )+)+
+        };
+    }
+
+    impl<'de> de::Deserializer<'de> for Recorder<'_> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+            self.trace.borrow_mut().skipped.insert(self.path);
+            Err(de::Error::custom("not traceable"))
+        }
+
+        fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_bool(false)
+        }
+
+        fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_u64(0)
+        }
+
+        fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_f64(0.0)
+        }
+
+        fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let mut trace = self.trace.borrow_mut();
+            let value = STRINGS[trace.strings.get(&self.path).copied().unwrap_or_default()];
+            trace.last_string = Some(self.path);
+            visitor.visit_str(value)
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &str, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_seq(self.items())
+        }
+
+        fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            // Keys are up to the user
+            visitor.visit_map(MapDeserializer::new(std::iter::empty::<(&str, &str)>()))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &str,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            let present: Vec<_> = {
+                let mut trace = self.trace.borrow_mut();
+                let recorded = trace.fields.entry(self.path.clone()).or_default();
+                recorded.extend(fields.iter().map(|f| f.to_string()));
+                fields
+                    .iter()
+                    .map(|f| (*f, join(&self.path, f)))
+                    .filter(|(_, path)| !trace.skipped.contains(path))
+                    .collect()
+            };
+            let entries = present.into_iter().map(|(field, path)| (field, self.at(path)));
+            visitor.visit_map(MapDeserializer::new(entries))
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &str,
+            variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_enum(Variant {
+                name: variants[0],
+                recorder: self,
+            })
+        }
+
+        fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &str, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_seq(self.items())
+        }
+
+        fn deserialize_tuple_struct<V: Visitor<'de>>(
+            self,
+            _name: &str,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_seq(self.items())
+        }
+
+        forward! {
+            deserialize_i8, deserialize_i16, deserialize_i32, deserialize_i64, deserialize_i128, deserialize_u8,
+            deserialize_u16, deserialize_u32, deserialize_u128 => deserialize_u64;
+            deserialize_f32 => deserialize_f64;
+            deserialize_char, deserialize_string, deserialize_bytes, deserialize_byte_buf,
+            deserialize_identifier => deserialize_str;
+        }
+    }
+
+    /// The first variant of an enum.
+    struct Variant<'a> {
+        name: &'static str,
+        recorder: Recorder<'a>,
+    }
+
+    impl<'de, 'a> de::EnumAccess<'de> for Variant<'a> {
+        type Error = Error;
+        type Variant = Recorder<'a>;
+
+        fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Recorder<'a>), Error> {
+            let name = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.name))?;
+            Ok((name, self.recorder))
+        }
+    }
+
+    impl<'de> de::VariantAccess<'de> for Recorder<'_> {
+        type Error = Error;
+
+        fn unit_variant(self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Error> {
+            seed.deserialize(self)
+        }
+
+        fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_seq(self.items())
+        }
+
+        fn struct_variant<V: Visitor<'de>>(
+            self,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            de::Deserializer::deserialize_struct(self, "", fields, visitor)
+        }
+    }
+
+    fn join(path: &str, field: &str) -> String {
+        if path.is_empty() {
+            field.to_string()
+        } else {
+            format!("{path}.{field}")
+        }
+    }
+
+    /// Fields of the structs `T` is made of, as found at `path` of its schema.
+    fn fields_of<T: DeserializeOwned>(path: &str) -> Fields {
+        let trace = RefCell::new(Trace::default());
+        loop {
+            let skipped = trace.borrow().skipped.len();
+            trace.borrow_mut().last_string = None;
+            let res = T::deserialize(Recorder {
+                path: path.to_string(),
+                trace: &trace,
+            });
+            let mut trace_mut = trace.borrow_mut();
+            match res {
+                Ok(_) => break,
+                Err(_) if trace_mut.skipped.len() > skipped => {}
+                Err(error) => {
+                    // Most likely the last string didn't parse, try the next one
+                    let Some(at) = trace_mut.last_string.clone() else {
+                        panic!("Failed to trace {}: {error}", std::any::type_name::<T>());
+                    };
+                    let tried = trace_mut.strings.entry(at.clone()).or_default();
+                    *tried += 1;
+                    assert!(*tried < STRINGS.len(), "No string deserializes at {at}: {error}");
+                }
+            }
+        }
+        trace.into_inner().fields
+    }
+
+    fn schema(name: &str) -> Value {
+        serde_json::from_slice(&get(name).unwrap()).unwrap()
+    }
+
+    /// Properties of `node` and everything below it by path, following `$ref`s.
+    fn properties_of(node: &Value, root: &Value, path: &str, out: &mut Fields) {
+        if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+            let (file, pointer) = reference.split_once('#').unwrap_or((reference, ""));
+            let root = if file.is_empty() { root.clone() } else { schema(file) };
+            let target = root
+                .pointer(pointer)
+                .unwrap_or_else(|| panic!("Dangling $ref {reference}"));
+            properties_of(target, &root, path, out);
+        }
+        if let Some(properties) = node.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                out.entry(path.to_string()).or_default().insert(name.clone());
+                properties_of(property, root, &join(path, name), out);
+            }
+        }
+        if let Some(items) = node.get("items") {
+            properties_of(items, root, &format!("{path}[]"), out);
+        }
+        for key in ["anyOf", "oneOf", "allOf"] {
+            for node in node.get(key).and_then(Value::as_array).into_iter().flatten() {
+                properties_of(node, root, path, out);
+            }
+        }
+    }
+
+    /// Fields missing from the schema, and properties the structs don't have.
+    fn differences(name: &str, fields: Fields) -> Vec<String> {
+        let root = schema(name);
+        let mut properties = Fields::new();
+        properties_of(&root, &root, "", &mut properties);
+
+        let mut differences = vec![];
+        for (path, fields) in &fields {
+            let described = properties.get(path).cloned().unwrap_or_default();
+            for field in fields.difference(&described) {
+                differences.push(format!("{name}: {} is not described", join(path, field)));
+            }
+            for property in described.difference(fields) {
+                differences.push(format!("{name}: {} is not read", join(path, property)));
+            }
+        }
+        differences
+    }
+
+    #[test]
+    fn schemas_describe_every_field() {
+        let mut playlist = fields_of::<PlaylistMeta>("");
+        playlist.extend(fields_of::<TrackMeta>("tracks[]"));
+
+        let differences: Vec<_> = [
+            differences("settings", fields_of::<Settings>("")),
+            differences("jobs", fields_of::<Job>("[]")),
+            differences("playlist", playlist),
+        ]
+        .concat();
+        assert!(differences.is_empty(), "{differences:#?}");
+    }
+
+    /// Resolves references between the schemas, eg. `jobs.json#/$defs/alert`.
+    struct Embedded;
+
+    impl jsonschema::Retrieve for Embedded {
+        fn retrieve(&self, uri: &jsonschema::Uri<String>) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+            let name = uri.as_str().rsplit('/').next().unwrap_or_default();
+            let data = get(name).ok_or_else(|| format!("No schema {uri}"))?;
+            Ok(serde_json::from_slice(&data)?)
+        }
+    }
+
+    #[test]
+    fn example_settings_match_the_schema() {
+        let validator = jsonschema::options()
+            .with_base_uri("file:///schema/settings.json")
+            .with_retriever(Embedded)
+            .build(&schema("settings"))
+            .unwrap();
+        let example: Value = serde_json::from_str(include_str!("../settings.example.json")).unwrap();
+
+        let errors: Vec<_> = validator
+            .iter_errors(&example)
+            .map(|e| format!("{}: {e}", e.instance_path()))
+            .collect();
+        assert!(errors.is_empty(), "{errors:#?}");
+    }
+}
@@ -0,0 +1,220 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio_util::sync::CancellationToken;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode, ServiceInfo,
+    ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::settings::Overrides;
+use crate::telemetry::Telemetry;
+
+const SERVICE_NAME: &str = "musicd";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+/// How long stopping may take, as told to the service control manager: open requests, then the checkpoint.
+const STOP_WAIT_HINT_SECS: u64 = 10;
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum ServiceCommand {
+    /// Install a service running the daemon from the current directory at boot (as administrator)
+    Install {
+        /// Settings file to use instead of `settings.json`
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+    /// Stop and remove the installed service (as administrator)
+    Uninstall,
+    /// Run the daemon as the service; started by the service control manager, not by hand
+    Run {
+        /// Directory the service runs from, with its settings files
+        #[arg(long)]
+        dir: PathBuf,
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+}
+
+/// What the service thread starts the daemon with.
+struct Launch {
+    overrides: Overrides,
+    telemetry: Telemetry,
+    runtime: tokio::runtime::Handle,
+}
+
+/// Handed over to `service_main`, which the service control manager calls without arguments of ours.
+static LAUNCH: Mutex<Option<Launch>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Run `cmd`. `service run` blocks until the service is stopped.
+pub fn run(cmd: ServiceCommand, telemetry: Telemetry) -> anyhow::Result<()> {
+    match cmd {
+        ServiceCommand::Install { config } => install(config),
+        ServiceCommand::Uninstall => uninstall(),
+        ServiceCommand::Run { dir, config } => {
+            // Services start in the system directory
+            std::env::set_current_dir(&dir).with_context(|| format!("Failed to change to {}", dir.display()))?;
+            dotenvy::dotenv().ok();
+
+            *LAUNCH.lock().unwrap() = Some(Launch {
+                overrides: Overrides {
+                    config,
+                    ..Default::default()
+                },
+                telemetry,
+                runtime: tokio::runtime::Handle::current(),
+            });
+            service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+                .context("Failed to connect to the service control manager, `musicd service run` is started by it")?;
+            Ok(())
+        }
+    }
+}
+
+fn install(config: Option<PathBuf>) -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("Failed to open the service control manager, run as administrator")?;
+
+    let mut arguments: Vec<OsString> = vec![
+        "service".into(),
+        "run".into(),
+        "--dir".into(),
+        std::env::current_dir()?.into_os_string(),
+    ];
+    if let Some(config) = config {
+        arguments.push("--config".into());
+        arguments.push(std::path::absolute(config)?.into_os_string());
+    }
+
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: SERVICE_NAME.into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe().context("Failed to locate the musicd binary")?,
+        launch_arguments: arguments,
+        dependencies: vec![],
+        // LocalSystem
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .context("Failed to create the service")?;
+    service.set_description(env!("CARGO_PKG_DESCRIPTION"))?;
+
+    println!("Installed the {SERVICE_NAME} service, it starts at boot or with `sc start {SERVICE_NAME}`");
+    Ok(())
+}
+
+fn uninstall() -> anyhow::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .context("Failed to open the service control manager, run as administrator")?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .context("Failed to open the service, is it installed?")?;
+
+    // Removed once stopped and every handle to it is closed
+    service.delete().context("Failed to remove the service")?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop().context("Failed to stop the service")?;
+    }
+
+    println!("Removed the {SERVICE_NAME} service");
+    Ok(())
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    let Some(launch) = LAUNCH.lock().unwrap().take() else {
+        return;
+    };
+    if let Err(error) = run_service(launch) {
+        tracing::error!("Service failed: {error:#}");
+    }
+}
+
+/// Run the daemon until the service control manager stops it (or Windows shuts down), through the same
+/// graceful shutdown as Ctrl+C.
+fn run_service(launch: Launch) -> anyhow::Result<()> {
+    let shutdown = CancellationToken::new();
+    let handler = {
+        let shutdown = shutdown.clone();
+        move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                shutdown.cancel();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        }
+    };
+    let status = service_control_handler::register(SERVICE_NAME, handler)?;
+    set_status(
+        status,
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ServiceExitCode::Win32(0),
+    )?;
+
+    let res = launch.runtime.block_on(async {
+        // Saving the playback state may take a while, tell so
+        tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown.cancelled().await;
+                if let Err(error) = set_status(
+                    status,
+                    ServiceState::StopPending,
+                    ServiceControlAccept::empty(),
+                    ServiceExitCode::Win32(0),
+                ) {
+                    tracing::warn!("Failed to report stopping: {error}");
+                }
+            }
+        });
+        crate::start(launch.overrides, launch.telemetry, shutdown).await
+    });
+
+    let exit_code = match &res {
+        Ok(()) => ServiceExitCode::Win32(0),
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    set_status(status, ServiceState::Stopped, ServiceControlAccept::empty(), exit_code)?;
+    res
+}
+
+fn set_status(
+    status: ServiceStatusHandle,
+    state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+    exit_code: ServiceExitCode,
+) -> windows_service::Result<()> {
+    let wait_hint = if state == ServiceState::StopPending {
+        Duration::from_secs(STOP_WAIT_HINT_SECS)
+    } else {
+        Duration::ZERO
+    };
+    status.set_service_status(ServiceStatus {
+        service_type: SERVICE_TYPE,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint,
+        process_id: None,
+    })
+}
@@ -1,21 +1,56 @@
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::{fs, path::PathBuf};
 
+use anyhow::Context;
+use config::Source;
+
 use crate::downloader::DownloaderKind;
+use crate::job::{AlertTarget, JobStoreKind, blackout::Blackout};
+use crate::transcode::TranscodeFormat;
 
 /// The possible runtime environment for our application.
+/// Any other name selects `<name>.json` as an overlay file, with `local` defaults.
 #[derive(serde::Deserialize, Clone, Debug, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[serde(try_from = "String")]
 pub enum Environment {
     Local,
     Test,
+    Production,
+    Named(String),
 }
 
 impl Environment {
-    pub fn as_str(&self) -> &'static str {
+    /// Detect the running environment.
+    /// Default to `local` if unspecified.
+    pub fn detect() -> Self {
+        std::env::var("MUSICD_ENVIRONMENT")
+            .unwrap_or_else(|_| "local".into())
+            .try_into()
+            .expect("Failed to parse MUSICD_ENVIRONMENT")
+    }
+
+    pub fn as_str(&self) -> &str {
         match self {
             Environment::Local => "local",
             Environment::Test => "test",
+            Environment::Production => "production",
+            Environment::Named(name) => name,
+        }
+    }
+
+    pub fn default_data_dir(&self) -> &'static str {
+        match self {
+            Environment::Production => "/var/lib/musicd",
+            _ => "./data",
+        }
+    }
+
+    /// Log filter used when `RUST_LOG` is not set.
+    pub fn default_log_filter(&self) -> &'static str {
+        match self {
+            Environment::Production => "warn,musicd=info",
+            _ => "info,tower_http=info",
         }
     }
 }
@@ -27,6 +62,11 @@ impl TryFrom<String> for Environment {
         match s.to_lowercase().as_str() {
             "local" => Ok(Self::Local),
             "test" => Ok(Self::Test),
+            "production" | "prod" => Ok(Self::Production),
+            // Names are used as file names
+            other if !other.is_empty() && other.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => {
+                Ok(Self::Named(other.to_string()))
+            }
             other => Err(format!("{} is not a supported environment.", other)),
         }
     }
@@ -46,16 +86,70 @@ pub struct Settings {
     pub player: PlayerSettings,
     /// Publish settings.
     pub publish: PublishSettings,
+    /// Loudness analysis settings.
+    pub loudness: LoudnessSettings,
+    /// Lyrics settings.
+    pub lyrics: LyricsSettings,
+    /// Folder watch settings.
+    pub watch: WatchSettings,
     /// Job settings.
     pub job: JobSettings,
     /// Downloader settings.
     pub downloader: DownloaderSettings,
+    /// State db backup settings.
+    pub backup: BackupSettings,
+    /// Notification settings.
+    pub notifier: NotifierSettings,
+    /// Free disk space settings.
+    pub disk: DiskSettings,
+    /// Media key settings.
+    pub input: InputSettings,
+    /// GPIO controls (with the `gpio` feature).
+    pub gpio: GpioSettings,
+    /// Alarm defaults.
+    pub alarm: AlarmSettings,
+    /// Multi-room output through Snapcast.
+    pub snapcast: SnapcastSettings,
+    /// Streaming of the player's output over HTTP.
+    pub streamer: StreamerSettings,
+    /// Extra players, each with its own output device and playlist.
+    pub zones: Vec<ZoneSettings>,
+    /// Discord Rich Presence settings.
+    pub discord: DiscordSettings,
+    /// MQTT events and commands.
+    pub mqtt: MqttSettings,
+    /// Trace export settings.
+    pub telemetry: TelemetrySettings,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct ServerSettings {
     pub host: String,
     pub port: u16,
+    /// Key required on API requests, as a bearer token or `api_key` query param. Unset leaves the API open.
+    pub api_key: Option<String>,
+    /// Serve HTTPS with this certificate, plain HTTP when unset.
+    pub tls: TlsSettings,
+    /// Requests per client and largest request bodies.
+    pub rate_limit: RateLimitSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RateLimitSettings {
+    /// Publishes a minute per client IP (`POST /publish`, `/publish/upload`), 0 for no limit.
+    pub publish_per_minute: u32,
+    /// Player controls a minute per client IP (`/control/*`, `/zones/{zone}/control/*`), 0 for no limit.
+    pub control_per_minute: u32,
+    /// Largest request body (bytes), uploads and restores have their own limits.
+    pub max_body_bytes: usize,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct TlsSettings {
+    /// PEM certificate chain, the server certificate first.
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key of the certificate.
+    pub key_path: Option<PathBuf>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -66,6 +160,16 @@ pub struct ManifestSettings {
     pub url: Option<String>,
     /// How often to check for new manifest/downloads (seconds).    
     pub check_interval_secs: u64,
+    /// Failed checks back off exponentially up to this (seconds).
+    pub max_backoff_secs: u64,
+    /// Send a warning after this many failed checks in a row.
+    pub warn_after_failures: u32,
+    /// Sent as `Authorization: Bearer <token>`.
+    pub bearer_token: Option<String>,
+    /// Extra request headers, eg. `{"X-Api-Key": "..."}`.
+    pub headers: HashMap<String, String>,
+    /// Proxy for the manifest request, eg. `http://proxy:3128`.
+    pub proxy: Option<String>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -74,18 +178,347 @@ pub struct PlayerSettings {
     pub auto_play: bool,
     /// Use default audio effects.
     pub default_audio_effects: bool,
+    /// Line up the next track before the current one ends, so they play without a gap.
+    pub gapless: bool,
+    /// Skip tracks rated below this (1-5) during playback.
+    pub skip_below_rating: Option<u8>,
+    /// Skip tracks shorter than this (seconds) during playback.
+    pub min_track_secs: Option<u64>,
+    /// Skip tracks longer than this (seconds) during playback, eg. hours long loop videos.
+    pub max_track_secs: Option<u64>,
+    /// Play tracks with measured loudness at this level (LUFS). Unset plays them as they are.
+    pub loudness_target: Option<f32>,
+    /// Override path to ffmpeg, which decodes radio stations.
+    pub ffmpeg_path: PathBuf,
+    /// When to play insert clips between tracks.
+    pub inserts: InsertSettings,
+    /// Name of the output device, as listed by `GET /devices`. Unset plays on the default device.
+    pub output_device: Option<String>,
+    /// Equalizer applied to tracks and stations, adjustable with `POST /control/eq`.
+    pub equalizer: EqualizerSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct EqualizerSettings {
+    /// Equalize from the start.
+    pub enable: bool,
+    /// Center frequency of each band (Hz), 5 to 10 bands.
+    pub bands_hz: Vec<f32>,
+    /// Width of the bands, higher is narrower.
+    pub q: f32,
+    /// Preset played from the start.
+    pub preset: String,
+    /// Gain of each band (dB) by preset name, besides the built-in `flat`, `bass`, `treble`, `vocal` and `loudness`.
+    pub presets: HashMap<String, Vec<f32>>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct InsertSettings {
+    /// Play an insert after this many tracks played to their end.
+    pub every_tracks: Option<u32>,
+    /// Play an insert at the first track end after this many minutes.
+    pub every_minutes: Option<u64>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct PublishSettings {
     /// Set playlist after publish.
     pub auto_set_playlist: bool,
+    /// Server directories that local files may be imported from.
+    pub local_roots: Vec<PathBuf>,
+    /// Old playlists removed after each publish.
+    pub retention: RetentionSettings,
+    /// Convert published tracks to a single format.
+    pub transcode: TranscodeSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct RetentionSettings {
+    /// Keep at most this many playlists.
+    pub keep_last: Option<usize>,
+    /// Keep the playlists under this total size (GB).
+    pub max_total_gb: Option<f64>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TranscodeSettings {
+    /// Convert tracks with ffmpeg before publishing.
+    pub enable: bool,
+    /// Override path to ffmpeg.
+    pub ffmpeg_path: PathBuf,
+    pub format: TranscodeFormat,
+    /// Target bitrate (kbps), ignored for flac. Unset leaves it to the encoder.
+    pub bitrate_kbps: Option<u32>,
+    /// Target sample rate (Hz). Unset keeps the source rate.
+    pub sample_rate: Option<u32>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LoudnessSettings {
+    /// Measure the loudness of tracks that have none yet.
+    pub enable: bool,
+    /// Measure new tracks while publishing, so they're normalized from their first play.
+    pub on_publish: bool,
+    /// Override path to ffmpeg.
+    pub ffmpeg_path: PathBuf,
+    /// How often to look for tracks to measure (seconds).
+    pub interval_secs: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LyricsSettings {
+    /// Look lyrics up online and send the lines of the current track as it plays.
+    pub enable: bool,
+    /// LRCLIB instance lyrics are looked up on. Unset only uses lyrics embedded in tags.
+    pub lrclib_url: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct WatchSettings {
+    /// Local folders mirrored as playlists.
+    pub folders: Vec<WatchFolder>,
+    /// How often to rescan folders and check for (re)mounts (seconds).
+    pub rescan_secs: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct WatchFolder {
+    /// Directory to mirror, eg. a USB drive mount.
+    pub path: PathBuf,
+    /// Playlist name, defaults to the directory name.
+    pub name: Option<String>,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct JobSettings {
+    /// Where jobs are stored.
+    pub store: JobStoreKind,
     /// Number of seconds before expire.
     pub max_late_secs: u64,
+    /// Max number of jobs executing at the same time.
+    pub max_concurrent: usize,
+    /// Default alert target for failed or missed jobs.
+    pub alert: Option<AlertTarget>,
+    /// Blackout rules applied to every job.
+    pub blackouts: Vec<Blackout>,
+    /// Exec job settings.
+    pub exec: ExecSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ExecSettings {
+    /// Allow `exec` jobs to run local commands.
+    pub enable: bool,
+    /// Commands that exec jobs are allowed to run (exact match).
+    pub allowlist: Vec<String>,
+    /// Number of seconds before a command is killed.
+    pub default_timeout_secs: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct BackupSettings {
+    /// Periodically back up the state db.
+    pub enable: bool,
+    /// How often to back up (seconds).
+    pub interval_secs: u64,
+    /// Number of backups kept.
+    pub keep: usize,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DiskSettings {
+    /// Notify when less than this is free on the disk holding the data dir (bytes).
+    pub low_free_bytes: u64,
+    /// Refuse to publish when less than this is free (bytes).
+    pub min_free_bytes: u64,
+    /// How often to check the free space (seconds), 0 to disable.
+    pub check_interval_secs: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct InputSettings {
+    /// Control playback with the media keys of input devices (Linux only).
+    pub enable: bool,
+    /// Event devices to read, eg. `/dev/input/event3`. Empty reads all of them.
+    pub devices: Vec<PathBuf>,
+    /// Volume change per key press (0-1).
+    pub volume_step: f32,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct AlarmSettings {
+    /// Seconds to ramp the volume from 0 to the target.
+    pub ramp_secs: u64,
+    /// Volume reached at the end of the ramp (0-1).
+    pub target_volume: f32,
+    /// Seconds a snooze pauses the alarm for.
+    pub snooze_secs: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SnapcastSettings {
+    /// Play into a Snapcast server instead of the audio device.
+    pub enable: bool,
+    /// Source snapserver reads from, `pipe:///tmp/snapfifo` or `tcp://host:port` of a source in server mode.
+    pub sink: String,
+    /// Sample rate of the stream; samples are 16 bit stereo.
+    pub sample_rate: u32,
+    /// snapserver control address (eg. `localhost:1705`) to add the stream to on start. Unset leaves its configuration alone.
+    pub control: Option<String>,
+    /// Name of the added stream.
+    pub stream_name: String,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct StreamerSettings {
+    /// Serve what the player plays at `/stream`, as MP3 or Ogg Vorbis.
+    pub enable: bool,
+    /// Path to the ffmpeg binary encoding the stream.
+    pub ffmpeg_path: PathBuf,
+    /// Bitrate of the encoded stream.
+    pub bitrate_kbps: u32,
+    /// Listeners at once, each with its own encoder.
+    pub max_listeners: usize,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct ZoneSettings {
+    /// Name in the zone's API paths, eg. `patio` for `/zones/patio/control/play`.
+    pub name: String,
+    /// Name of the output device, unset plays on the default device.
+    pub device: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DiscordSettings {
+    /// Show the current track as Rich Presence of the Discord app running on this machine.
+    pub enable: bool,
+    /// Application id from the Discord developer portal, its name shows as what is being played.
+    pub client_id: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct MqttSettings {
+    /// Publish notifications to an MQTT broker and take commands from it.
+    pub enable: bool,
+    /// Broker to connect to, eg. `mqtt://localhost:1883`.
+    pub url: String,
+    /// Topics are published and subscribed under this prefix.
+    pub topic_prefix: String,
+    /// Client id, unique per broker.
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Requires `username`.
+    pub password: Option<String>,
+    /// Keep alive interval agreed with the broker (seconds).
+    pub keep_alive_secs: u16,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP collector to export traces to, eg. `http://localhost:4318`. Falls back to `OTEL_EXPORTER_OTLP_ENDPOINT`.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of the exported traces.
+    pub service_name: String,
+    /// Headers sent to the collector, eg. for auth.
+    pub headers: HashMap<String, String>,
+}
+
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct GpioSettings {
+    /// Control playback with buttons and a rotary encoder on GPIO pins.
+    pub enable: bool,
+    /// Number of the first GPIO in sysfs, found out when not set.
+    pub chip_base: Option<u32>,
+    /// Volume change per press or encoder detent (0-1).
+    pub volume_step: f32,
+    pub buttons: Vec<GpioButton>,
+    /// Rotary encoder changing the volume.
+    pub encoder: Option<GpioEncoder>,
+    /// Output that is on while playing, eg. for a LED or amp relay.
+    pub status_pin: Option<GpioOutput>,
+}
+
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GpioAction {
+    Play,
+    Pause,
+    PlayPause,
+    Next,
+    Prev,
+    VolumeUp,
+    VolumeDown,
+}
+
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct GpioButton {
+    /// BCM pin number.
+    pub pin: u32,
+    pub action: GpioAction,
+    /// Pressed when pulled low, as with a pull-up and the button to ground.
+    #[serde(default = "default_true")]
+    pub active_low: bool,
+}
+
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct GpioEncoder {
+    /// BCM pin numbers of the A and B outputs.
+    pub a: u32,
+    pub b: u32,
+}
+
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct GpioOutput {
+    /// BCM pin number.
+    pub pin: u32,
+    #[serde(default)]
+    pub active_low: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct NotifierSettings {
+    /// Services notifications are POSTed to.
+    pub webhooks: Vec<WebhookSettings>,
+    /// How often to send a `HEARTBEAT` (seconds), 0 to disable.
+    pub heartbeat_interval_secs: u64,
+    /// Event journal settings.
+    pub journal: JournalSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct JournalSettings {
+    /// Append notifications to `events.jsonl` in the data dir.
+    pub enable: bool,
+    /// Size the journal is capped at (bytes), the oldest half is dropped beyond it.
+    pub max_bytes: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct WebhookSettings {
+    pub url: String,
+    /// Notification types to send, eg. `TRACK_CHANGED`. Empty sends all but `SEEK_POSITION_CHANGED`, `LYRIC_LINE` and `HEARTBEAT`.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Signs the body with HMAC-SHA256 in the `X-Musicd-Signature` header.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Number of times to retry a failed delivery.
+    #[serde(default = "default_webhook_retries")]
+    pub retries: u32,
+}
+
+fn default_webhook_retries() -> u32 {
+    3
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
@@ -94,22 +527,85 @@ pub struct DownloaderSettings {
     pub default: DownloaderKind,
     /// Override path to yt-dlp.
     pub yt_dlp: YtDlpSettings,
+    /// Local folder importer settings.
+    pub local_dir: LocalDirSettings,
+    /// Direct URL downloader settings.
+    pub ffmpeg: FfmpegSettings,
+    /// Spotify downloader settings.
+    pub spotdl: SpotDlSettings,
 }
 
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct YtDlpSettings {
     /// Override path to yt-dlp.
     pub path: Option<PathBuf>,
+    /// Download the thumbnail of each track as its art.
+    pub thumbnails: bool,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LocalDirSettings {
+    /// Hardlink files instead of copying them, falling back to a copy across filesystems.
+    pub link: bool,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct FfmpegSettings {
+    /// Override path to ffmpeg.
+    pub path: PathBuf,
+    /// Download URLs of audio files as they are, without ffmpeg.
+    pub direct: bool,
+    /// Stop recording a stream after this many seconds. Unset records until it ends.
+    pub max_secs: Option<u64>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SpotDlSettings {
+    /// Override path to spotdl. It converts with `downloader.ffmpeg.path`.
+    pub path: PathBuf,
+}
+
+/// Any setting can be read from a file by appending this to its key, eg. `manifest.url_file`.
+const SECRET_FILE_SUFFIX: &str = "_file";
+
+/// Collect settings ending in `_file` as (setting, path), eg. (`manifest.url`, `/run/secrets/url`).
+fn secret_files(prefix: &str, table: config::Map<String, config::Value>, out: &mut Vec<(String, PathBuf)>) {
+    for (key, value) in table {
+        let key = match prefix {
+            "" => key,
+            _ => format!("{prefix}.{key}"),
+        };
+        if let Some(setting) = key.strip_suffix(SECRET_FILE_SUFFIX)
+            && let Ok(path) = value.clone().into_string()
+        {
+            out.push((setting.to_string(), PathBuf::from(path)));
+        } else if let Ok(array) = value.clone().into_array() {
+            for (i, value) in array.into_iter().enumerate() {
+                if let Ok(table) = value.into_table() {
+                    secret_files(&format!("{key}[{i}]"), table, out);
+                }
+            }
+        } else if let Ok(table) = value.into_table() {
+            secret_files(&key, table, out);
+        }
+    }
+}
+
+/// Settings given on the command line, applied over the settings files and environment.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// Used instead of `settings.json`.
+    pub config: Option<PathBuf>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub data_dir: Option<PathBuf>,
+    pub auto_play: Option<bool>,
 }
 
 impl Settings {
-    pub fn load_or_init() -> anyhow::Result<Self> {
-        // Detect the running environment.
-        // Default to `local` if unspecified.
-        let environment: Environment = std::env::var("MUSICD_ENVIRONMENT")
-            .unwrap_or_else(|_| "local".into())
-            .try_into()
-            .expect("Failed to parse MUSICD_ENVIRONMENT");
+    /// Settings files, in the order they are applied.
+    pub fn files(overrides: &Overrides) -> Vec<PathBuf> {
+        let environment = Environment::detect();
 
         let mut base_path = std::env::current_dir().expect("Failed to determine the current directory");
 
@@ -118,31 +614,182 @@ impl Settings {
             base_path = std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../.."));
         }
 
-        let environment_filename = format!("{}.json", environment.as_str());
-        let settings = config::Config::builder()
+        vec![
+            overrides
+                .config
+                .clone()
+                .unwrap_or_else(|| base_path.join("settings.json")),
+            base_path.join(format!("{}.json", environment.as_str())),
+        ]
+    }
+
+    pub fn load_or_init(overrides: &Overrides) -> anyhow::Result<Self> {
+        let environment = Environment::detect();
+
+        let mut builder = config::Config::builder()
             .set_default("environment", environment.as_str())?
-            .set_default("data_dir", "./data")?
+            .set_default("data_dir", environment.default_data_dir())?
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 8371)?
+            .set_default("server.api_key", None::<Option<String>>)?
+            .set_default("server.tls.cert_path", None::<Option<String>>)?
+            .set_default("server.tls.key_path", None::<Option<String>>)?
+            .set_default("server.rate_limit.publish_per_minute", 10)?
+            .set_default("server.rate_limit.control_per_minute", 300)?
+            .set_default("server.rate_limit.max_body_bytes", 2 * 1024 * 1024)?
             .set_default("manifest.enable", false)?
             .set_default("manifest.url", None::<Option<String>>)?
             .set_default("manifest.check_interval_secs", 900)?
+            .set_default("manifest.max_backoff_secs", 21600)?
+            .set_default("manifest.warn_after_failures", 3)?
+            .set_default("manifest.bearer_token", None::<Option<String>>)?
+            .set_default("manifest.headers", config::Map::<String, String>::new())?
+            .set_default("manifest.proxy", None::<Option<String>>)?
             .set_default("player.auto_play", true)?
             .set_default("player.default_audio_effects", true)?
+            .set_default("player.gapless", true)?
+            .set_default("player.skip_below_rating", None::<Option<u8>>)?
+            .set_default("player.min_track_secs", None::<Option<u64>>)?
+            .set_default("player.max_track_secs", None::<Option<u64>>)?
+            .set_default("player.loudness_target", None::<Option<f64>>)?
+            .set_default("player.ffmpeg_path", "ffmpeg")?
+            .set_default("player.inserts.every_tracks", None::<Option<u32>>)?
+            .set_default("player.inserts.every_minutes", None::<Option<u64>>)?
+            .set_default("player.output_device", None::<Option<String>>)?
+            .set_default("player.equalizer.enable", false)?
+            .set_default("player.equalizer.bands_hz", vec![60.0, 250.0, 1000.0, 4000.0, 12000.0])?
+            .set_default("player.equalizer.q", 1.0)?
+            .set_default("player.equalizer.preset", "flat")?
+            .set_default("player.equalizer.presets", config::Map::<String, String>::new())?
             .set_default("publish.auto_set_playlist", false)?
+            .set_default("publish.local_roots", Vec::<String>::new())?
+            .set_default("publish.retention.keep_last", None::<Option<u64>>)?
+            .set_default("publish.retention.max_total_gb", None::<Option<f64>>)?
+            .set_default("publish.transcode.enable", false)?
+            .set_default("publish.transcode.ffmpeg_path", "ffmpeg")?
+            .set_default("publish.transcode.format", "mp3")?
+            .set_default("publish.transcode.bitrate_kbps", 192)?
+            .set_default("publish.transcode.sample_rate", 44100)?
+            .set_default("loudness.enable", false)?
+            .set_default("loudness.on_publish", false)?
+            .set_default("loudness.ffmpeg_path", "ffmpeg")?
+            .set_default("loudness.interval_secs", 3600)?
+            .set_default("lyrics.enable", false)?
+            .set_default("lyrics.lrclib_url", "https://lrclib.net")?
+            .set_default("watch.folders", Vec::<String>::new())?
+            .set_default("watch.rescan_secs", 300)?
+            .set_default("job.store", "file")?
             .set_default("job.max_late_secs", 10)?
+            .set_default("job.max_concurrent", 4)?
+            .set_default("job.alert", None::<Option<String>>)?
+            .set_default("job.blackouts", Vec::<String>::new())?
+            .set_default("job.exec.enable", false)?
+            .set_default("job.exec.allowlist", Vec::<String>::new())?
+            .set_default("job.exec.default_timeout_secs", 60)?
             .set_default("downloader.default", DownloaderKind::YtDlp.as_str())?
             .set_default("downloader.yt_dlp.path", "yt-dlp")?
-            .add_source(config::File::from(base_path.join("settings.json")).required(false))
-            .add_source(config::File::from(base_path.join(environment_filename)).required(false))
+            .set_default("downloader.yt_dlp.thumbnails", true)?
+            .set_default("downloader.local_dir.link", false)?
+            .set_default("downloader.ffmpeg.path", "ffmpeg")?
+            .set_default("downloader.ffmpeg.direct", true)?
+            .set_default("downloader.ffmpeg.max_secs", 21600)?
+            .set_default("downloader.spotdl.path", "spotdl")?
+            .set_default("backup.enable", true)?
+            .set_default("backup.interval_secs", 86400)?
+            .set_default("backup.keep", 7)?
+            .set_default("notifier.webhooks", Vec::<String>::new())?
+            .set_default("notifier.heartbeat_interval_secs", 30)?
+            .set_default("notifier.journal.enable", false)?
+            .set_default("notifier.journal.max_bytes", 10 * 1024 * 1024)?
+            .set_default("disk.low_free_bytes", 1024 * 1024 * 1024)?
+            .set_default("disk.min_free_bytes", 200 * 1024 * 1024)?
+            .set_default("disk.check_interval_secs", 300)?
+            .set_default("input.enable", false)?
+            .set_default("input.devices", Vec::<String>::new())?
+            .set_default("input.volume_step", 0.05)?
+            .set_default("gpio.enable", false)?
+            .set_default("gpio.chip_base", None::<Option<u64>>)?
+            .set_default("gpio.volume_step", 0.05)?
+            .set_default("gpio.buttons", Vec::<String>::new())?
+            .set_default("gpio.encoder", None::<Option<String>>)?
+            .set_default("gpio.status_pin", None::<Option<String>>)?
+            .set_default("alarm.ramp_secs", 900)?
+            .set_default("alarm.target_volume", 0.8)?
+            .set_default("alarm.snooze_secs", 540)?
+            .set_default("snapcast.enable", false)?
+            .set_default("snapcast.sink", "pipe:///tmp/snapfifo")?
+            .set_default("snapcast.sample_rate", 48000)?
+            .set_default("snapcast.control", None::<Option<String>>)?
+            .set_default("snapcast.stream_name", "musicd")?
+            .set_default("streamer.enable", false)?
+            .set_default("streamer.ffmpeg_path", "ffmpeg")?
+            .set_default("streamer.bitrate_kbps", 128)?
+            .set_default("streamer.max_listeners", 10)?
+            .set_default("zones", Vec::<String>::new())?
+            .set_default("discord.enable", false)?
+            .set_default("discord.client_id", None::<Option<String>>)?
+            .set_default("mqtt.enable", false)?
+            .set_default("mqtt.url", "mqtt://localhost:1883")?
+            .set_default("mqtt.topic_prefix", "musicd")?
+            .set_default("mqtt.client_id", "musicd")?
+            .set_default("mqtt.username", None::<Option<String>>)?
+            .set_default("mqtt.password", None::<Option<String>>)?
+            .set_default("mqtt.keep_alive_secs", 60)?
+            .set_default("telemetry.otlp_endpoint", None::<Option<String>>)?
+            .set_default("telemetry.service_name", "musicd")?
+            .set_default("telemetry.headers", config::Map::<String, String>::new())?;
+        for (i, file) in Self::files(overrides).into_iter().enumerate() {
+            // A config given on the command line must exist
+            let required = i == 0 && overrides.config.is_some();
+            builder = builder.add_source(config::File::from(file).required(required));
+        }
+        let settings = builder
             .add_source(
                 config::Environment::with_prefix("MUSICD")
                     .prefix_separator("_")
                     .separator("__"),
             )
+            .set_override_option("server.host", overrides.host.clone())?
+            .set_override_option("server.port", overrides.port)?
+            .set_override_option(
+                "data_dir",
+                overrides.data_dir.as_ref().map(|p| p.to_string_lossy().to_string()),
+            )?
+            .set_override_option("player.auto_play", overrides.auto_play)?
             .build()?;
 
+        // Read secrets from files, eg. Docker or Kubernetes secrets
+        let mut secrets = vec![];
+        secret_files("", settings.collect()?, &mut secrets);
+        let settings = if secrets.is_empty() {
+            settings
+        } else {
+            let mut builder = config::Config::builder().add_source(settings);
+            for (setting, path) in secrets {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {setting} from {}", path.display()))?;
+                builder = builder.set_override(setting, contents.trim_end_matches(['\r', '\n']))?;
+            }
+            builder.build()?
+        };
+
         let settings = settings.try_deserialize::<Self>()?;
+
+        // Production never serves an open API
+        if settings.environment == Environment::Production
+            && settings
+                .server
+                .api_key
+                .as_deref()
+                .is_none_or(|key| key.trim().is_empty())
+        {
+            anyhow::bail!("server.api_key is required in production, eg. set MUSICD_SERVER__API_KEY_FILE");
+        }
+        // MQTT 3.1.1 has no password without a username
+        if settings.mqtt.password.is_some() && settings.mqtt.username.is_none() {
+            anyhow::bail!("mqtt.password is set without mqtt.username");
+        }
+
         Ok(settings)
     }
 
@@ -151,19 +798,26 @@ impl Settings {
         let playlists = root.join("playlists");
         let tmp = root.join("tmp");
         let db = root.join("db");
+        let backups = root.join("backups");
+        let inserts = root.join("inserts");
 
         fs::create_dir_all(&playlists)?;
         fs::create_dir_all(&tmp)?;
         fs::create_dir_all(&db)?;
+        fs::create_dir_all(&inserts)?;
 
         let jobs = root.join("jobs.json");
+        let journal = root.join("events.jsonl");
 
         Ok(Paths {
             root,
             playlists,
             tmp,
             db,
+            backups,
+            inserts,
             jobs,
+            journal,
         })
     }
 }
@@ -174,5 +828,9 @@ pub struct Paths {
     pub playlists: PathBuf,
     pub tmp: PathBuf,
     pub db: PathBuf,
+    pub backups: PathBuf,
+    /// Clips played between tracks.
+    pub inserts: PathBuf,
     pub jobs: PathBuf,
+    pub journal: PathBuf,
 }
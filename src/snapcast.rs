@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use rodio::mixer::Mixer;
+use serde_json::json;
+
+use crate::settings::SnapcastSettings;
+use crate::streamer::SampleTap;
+
+const CHANNELS: u16 = 2;
+/// Samples are written in chunks of this length.
+const CHUNK_MS: u32 = 20;
+/// Wait before reconnecting to a sink that went away.
+const RECONNECT_SECS: u64 = 2;
+/// Behind by more than this, eg. after the machine slept, the clock starts over instead of catching up.
+const MAX_LAG_MS: u64 = 1000;
+const CONTROL_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Debug, Clone)]
+enum Sink {
+    Pipe(PathBuf),
+    Tcp(String),
+}
+
+impl Sink {
+    fn parse(sink: &str) -> anyhow::Result<Self> {
+        if let Some(path) = sink.strip_prefix("pipe://") {
+            Ok(Sink::Pipe(PathBuf::from(path)))
+        } else if let Some(addr) = sink.strip_prefix("tcp://") {
+            Ok(Sink::Tcp(addr.to_string()))
+        } else {
+            anyhow::bail!("Invalid snapcast.sink {sink:?}, expected pipe://<path> or tcp://<host:port>")
+        }
+    }
+
+    /// Blocks until snapserver opened the pipe for reading.
+    fn open(&self) -> std::io::Result<Box<dyn Write + Send>> {
+        Ok(match self {
+            Sink::Pipe(path) => Box::new(File::options().write(true).open(path)?),
+            Sink::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                Box::new(stream)
+            }
+        })
+    }
+
+    /// Source URI for `Stream.AddStream`, with snapserver reading raw samples from the sink.
+    fn stream_uri(&self, settings: &SnapcastSettings) -> String {
+        let params = format!(
+            "name={}&sampleformat={}:16:{}",
+            settings.stream_name, settings.sample_rate, CHANNELS
+        );
+        match self {
+            Sink::Pipe(path) => format!("pipe://{}?{params}", path.display()),
+            Sink::Tcp(addr) => format!("tcp://{addr}?{params}&mode=server"),
+        }
+    }
+}
+
+/// Add the stream to snapserver through its JSON-RPC control interface.
+fn add_stream(control: &str, uri: &str) -> anyhow::Result<()> {
+    let timeout = Duration::from_secs(CONTROL_TIMEOUT_SECS);
+    let mut stream =
+        TcpStream::connect(control).with_context(|| format!("Failed to connect to snapserver at {control}"))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let request = json!({"id": 1, "jsonrpc": "2.0", "method": "Stream.AddStream", "params": {"streamUri": uri}});
+    stream.write_all(format!("{request}\r\n").as_bytes())?;
+
+    // Notifications about other clients may come before the response
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("snapserver closed the connection");
+        }
+        let Ok(response) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        if response.get("id") != Some(&json!(1)) {
+            continue;
+        }
+        if let Some(error) = response.get("error") {
+            anyhow::bail!("snapserver refused the stream: {error}");
+        }
+        return Ok(());
+    }
+}
+
+type Writer = Arc<Mutex<Option<Box<dyn Write + Send>>>>;
+
+/// A mixer for the player's sinks, played into the Snapcast sink in real time as 16 bit little endian PCM.
+/// Playback goes on while the sink is away, so positions and track ends keep up. What's played is also handed to `tap`.
+pub fn output(settings: &SnapcastSettings, mut tap: Option<SampleTap>) -> anyhow::Result<Mixer> {
+    let sink = Sink::parse(&settings.sink)?;
+    let sample_rate = settings.sample_rate;
+    let (mixer, mut source) = rodio::mixer::mixer(CHANNELS, sample_rate);
+    // Keeps the mixer going while nothing plays
+    mixer.add(rodio::source::Zero::new(CHANNELS, sample_rate));
+
+    if let Some(control) = settings.control.clone() {
+        let uri = sink.stream_uri(settings);
+        match add_stream(&control, &uri) {
+            Ok(()) => tracing::info!("Added stream {uri} to snapserver"),
+            Err(error) => tracing::warn!("Failed to add stream to snapserver: {error:#}"),
+        }
+    }
+
+    // Connect on its own thread, opening a pipe blocks until snapserver reads it
+    let writer: Writer = Default::default();
+    {
+        let writer = writer.clone();
+        let sink = sink.clone();
+        std::thread::Builder::new()
+            .name("musicd-snapcast-connect".into())
+            .spawn(move || {
+                let mut failing = false;
+                loop {
+                    if writer.lock().unwrap().is_none() {
+                        match sink.open() {
+                            Ok(w) => {
+                                tracing::info!("Playing into snapcast sink {:?}", sink);
+                                *writer.lock().unwrap() = Some(w);
+                                failing = false;
+                            }
+                            Err(error) if !failing => {
+                                tracing::warn!("Failed to open snapcast sink {:?}: {error}", sink);
+                                failing = true;
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                    std::thread::sleep(Duration::from_secs(RECONNECT_SECS));
+                }
+            })?;
+    }
+
+    std::thread::Builder::new()
+        .name("musicd-snapcast".into())
+        .spawn(move || {
+            let frames = (sample_rate * CHUNK_MS / 1000) as usize;
+            let mut buf = Vec::with_capacity(frames * CHANNELS as usize * 2);
+            let mut start = Instant::now();
+            let mut written = 0u64;
+            loop {
+                buf.clear();
+                for _ in 0..frames * CHANNELS as usize {
+                    let sample = source.next().unwrap_or(0.0).clamp(-1.0, 1.0);
+                    if let Some(tap) = tap.as_mut() {
+                        tap.push(sample);
+                    }
+                    buf.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+                }
+
+                let mut w = writer.lock().unwrap();
+                if let Some(sink) = w.as_mut()
+                    && let Err(error) = sink.write_all(&buf)
+                {
+                    tracing::warn!("Snapcast sink went away: {error}");
+                    *w = None;
+                }
+                drop(w);
+
+                // Pace to the sample rate
+                written += frames as u64;
+                let due = start + Duration::from_micros(written * 1_000_000 / sample_rate as u64);
+                let now = Instant::now();
+                if due > now {
+                    std::thread::sleep(due - now);
+                } else if now - due > Duration::from_millis(MAX_LAG_MS) {
+                    start = now;
+                    written = 0;
+                }
+            }
+        })?;
+
+    Ok(mixer)
+}
@@ -1,15 +1,114 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sled::Db;
 
 const KEY_CURRENT: &str = "current_playlist_id";
+const KEY_JOBS_IMPORTED: &str = "jobs_imported";
+const KEY_CURRENT_STATUS: &str = "current_status";
+const TREE_JOBS: &str = "jobs";
+const TREE_RATINGS: &str = "ratings";
+const TREE_HISTORY: &str = "history";
+const TREE_KV: &str = "kv";
+const TREE_PROGRESS: &str = "progress";
+const TREE_STATIONS: &str = "stations";
+const TREE_JOB_RUNS: &str = "job_runs";
+/// Runs kept per job, the oldest are dropped beyond it.
+pub const MAX_JOB_RUNS: usize = 100;
 
+/// Player state saved periodically, so playback resumes after a restart or power loss.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CurrentStatus {
     pub playlist_id: Option<String>,
     pub track_path: Option<String>,
     pub track_index: usize,
+    #[serde(default)]
+    pub position: Option<Duration>,
+    #[serde(default)]
+    pub is_paused: bool,
+    #[serde(default)]
+    pub volume: Option<f32>,
+    /// Set while a station plays instead of a playlist.
+    #[serde(default)]
+    pub station_id: Option<String>,
+}
+
+/// An internet radio station, played live instead of a playlist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Station {
+    pub id: String,
+    pub name: String,
+    /// Stream URL, anything ffmpeg can play (Icecast, Shoutcast, HLS).
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A track that was played, appended to the history when it ends or is skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the track ended or was left.
+    pub at: DateTime<Utc>,
+    /// When the track started playing, unset in entries of older versions.
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    /// Zone that played it, unset for the main player.
+    #[serde(default)]
+    pub zone: Option<String>,
+    pub playlist_id: String,
+    pub playlist_name: String,
+    pub file: String,
+    pub title: String,
+    /// How long the track was listened to.
+    pub listened: Duration,
+    /// Left with prev/next/track before it ended.
+    pub skipped: bool,
+}
+
+/// An attempt at running a job, kept for `GET /jobs/{id}/runs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRun {
+    pub started_at: DateTime<Utc>,
+    pub duration: Duration,
+    /// 0 for the first attempt, counting up with retries.
+    pub attempt: u32,
+    /// HTTP status of the response, or exit status of an exec job; unset when there was none.
+    pub status: Option<String>,
+    /// Why the run failed, unset when it succeeded.
+    pub error: Option<String>,
+}
+
+/// Position within a track of a long-form playlist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackProgress {
+    pub position: Duration,
+    pub updated_at: DateTime<Utc>,
+    /// Played to the end; the track starts over next time.
+    pub finished: bool,
+}
+
+/// Portable copy of every tree in the db, with keys and values hex encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub exported_at: DateTime<Utc>,
+    pub trees: BTreeMap<String, Vec<(String, String)>>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        anyhow::bail!("invalid hex length");
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&s[i..i + 2], 16)?))
+        .collect()
 }
 
 pub struct State {
@@ -21,6 +120,12 @@ impl State {
         Ok(Self { db: sled::open(p)? })
     }
 
+    /// Write everything to disk, eg. before exiting.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
     pub fn get_current_playlist_id(&self) -> anyhow::Result<Option<String>> {
         Ok(self
             .db
@@ -33,4 +138,396 @@ impl State {
         self.db.flush()?;
         Ok(())
     }
+
+    /// Playlist of the player zone `zone`.
+    pub fn get_zone_playlist_id(&self, zone: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .db
+            .get(format!("{KEY_CURRENT}:{zone}"))?
+            .and_then(|ivec| String::from_utf8(ivec.to_vec()).ok()))
+    }
+
+    pub fn set_zone_playlist_id(&self, zone: &str, id: &str) -> anyhow::Result<()> {
+        self.db.insert(format!("{KEY_CURRENT}:{zone}"), id.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Playlists of all player zones.
+    pub fn list_zone_playlist_ids(&self) -> anyhow::Result<Vec<String>> {
+        let mut ids = vec![];
+        for entry in self.db.scan_prefix(format!("{KEY_CURRENT}:")) {
+            let (_, value) = entry?;
+            ids.extend(String::from_utf8(value.to_vec()).ok());
+        }
+        Ok(ids)
+    }
+
+    /// Checkpoint of the main player, or of the player zone `zone`.
+    pub fn get_current_status(&self, zone: Option<&str>) -> anyhow::Result<Option<CurrentStatus>> {
+        Ok(self
+            .db
+            .get(Self::status_key(zone))?
+            .and_then(|ivec| serde_json::from_slice(&ivec).ok()))
+    }
+
+    pub fn set_current_status(&self, zone: Option<&str>, status: &CurrentStatus) -> anyhow::Result<()> {
+        self.db.insert(Self::status_key(zone), serde_json::to_vec(status)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn status_key(zone: Option<&str>) -> String {
+        match zone {
+            Some(zone) => format!("{KEY_CURRENT_STATUS}:{zone}"),
+            None => KEY_CURRENT_STATUS.to_string(),
+        }
+    }
+
+    pub fn jobs_imported(&self) -> anyhow::Result<bool> {
+        Ok(self.db.contains_key(KEY_JOBS_IMPORTED)?)
+    }
+
+    pub fn set_jobs_imported(&self) -> anyhow::Result<()> {
+        self.db.insert(KEY_JOBS_IMPORTED, &[1])?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn list_jobs(&self) -> anyhow::Result<Vec<Value>> {
+        let tree = self.db.open_tree(TREE_JOBS)?;
+        let mut jobs = vec![];
+        for entry in tree.iter() {
+            let (_, value) = entry?;
+            jobs.push(serde_json::from_slice(&value)?);
+        }
+        Ok(jobs)
+    }
+
+    pub fn put_job(&self, id: &str, job: &Value) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_JOBS)?;
+        tree.insert(id, serde_json::to_vec(job)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    pub fn remove_job(&self, id: &str) -> anyhow::Result<bool> {
+        let tree = self.db.open_tree(TREE_JOBS)?;
+        let existed = tree.remove(id)?.is_some();
+        tree.flush()?;
+        Ok(existed)
+    }
+
+    pub fn replace_jobs(&self, jobs: &[(String, Value)]) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_JOBS)?;
+        let mut batch = sled::Batch::default();
+        for entry in tree.iter() {
+            let (key, _) = entry?;
+            batch.remove(key);
+        }
+        for (id, job) in jobs {
+            batch.insert(id.as_bytes(), serde_json::to_vec(job)?);
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// Run keys are the job id, a nul byte and big-endian millis, so runs of a job are ordered by time.
+    fn job_run_prefix(id: &str) -> Vec<u8> {
+        let mut prefix = id.as_bytes().to_vec();
+        prefix.push(0);
+        prefix
+    }
+
+    /// Append a run of job `id`, dropping its oldest runs beyond `MAX_JOB_RUNS`.
+    pub fn append_job_run(&self, id: &str, run: &JobRun) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_JOB_RUNS)?;
+        let prefix = Self::job_run_prefix(id);
+        let mut millis = run.started_at.timestamp_millis().max(0) as u64;
+        let key = loop {
+            let key = [prefix.as_slice(), &millis.to_be_bytes()].concat();
+            // Bump on collision
+            if !tree.contains_key(&key)? {
+                break key;
+            }
+            millis += 1;
+        };
+        tree.insert(key, serde_json::to_vec(run)?)?;
+
+        let keys = tree.scan_prefix(&prefix).keys().collect::<Result<Vec<_>, _>>()?;
+        let excess = keys.len().saturating_sub(MAX_JOB_RUNS);
+        let mut batch = sled::Batch::default();
+        for key in keys.into_iter().take(excess) {
+            batch.remove(key);
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// The last `limit` runs of job `id`, newest first.
+    pub fn list_job_runs(&self, id: &str, limit: usize) -> anyhow::Result<Vec<JobRun>> {
+        let tree = self.db.open_tree(TREE_JOB_RUNS)?;
+        let mut runs = vec![];
+        for entry in tree.scan_prefix(Self::job_run_prefix(id)).rev().take(limit) {
+            let (_, value) = entry?;
+            if let Ok(run) = serde_json::from_slice(&value) {
+                runs.push(run);
+            }
+        }
+        Ok(runs)
+    }
+
+    /// Remove the runs of job `id`.
+    pub fn remove_job_runs(&self, id: &str) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_JOB_RUNS)?;
+        let mut batch = sled::Batch::default();
+        for key in tree.scan_prefix(Self::job_run_prefix(id)).keys() {
+            batch.remove(key?);
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn track_key(playlist_id: &str, file: &str) -> String {
+        format!("{playlist_id}/{file}")
+    }
+
+    pub fn get_rating(&self, playlist_id: &str, file: &str) -> anyhow::Result<Option<u8>> {
+        let tree = self.db.open_tree(TREE_RATINGS)?;
+        Ok(tree
+            .get(Self::track_key(playlist_id, file))?
+            .and_then(|ivec| ivec.first().copied()))
+    }
+
+    /// Set or clear (`None`) the rating of a track.
+    pub fn set_rating(&self, playlist_id: &str, file: &str, rating: Option<u8>) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_RATINGS)?;
+        let key = Self::track_key(playlist_id, file);
+        match rating {
+            Some(rating) => tree.insert(key, &[rating])?,
+            None => tree.remove(key)?,
+        };
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// Ratings of a playlist keyed by track file.
+    pub fn list_ratings(&self, playlist_id: &str) -> anyhow::Result<HashMap<String, u8>> {
+        let tree = self.db.open_tree(TREE_RATINGS)?;
+        let prefix = Self::track_key(playlist_id, "");
+        let mut ratings = HashMap::new();
+        for entry in tree.scan_prefix(&prefix) {
+            let (key, value) = entry?;
+            let key = String::from_utf8_lossy(&key);
+            if let (Some(file), Some(rating)) = (key.strip_prefix(&prefix), value.first()) {
+                ratings.insert(file.to_string(), *rating);
+            }
+        }
+        Ok(ratings)
+    }
+
+    /// Stations sorted by name.
+    pub fn list_stations(&self) -> anyhow::Result<Vec<Station>> {
+        let tree = self.db.open_tree(TREE_STATIONS)?;
+        let mut stations: Vec<Station> = vec![];
+        for entry in tree.iter() {
+            let (_, value) = entry?;
+            stations.extend(serde_json::from_slice(&value).ok());
+        }
+        stations.sort_by_key(|s| s.name.to_lowercase());
+        Ok(stations)
+    }
+
+    pub fn get_station(&self, id: &str) -> anyhow::Result<Option<Station>> {
+        let tree = self.db.open_tree(TREE_STATIONS)?;
+        match tree.get(id)? {
+            Some(ivec) => Ok(Some(serde_json::from_slice(&ivec)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Add or replace the station with the id of `station`.
+    pub fn set_station(&self, station: &Station) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_STATIONS)?;
+        tree.insert(&station.id, serde_json::to_vec(station)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// Remove a station, returning whether it existed.
+    pub fn remove_station(&self, id: &str) -> anyhow::Result<bool> {
+        let tree = self.db.open_tree(TREE_STATIONS)?;
+        let existed = tree.remove(id)?.is_some();
+        tree.flush()?;
+        Ok(existed)
+    }
+
+    /// History keys are big-endian millis, so entries are ordered by time.
+    fn history_key(at: &DateTime<Utc>) -> [u8; 8] {
+        (at.timestamp_millis().max(0) as u64).to_be_bytes()
+    }
+
+    pub fn append_history(&self, entry: &HistoryEntry) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_HISTORY)?;
+        let mut key = Self::history_key(&entry.at);
+        // Bump on collision
+        while tree.contains_key(key)? {
+            key = (u64::from_be_bytes(key) + 1).to_be_bytes();
+        }
+        tree.insert(key, serde_json::to_vec(entry)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    pub fn list_history_since(&self, since: &DateTime<Utc>) -> anyhow::Result<Vec<HistoryEntry>> {
+        let tree = self.db.open_tree(TREE_HISTORY)?;
+        let mut entries = vec![];
+        for entry in tree.range(Self::history_key(since)..) {
+            let (_, value) = entry?;
+            if let Ok(entry) = serde_json::from_slice(&value) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Page of the history, newest first, with the total number of entries.
+    pub fn list_history(&self, limit: usize, offset: usize) -> anyhow::Result<(Vec<HistoryEntry>, usize)> {
+        let tree = self.db.open_tree(TREE_HISTORY)?;
+        let mut entries = vec![];
+        for entry in tree.iter().rev().skip(offset).take(limit) {
+            let (_, value) = entry?;
+            if let Ok(entry) = serde_json::from_slice(&value) {
+                entries.push(entry);
+            }
+        }
+        Ok((entries, tree.len()))
+    }
+
+    /// Remove history older than `before`, returning how many entries were removed.
+    pub fn prune_history(&self, before: &DateTime<Utc>) -> anyhow::Result<usize> {
+        let tree = self.db.open_tree(TREE_HISTORY)?;
+        let mut batch = sled::Batch::default();
+        let mut removed = 0;
+        for entry in tree.range(..Self::history_key(before)) {
+            let (key, _) = entry?;
+            batch.remove(key);
+            removed += 1;
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        Ok(removed)
+    }
+
+    pub fn get_progress(&self, playlist_id: &str, file: &str) -> anyhow::Result<Option<TrackProgress>> {
+        let tree = self.db.open_tree(TREE_PROGRESS)?;
+        match tree.get(Self::track_key(playlist_id, file))? {
+            Some(ivec) => Ok(Some(serde_json::from_slice(&ivec)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_progress(&self, playlist_id: &str, file: &str, progress: &TrackProgress) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_PROGRESS)?;
+        tree.insert(Self::track_key(playlist_id, file), serde_json::to_vec(progress)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// Progress of a playlist keyed by track file.
+    pub fn list_progress(&self, playlist_id: &str) -> anyhow::Result<HashMap<String, TrackProgress>> {
+        let tree = self.db.open_tree(TREE_PROGRESS)?;
+        let prefix = Self::track_key(playlist_id, "");
+        let mut progress = HashMap::new();
+        for entry in tree.scan_prefix(&prefix) {
+            let (key, value) = entry?;
+            let key = String::from_utf8_lossy(&key);
+            if let (Some(file), Ok(value)) = (key.strip_prefix(&prefix), serde_json::from_slice(&value)) {
+                progress.insert(file.to_string(), value);
+            }
+        }
+        Ok(progress)
+    }
+
+    fn kv_key(namespace: &str, key: &str) -> String {
+        format!("{namespace}/{key}")
+    }
+
+    pub fn kv_get(&self, namespace: &str, key: &str) -> anyhow::Result<Option<Value>> {
+        let tree = self.db.open_tree(TREE_KV)?;
+        match tree.get(Self::kv_key(namespace, key))? {
+            Some(ivec) => Ok(Some(serde_json::from_slice(&ivec)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn kv_put(&self, namespace: &str, key: &str, value: &Value) -> anyhow::Result<()> {
+        let tree = self.db.open_tree(TREE_KV)?;
+        tree.insert(Self::kv_key(namespace, key), serde_json::to_vec(value)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    pub fn kv_remove(&self, namespace: &str, key: &str) -> anyhow::Result<bool> {
+        let tree = self.db.open_tree(TREE_KV)?;
+        let removed = tree.remove(Self::kv_key(namespace, key))?.is_some();
+        tree.flush()?;
+        Ok(removed)
+    }
+
+    /// Keys of a namespace with the size of their values in bytes.
+    pub fn kv_list(&self, namespace: &str) -> anyhow::Result<Vec<(String, usize)>> {
+        let tree = self.db.open_tree(TREE_KV)?;
+        let prefix = Self::kv_key(namespace, "");
+        let mut keys = vec![];
+        for entry in tree.scan_prefix(&prefix) {
+            let (key, value) = entry?;
+            let key = String::from_utf8_lossy(&key);
+            if let Some(key) = key.strip_prefix(&prefix) {
+                keys.push((key.to_string(), value.len()));
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn export_snapshot(&self) -> anyhow::Result<StateSnapshot> {
+        let mut trees = BTreeMap::new();
+        for name in self.db.tree_names() {
+            let tree = self.db.open_tree(&name)?;
+            let mut entries = vec![];
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                entries.push((to_hex(&key), to_hex(&value)));
+            }
+            trees.insert(String::from_utf8_lossy(&name).to_string(), entries);
+        }
+        Ok(StateSnapshot {
+            exported_at: Utc::now(),
+            trees,
+        })
+    }
+
+    /// Replace the contents of every tree in `snapshot`. Trees missing from it are left as is.
+    pub fn import_snapshot(&self, snapshot: &StateSnapshot) -> anyhow::Result<()> {
+        // Decode everything first, so a bad snapshot changes nothing
+        let mut decoded = vec![];
+        for (name, entries) in &snapshot.trees {
+            let mut batch = sled::Batch::default();
+            for (key, value) in entries {
+                batch.insert(from_hex(key)?, from_hex(value)?);
+            }
+            decoded.push((name, batch));
+        }
+
+        for (name, batch) in decoded {
+            let tree = self.db.open_tree(name)?;
+            tree.clear()?;
+            tree.apply_batch(batch)?;
+        }
+        self.db.flush()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,131 @@
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use crossbeam_channel::{Receiver, TryRecvError};
+use rodio::Source;
+
+/// Streams are decoded to 16 bit stereo at this rate.
+const SAMPLE_RATE: u32 = 44100;
+const CHANNELS: u16 = 2;
+/// Samples read from ffmpeg at once, 50 ms.
+const CHUNK_SAMPLES: usize = SAMPLE_RATE as usize * CHANNELS as usize / 20;
+/// Chunks decoded ahead of playback, 2 s.
+const BUFFER_CHUNKS: usize = 40;
+/// How long to wait for the first audio of a stream.
+const CONNECT_TIMEOUT_SECS: u64 = 15;
+
+/// Live audio of a station, decoded by ffmpeg in the background.
+/// Plays silence when the stream stalls, and ends when ffmpeg gives up on it.
+pub struct LiveSource {
+    child: Child,
+    rx: Receiver<Vec<f32>>,
+    chunk: std::vec::IntoIter<f32>,
+}
+
+/// Connect to the stream at `url`, returning once its first audio arrived.
+pub fn open(ffmpeg_path: &Path, url: &str) -> anyhow::Result<LiveSource> {
+    let mut child = Command::new(ffmpeg_path)
+        .arg("-nostdin")
+        .arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        // Ride out short network drops
+        .arg("-reconnect")
+        .arg("1")
+        .arg("-reconnect_streamed")
+        .arg("1")
+        .arg("-reconnect_delay_max")
+        .arg("10")
+        .arg("-i")
+        .arg(url)
+        .arg("-vn")
+        .arg("-f")
+        .arg("s16le")
+        .arg("-ac")
+        .arg(CHANNELS.to_string())
+        .arg("-ar")
+        .arg(SAMPLE_RATE.to_string())
+        .arg("-")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to spawn ffmpeg from path: {:?}", ffmpeg_path))?;
+    let stdout = child.stdout.take().context("No ffmpeg output")?;
+
+    // Read off the audio thread, the network may stall
+    let (tx, rx) = crossbeam_channel::bounded(BUFFER_CHUNKS);
+    thread::Builder::new().name("musicd-station".into()).spawn(move || {
+        let mut reader = BufReader::new(stdout);
+        let mut bytes = vec![0u8; CHUNK_SAMPLES * 2];
+        while reader.read_exact(&mut bytes).is_ok() {
+            let chunk = bytes
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                .collect();
+            if tx.send(chunk).is_err() {
+                break;
+            }
+        }
+    })?;
+
+    match rx.recv_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS)) {
+        Ok(chunk) => Ok(LiveSource {
+            child,
+            rx,
+            chunk: chunk.into_iter(),
+        }),
+        Err(_) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!("No audio from {url}")
+        }
+    }
+}
+
+impl Iterator for LiveSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        loop {
+            if let Some(sample) = self.chunk.next() {
+                return Some(sample);
+            }
+            self.chunk = match self.rx.try_recv() {
+                Ok(chunk) => chunk.into_iter(),
+                // Keep whole frames, so the channels don't swap
+                Err(TryRecvError::Empty) => vec![0.0; CHANNELS as usize].into_iter(),
+                Err(TryRecvError::Disconnected) => return None,
+            };
+        }
+    }
+}
+
+impl Source for LiveSource {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Drop for LiveSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::state::HistoryEntry;
+
+/// Days of history kept; older entries are pruned on start.
+pub const HISTORY_RETENTION_DAYS: i64 = 90;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsWindow {
+    Day,
+    #[default]
+    Week,
+    Month,
+    /// Everything kept in the history.
+    All,
+}
+
+impl StatsWindow {
+    pub fn since(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let days = match self {
+            StatsWindow::Day => 1,
+            StatsWindow::Week => 7,
+            StatsWindow::Month => 30,
+            StatsWindow::All => HISTORY_RETENTION_DAYS,
+        };
+        now - chrono::Duration::days(days)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrackStats {
+    pub playlist_id: String,
+    pub file: String,
+    pub title: String,
+    pub plays: usize,
+    pub skips: usize,
+    pub listening_time: Duration,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlaylistStats {
+    pub playlist_id: String,
+    pub name: String,
+    pub plays: usize,
+    pub skips: usize,
+    pub listening_time: Duration,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub since: DateTime<Utc>,
+    pub plays: usize,
+    pub skips: usize,
+    pub listening_time: Duration,
+    /// Most played first.
+    pub tracks: Vec<TrackStats>,
+    /// Most played first.
+    pub playlists: Vec<PlaylistStats>,
+}
+
+/// Aggregate history `entries` into totals and the top `limit` tracks and playlists.
+pub fn aggregate(since: DateTime<Utc>, entries: &[HistoryEntry], limit: usize) -> Stats {
+    let mut tracks: HashMap<(String, String), TrackStats> = HashMap::new();
+    let mut playlists: HashMap<String, PlaylistStats> = HashMap::new();
+
+    for entry in entries {
+        let track = tracks
+            .entry((entry.playlist_id.clone(), entry.file.clone()))
+            .or_insert_with(|| TrackStats {
+                playlist_id: entry.playlist_id.clone(),
+                file: entry.file.clone(),
+                ..Default::default()
+            });
+        track.title = entry.title.clone();
+        track.listening_time += entry.listened;
+
+        let playlist = playlists
+            .entry(entry.playlist_id.clone())
+            .or_insert_with(|| PlaylistStats {
+                playlist_id: entry.playlist_id.clone(),
+                ..Default::default()
+            });
+        playlist.name = entry.playlist_name.clone();
+        playlist.listening_time += entry.listened;
+
+        if entry.skipped {
+            track.skips += 1;
+            playlist.skips += 1;
+        } else {
+            track.plays += 1;
+            playlist.plays += 1;
+        }
+    }
+
+    let mut tracks: Vec<TrackStats> = tracks.into_values().collect();
+    tracks.sort_by(|a, b| b.plays.cmp(&a.plays).then(b.listening_time.cmp(&a.listening_time)));
+    tracks.truncate(limit);
+
+    let mut playlists: Vec<PlaylistStats> = playlists.into_values().collect();
+    playlists.sort_by(|a, b| b.plays.cmp(&a.plays).then(b.listening_time.cmp(&a.listening_time)));
+    playlists.truncate(limit);
+
+    Stats {
+        since,
+        plays: entries.iter().filter(|e| !e.skipped).count(),
+        skips: entries.iter().filter(|e| e.skipped).count(),
+        listening_time: entries.iter().map(|e| e.listened).sum(),
+        tracks,
+        playlists,
+    }
+}
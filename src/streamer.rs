@@ -0,0 +1,196 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Context;
+use axum::body::Bytes;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::settings::StreamerSettings;
+
+const CHANNELS: u16 = 2;
+/// Samples are handed to the encoders in chunks of this length.
+const CHUNK_MS: u32 = 50;
+/// Chunks waiting for the encoders before the tap drops them, 2 s.
+const BUFFER_CHUNKS: usize = 40;
+/// Encoded chunks waiting for a listener before they're dropped.
+const LISTENER_CHUNKS: usize = 64;
+const READ_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    #[default]
+    Mp3,
+    Ogg,
+}
+
+impl StreamFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Mp3 => "audio/mpeg",
+            StreamFormat::Ogg => "audio/ogg",
+        }
+    }
+
+    fn ffmpeg_args(&self) -> [&'static str; 4] {
+        match self {
+            StreamFormat::Mp3 => ["-c:a", "libmp3lame", "-f", "mp3"],
+            StreamFormat::Ogg => ["-c:a", "libvorbis", "-f", "ogg"],
+        }
+    }
+}
+
+/// Hands the samples played to the streamer, from the audio thread. Does nothing while nobody listens.
+#[derive(Clone)]
+pub struct SampleTap {
+    tx: crossbeam_channel::Sender<Vec<f32>>,
+    active: Arc<AtomicBool>,
+    chunk: usize,
+    buf: Vec<f32>,
+}
+
+impl SampleTap {
+    pub fn push(&mut self, sample: f32) {
+        if !self.active.load(Ordering::Relaxed) {
+            self.buf.clear();
+            return;
+        }
+        self.buf.push(sample);
+        if self.buf.len() >= self.chunk {
+            // Dropped when the encoders fall behind, playback never waits for them
+            let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(self.chunk));
+            let _ = self.tx.try_send(chunk);
+        }
+    }
+}
+
+/// An ffmpeg process encoding the stream for one listener.
+struct Encoder {
+    child: Child,
+    stdin: ChildStdin,
+    /// Closed once the listener is gone.
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+/// Serves what the player plays to `/stream` listeners, each with its own ffmpeg encoder,
+/// so every listener gets the headers of its format from the start.
+#[derive(Clone)]
+pub struct Streamer {
+    settings: StreamerSettings,
+    sample_rate: u32,
+    tap: SampleTap,
+    encoders: Arc<Mutex<Vec<Encoder>>>,
+}
+
+impl Streamer {
+    /// Samples arrive through `tap()` as interleaved stereo at `sample_rate`.
+    pub fn new(settings: StreamerSettings, sample_rate: u32) -> anyhow::Result<Self> {
+        let (tx, rx) = crossbeam_channel::bounded(BUFFER_CHUNKS);
+        let chunk = (sample_rate * CHUNK_MS / 1000) as usize * CHANNELS as usize;
+        let streamer = Self {
+            settings,
+            sample_rate,
+            tap: SampleTap {
+                tx,
+                active: Arc::new(AtomicBool::new(false)),
+                chunk,
+                buf: Vec::with_capacity(chunk),
+            },
+            encoders: Default::default(),
+        };
+
+        let pump = streamer.clone();
+        thread::Builder::new()
+            .name("musicd-streamer".into())
+            .spawn(move || pump.pump(rx))?;
+        Ok(streamer)
+    }
+
+    pub fn tap(&self) -> SampleTap {
+        self.tap.clone()
+    }
+
+    /// Feed the tapped samples to the encoders as 16 bit PCM, stopping those whose listener left.
+    fn pump(&self, rx: crossbeam_channel::Receiver<Vec<f32>>) {
+        let mut bytes = vec![];
+        for chunk in rx {
+            bytes.clear();
+            for sample in chunk {
+                let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                bytes.extend_from_slice(&sample.to_le_bytes());
+            }
+
+            let mut encoders = self.encoders.lock().unwrap();
+            encoders.retain_mut(|encoder| {
+                let keep = !encoder.tx.is_closed() && encoder.stdin.write_all(&bytes).is_ok();
+                if !keep {
+                    let _ = encoder.child.kill();
+                    let _ = encoder.child.wait();
+                }
+                keep
+            });
+            if encoders.is_empty() && self.tap.active.swap(false, Ordering::Relaxed) {
+                tracing::info!("Last stream listener left");
+            }
+        }
+    }
+
+    /// Start encoding the stream in `format` for a new listener, returning its chunks.
+    pub fn listen(&self, format: StreamFormat) -> anyhow::Result<mpsc::Receiver<io::Result<Bytes>>> {
+        let mut encoders = self.encoders.lock().unwrap();
+        if encoders.len() >= self.settings.max_listeners {
+            anyhow::bail!("Too many listeners, at most {}", self.settings.max_listeners);
+        }
+
+        let mut child = Command::new(&self.settings.ffmpeg_path)
+            .arg("-hide_banner")
+            .arg("-loglevel")
+            .arg("error")
+            .arg("-f")
+            .arg("s16le")
+            .arg("-ar")
+            .arg(self.sample_rate.to_string())
+            .arg("-ac")
+            .arg(CHANNELS.to_string())
+            .arg("-i")
+            .arg("pipe:0")
+            .args(format.ffmpeg_args())
+            .arg("-b:a")
+            .arg(format!("{}k", self.settings.bitrate_kbps))
+            .arg("pipe:1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("failed to spawn ffmpeg from path: {:?}", self.settings.ffmpeg_path))?;
+        let stdin = child.stdin.take().context("No ffmpeg input")?;
+        let mut stdout = child.stdout.take().context("No ffmpeg output")?;
+
+        let (tx, rx) = mpsc::channel(LISTENER_CHUNKS);
+        let out = tx.clone();
+        thread::Builder::new()
+            .name("musicd-stream-encoder".into())
+            .spawn(move || {
+                let mut buf = vec![0u8; READ_BYTES];
+                while let Ok(n) = stdout.read(&mut buf)
+                    && n > 0
+                {
+                    // A slow listener skips ahead rather than holding up the encoder
+                    if let Err(mpsc::error::TrySendError::Closed(_)) =
+                        out.try_send(Ok(Bytes::copy_from_slice(&buf[..n])))
+                    {
+                        break;
+                    }
+                }
+            })?;
+
+        encoders.push(Encoder { child, stdin, tx });
+        self.tap.active.store(true, Ordering::Relaxed);
+        tracing::info!("Stream listener joined ({} listening)", encoders.len());
+        Ok(rx)
+    }
+}
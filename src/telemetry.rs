@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, reload};
+
+use crate::settings::TelemetrySettings;
+
+/// Finished spans queued for export before new ones are dropped.
+const QUEUE_SIZE: usize = 4096;
+/// Spans sent per request at most.
+const MAX_BATCH: usize = 512;
+const EXPORT_INTERVAL_SECS: u64 = 5;
+const TIMEOUT_SECS: u64 = 10;
+
+/// OTLP `SPAN_KIND_INTERNAL`.
+const KIND_INTERNAL: u8 = 1;
+/// OTLP `STATUS_CODE_ERROR`.
+const STATUS_ERROR: u8 = 2;
+
+enum Export {
+    Span(Value),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Ids and timing of a span, kept in its registry extensions.
+struct SpanData {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    start: SystemTime,
+    attributes: Vec<Value>,
+    events: Vec<Value>,
+    error: Option<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unix_nanos(at: SystemTime) -> String {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+fn random<const N: usize>(rng: &SystemRandom) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    // Failing leaves an all zero id, which collectors drop
+    let _ = rng.fill(&mut bytes);
+    bytes
+}
+
+/// Collects fields as OTLP attributes, and the `message` of events.
+/// A span with an `error` field recorded failed.
+#[derive(Default)]
+struct Fields {
+    attributes: Vec<Value>,
+    message: Option<String>,
+    error: Option<String>,
+}
+
+impl Fields {
+    fn push(&mut self, field: &Field, value: Value) {
+        match field.name() {
+            "message" => self.message = value["stringValue"].as_str().map(String::from),
+            name => {
+                if name == "error" {
+                    self.error = value["stringValue"].as_str().map(String::from);
+                }
+                self.attributes.push(json!({"key": name, "value": value}));
+            }
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, json!({"intValue": value.to_string()}));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, json!({"intValue": value.to_string()}));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, json!({"boolValue": value}));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, json!({"doubleValue": value}));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, json!({"stringValue": value}));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.push(field, json!({"stringValue": format!("{value:?}")}));
+    }
+}
+
+/// Turns closed spans into OTLP spans for the exporter.
+struct OtlpLayer {
+    tx: mpsc::Sender<Export>,
+    rng: SystemRandom,
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span
+            .parent()
+            .and_then(|p| p.extensions().get::<SpanData>().map(|d| (d.trace_id, d.span_id)));
+
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanData {
+            trace_id: parent
+                .map(|(trace_id, _)| trace_id)
+                .unwrap_or_else(|| random(&self.rng)),
+            span_id: random(&self.rng),
+            parent_span_id: parent.map(|(_, span_id)| span_id),
+            start: SystemTime::now(),
+            attributes: fields.attributes,
+            events: vec![],
+            error: fields.error,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+            data.attributes.extend(fields.attributes);
+            data.error = fields.error.or(data.error.take());
+        }
+    }
+
+    /// Warnings and errors are kept as span events, and errors fail the span.
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let message = fields.message.unwrap_or_default();
+        if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+            fields
+                .attributes
+                .push(json!({"key": "level", "value": {"stringValue": level.as_str()}}));
+            data.events.push(json!({
+                "timeUnixNano": unix_nanos(SystemTime::now()),
+                "name": message,
+                "attributes": fields.attributes,
+            }));
+            if level == Level::ERROR {
+                data.error = Some(message);
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+
+        let mut otlp = json!({
+            "traceId": hex(&data.trace_id),
+            "spanId": hex(&data.span_id),
+            "name": span.name(),
+            "kind": KIND_INTERNAL,
+            "startTimeUnixNano": unix_nanos(data.start),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": data.attributes,
+            "events": data.events,
+        });
+        if let Some(parent) = data.parent_span_id {
+            otlp["parentSpanId"] = json!(hex(&parent));
+        }
+        if let Some(message) = data.error {
+            otlp["status"] = json!({"code": STATUS_ERROR, "message": message});
+        }
+        // Never block the traced code; a full queue means the collector is down
+        let _ = self.tx.try_send(Export::Span(otlp));
+    }
+}
+
+type OtlpHandle = reload::Handle<Option<OtlpLayer>, Registry>;
+
+/// Logging, with an OTLP exporter once the settings are known.
+pub struct Telemetry {
+    handle: OtlpHandle,
+    tx: Option<mpsc::Sender<Export>>,
+}
+
+/// Log to stdout; spans are exported after `Telemetry::start`.
+pub fn init(filter: EnvFilter) -> Telemetry {
+    let (otlp, handle) = reload::Layer::new(None);
+    tracing_subscriber::registry()
+        .with(otlp)
+        .with(filter)
+        .with(fmt::layer())
+        .init();
+    Telemetry { handle, tx: None }
+}
+
+async fn send(client: &reqwest::Client, url: &str, service_name: &str, spans: Vec<Value>) {
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {"attributes": [
+                {"key": "service.name", "value": {"stringValue": service_name}},
+                {"key": "service.version", "value": {"stringValue": env!("CARGO_PKG_VERSION")}},
+            ]},
+            "scopeSpans": [{"scope": {"name": env!("CARGO_PKG_NAME")}, "spans": spans}],
+        }]
+    });
+    let res = client
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status());
+    if let Err(error) = res {
+        // Outside of any span, so it isn't exported itself
+        tracing::debug!("Failed to export spans to {url}: {error}");
+    }
+}
+
+impl Telemetry {
+    /// Export spans to the OTLP/HTTP collector at `telemetry.otlp_endpoint`, or `OTEL_EXPORTER_OTLP_ENDPOINT`.
+    pub fn start(&mut self, settings: &TelemetrySettings) {
+        let Some(endpoint) = settings
+            .otlp_endpoint
+            .clone()
+            .or_else(|| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok())
+        else {
+            return;
+        };
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+
+        let client = match client(&settings.headers) {
+            Ok(client) => client,
+            Err(error) => {
+                tracing::warn!("Failed to create OTLP client: {error:#}");
+                return;
+            }
+        };
+
+        let (tx, mut rx) = mpsc::channel(QUEUE_SIZE);
+        let layer = OtlpLayer {
+            tx: tx.clone(),
+            rng: SystemRandom::new(),
+        };
+        if let Err(error) = self.handle.modify(|otlp| *otlp = Some(layer)) {
+            tracing::warn!("Failed to enable the OTLP exporter: {error}");
+            return;
+        }
+        self.tx = Some(tx);
+        tracing::info!("Exporting traces to {url}");
+
+        let service_name = settings.service_name.clone();
+        tokio::spawn(async move {
+            let mut spans = vec![];
+            let mut interval = tokio::time::interval(Duration::from_secs(EXPORT_INTERVAL_SECS));
+            loop {
+                let flushed = tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Some(Export::Span(span)) => {
+                            spans.push(span);
+                            if spans.len() < MAX_BATCH {
+                                continue;
+                            }
+                            None
+                        }
+                        Some(Export::Flush(done)) => Some(done),
+                        None => return,
+                    },
+                    _ = interval.tick() => None,
+                };
+
+                if !spans.is_empty() {
+                    send(&client, &url, &service_name, std::mem::take(&mut spans)).await;
+                }
+                if let Some(done) = flushed {
+                    let _ = done.send(());
+                }
+            }
+        });
+    }
+
+    /// Export the spans not sent yet, eg. on shutdown.
+    pub async fn flush(&self, timeout: Duration) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let (done, flushed) = oneshot::channel();
+        if tx.send(Export::Flush(done)).await.is_ok() {
+            let _ = tokio::time::timeout(timeout, flushed).await;
+        }
+    }
+}
+
+fn client(headers: &HashMap<String, String>) -> anyhow::Result<reqwest::Client> {
+    let mut map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        map.insert(
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())?,
+            reqwest::header::HeaderValue::from_str(value)?,
+        );
+    }
+    Ok(reqwest::Client::builder()
+        .default_headers(map)
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .build()?)
+}
+
+/// W3C `traceparent` of the current span, for outgoing requests to continue the trace.
+pub fn traceparent() -> Option<String> {
+    tracing::Span::current()
+        .with_subscriber(|(id, dispatch)| {
+            let registry = dispatch.downcast_ref::<Registry>()?;
+            let span = registry.span(id)?;
+            let extensions = span.extensions();
+            let data = extensions.get::<SpanData>()?;
+            Some(format!("00-{}-{}-01", hex(&data.trace_id), hex(&data.span_id)))
+        })
+        .flatten()
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::Context;
+use lofty::file::AudioFile;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::downloader::{Progress, ProgressFn};
+use crate::playlist::{PlaylistMeta, TrackMeta, names};
+use crate::settings::TranscodeSettings;
+
+/// Codec tracks are converted to, named by the extension of the converted files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscodeFormat {
+    Mp3,
+    Aac,
+    Opus,
+    Ogg,
+    Flac,
+}
+
+impl TranscodeFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TranscodeFormat::Mp3 => "mp3",
+            TranscodeFormat::Aac => "m4a",
+            TranscodeFormat::Opus => "opus",
+            TranscodeFormat::Ogg => "ogg",
+            TranscodeFormat::Flac => "flac",
+        }
+    }
+
+    /// ffmpeg encoder and muxer.
+    fn ffmpeg_args(&self) -> [&'static str; 2] {
+        match self {
+            TranscodeFormat::Mp3 => ["libmp3lame", "mp3"],
+            TranscodeFormat::Aac => ["aac", "ipod"],
+            TranscodeFormat::Opus => ["libopus", "opus"],
+            TranscodeFormat::Ogg => ["libvorbis", "ogg"],
+            TranscodeFormat::Flac => ["flac", "flac"],
+        }
+    }
+
+    fn is_lossless(&self) -> bool {
+        matches!(self, TranscodeFormat::Flac)
+    }
+}
+
+/// Convert the tracks of `meta` in `dir` to the configured format, updating their file names.
+/// Tracks already in the format, at the sample rate and at most the bitrate are left alone.
+/// A track that fails to convert keeps its original file.
+pub async fn transcode_dir(
+    dir: &Path,
+    meta: &mut PlaylistMeta,
+    settings: &TranscodeSettings,
+    progress: &ProgressFn<'_>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let total = meta.tracks.len();
+    for (i, track) in meta.tracks.iter_mut().enumerate() {
+        if cancel.is_cancelled() {
+            anyhow::bail!("Cancelled");
+        }
+        // Tracks of mirrored folders live outside the data dir
+        if Path::new(&track.file).is_absolute() {
+            continue;
+        }
+
+        let path = dir.join(&track.file);
+        let probe_path = path.clone();
+        let probe_settings = settings.clone();
+        let up_to_date = tokio::task::spawn_blocking(move || is_up_to_date(&probe_path, &probe_settings))
+            .await
+            .unwrap_or(false);
+        if up_to_date {
+            continue;
+        }
+
+        progress(Progress {
+            percent: Some(i as f32 / total as f32 * 100.0),
+            current: Some(track.display_name()),
+        });
+
+        if let Err(error) = transcode_track(dir, track, settings, cancel).await {
+            if cancel.is_cancelled() {
+                return Err(error);
+            }
+            tracing::warn!("Keeping {} as is: {error:#}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Whether the file at `path` already matches `settings`.
+fn is_up_to_date(path: &Path, settings: &TranscodeSettings) -> bool {
+    let has_extension = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(settings.format.extension()));
+    if !has_extension {
+        return false;
+    }
+
+    let Ok(file) = lofty::read_from_path(path) else {
+        return false;
+    };
+    let properties = file.properties();
+    let sample_rate_ok = settings
+        .sample_rate
+        .is_none_or(|rate| properties.sample_rate() == Some(rate));
+    let bitrate_ok = settings.format.is_lossless()
+        || settings
+            .bitrate_kbps
+            .is_none_or(|kbps| properties.audio_bitrate().is_some_and(|b| b <= kbps));
+    sample_rate_ok && bitrate_ok
+}
+
+/// Convert one track next to the original, then replace it.
+async fn transcode_track(
+    dir: &Path,
+    track: &mut TrackMeta,
+    settings: &TranscodeSettings,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let source = dir.join(&track.file);
+    let file = Path::new(&track.file)
+        .with_extension(settings.format.extension())
+        .to_string_lossy()
+        .to_string();
+    let target = dir.join(&file);
+    let partial = PathBuf::from(format!("{}.part", target.display()));
+
+    let [codec, muxer] = settings.format.ffmpeg_args();
+    let mut command = Command::new(&settings.ffmpeg_path);
+    command
+        .arg("-nostdin")
+        .arg("-hide_banner")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-y")
+        .arg("-i")
+        .arg(&source)
+        .arg("-map")
+        .arg("0:a:0")
+        .arg("-map_metadata")
+        .arg("0")
+        .arg("-c:a")
+        .arg(codec);
+    if let Some(kbps) = settings.bitrate_kbps
+        && !settings.format.is_lossless()
+    {
+        command.arg("-b:a").arg(format!("{kbps}k"));
+    }
+    if let Some(rate) = settings.sample_rate {
+        command.arg("-ar").arg(rate.to_string());
+    }
+    let mut child = command
+        .arg("-f")
+        .arg(muxer)
+        .arg(&partial)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to spawn ffmpeg from path: {:?}", settings.ffmpeg_path))?;
+
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        _ = cancel.cancelled() => {
+            let _ = child.kill().await;
+            fs::remove_file(&partial).await.ok();
+            anyhow::bail!("Cancelled");
+        }
+    };
+    if !status.success() {
+        fs::remove_file(&partial).await.ok();
+        anyhow::bail!("ffmpeg failed with status {status}");
+    }
+
+    fs::remove_file(&source)
+        .await
+        .with_context(|| format!("Failed to remove {}", source.display()))?;
+    fs::rename(&partial, &target)
+        .await
+        .with_context(|| format!("Failed to rename {}", partial.display()))?;
+
+    track.size = fs::metadata(&target).await.ok().map(|m| m.len());
+    track.inode = names::inode(&target);
+    track.file = file;
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+
+use crate::notifier::{Event, Notification, Notifier};
+use crate::settings::WebhookSettings;
+
+/// Deliveries queued per webhook before new ones are dropped.
+const QUEUE_SIZE: usize = 1000;
+const TIMEOUT_SECS: u64 = 10;
+const RETRY_DELAY_SECS: u64 = 2;
+
+/// Event type and JSON body.
+type Delivery = (String, Vec<u8>);
+
+fn wants(webhook: &WebhookSettings, notification: &Notification) -> bool {
+    if webhook.events.is_empty() {
+        return !notification.is_noisy();
+    }
+    webhook
+        .events
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(notification.kind()))
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = ring::hmac::sign(&key, body);
+    let hex: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    format!("sha256={hex}")
+}
+
+/// Deliver events to one webhook in order, retrying with a growing delay.
+async fn deliver(client: reqwest::Client, webhook: WebhookSettings, mut rx: mpsc::Receiver<Delivery>) {
+    while let Some((event_type, body)) = rx.recv().await {
+        let mut attempt = 0;
+        loop {
+            let mut req = client
+                .post(&webhook.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Musicd-Event", &event_type)
+                .body(body.clone());
+            if let Some(secret) = &webhook.secret {
+                req = req.header("X-Musicd-Signature", sign(secret, &body));
+            }
+
+            match req.send().await.and_then(|resp| resp.error_for_status()) {
+                Ok(_) => break,
+                Err(error) if attempt < webhook.retries => {
+                    attempt += 1;
+                    tracing::debug!("Webhook {} failed (attempt {}): {}", webhook.url, attempt, error);
+                    tokio::time::sleep(Duration::from_secs(RETRY_DELAY_SECS * attempt as u64)).await;
+                }
+                Err(error) => {
+                    tracing::warn!("Webhook {} failed, dropping {}: {}", webhook.url, event_type, error);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// POST notifications to the configured webhooks.
+pub fn spawn(notifier: &Notifier, webhooks: Vec<WebhookSettings>) {
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(error) => {
+            tracing::warn!("Failed to create webhook client: {error}");
+            return;
+        }
+    };
+
+    let queues: Vec<(WebhookSettings, mpsc::Sender<Delivery>)> = webhooks
+        .into_iter()
+        .map(|webhook| {
+            let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+            tokio::spawn(deliver(client.clone(), webhook.clone(), rx));
+            (webhook, tx)
+        })
+        .collect();
+
+    let wanted: Vec<WebhookSettings> = queues.iter().map(|(webhook, _)| webhook.clone()).collect();
+    let mut rx = notifier.subscribe_with_zones(move |n| wanted.iter().any(|webhook| wants(webhook, n)));
+    tokio::spawn(async move {
+        loop {
+            let event: Event = match rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("Webhooks missed {} notifications", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            let event_type = event.notification.kind();
+            let Ok(body) = serde_json::to_vec(&event) else {
+                continue;
+            };
+
+            for (webhook, tx) in &queues {
+                if wants(webhook, &event.notification) && tx.try_send((event_type.to_string(), body.clone())).is_err() {
+                    tracing::warn!("Webhook {} queue is full, dropping {}", webhook.url, event_type);
+                }
+            }
+        }
+    });
+}
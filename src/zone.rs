@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::notifier::Notifier;
+use crate::player::{PlayerConfig, PlayerHandle, PlayerOutput, SetPlaylistMode};
+use crate::playlist::find_playlist;
+use crate::settings::{Paths, PlayerSettings, ZoneSettings};
+use crate::state::State as Kv;
+
+/// A player besides the main one, sharing its playlists but with its own output, playlist and volume.
+#[derive(Clone)]
+pub struct Zone {
+    pub player: PlayerHandle,
+    /// Events of this zone's player, served on `/zones/{name}/ws`.
+    pub notifier: Notifier,
+}
+
+/// Zones by name.
+pub type Zones = Arc<BTreeMap<String, Zone>>;
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Start a player for each of `zones`, back on the playlist it last played.
+/// Their events are passed on to the webhooks and journal of `notifier`.
+/// Zones with an invalid or repeated name are skipped.
+pub fn spawn(
+    zones: &[ZoneSettings],
+    player: &PlayerSettings,
+    paths: &Paths,
+    kv: Arc<Kv>,
+    notifier: &Notifier,
+) -> Zones {
+    let root = &paths.playlists;
+    let mut started = BTreeMap::new();
+    for settings in zones {
+        if !valid_name(&settings.name) || started.contains_key(&settings.name) {
+            tracing::warn!("Skipping zone with invalid or repeated name {:?}", settings.name);
+            continue;
+        }
+
+        let notifier = notifier.for_zone(&settings.name);
+        let output = match &settings.device {
+            Some(device) => PlayerOutput::Device(device.clone()),
+            None => PlayerOutput::DefaultDevice,
+        };
+        let handle = match PlayerHandle::new(
+            notifier.clone(),
+            PlayerConfig::from(player),
+            kv.clone(),
+            output,
+            Some(settings.name.clone()),
+            paths.inserts.clone(),
+            None,
+        ) {
+            Ok(handle) => handle,
+            Err(error) => {
+                tracing::warn!("Failed to start zone '{}': {error:#}", settings.name);
+                continue;
+            }
+        };
+
+        if let Some(id) = kv.get_zone_playlist_id(&settings.name).ok().flatten()
+            && let Some((folder, _meta)) = find_playlist(root, &id)
+        {
+            handle.set_playlist_dir(root.join(folder), SetPlaylistMode::Queue);
+        }
+        if let Some(id) = kv
+            .get_current_status(Some(&settings.name))
+            .ok()
+            .flatten()
+            .and_then(|c| c.station_id)
+            && let Some(station) = kv.get_station(&id).ok().flatten()
+        {
+            handle.set_station(station);
+        }
+
+        tracing::info!("Started zone '{}'", settings.name);
+        started.insert(
+            settings.name.clone(),
+            Zone {
+                player: handle,
+                notifier,
+            },
+        );
+    }
+    Arc::new(started)
+}
@@ -7,6 +7,7 @@ const nextBtnEl = document.getElementById('next');
 const playBtnEl = document.getElementById('play');
 const seekEl = document.getElementById('seek');
 const volumeEl = document.getElementById('volume');
+const ratingEl = document.getElementById('rating');
 const currentTimeEl = document.getElementById('current');
 const durationEl = document.getElementById('duration');
 const playlistsPlaylistNameEl = document.getElementById('playlists-playlist-name');
@@ -17,12 +18,35 @@ const devStatusEl = document.getElementById('dev-status');
 const devStateEl = document.getElementById('dev-state');
 const devJobsEl = document.getElementById('dev-jobs');
 const publishAlertEl = document.getElementById('publish-alert');
+const tabsEl = document.getElementById('tabs');
+const statsWindowEl = document.getElementById('stats-window');
+const statsSummaryEl = document.getElementById('stats-summary');
 const publishNameEl = document.getElementById('publish-name');
 const publishSourcesEl = document.getElementById('publish-sources');
 const publishBtnEl = document.getElementById('publish-btn');
+const publishProgressEl = document.getElementById('publish-progress');
+const publishProgressLabelEl = document.getElementById('publish-progress-label');
+const publishProgressBarEl = document.getElementById('publish-progress-bar');
+const publishCancelEl = document.getElementById('publish-cancel');
+
+// API key from `?api_key=` of the page, kept for later visits
+const apiKey = new URLSearchParams(location.search).get('api_key') || localStorage.getItem('api_key');
+if (apiKey) {
+  localStorage.setItem('api_key', apiKey);
+  const plainFetch = window.fetch;
+  window.fetch = (url, options = {}) =>
+    plainFetch(url, { ...options, headers: { ...options.headers, Authorization: `Bearer ${apiKey}` } });
+}
+const apiKeyParam = apiKey ? `api_key=${encodeURIComponent(apiKey)}` : '';
 
 let statusRef = null;
 
+// Publish jobs in progress by job id
+const publishJobs = new Map();
+
+// UI preferences kept on the daemon
+let prefs = {};
+
 let state = {
   isScrubbing: false,
   isChangingVolume: false,
@@ -30,6 +54,7 @@ let state = {
   currentPlaylistId: undefined,
   currentTrackIndex: undefined,
   currentTrackName: undefined,
+  lastHeartbeat: undefined,
 };
 
 const formatTime = (secs) => {
@@ -190,6 +215,22 @@ async function refreshJobs() {
   devJobsEl.textContent = JSON.stringify(jobs, null, 2);
 }
 
+async function refreshStats() {
+  const { stats } = await fetch(`/stats?window=${statsWindowEl.value}`).then((r) => r.json());
+
+  const hours = (stats.listening_time.secs / 3600).toFixed(1);
+  statsSummaryEl.textContent = `${stats.plays} plays, ${stats.skips} skips, ${hours} h listened`;
+
+  document.getElementById('stats-tbody').innerHTML = stats.tracks.map((track, i) =>
+    `<tr>
+      <td>${i + 1}</td>
+      <td>${escapeHtml(track.title)}</td>
+      <td>${track.plays}</td>
+      <td>${track.skips}</td>
+    </tr>`
+  ).join('');
+}
+
 async function selectTrack(index) {
   if (state.currentTrackIndex === index) {
     return;
@@ -209,6 +250,23 @@ async function setPlaylist(playlistId, mode) {
   });
 }
 
+function formatBytes(bytes) {
+  const units = ['B', 'KB', 'MB', 'GB', 'TB'];
+  let i = 0;
+  while (bytes >= 1024 && i < units.length - 1) {
+    bytes /= 1024;
+    i++;
+  }
+  return `${bytes.toFixed(i ? 1 : 0)} ${units[i]}`;
+}
+
+function trackTitle(track) {
+  if (track.title) {
+    return track.artist ? `${track.artist} - ${track.title}` : track.title;
+  }
+  return track.file.replace(/\.[^/.]+$/, '');
+}
+
 function renderPlaylists(playlists) {
   document.getElementById('playlists-tbody').innerHTML = playlists.map((p, i) =>
     `<tr
@@ -217,7 +275,10 @@ function renderPlaylists(playlists) {
       class="playlist-tr ${state.currentPlaylistId === p.meta.id ? 'current-playlist' : ''}"
     >
       <td>${i + 1}</td>
-      <td>${p.meta.name}</td>
+      <td>
+        ${p.meta.name} <span class="playlist-size">${formatBytes(p.size)}</span>
+        ${p.meta.description ? `<div class="playlist-description">${escapeHtml(p.meta.description)}</div>` : ''}
+      </td>
       <td class="playlist-actions">
         <sl-tooltip content="Queue Playlist">
           <sl-button
@@ -238,6 +299,10 @@ function renderPlaylists(playlists) {
               <sl-icon slot="prefix" name="copy" class="playlist-menu-item-icon"></sl-icon>
               <span class="playlist-menu-item-text">Copy ID</span>
             </sl-menu-item>
+            <sl-menu-item onclick="window.open('/playlists/${p.meta.id}/export?format=m3u8');">
+              <sl-icon slot="prefix" name="download" class="playlist-menu-item-icon"></sl-icon>
+              <span class="playlist-menu-item-text">Export M3U</span>
+            </sl-menu-item>
           </sl-menu>
         </sl-dropdown>
       </td>
@@ -250,16 +315,20 @@ function renderTracks(playlist) {
     return;
   }
 
-  document.getElementById('tracks-tbody').innerHTML = playlist.meta.tracks.map((trackName, i) =>
+  document.getElementById('tracks-tbody').innerHTML = playlist.meta.tracks.map((track, i) =>
     `<tr
       data-src="${i}"
-      class="track-tr ${state.currentTrackName === trackName ? 'current-track' : ''}"
+      class="track-tr ${state.currentTrackName === track.file ? 'current-track' : ''}"
       onclick="selectTrack(${i})"
     >
       <td>${i + 1}</td>
-      <td>${trackName}</td>
+      <td>${escapeHtml(trackTitle(track))}</td>
+      <td class="track-rating">${'★'.repeat(track.rating || 0)}</td>
     </tr>`
   ).join('');
+
+  const currentTrack = playlist.meta.tracks.find((track) => track.file === state.currentTrackName);
+  ratingEl.value = currentTrack?.rating || 0;
 }
 
 async function refreshPlaylist() {
@@ -309,6 +378,18 @@ seekEl.addEventListener('sl-input', () => {
   state.isChangingVolume = true;
 });
 
+// Rate current track
+ratingEl.addEventListener('sl-change', async () => {
+  await fetch('/control/rating', {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify({ rating: ratingEl.value || null }),
+  });
+  refreshPlaylist();
+});
+
 volumeEl.addEventListener('sl-change', () => {
   setTimeout(() => {
     state.isChangingVolume = false;
@@ -339,6 +420,14 @@ nextBtnEl.addEventListener('click', async () => {
 refreshTracksBtnEl.addEventListener('click', () => {
   refreshPlaylist();
 });
+statsWindowEl.addEventListener('sl-change', () => {
+  savePrefs({ statsWindow: statsWindowEl.value });
+  refreshStats();
+});
+tabsEl.addEventListener('sl-tab-show', (event) => {
+  savePrefs({ tab: event.detail.name });
+});
+
 refreshPlaylistsBtnEl.addEventListener('click', () => {
   refreshPlaylist();
 });
@@ -355,6 +444,26 @@ playBtnEl.addEventListener('click', async () => {
 });
 
 // Publish
+function renderPublishProgress() {
+  const jobs = [...publishJobs.values()];
+  publishProgressEl.hidden = jobs.length === 0;
+  if (jobs.length === 0) {
+    return;
+  }
+
+  // Latest job
+  const { id, name, percent, current } = jobs[jobs.length - 1];
+  publishCancelEl.dataset.id = id;
+  const more = jobs.length > 1 ? ` (+${jobs.length - 1} more)` : '';
+  publishProgressLabelEl.textContent = `${name}${current ? ` – ${current}` : ''}${more}`;
+  publishProgressBarEl.indeterminate = percent == null;
+  publishProgressBarEl.value = percent ?? 0;
+}
+
+publishCancelEl.addEventListener('click', async () => {
+  await fetch(`/publish/${publishCancelEl.dataset.id}/cancel`, { method: 'POST' });
+});
+
 publishBtnEl.addEventListener('click', async () => {
   const name = publishNameEl.value;
   const sources = publishSourcesEl.value
@@ -374,12 +483,39 @@ publishBtnEl.addEventListener('click', async () => {
   publishAlertEl.show();
 });
 
+async function loadPrefs() {
+  const res = await fetch('/kv/web/prefs').then((r) => r.json()).catch(() => ({}));
+  prefs = res.value || {};
+
+  if (prefs.statsWindow) {
+    statsWindowEl.value = prefs.statsWindow;
+  }
+  if (prefs.tab) {
+    await customElements.whenDefined('sl-tab-group');
+    tabsEl.show(prefs.tab);
+  }
+}
+
+function savePrefs(changes) {
+  prefs = { ...prefs, ...changes };
+  fetch('/kv/web/prefs', {
+    method: 'PUT',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify(prefs),
+  });
+}
+
 async function init() {
   setupTrackNameMarquee();
 
+  await loadPrefs();
+
   await refreshStatus();
   refreshPlaylist();
   refreshJobs();
+  refreshStats();
 
   setInterval(refreshStatus, 1500);
 }
@@ -389,7 +525,7 @@ document.addEventListener("DOMContentLoaded", async (event) => {
   app.classList.add('sl-ready');
 });
 
-const ws = new ReconnectingWebSocket('/ws');
+const ws = new ReconnectingWebSocket(apiKeyParam ? `/ws?${apiKeyParam}` : '/ws');
 
 ws.on('open', () => {
   console.log('Connected!');
@@ -405,7 +541,10 @@ ws.on('error', (err) => {
 
 ws.on('message', (event) => {
   const json = JSON.parse(event.data);
-  const { type, payload } = json;
+  const { seq, type, payload } = json;
+
+  // Catch up on missed events after reconnecting
+  ws.url = apiKeyParam ? `/ws?since_seq=${seq}&${apiKeyParam}` : `/ws?since_seq=${seq}`;
 
   switch (type) {
     case 'PLAYED': {
@@ -423,6 +562,7 @@ ws.on('message', (event) => {
     case 'TRACK_CHANGED': {
       const { idx, name } = payload;
 
+      refreshStats();
       state.currentTrackIndex = idx;
       state.currentTrackName = name;
 
@@ -448,10 +588,54 @@ ws.on('message', (event) => {
       break;
     }
     case 'PLAYLIST_PUBLISHED': {
-      const { name } = payload;
+      const { name, tracks } = payload;
+
+      toast(`Playlist ${name} (${tracks} tracks) successfully published`);
+
+      refreshPlaylist();
+      break;
+    }
+    case 'PUBLISH_STARTED': {
+      const { job_id, name } = payload;
+
+      publishJobs.set(job_id, { id: job_id, name, percent: null, current: null });
+      renderPublishProgress();
+      break;
+    }
+    case 'DOWNLOAD_PROGRESS': {
+      const { job_id, percent, current } = payload;
+
+      const job = publishJobs.get(job_id);
+      if (job) {
+        job.percent = percent;
+        job.current = current;
+        renderPublishProgress();
+      }
+      break;
+    }
+    case 'PUBLISH_COMPLETED': {
+      publishJobs.delete(payload.job_id);
+      renderPublishProgress();
+      break;
+    }
+    case 'PUBLISH_CANCELLED': {
+      const { job_id, name } = payload;
 
-      toast(`Playlist ${name} successfully published`);
+      publishJobs.delete(job_id);
+      renderPublishProgress();
+      toast(`Publishing ${name} cancelled`);
+      break;
+    }
+    case 'PUBLISH_FAILED': {
+      const { job_id, name, error } = payload;
 
+      publishJobs.delete(job_id);
+      renderPublishProgress();
+      toast(`Publishing ${name} failed: ${error}`, 'danger', 'exclamation-octagon', 10000);
+      break;
+    }
+    case 'PLAYLIST_UPDATED':
+    case 'PLAYLIST_REMOVED': {
       refreshPlaylist();
       break;
     }
@@ -482,5 +666,42 @@ ws.on('message', (event) => {
       toast(`Running job (id: ${id})`);
       break;
     }
+    case 'JOBS_FILE_INVALID': {
+      const { errors } = payload;
+
+      toast(`Jobs file has ${errors.length} invalid entries`, 'warning', 'exclamation-triangle', 10000);
+      break;
+    }
+    case 'JOB_ALERT': {
+      const { id, reason } = payload;
+
+      toast(`Job ${id} ${reason}`, 'danger', 'exclamation-octagon', 10000);
+      break;
+    }
+    case 'SETTINGS_RELOADED': {
+      toast('Settings reloaded');
+      break;
+    }
+    case 'HEARTBEAT': {
+      const { uptime } = payload;
+
+      // Reconnect after missing three heartbeats
+      const interval = uptime.secs - (state.lastHeartbeat ?? 0);
+      state.lastHeartbeat = uptime.secs;
+      ws.expectMessageWithin((interval > 0 && interval < uptime.secs ? interval : 30) * 3 * 1000);
+      break;
+    }
+    case 'ERROR': {
+      const { message } = payload;
+
+      toast(message, 'danger', 'exclamation-octagon', 10000);
+      break;
+    }
+    case 'WARNING': {
+      const { message } = payload;
+
+      toast(message, 'warning', 'exclamation-triangle', 10000);
+      break;
+    }
   }
 });
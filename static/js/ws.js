@@ -38,6 +38,7 @@ class ReconnectingWebSocket {
 
     this.ws.onclose = (event) => {
       this.stopHeartbeat();
+      clearTimeout(this.staleTimer);
       this._emit("close", event);
 
       if (this.shouldReconnect) {
@@ -72,6 +73,15 @@ class ReconnectingWebSocket {
     }, this.heartbeatInterval);
   }
 
+  // Close the connection if nothing arrives within timeout, so it reconnects
+  expectMessageWithin(timeout) {
+    clearTimeout(this.staleTimer);
+    this.staleTimer = setTimeout(() => {
+      console.warn("WebSocket went quiet, reconnecting");
+      this.ws.close();
+    }, timeout);
+  }
+
   stopHeartbeat() {
     if (this.heartbeatTimer) {
       clearInterval(this.heartbeatTimer);